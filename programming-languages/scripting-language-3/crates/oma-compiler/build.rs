@@ -0,0 +1,43 @@
+// Hashes the compiler's sources into `OMA_COMPILER_HASH`, which the cache keys
+// entries on, so that executables from an older build of the compiler are
+// never reused, even if its version wasn't bumped.
+
+use std::{
+  env, fs,
+  path::{Path, PathBuf},
+};
+
+fn main() {
+  let root = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+  let mut files = Vec::new();
+  sources(&root.join("src"), &mut files);
+  files.push(root.join("build.rs"));
+  files.sort();
+
+  // 64-bit FNV-1a, like the cache's hashes.
+  let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+  for file in &files {
+    println!("cargo:rerun-if-changed={}", file.display());
+    let name = file.strip_prefix(&root).unwrap().to_string_lossy();
+    let content = fs::read(file).unwrap();
+    for part in [name.as_bytes(), &content] {
+      for byte in (part.len() as u64).to_le_bytes().iter().chain(part) {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100_0000_01b3);
+      }
+    }
+  }
+  println!("cargo:rerun-if-changed=src");
+  println!("cargo:rustc-env=OMA_COMPILER_HASH={:016x}", hash);
+}
+
+fn sources(dir: &Path, files: &mut Vec<PathBuf>) {
+  for entry in fs::read_dir(dir).unwrap() {
+    let path = entry.unwrap().path();
+    if path.is_dir() {
+      sources(&path, files);
+    } else if path.extension().is_some_and(|extension| extension == "rs") {
+      files.push(path);
+    }
+  }
+}
@@ -1,3 +1,5 @@
+use std::fmt;
+
 use crate::{lex::Token, source::Span};
 
 #[derive(Clone, Debug)]
@@ -100,7 +102,7 @@ pub enum ArrayExprItem {
 pub struct TagExpr {
   pub span: Span, // span needs to include close paren
   pub tag: Ident,
-  pub expr: Box<Expr>,
+  pub fields: TagFields<Expr>,
 }
 
 impl TagExpr {
@@ -109,6 +111,34 @@ impl TagExpr {
   }
 }
 
+// Fields of a tag expression or pattern, e.g. `Some(a, b)` or
+// `Point(x: 1, y: 2)`.
+#[derive(Clone, Debug)]
+pub enum TagFields<T> {
+  Positional(Vec<T>),
+  Named(Vec<(Ident, T)>),
+}
+
+impl<T> TagFields<T> {
+  pub fn len(&self) -> usize {
+    match self {
+      Self::Positional(fields) => fields.len(),
+      Self::Named(fields) => fields.len(),
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn values(&self) -> Vec<&T> {
+    match self {
+      Self::Positional(fields) => fields.iter().collect(),
+      Self::Named(fields) => fields.iter().map(|(_, field)| field).collect(),
+    }
+  }
+}
+
 #[derive(Clone, Debug)]
 pub struct BlockExpr {
   pub span: Span,
@@ -141,6 +171,13 @@ pub enum BinaryOp {
   Subtract,
   Multiply,
   Divide,
+  Remainder,
+  Power,
+  BitAnd,
+  BitOr,
+  BitXor,
+  ShiftLeft,
+  ShiftRight,
   And,
   Or,
   Equal,
@@ -166,6 +203,13 @@ impl BinaryOp {
       Token::Dash => Self::Subtract,
       Token::Star => Self::Multiply,
       Token::Slash => Self::Divide,
+      Token::Percent => Self::Remainder,
+      Token::StarStar => Self::Power,
+      Token::Ampersand => Self::BitAnd,
+      Token::Pipe => Self::BitOr,
+      Token::Caret => Self::BitXor,
+      Token::LessLess => Self::ShiftLeft,
+      Token::GreaterGreater => Self::ShiftRight,
       _ => return None,
     };
     Some(op)
@@ -205,6 +249,7 @@ impl UnaryOp {
 #[derive(Clone, Debug)]
 pub struct BindExpr {
   pub bindee: Pat,
+  pub ty: Option<TypeExpr>,
   pub value: Box<Expr>,
 }
 
@@ -266,7 +311,7 @@ impl IfExpr {
 pub struct CaseExpr {
   pub span: Span,
   pub subject: Box<Expr>,
-  pub arms: Vec<(Pat, Expr)>,
+  pub arms: Vec<CaseExprArm>,
 }
 
 impl CaseExpr {
@@ -275,6 +320,15 @@ impl CaseExpr {
   }
 }
 
+// An arm matches if any of its alternative patterns match and the guard, if
+// present, is truthy. All alternatives bind the same identifiers.
+#[derive(Clone, Debug)]
+pub struct CaseExprArm {
+  pub pats: Vec<Pat>,
+  pub guard: Option<Expr>,
+  pub expr: Expr,
+}
+
 #[derive(Clone, Debug)]
 pub struct ForExpr {
   pub span: Span,
@@ -306,6 +360,7 @@ impl WhileExpr {
 pub struct LambdaExpr {
   pub span: Span,
   pub parameters: Vec<LambdaExprParameter>,
+  pub return_ty: Option<TypeExpr>,
   pub body: Box<Expr>,
 }
 
@@ -318,7 +373,107 @@ impl LambdaExpr {
 #[derive(Clone, Debug)]
 pub enum LambdaExprParameter {
   Spread(Ident),
-  Pat(Pat),
+  Pat(Pat, Option<TypeExpr>),
+}
+
+// A type annotation, such as `int`, `[string]`, `{ x: float }` or
+// `Some(int) | None()`. Annotations are checked by `check`, and have no effect
+// on the generated code.
+#[derive(Clone, Debug)]
+pub enum TypeExpr {
+  // A named type, such as `int` or `any`.
+  Name(Ident),
+  Array(ArrayTypeExpr),
+  Map(MapTypeExpr),
+  Tag(TagTypeExpr),
+  // Alternatives separated by `|`, of which there are at least two.
+  Union(Vec<TypeExpr>),
+}
+
+impl TypeExpr {
+  pub fn span(&self) -> Span {
+    match self {
+      Self::Name(ident) => ident.span(),
+      Self::Array(array_type) => array_type.span.clone(),
+      Self::Map(map_type) => map_type.span.clone(),
+      Self::Tag(tag_type) => tag_type.span.clone(),
+      Self::Union(types) => {
+        Span::combine(&types[0].span(), &types[types.len() - 1].span())
+      }
+    }
+  }
+}
+
+impl fmt::Display for TypeExpr {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Self::Name(ident) => write!(f, "{}", ident.content),
+      Self::Array(array_type) => write!(f, "[{}]", array_type.item),
+      Self::Map(map_type) => {
+        if map_type.fields.is_empty() {
+          return write!(f, "{{}}");
+        }
+        write!(f, "{{ ")?;
+        for (index, (name, ty)) in map_type.fields.iter().enumerate() {
+          if index > 0 {
+            write!(f, ", ")?;
+          }
+          write!(f, "{}: {}", name.content, ty)?;
+        }
+        write!(f, " }}")
+      }
+      Self::Tag(tag_type) => {
+        write!(f, "{}(", tag_type.tag.content)?;
+        match &tag_type.fields {
+          TagFields::Positional(fields) => {
+            for (index, ty) in fields.iter().enumerate() {
+              if index > 0 {
+                write!(f, ", ")?;
+              }
+              write!(f, "{}", ty)?;
+            }
+          }
+          TagFields::Named(fields) => {
+            for (index, (name, ty)) in fields.iter().enumerate() {
+              if index > 0 {
+                write!(f, ", ")?;
+              }
+              write!(f, "{}: {}", name.content, ty)?;
+            }
+          }
+        }
+        write!(f, ")")
+      }
+      Self::Union(types) => {
+        for (index, ty) in types.iter().enumerate() {
+          if index > 0 {
+            write!(f, " | ")?;
+          }
+          write!(f, "{}", ty)?;
+        }
+        Ok(())
+      }
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct ArrayTypeExpr {
+  pub span: Span,
+  pub item: Box<TypeExpr>,
+}
+
+#[derive(Clone, Debug)]
+pub struct MapTypeExpr {
+  pub span: Span,
+  pub fields: Vec<(Ident, TypeExpr)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TagTypeExpr {
+  pub span: Span,
+  pub tag: Ident,
+  pub fields: TagFields<TypeExpr>,
 }
 
 #[derive(Clone, Debug)]
@@ -369,6 +524,8 @@ impl AccessExpr {
 pub enum AccessExprField {
   Ident(Ident),
   Expr(Box<Expr>),
+  // A range of an array, such as `a[1..3]`. Either bound can be left out.
+  Slice(Option<Box<Expr>>, Option<Box<Expr>>),
 }
 
 #[derive(Clone, Debug)]
@@ -385,16 +542,22 @@ impl Pat {
     let pat = match expr {
       Expr::Lit(lit) => Self::Lit(lit),
       Expr::Ident(ident) => Self::Ident(ident),
-      Expr::Tag(TagExpr { span, tag, expr }) => {
-        if let Some(pat) = Self::from_expr(*expr) {
-          Pat::Tag(TagPat {
-            span,
-            tag,
-            pat: Box::new(pat),
-          })
-        } else {
-          return None;
-        }
+      Expr::Tag(TagExpr { span, tag, fields }) => {
+        let fields = match fields {
+          TagFields::Positional(fields) => TagFields::Positional(
+            fields
+              .into_iter()
+              .map(Self::from_expr)
+              .collect::<Option<_>>()?,
+          ),
+          TagFields::Named(fields) => TagFields::Named(
+            fields
+              .into_iter()
+              .map(|(name, field)| Some((name, Self::from_expr(field)?)))
+              .collect::<Option<_>>()?,
+          ),
+        };
+        Pat::Tag(TagPat { span, tag, fields })
       }
       Expr::Map(MapExpr {
         span,
@@ -467,7 +630,9 @@ impl Pat {
         idents.push(ident.clone());
       }
       Self::Tag(tag_pat) => {
-        idents.extend(tag_pat.pat.idents());
+        for pat in tag_pat.fields.values() {
+          idents.extend(pat.idents());
+        }
       }
       Self::Map(map_pat) => {
         for pair in &map_pat.pairs {
@@ -475,11 +640,24 @@ impl Pat {
             MapPatPair::Ident(_, pat) => {
               idents.extend(pat.idents());
             }
-            _ => unimplemented!(),
+            MapPatPair::Spread(ident) => {
+              idents.push(ident.clone());
+            }
+          }
+        }
+      }
+      Self::Array(array_pat) => {
+        for item in &array_pat.items {
+          match item {
+            ArrayPatItem::Pat(pat) => {
+              idents.extend(pat.idents());
+            }
+            ArrayPatItem::Spread(ident) => {
+              idents.push(ident.clone());
+            }
           }
         }
       }
-      _ => unimplemented!(),
     }
 
     idents
@@ -500,7 +678,7 @@ impl Pat {
 pub struct TagPat {
   pub span: Span,
   pub tag: Ident,
-  pub pat: Box<Pat>,
+  pub fields: TagFields<Pat>,
 }
 
 impl TagPat {
@@ -585,7 +763,14 @@ pub struct IntLit {
 #[derive(Clone, Debug)]
 pub struct NumberLit {
   pub span: Span,
-  pub number: f64,
+  pub number: Number,
+}
+
+// Literals with a fraction or exponent are floats, and the rest are ints.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Number {
+  Int(i64),
+  Float(f64),
 }
 
 #[derive(Clone, Debug)]
@@ -643,73 +828,116 @@ pub fn count_expr_locals(expr: &Expr) -> usize {
   ctx.total_locals
 }
 
+// Locals are only freed when a case arm ends, as in the generator, so
+// everything else is visited in the enclosing scope. Lambdas have their own
+// frames and aren't visited.
 fn count_expr_locals_with_ctx(ctx: &mut Context, expr: &Expr) {
   match expr {
+    Expr::Lit(_) | Expr::Ident(_) | Expr::Lambda(_) => {}
+    Expr::Map(map_expr) => {
+      for pair in &map_expr.pairs {
+        match pair {
+          MapExprPair::Spread(value) | MapExprPair::Ident(_, value) => {
+            count_expr_locals_with_ctx(ctx, value)
+          }
+          MapExprPair::Expr(key, value) => {
+            count_expr_locals_with_ctx(ctx, key);
+            count_expr_locals_with_ctx(ctx, value);
+          }
+        }
+      }
+    }
+    Expr::Array(array_expr) => {
+      for item in &array_expr.items {
+        match item {
+          ArrayExprItem::Spread(expr) | ArrayExprItem::Expr(expr) => {
+            count_expr_locals_with_ctx(ctx, expr)
+          }
+        }
+      }
+    }
+    Expr::Tag(tag_expr) => {
+      for field in tag_expr.fields.values() {
+        count_expr_locals_with_ctx(ctx, field);
+      }
+    }
     Expr::Block(block_expr) => {
       for expr in &block_expr.exprs {
         count_expr_locals_with_ctx(ctx, expr);
       }
     }
-    Expr::Bind(bind_expr) => count_pat_locals(ctx, &bind_expr.bindee),
+    Expr::Binary(binary_expr) => {
+      count_expr_locals_with_ctx(ctx, &binary_expr.left);
+      count_expr_locals_with_ctx(ctx, &binary_expr.right);
+    }
+    Expr::Unary(unary_expr) => {
+      count_expr_locals_with_ctx(ctx, &unary_expr.operand)
+    }
+    Expr::Bind(bind_expr) => {
+      count_expr_locals_with_ctx(ctx, &bind_expr.value);
+      count_pat_locals(ctx, &bind_expr.bindee);
+    }
+    Expr::Assign(assign_expr) => {
+      if let AssignExprAssignee::Access(access_expr) = &assign_expr.assignee {
+        count_access_expr_locals(ctx, access_expr);
+      }
+      count_expr_locals_with_ctx(ctx, &assign_expr.value);
+    }
     Expr::Call(call_expr) => {
+      count_expr_locals_with_ctx(ctx, &call_expr.receiver);
       for argument in &call_expr.arguments {
         match argument {
-          CallExprArgument::Expr(expr) => count_expr_locals_with_ctx(ctx, expr),
-          _ => unimplemented!(),
+          CallExprArgument::Spread(expr) | CallExprArgument::Expr(expr) => {
+            count_expr_locals_with_ctx(ctx, expr)
+          }
         }
       }
     }
+    Expr::Access(access_expr) => count_access_expr_locals(ctx, access_expr),
     Expr::If(if_expr) => {
-      ctx.enter_scope();
+      count_expr_locals_with_ctx(ctx, &if_expr.condition);
       count_expr_locals_with_ctx(ctx, &if_expr.body);
-      ctx.exit_scope();
       if let Some(otherwise) = if_expr.otherwise.as_ref() {
-        ctx.enter_scope();
         count_expr_locals_with_ctx(ctx, otherwise);
-        ctx.exit_scope();
       }
     }
     Expr::Case(case_expr) => {
-      for (pat, expr) in &case_expr.arms {
+      count_expr_locals_with_ctx(ctx, &case_expr.subject);
+      for arm in &case_expr.arms {
         ctx.enter_scope();
-        count_pat_locals(ctx, pat);
-        count_expr_locals_with_ctx(ctx, expr);
+        count_pat_locals(ctx, &arm.pats[0]);
+        if let Some(guard) = &arm.guard {
+          count_expr_locals_with_ctx(ctx, guard);
+        }
+        count_expr_locals_with_ctx(ctx, &arm.expr);
         ctx.exit_scope();
       }
     }
+    Expr::For(for_expr) => {
+      count_expr_locals_with_ctx(ctx, &for_expr.iterator);
+      count_pat_locals(ctx, &for_expr.item);
+      count_expr_locals_with_ctx(ctx, &for_expr.body);
+    }
     Expr::While(while_expr) => {
-      ctx.enter_scope();
+      count_expr_locals_with_ctx(ctx, &while_expr.condition);
       count_expr_locals_with_ctx(ctx, &while_expr.body);
-      ctx.exit_scope();
-    }
-    Expr::Lit(_) => {}
-    Expr::Ident(_) => {}
-    Expr::Binary(_) => {}
-    Expr::Tag(_) => {}
-    Expr::Array(_) => {}
-    Expr::Access(_) => {}
-    Expr::Assign(_) => {}
-    Expr::Lambda(_) => {}
-    expr => {
-      dbg!(expr);
-      unimplemented!()
     }
   }
 }
 
-fn count_pat_locals(ctx: &mut Context, pat: &Pat) {
-  match pat {
-    Pat::Ident(_) => ctx.add(1),
-    Pat::Tag(tag_pat) => count_pat_locals(ctx, &tag_pat.pat),
-    Pat::Map(map_pat) => {
-      for pair in &map_pat.pairs {
-        match pair {
-          MapPatPair::Ident(_, pat) => count_pat_locals(ctx, &pat),
-          _ => unimplemented!(),
-        }
+fn count_access_expr_locals(ctx: &mut Context, access_expr: &AccessExpr) {
+  count_expr_locals_with_ctx(ctx, &access_expr.receiver);
+  match &access_expr.field {
+    AccessExprField::Ident(_) => {}
+    AccessExprField::Expr(expr) => count_expr_locals_with_ctx(ctx, expr),
+    AccessExprField::Slice(start, end) => {
+      for bound in start.iter().chain(end) {
+        count_expr_locals_with_ctx(ctx, bound);
       }
     }
-    Pat::Lit(_) => {}
-    _ => unimplemented!(),
   }
 }
+
+fn count_pat_locals(ctx: &mut Context, pat: &Pat) {
+  ctx.add(pat.idents().len());
+}
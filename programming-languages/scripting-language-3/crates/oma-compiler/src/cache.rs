@@ -0,0 +1,295 @@
+// A cache of compiled executables, so that files that haven't changed aren't
+// compiled again.
+//
+// Each source file has one entry in the cache directory, named after a hash of
+// its path. An entry is:
+//
+// - The magic number `OMC` and the version of the entry format.
+// - The hashes of the source, the layout of the native registry and the
+//   options it was compiled with, each 8 bytes little-endian.
+// - The build of the compiler that compiled it, as a 4 byte little-endian
+//   length followed by its version and a hash of its sources.
+// - The executable.
+//
+// An entry is only used if all of these match. Entries that can't be read are
+// treated as missing, and failing to write one only means the file is
+// compiled again next time.
+
+use std::{
+  convert::TryInto,
+  fmt, fs,
+  path::{Path, PathBuf},
+};
+
+use crate::Options;
+
+const MAGIC: [u8; 3] = [b'O', b'M', b'C'];
+const VERSION: u8 = 1;
+
+pub struct Cache {
+  dir: PathBuf,
+}
+
+// Whether a file's executable was found in the cache, or why it wasn't.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Lookup {
+  Hit,
+  // The file has no entry, or it couldn't be read.
+  Missing,
+  SourceChanged,
+  CompilerChanged,
+  RegistryChanged,
+  OptionsChanged,
+}
+
+impl fmt::Display for Lookup {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Self::Hit => write!(f, "up to date"),
+      Self::Missing => write!(f, "not cached"),
+      Self::SourceChanged => write!(f, "source changed"),
+      Self::CompilerChanged => write!(f, "compiler changed"),
+      Self::RegistryChanged => write!(f, "native registry changed"),
+      Self::OptionsChanged => write!(f, "options changed"),
+    }
+  }
+}
+
+// What an executable was compiled from.
+pub(crate) struct Key {
+  source: u64,
+  registry: u64,
+  options: u64,
+  compiler: String,
+}
+
+impl Key {
+  pub(crate) fn new(
+    source: &str,
+    registry: &[(&'static str, usize)],
+    options: &Options,
+  ) -> Key {
+    let mut registry_hash = Fnv::new();
+    for (name, id) in registry {
+      registry_hash.write(name.as_bytes());
+      registry_hash.write(&(*id as u64).to_le_bytes());
+    }
+
+    let mut options_hash = Fnv::new();
+    options_hash.write(&[options.strict as u8]);
+    for (name, capability) in &options.denied {
+      options_hash.write(name.as_bytes());
+      options_hash.write(capability.as_bytes());
+    }
+
+    Key {
+      source: Fnv::hash(source.as_bytes()),
+      registry: registry_hash.finish(),
+      options: options_hash.finish(),
+      compiler: compiler(),
+    }
+  }
+}
+
+// Names the build of the compiler. The version alone isn't enough, as the
+// code it generates can change without it being bumped.
+fn compiler() -> String {
+  format!(
+    "{}+{}",
+    env!("CARGO_PKG_VERSION"),
+    env!("OMA_COMPILER_HASH")
+  )
+}
+
+impl Cache {
+  // Caches executables in the directory, which is created when the first one
+  // is written.
+  pub fn new(dir: impl Into<PathBuf>) -> Cache {
+    Cache { dir: dir.into() }
+  }
+
+  // The executable cached for the file, if it was compiled from the same key.
+  pub(crate) fn get(
+    &self,
+    path: &Path,
+    key: &Key,
+  ) -> (Option<Vec<u8>>, Lookup) {
+    let entry = match fs::read(self.entry(path)) {
+      Ok(entry) => entry,
+      Err(_) => return (None, Lookup::Missing),
+    };
+    let (cached, executable) = match read_entry(&entry) {
+      Some(read) => read,
+      None => return (None, Lookup::Missing),
+    };
+
+    let lookup = if cached.compiler != key.compiler {
+      Lookup::CompilerChanged
+    } else if cached.source != key.source {
+      Lookup::SourceChanged
+    } else if cached.registry != key.registry {
+      Lookup::RegistryChanged
+    } else if cached.options != key.options {
+      Lookup::OptionsChanged
+    } else {
+      return (Some(executable.to_vec()), Lookup::Hit);
+    };
+    (None, lookup)
+  }
+
+  pub(crate) fn put(&self, path: &Path, key: &Key, executable: &[u8]) {
+    let mut entry = MAGIC.to_vec();
+    entry.push(VERSION);
+    for hash in [key.source, key.registry, key.options] {
+      entry.extend(hash.to_le_bytes());
+    }
+    entry.extend((key.compiler.len() as u32).to_le_bytes());
+    entry.extend(key.compiler.as_bytes());
+    entry.extend(executable);
+
+    // Written to the side and moved into place, so a run reading the cache
+    // at the same time never sees half an entry.
+    let entry_path = self.entry(path);
+    let temporary = entry_path.with_extension("tmp");
+    let written = fs::create_dir_all(&self.dir)
+      .and_then(|_| fs::write(&temporary, entry))
+      .and_then(|_| fs::rename(&temporary, &entry_path));
+    if written.is_err() {
+      let _ = fs::remove_file(&temporary);
+    }
+  }
+
+  fn entry(&self, path: &Path) -> PathBuf {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let name = Fnv::hash(path.to_string_lossy().as_bytes());
+    self.dir.join(format!("{:016x}.omc", name))
+  }
+}
+
+fn read_entry(entry: &[u8]) -> Option<(Key, &[u8])> {
+  let rest = entry.strip_prefix(&MAGIC[..])?;
+  let (version, rest) = rest.split_first()?;
+  if *version != VERSION {
+    return None;
+  }
+
+  let mut hashes = [0; 3];
+  let mut rest = rest;
+  for hash in &mut hashes {
+    let (bytes, tail) = split(rest, 8)?;
+    *hash = u64::from_le_bytes(bytes.try_into().ok()?);
+    rest = tail;
+  }
+
+  let (len, rest) = split(rest, 4)?;
+  let len = u32::from_le_bytes(len.try_into().ok()?) as usize;
+  let (compiler, executable) = split(rest, len)?;
+  let compiler = String::from_utf8(compiler.to_vec()).ok()?;
+
+  let [source, registry, options] = hashes;
+  let key = Key {
+    source,
+    registry,
+    options,
+    compiler,
+  };
+  Some((key, executable))
+}
+
+fn split(bytes: &[u8], at: usize) -> Option<(&[u8], &[u8])> {
+  if bytes.len() < at {
+    None
+  } else {
+    Some(bytes.split_at(at))
+  }
+}
+
+// 64-bit FNV-1a, which unlike the standard library's hasher gives the same
+// hashes from one build to the next.
+struct Fnv(u64);
+
+impl Fnv {
+  fn new() -> Fnv {
+    Fnv(0xcbf2_9ce4_8422_2325)
+  }
+
+  fn hash(bytes: &[u8]) -> u64 {
+    let mut hasher = Fnv::new();
+    hasher.write(bytes);
+    hasher.finish()
+  }
+
+  // The length is hashed too, so that parts can't run into each other.
+  fn write(&mut self, bytes: &[u8]) {
+    for byte in (bytes.len() as u64).to_le_bytes().iter().chain(bytes) {
+      self.0 ^= *byte as u64;
+      self.0 = self.0.wrapping_mul(0x100_0000_01b3);
+    }
+  }
+
+  fn finish(&self) -> u64 {
+    self.0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::process;
+
+  use super::*;
+
+  fn cache(name: &str) -> (Cache, PathBuf) {
+    let dir = std::env::temp_dir().join(format!(
+      "oma-cache-test-{}-{}",
+      process::id(),
+      name
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("main.oma");
+    fs::write(&source, "1").unwrap();
+    (Cache::new(dir.join("cache")), source)
+  }
+
+  fn key() -> Key {
+    Key::new("1", &[("__console_info", 0)], &Options::default())
+  }
+
+  #[test]
+  fn same_key_hits() {
+    let (cache, source) = cache("hit");
+    cache.put(&source, &key(), &[1, 2, 3]);
+    assert_eq!(
+      cache.get(&source, &key()),
+      (Some(vec![1, 2, 3]), Lookup::Hit)
+    );
+  }
+
+  #[test]
+  fn other_compiler_misses() {
+    let (cache, source) = cache("compiler");
+    let older = Key {
+      compiler: format!("{}+0000000000000000", env!("CARGO_PKG_VERSION")),
+      ..key()
+    };
+    cache.put(&source, &older, &[1, 2, 3]);
+    assert_eq!(cache.get(&source, &key()), (None, Lookup::CompilerChanged));
+  }
+
+  #[test]
+  fn other_version_misses() {
+    let (cache, source) = cache("version");
+    let older = Key {
+      compiler: format!("0.0.0+{}", env!("OMA_COMPILER_HASH")),
+      ..key()
+    };
+    cache.put(&source, &older, &[1, 2, 3]);
+    assert_eq!(cache.get(&source, &key()), (None, Lookup::CompilerChanged));
+  }
+
+  #[test]
+  fn compiler_names_sources() {
+    assert!(key().compiler.ends_with(env!("OMA_COMPILER_HASH")));
+    assert_eq!(env!("OMA_COMPILER_HASH").len(), 16);
+  }
+}
@@ -0,0 +1,1119 @@
+// Type checking of annotated code, run between parsing and generating.
+//
+// The types of unannotated bindings are inferred from their values, and
+// anything that can't be worked out, such as the result of a native or a
+// method, is `any`, which every type is compatible with. Only mismatches that
+// are certain given the annotations and inferred types are reported, so code
+// without annotations still compiles.
+//
+// Arrays and maps can be changed through any binding to them, so a binding
+// without an annotation only keeps that its value is an array or a map, not
+// what's in it. Bindings without an annotation that are assigned to anywhere
+// in the module are `any`.
+
+use std::{
+  collections::{HashMap, HashSet},
+  fmt,
+  rc::Rc,
+};
+
+use crate::{
+  ast::*,
+  error::{CompileError, VerifyError},
+  source::Span,
+};
+
+pub fn check(module: &Module) -> Result<(), CompileError> {
+  let mut assigned = HashSet::new();
+  collect_assigned(&module.body, &mut assigned);
+
+  let mut checker = Checker {
+    assigned,
+    scopes: vec![HashMap::new()],
+    errors: Vec::new(),
+  };
+  checker.expr(&module.body);
+
+  let errors = checker.errors.into_iter().map(CompileError::Verify);
+  match CompileError::from_errors(errors.collect()) {
+    Some(error) => Err(error),
+    None => Ok(()),
+  }
+}
+
+#[derive(Clone, Debug)]
+enum Type {
+  Any,
+  Null,
+  Bool,
+  Int,
+  Float,
+  String,
+  Array(Box<Type>),
+  // A map with at least the given fields. Fields it doesn't list can have any
+  // value.
+  Map(Vec<(&'static str, Type)>),
+  Tag(TagType),
+  // A lambda, with its signature if it's known.
+  Lambda(Option<Rc<Signature>>),
+  // Alternatives, of which there are at least two and none is `any`.
+  Union(Vec<Type>),
+}
+
+#[derive(Clone, Debug)]
+struct TagType {
+  tag: &'static str,
+  // The names of the fields, if they are named.
+  names: Vec<&'static str>,
+  fields: Vec<Type>,
+}
+
+#[derive(Debug)]
+struct Signature {
+  // The types of the parameters, unless the lambda takes a spread of them.
+  parameters: Option<Vec<Type>>,
+  result: Type,
+}
+
+impl Type {
+  // Whether a value of this type can be used where `expected` is.
+  fn fits(&self, expected: &Type) -> bool {
+    match (self, expected) {
+      (Self::Any, _) | (_, Self::Any) => true,
+      (Self::Union(types), _) => types.iter().all(|ty| ty.fits(expected)),
+      (_, Self::Union(types)) => types.iter().any(|ty| self.fits(ty)),
+      (Self::Array(item), Self::Array(expected)) => item.fits(expected),
+      (Self::Map(fields), Self::Map(expected)) => {
+        expected.iter().all(|(name, expected)| {
+          match fields.iter().find(|(other, _)| other == name) {
+            Some((_, ty)) => ty.fits(expected),
+            None => true,
+          }
+        })
+      }
+      (Self::Tag(tag_type), Self::Tag(expected)) => {
+        tag_type.tag == expected.tag
+          && tag_type.fields.len() == expected.fields.len()
+          && tag_type
+            .fields
+            .iter()
+            .zip(&expected.fields)
+            .all(|(ty, other)| ty.fits(other))
+      }
+      (Self::Lambda(signature), Self::Lambda(expected)) => {
+        match (signature, expected) {
+          (Some(signature), Some(expected)) => {
+            match (&signature.parameters, &expected.parameters) {
+              (Some(parameters), Some(expected)) => {
+                parameters.len() == expected.len()
+              }
+              _ => true,
+            }
+          }
+          _ => true,
+        }
+      }
+      (Self::Null, Self::Null)
+      | (Self::Bool, Self::Bool)
+      | (Self::Int, Self::Int)
+      | (Self::Float, Self::Float)
+      | (Self::String, Self::String) => true,
+      _ => false,
+    }
+  }
+
+  fn union(types: Vec<Type>) -> Type {
+    let mut union: Vec<Type> = Vec::new();
+    for ty in types {
+      let alternatives = match ty {
+        Self::Any => return Self::Any,
+        Self::Union(types) => types,
+        ty => vec![ty],
+      };
+      for ty in alternatives {
+        if !union.iter().any(|other| ty.fits(other) && other.fits(&ty)) {
+          union.push(ty);
+        }
+      }
+    }
+
+    match union.len() {
+      0 => Self::Null,
+      1 => union.pop().unwrap(),
+      _ => Self::Union(union),
+    }
+  }
+
+  // The alternatives of a union, or the type itself.
+  fn alternatives(&self) -> &[Type] {
+    match self {
+      Self::Union(types) => types,
+      ty => std::slice::from_ref(ty),
+    }
+  }
+
+  // Forgets what's in arrays and maps, which can be changed after the value
+  // is bound.
+  fn widen(self) -> Type {
+    match self {
+      Self::Array(_) => Self::Array(Box::new(Self::Any)),
+      Self::Map(_) => Self::Map(Vec::new()),
+      Self::Union(types) => {
+        Self::union(types.into_iter().map(Type::widen).collect())
+      }
+      ty => ty,
+    }
+  }
+
+  fn is_number(&self) -> bool {
+    matches!(self, Self::Int | Self::Float)
+  }
+
+  // Whether operators on the value can call methods from its meta map.
+  fn has_methods(&self) -> bool {
+    matches!(
+      self,
+      Self::Array(_) | Self::Map(_) | Self::Tag(_) | Self::Lambda(_)
+    )
+  }
+}
+
+impl fmt::Display for Type {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Self::Any => write!(f, "any"),
+      Self::Null => write!(f, "null"),
+      Self::Bool => write!(f, "bool"),
+      Self::Int => write!(f, "int"),
+      Self::Float => write!(f, "float"),
+      Self::String => write!(f, "string"),
+      Self::Array(item) => write!(f, "[{}]", item),
+      Self::Map(fields) if fields.is_empty() => write!(f, "{{}}"),
+      Self::Map(fields) => {
+        write!(f, "{{ ")?;
+        for (index, (name, ty)) in fields.iter().enumerate() {
+          if index > 0 {
+            write!(f, ", ")?;
+          }
+          write!(f, "{}: {}", name, ty)?;
+        }
+        write!(f, " }}")
+      }
+      Self::Tag(tag_type) => {
+        write!(f, "{}(", tag_type.tag)?;
+        for (index, ty) in tag_type.fields.iter().enumerate() {
+          if index > 0 {
+            write!(f, ", ")?;
+          }
+          match tag_type.names.get(index) {
+            Some(name) => write!(f, "{}: {}", name, ty)?,
+            None => write!(f, "{}", ty)?,
+          }
+        }
+        write!(f, ")")
+      }
+      Self::Lambda(_) => write!(f, "lambda"),
+      Self::Union(types) => {
+        for (index, ty) in types.iter().enumerate() {
+          if index > 0 {
+            write!(f, " | ")?;
+          }
+          write!(f, "{}", ty)?;
+        }
+        Ok(())
+      }
+    }
+  }
+}
+
+struct Binding {
+  ty: Type,
+  annotated: bool,
+}
+
+struct Checker {
+  // Names that are assigned to somewhere in the module.
+  assigned: HashSet<&'static str>,
+  scopes: Vec<HashMap<&'static str, Binding>>,
+  errors: Vec<VerifyError>,
+}
+
+impl Checker {
+  fn expr(&mut self, expr: &Expr) -> Type {
+    match expr {
+      Expr::Lit(lit) => match lit {
+        Lit::Number(number_lit) => match number_lit.number {
+          Number::Int(_) => Type::Int,
+          Number::Float(_) => Type::Float,
+        },
+        Lit::Bool(_) => Type::Bool,
+        Lit::String(_) => Type::String,
+        Lit::Null(_) => Type::Null,
+      },
+      Expr::Ident(ident) => self
+        .lookup(ident.content)
+        .map_or(Type::Any, |binding| binding.ty.clone()),
+      Expr::Map(map_expr) => {
+        let mut fields = Vec::new();
+        let mut known = true;
+        for pair in &map_expr.pairs {
+          match pair {
+            MapExprPair::Ident(name, value) => {
+              let ty = self.expr(value);
+              fields.retain(|(other, _)| other != &name.content.as_str());
+              fields.push((name.content.as_str(), ty));
+            }
+            MapExprPair::Expr(key, value) => {
+              self.expr(key);
+              self.expr(value);
+              known = false;
+            }
+            MapExprPair::Spread(value) => {
+              self.expr(value);
+              known = false;
+            }
+          }
+        }
+        // Computed keys and spreads can replace any of the fields.
+        if known {
+          Type::Map(fields)
+        } else {
+          Type::Map(Vec::new())
+        }
+      }
+      Expr::Array(array_expr) => {
+        let mut items = Vec::new();
+        let mut known = true;
+        for item in &array_expr.items {
+          match item {
+            ArrayExprItem::Expr(expr) => items.push(self.expr(expr)),
+            ArrayExprItem::Spread(expr) => {
+              match self.expr(expr) {
+                Type::Array(item) => items.push(*item),
+                _ => known = false,
+              };
+            }
+          }
+        }
+        if known && !items.is_empty() {
+          Type::Array(Box::new(Type::union(items)))
+        } else {
+          Type::Array(Box::new(Type::Any))
+        }
+      }
+      Expr::Lambda(lambda_expr) => self.lambda_expr(lambda_expr),
+      Expr::Tag(tag_expr) => {
+        let (names, fields): (_, Vec<_>) = match &tag_expr.fields {
+          TagFields::Positional(fields) => {
+            (Vec::new(), fields.iter().collect())
+          }
+          TagFields::Named(fields) => fields
+            .iter()
+            .map(|(name, field)| (name.content.as_str(), field))
+            .unzip(),
+        };
+        Type::Tag(TagType {
+          tag: tag_expr.tag.content,
+          names,
+          fields: fields.into_iter().map(|field| self.expr(field)).collect(),
+        })
+      }
+      Expr::Block(block_expr) => {
+        self.scopes.push(HashMap::new());
+        let mut ty = Type::Null;
+        for expr in &block_expr.exprs {
+          ty = self.expr(expr);
+        }
+        self.scopes.pop();
+        if block_expr.has_semi {
+          Type::Null
+        } else {
+          ty
+        }
+      }
+      Expr::Binary(binary_expr) => self.binary_expr(binary_expr),
+      Expr::Unary(unary_expr) => {
+        let operand = self.expr(&unary_expr.operand);
+        let (op, result) = match unary_expr.op {
+          UnaryOp::Negate => ("-", unary(&operand, Type::is_number)),
+          UnaryOp::Not => ("!", unary(&operand, |ty| matches!(ty, Type::Bool))),
+        };
+        result.unwrap_or_else(|| {
+          self.errors.push(VerifyError::invalid_operands(
+            unary_expr.span(),
+            op,
+            operand.to_string(),
+          ));
+          Type::Any
+        })
+      }
+      Expr::Bind(bind_expr) => {
+        self.bind_expr(bind_expr);
+        Type::Null
+      }
+      Expr::Assign(assign_expr) => {
+        let ty = self.expr(&assign_expr.value);
+        match &assign_expr.assignee {
+          AssignExprAssignee::Pat(Pat::Ident(ident)) => {
+            let expected = self
+              .lookup(ident.content)
+              .filter(|binding| binding.annotated)
+              .map(|binding| binding.ty.clone());
+            if let Some(expected) = expected {
+              self.expect(&assign_expr.value.span(), &ty, &expected);
+            }
+          }
+          AssignExprAssignee::Pat(_) => {}
+          AssignExprAssignee::Access(access_expr) => {
+            self.access_expr(access_expr);
+          }
+        }
+        ty
+      }
+      Expr::Call(call_expr) => self.call_expr(call_expr),
+      Expr::Access(access_expr) => self.access_expr(access_expr),
+      Expr::If(if_expr) => {
+        self.expr(&if_expr.condition);
+        let body = self.expr(&if_expr.body);
+        let otherwise = match &if_expr.otherwise {
+          Some(otherwise) => self.expr(otherwise),
+          None => Type::Null,
+        };
+        Type::union(vec![body, otherwise])
+      }
+      Expr::Case(case_expr) => {
+        let subject = self.expr(&case_expr.subject);
+        let mut arms = Vec::new();
+        for arm in &case_expr.arms {
+          self.scopes.push(HashMap::new());
+          // Every alternative binds the same names, so the first one is used.
+          self.bind_pat(&arm.pats[0], &subject);
+          if let Some(guard) = &arm.guard {
+            self.expr(guard);
+          }
+          arms.push(self.expr(&arm.expr));
+          self.scopes.pop();
+        }
+        Type::union(arms)
+      }
+      Expr::For(for_expr) => {
+        self.expr(&for_expr.iterator);
+        self.scopes.push(HashMap::new());
+        self.bind_pat(&for_expr.item, &Type::Any);
+        self.expr(&for_expr.body);
+        self.scopes.pop();
+        Type::Null
+      }
+      Expr::While(while_expr) => {
+        self.expr(&while_expr.condition);
+        self.expr(&while_expr.body);
+        Type::Null
+      }
+    }
+  }
+
+  fn lambda_expr(&mut self, lambda_expr: &LambdaExpr) -> Type {
+    self.scopes.push(HashMap::new());
+
+    let mut parameters = Some(Vec::new());
+    for parameter in &lambda_expr.parameters {
+      match parameter {
+        LambdaExprParameter::Pat(pat, ty) => {
+          let ty = ty.as_ref().map(|ty| self.type_expr(ty));
+          match (pat, &ty) {
+            (Pat::Ident(ident), Some(ty)) => {
+              self.define(ident.content, ty.clone(), true)
+            }
+            (pat, ty) => self.bind_pat(pat, ty.as_ref().unwrap_or(&Type::Any)),
+          }
+          if let Some(parameters) = &mut parameters {
+            parameters.push(ty.unwrap_or(Type::Any));
+          }
+        }
+        LambdaExprParameter::Spread(ident) => {
+          self.define(ident.content, Type::Array(Box::new(Type::Any)), false);
+          parameters = None;
+        }
+      }
+    }
+
+    let return_ty = lambda_expr.return_ty.as_ref().map(|ty| self.type_expr(ty));
+    let body = self.expr(&lambda_expr.body);
+    if let Some(return_ty) = &return_ty {
+      self.expect(&result_span(&lambda_expr.body), &body, return_ty);
+    }
+
+    self.scopes.pop();
+
+    Type::Lambda(Some(Rc::new(Signature {
+      parameters,
+      result: return_ty.unwrap_or(body),
+    })))
+  }
+
+  fn bind_expr(&mut self, bind_expr: &BindExpr) {
+    let expected = bind_expr.ty.as_ref().map(|ty| self.type_expr(ty));
+
+    // A lambda can refer to itself.
+    if let (Pat::Ident(ident), Expr::Lambda(_)) =
+      (&bind_expr.bindee, &*bind_expr.value)
+    {
+      let ty = expected.clone().unwrap_or(Type::Any);
+      self.define(ident.content, ty, expected.is_some());
+    }
+
+    let ty = self.expr(&bind_expr.value);
+    match expected {
+      Some(expected) => {
+        self.expect(&bind_expr.value.span(), &ty, &expected);
+        match &bind_expr.bindee {
+          Pat::Ident(ident) => self.define(ident.content, expected, true),
+          pat => self.bind_pat(pat, &expected),
+        }
+      }
+      None => self.bind_pat(&bind_expr.bindee, &ty.widen()),
+    }
+  }
+
+  fn binary_expr(&mut self, binary_expr: &BinaryExpr) -> Type {
+    let left = self.expr(&binary_expr.left);
+    let right = self.expr(&binary_expr.right);
+
+    // Only some operators call methods from the left operand's meta map.
+    let (op, methods, result): (_, _, fn(&Type, &Type) -> Option<Type>) =
+      match binary_expr.op {
+        BinaryOp::Add => ("+", true, add),
+        BinaryOp::Subtract => ("-", true, arithmetic),
+        BinaryOp::Multiply => ("*", true, arithmetic),
+        BinaryOp::Divide => ("/", true, arithmetic),
+        BinaryOp::Remainder => ("%", true, arithmetic),
+        BinaryOp::Power => ("**", false, power),
+        BinaryOp::BitAnd => ("&", false, bitwise),
+        BinaryOp::BitOr => ("|", false, bitwise),
+        BinaryOp::BitXor => ("^", false, bitwise),
+        BinaryOp::ShiftLeft => ("<<", false, bitwise),
+        BinaryOp::ShiftRight => (">>", false, bitwise),
+        BinaryOp::Greater => (">", false, comparison),
+        BinaryOp::GreaterEqual => (">=", false, comparison),
+        BinaryOp::Less => ("<", false, comparison),
+        BinaryOp::LessEqual => ("<=", false, comparison),
+        BinaryOp::Equal | BinaryOp::NotEqual => {
+          return match binary(&left, &right, true, |_, _| Some(Type::Bool)) {
+            Some(ty) => ty,
+            None => Type::Bool,
+          };
+        }
+        BinaryOp::And | BinaryOp::Or => return Type::Any,
+      };
+    let ty = binary(&left, &right, methods, result);
+
+    ty.unwrap_or_else(|| {
+      self.errors.push(VerifyError::invalid_operands(
+        binary_expr.span(),
+        op,
+        format!("{} and {}", left, right),
+      ));
+      Type::Any
+    })
+  }
+
+  fn call_expr(&mut self, call_expr: &CallExpr) -> Type {
+    let receiver = self.expr(&call_expr.receiver);
+    let mut arguments = Some(Vec::new());
+    for argument in &call_expr.arguments {
+      match argument {
+        CallExprArgument::Expr(expr) => {
+          let ty = self.expr(expr);
+          if let Some(arguments) = &mut arguments {
+            arguments.push((expr.span(), ty));
+          }
+        }
+        CallExprArgument::Spread(expr) => {
+          self.expr(expr);
+          arguments = None;
+        }
+      }
+    }
+
+    let signature = match receiver {
+      Type::Lambda(Some(signature)) => signature,
+      _ => return Type::Any,
+    };
+    if let (Some(parameters), Some(arguments)) =
+      (&signature.parameters, &arguments)
+    {
+      if parameters.len() != arguments.len() {
+        self.errors.push(VerifyError::wrong_argument_count(
+          call_expr.span(),
+          parameters.len(),
+          arguments.len(),
+        ));
+      } else {
+        for (parameter, (span, argument)) in parameters.iter().zip(arguments) {
+          self.expect(span, argument, parameter);
+        }
+      }
+    }
+    signature.result.clone()
+  }
+
+  fn access_expr(&mut self, access_expr: &AccessExpr) -> Type {
+    let receiver = self.expr(&access_expr.receiver);
+    match &access_expr.field {
+      AccessExprField::Ident(field) => match receiver {
+        Type::Map(fields) => fields
+          .into_iter()
+          .find(|(name, _)| name == field.content)
+          .map_or(Type::Any, |(_, ty)| ty),
+        Type::Tag(tag_type) => tag_type
+          .names
+          .iter()
+          .position(|name| name == field.content)
+          .map_or(Type::Any, |index| tag_type.fields[index].clone()),
+        _ => Type::Any,
+      },
+      AccessExprField::Expr(key) => {
+        let key = self.expr(key);
+        match (receiver, key) {
+          (Type::Array(item), Type::Int) => *item,
+          _ => Type::Any,
+        }
+      }
+      AccessExprField::Slice(start, end) => {
+        for bound in start.iter().chain(end) {
+          self.expr(bound);
+        }
+        match receiver {
+          Type::Array(item) => Type::Array(item),
+          _ => Type::Any,
+        }
+      }
+    }
+  }
+
+  // Binds the names in the pattern to the parts of a value of the given type.
+  fn bind_pat(&mut self, pat: &Pat, ty: &Type) {
+    match pat {
+      Pat::Lit(_) => {}
+      Pat::Ident(ident) => self.define(ident.content, ty.clone(), false),
+      Pat::Tag(tag_pat) => {
+        // The alternatives that the pattern can match. Named fields are
+        // matched by name, so the pattern can leave some out.
+        let tag_types = ty
+          .alternatives()
+          .iter()
+          .filter_map(|ty| match ty {
+            Type::Tag(tag_type) if tag_type.tag == tag_pat.tag.content => {
+              Some(tag_type)
+            }
+            _ => None,
+          })
+          .filter(|tag_type| match &tag_pat.fields {
+            TagFields::Positional(fields) => {
+              tag_type.fields.len() == fields.len()
+            }
+            TagFields::Named(_) => !tag_type.names.is_empty(),
+          })
+          .collect::<Vec<_>>();
+
+        let field_types = |field: &dyn Fn(&TagType) -> Option<Type>| {
+          let types = tag_types
+            .iter()
+            .filter_map(|tag_type| field(tag_type))
+            .collect::<Vec<_>>();
+          if types.is_empty() {
+            Type::Any
+          } else {
+            Type::union(types)
+          }
+        };
+        match &tag_pat.fields {
+          TagFields::Positional(fields) => {
+            for (index, field) in fields.iter().enumerate() {
+              let ty =
+                field_types(&|tag_type| tag_type.fields.get(index).cloned());
+              self.bind_pat(field, &ty);
+            }
+          }
+          TagFields::Named(fields) => {
+            for (name, field) in fields {
+              let ty = field_types(&|tag_type| {
+                let index = tag_type
+                  .names
+                  .iter()
+                  .position(|other| *other == name.content)?;
+                tag_type.fields.get(index).cloned()
+              });
+              self.bind_pat(field, &ty);
+            }
+          }
+        }
+      }
+      Pat::Map(map_pat) => {
+        for pair in &map_pat.pairs {
+          match pair {
+            MapPatPair::Ident(name, pat) => {
+              let ty = match ty {
+                Type::Map(fields) => fields
+                  .iter()
+                  .find(|(other, _)| other == name.content)
+                  .map_or(Type::Any, |(_, ty)| ty.clone()),
+                _ => Type::Any,
+              };
+              self.bind_pat(pat, &ty);
+            }
+            MapPatPair::Spread(ident) => {
+              self.define(ident.content, Type::Map(Vec::new()), false)
+            }
+          }
+        }
+      }
+      Pat::Array(array_pat) => {
+        let item = match ty {
+          Type::Array(item) => (**item).clone(),
+          _ => Type::Any,
+        };
+        for array_item in &array_pat.items {
+          match array_item {
+            ArrayPatItem::Pat(pat) => self.bind_pat(pat, &item),
+            ArrayPatItem::Spread(ident) => self.define(
+              ident.content,
+              Type::Array(Box::new(item.clone())),
+              false,
+            ),
+          }
+        }
+      }
+    }
+  }
+
+  fn type_expr(&mut self, type_expr: &TypeExpr) -> Type {
+    match type_expr {
+      TypeExpr::Name(ident) => match ident.content.as_str() {
+        "any" => Type::Any,
+        "null" => Type::Null,
+        "bool" => Type::Bool,
+        "int" => Type::Int,
+        "float" => Type::Float,
+        "string" => Type::String,
+        "lambda" => Type::Lambda(None),
+        _ => {
+          self.errors.push(VerifyError::unknown_type(ident.span()));
+          Type::Any
+        }
+      },
+      TypeExpr::Array(array_type) => {
+        Type::Array(Box::new(self.type_expr(&array_type.item)))
+      }
+      TypeExpr::Map(map_type) => Type::Map(
+        map_type
+          .fields
+          .iter()
+          .map(|(name, ty)| (name.content.as_str(), self.type_expr(ty)))
+          .collect(),
+      ),
+      TypeExpr::Tag(tag_type) => {
+        let (names, fields): (_, Vec<_>) = match &tag_type.fields {
+          TagFields::Positional(fields) => {
+            (Vec::new(), fields.iter().collect())
+          }
+          TagFields::Named(fields) => fields
+            .iter()
+            .map(|(name, field)| (name.content.as_str(), field))
+            .unzip(),
+        };
+        Type::Tag(TagType {
+          tag: tag_type.tag.content,
+          names,
+          fields: fields.into_iter().map(|ty| self.type_expr(ty)).collect(),
+        })
+      }
+      TypeExpr::Union(types) => {
+        Type::union(types.iter().map(|ty| self.type_expr(ty)).collect())
+      }
+    }
+  }
+
+  fn expect(&mut self, span: &Span, ty: &Type, expected: &Type) {
+    if !ty.fits(expected) {
+      self.errors.push(VerifyError::mismatched_types(
+        span.clone(),
+        expected.to_string(),
+        ty.to_string(),
+      ));
+    }
+  }
+
+  fn define(&mut self, name: &'static str, ty: Type, annotated: bool) {
+    let ty = if annotated || !self.assigned.contains(name) {
+      ty
+    } else {
+      Type::Any
+    };
+    self
+      .scopes
+      .last_mut()
+      .unwrap()
+      .insert(name, Binding { ty, annotated });
+  }
+
+  fn lookup(&self, name: &str) -> Option<&Binding> {
+    self.scopes.iter().rev().find_map(|scope| scope.get(name))
+  }
+}
+
+// The type of a unary operator's result, or `None` if no alternative of the
+// operand supports it.
+fn unary(operand: &Type, supports: fn(&Type) -> bool) -> Option<Type> {
+  let mut results = Vec::new();
+  for ty in operand.alternatives() {
+    if let Type::Any = ty {
+      return Some(Type::Any);
+    }
+    if supports(ty) {
+      results.push(ty.clone());
+    }
+  }
+  if results.is_empty() {
+    None
+  } else {
+    Some(Type::union(results))
+  }
+}
+
+// The type of a binary operator's result over every pair of alternatives of
+// its operands, or `None` if no pair supports it.
+fn binary(
+  left: &Type,
+  right: &Type,
+  methods: bool,
+  result: fn(&Type, &Type) -> Option<Type>,
+) -> Option<Type> {
+  let mut results = Vec::new();
+  for left in left.alternatives() {
+    for right in right.alternatives() {
+      match (left, right) {
+        (Type::Any, _) | (_, Type::Any) => return Some(Type::Any),
+        (left, _) if methods && left.has_methods() => return Some(Type::Any),
+        (left, right) => results.extend(result(left, right)),
+      }
+    }
+  }
+  if results.is_empty() {
+    None
+  } else {
+    Some(Type::union(results))
+  }
+}
+
+fn add(left: &Type, right: &Type) -> Option<Type> {
+  match (left, right) {
+    (Type::String, Type::String) => Some(Type::String),
+    _ => arithmetic(left, right),
+  }
+}
+
+fn arithmetic(left: &Type, right: &Type) -> Option<Type> {
+  match (left, right) {
+    (Type::Int, Type::Int) => Some(Type::Int),
+    (left, right) if left.is_number() && right.is_number() => Some(Type::Float),
+    _ => None,
+  }
+}
+
+// Integers raised to a negative power are floats, so whether an integer result
+// is an int isn't known.
+fn power(left: &Type, right: &Type) -> Option<Type> {
+  match (left, right) {
+    (Type::Int, Type::Int) => Some(Type::Any),
+    (left, right) => arithmetic(left, right),
+  }
+}
+
+fn bitwise(left: &Type, right: &Type) -> Option<Type> {
+  match (left, right) {
+    (Type::Int, Type::Int) => Some(Type::Int),
+    _ => None,
+  }
+}
+
+fn comparison(left: &Type, right: &Type) -> Option<Type> {
+  if left.is_number() && right.is_number() {
+    Some(Type::Bool)
+  } else {
+    None
+  }
+}
+
+// The span of the expression a lambda's body evaluates to.
+fn result_span(body: &Expr) -> Span {
+  match body {
+    Expr::Block(block_expr) if !block_expr.has_semi => block_expr
+      .exprs
+      .last()
+      .map_or(block_expr.span(), Expr::span),
+    body => body.span(),
+  }
+}
+
+// Collects the names that are assigned to, or whose values have fields or
+// items assigned to.
+fn collect_assigned(expr: &Expr, assigned: &mut HashSet<&'static str>) {
+  match expr {
+    Expr::Lit(_) | Expr::Ident(_) => {}
+    Expr::Map(map_expr) => {
+      for pair in &map_expr.pairs {
+        match pair {
+          MapExprPair::Spread(value) | MapExprPair::Ident(_, value) => {
+            collect_assigned(value, assigned)
+          }
+          MapExprPair::Expr(key, value) => {
+            collect_assigned(key, assigned);
+            collect_assigned(value, assigned);
+          }
+        }
+      }
+    }
+    Expr::Array(array_expr) => {
+      for item in &array_expr.items {
+        match item {
+          ArrayExprItem::Spread(expr) | ArrayExprItem::Expr(expr) => {
+            collect_assigned(expr, assigned)
+          }
+        }
+      }
+    }
+    Expr::Lambda(lambda_expr) => collect_assigned(&lambda_expr.body, assigned),
+    Expr::Tag(tag_expr) => {
+      for field in tag_expr.fields.values() {
+        collect_assigned(field, assigned);
+      }
+    }
+    Expr::Block(block_expr) => {
+      for expr in &block_expr.exprs {
+        collect_assigned(expr, assigned);
+      }
+    }
+    Expr::Binary(binary_expr) => {
+      collect_assigned(&binary_expr.left, assigned);
+      collect_assigned(&binary_expr.right, assigned);
+    }
+    Expr::Unary(unary_expr) => collect_assigned(&unary_expr.operand, assigned),
+    Expr::Bind(bind_expr) => collect_assigned(&bind_expr.value, assigned),
+    Expr::Assign(assign_expr) => {
+      match &assign_expr.assignee {
+        AssignExprAssignee::Pat(pat) => assigned
+          .extend(pat.idents().iter().map(|ident| ident.content.as_str())),
+        AssignExprAssignee::Access(access_expr) => {
+          let mut receiver = &*access_expr.receiver;
+          while let Expr::Access(access_expr) = receiver {
+            receiver = &access_expr.receiver;
+          }
+          if let Expr::Ident(ident) = receiver {
+            assigned.insert(ident.content);
+          }
+          collect_access_assigned(access_expr, assigned);
+        }
+      }
+      collect_assigned(&assign_expr.value, assigned);
+    }
+    Expr::Call(call_expr) => {
+      collect_assigned(&call_expr.receiver, assigned);
+      for argument in &call_expr.arguments {
+        match argument {
+          CallExprArgument::Spread(expr) | CallExprArgument::Expr(expr) => {
+            collect_assigned(expr, assigned)
+          }
+        }
+      }
+    }
+    Expr::Access(access_expr) => collect_access_assigned(access_expr, assigned),
+    Expr::If(if_expr) => {
+      collect_assigned(&if_expr.condition, assigned);
+      collect_assigned(&if_expr.body, assigned);
+      if let Some(otherwise) = &if_expr.otherwise {
+        collect_assigned(otherwise, assigned);
+      }
+    }
+    Expr::Case(case_expr) => {
+      collect_assigned(&case_expr.subject, assigned);
+      for arm in &case_expr.arms {
+        if let Some(guard) = &arm.guard {
+          collect_assigned(guard, assigned);
+        }
+        collect_assigned(&arm.expr, assigned);
+      }
+    }
+    Expr::For(for_expr) => {
+      collect_assigned(&for_expr.iterator, assigned);
+      collect_assigned(&for_expr.body, assigned);
+    }
+    Expr::While(while_expr) => {
+      collect_assigned(&while_expr.condition, assigned);
+      collect_assigned(&while_expr.body, assigned);
+    }
+  }
+}
+
+fn collect_access_assigned(
+  access_expr: &AccessExpr,
+  assigned: &mut HashSet<&'static str>,
+) {
+  collect_assigned(&access_expr.receiver, assigned);
+  match &access_expr.field {
+    AccessExprField::Ident(_) => {}
+    AccessExprField::Expr(field) => collect_assigned(field, assigned),
+    AccessExprField::Slice(start, end) => {
+      for bound in start.iter().chain(end) {
+        collect_assigned(bound, assigned);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::Path;
+
+  use super::*;
+  use crate::{error::VerifyErrorReason, parse::Parser, source::Source};
+
+  // The messages of the errors in the source, in source order.
+  fn errors(source: &str) -> Vec<String> {
+    let source = Source::from_str(source, Path::new("test.oma"));
+    let module = Parser::new(source).parse().unwrap();
+    match check(&module) {
+      Ok(()) => Vec::new(),
+      Err(error) => {
+        error.errors().iter().map(|error| error.message()).collect()
+      }
+    }
+  }
+
+  fn mismatched(expected: &str, found: &str) -> String {
+    VerifyErrorReason::MismatchedTypes(expected.to_string(), found.to_string())
+      .to_string()
+  }
+
+  #[test]
+  fn named_types() {
+    let source = "let a: int = 1; let b: float = 1.5; let c: bool = true;
+      let d: string = \"d\"; let e: null = null; let f: lambda = () -> 1;";
+    assert!(errors(source).is_empty());
+    assert_eq!(
+      errors("let a: int = 1.5;"),
+      vec![mismatched("int", "float")]
+    );
+    assert_eq!(errors("let a: null = 1;"), vec![mismatched("null", "int")]);
+  }
+
+  #[test]
+  fn unknown_type() {
+    assert_eq!(
+      errors("let a: integer = 1;"),
+      vec![VerifyErrorReason::UnknownType.to_string()]
+    );
+  }
+
+  #[test]
+  fn array_type() {
+    assert!(errors("let a: [int] = [1, 2];").is_empty());
+    assert_eq!(
+      errors("let a: [int] = [1, \"b\"];"),
+      vec![mismatched("[int]", "[int | string]")]
+    );
+  }
+
+  #[test]
+  fn map_type() {
+    assert!(errors("let p: { x: int } = { x: 1, y: \"y\" };").is_empty());
+    assert!(errors("let p: { x: int } = {};").is_empty());
+    assert_eq!(
+      errors("let p: { x: int } = { x: \"x\" };"),
+      vec![mismatched("{ x: int }", "{ x: string }")]
+    );
+  }
+
+  #[test]
+  fn tag_types() {
+    assert!(errors("let a: Some(int) = Some(1);").is_empty());
+    assert!(errors("let p: Point(x: int) = Point(x: 1);").is_empty());
+    assert_eq!(
+      errors("let a: Some(int) = Some(\"a\");"),
+      vec![mismatched("Some(int)", "Some(string)")]
+    );
+    assert_eq!(
+      errors("let a: Some(int) = Other(1);"),
+      vec![mismatched("Some(int)", "Other(int)")]
+    );
+  }
+
+  #[test]
+  fn union_type() {
+    let source =
+      "let a: Some(int) | None() = None(); let b: int | string = \"b\";";
+    assert!(errors(source).is_empty());
+    assert_eq!(
+      errors("let a: int | string = true;"),
+      vec![mismatched("int | string", "bool")]
+    );
+  }
+
+  #[test]
+  fn tag_without_fields_needs_no_parentheses() {
+    assert!(errors("let a: Some(int) | None = None();").is_empty());
+    assert_eq!(
+      errors("let a: Some(int) | None = Other();"),
+      vec![mismatched("Some(int) | None()", "Other()")]
+    );
+  }
+
+  #[test]
+  fn lambda_parameters_and_results() {
+    assert!(errors("let f = (a: int): int -> a + 1; f(1)").is_empty());
+    assert_eq!(
+      errors("let f = (a: int) -> a; f(\"a\")"),
+      vec![mismatched("int", "string")]
+    );
+    assert_eq!(
+      errors("let f = (a: int): string -> a;"),
+      vec![mismatched("string", "int")]
+    );
+  }
+
+  #[test]
+  fn inferred_types_flow_through_calls() {
+    assert_eq!(
+      errors("let f = (a: int): int -> a; let s: string = f(1);"),
+      vec![mismatched("string", "int")]
+    );
+    assert_eq!(
+      errors("let f = () -> 1.5; let g = () -> f(); let i: int = g();"),
+      vec![mismatched("int", "float")]
+    );
+    assert_eq!(
+      errors("let f = () -> \"a\"; f() + 1"),
+      vec![VerifyErrorReason::InvalidOperands(
+        "+",
+        "string and int".to_string()
+      )
+      .to_string()]
+    );
+  }
+
+  #[test]
+  fn reassigned_binding_is_any() {
+    assert!(errors("let x = 1; x = \"a\"; let y: int = x;").is_empty());
+    assert_eq!(
+      errors("let x: int = 1; x = \"a\";"),
+      vec![mismatched("int", "string")]
+    );
+  }
+
+  #[test]
+  fn any_fits_every_type() {
+    assert!(errors("let x: any = 1; let s: string = x;").is_empty());
+    assert!(errors("let x: any = 1; x = \"a\"; x = [x];").is_empty());
+    assert!(errors("let f = (a: any): int -> a; f(\"a\")").is_empty());
+  }
+}
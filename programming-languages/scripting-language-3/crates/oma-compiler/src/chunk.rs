@@ -90,6 +90,12 @@ impl Chunk {
   fn normalize_operand(&mut self, operand: Operand) -> (u64, u8) {
     match operand {
       Operand::F64(float) => (float.to_bits(), 3),
+      // Integers that aren't negative, such as literals, lengths and indices,
+      // fit in a smaller operand when they're small, as operands are read
+      // without sign extension. Negative ones take all 8 bytes.
+      Operand::I64(int) if int >= 0 => {
+        self.normalize_operand(Operand::Usize(int as usize))
+      }
       Operand::I64(int) => (int as u64, 3),
       Operand::Usize(uint) => match uint {
         uint if uint <= u8::MAX as usize => (uint as u64, 0),
@@ -196,6 +202,23 @@ pub enum Opcode {
   // Lambda operations
   Cal,
   Ret,
+  // Opcodes added since the first version of the format. New ones go last,
+  // so that the ones before keep their numbers.
+  Pow,
+  Ban,
+  Bor,
+  Bxr,
+  Shl,
+  Shr,
+  Tgn,
+  Gnt,
+  Has,
+  Len,
+  Slc,
+  Nma,
+  Rmv,
+  Ins,
+  Ivk,
 }
 
 impl Opcode {
@@ -212,7 +235,12 @@ impl Opcode {
     | Opcode::Jmp
     | Opcode::Jit
     | Opcode::Jif
-    | Opcode::Cal = self
+    | Opcode::Tag
+    | Opcode::Tgn
+    | Opcode::Utg
+    | Opcode::Gtg
+    | Opcode::Cal
+    | Opcode::Ivk = self
     {
       true
     } else {
@@ -1,2 +1,9 @@
+// Debug info for a chunk, which the VM uses in stack traces.
 #[derive(Debug)]
-pub struct Info {}
+pub struct Info {
+  pub name: String,
+  pub path: String,
+  // Line and column of the expression ops were emitted for, by the offset of
+  // the first op. Each entry covers the ops up to the next one.
+  pub spans: Vec<(usize, usize, usize)>,
+}
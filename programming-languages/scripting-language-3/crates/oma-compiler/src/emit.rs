@@ -1,79 +1,163 @@
-use crate::chunk::{Chunk, Constant, Function};
-
-const SECTION_INFO: u8 = 1;
-const SECTION_DATA: u8 = 2;
-const SECTION_CODE: u8 = 3;
+// Executables are written in version 2 of the format:
+//
+// - A header: the magic number `OMA`, the format version, and the version of
+//   the compiler that wrote it.
+// - A table of the strings used by constants and debug info, which refer to
+//   them by index.
+// - A table of every function, starting with the module, each followed by
+//   the functions nested in it. Constants refer to nested functions by index,
+//   which is always after their own.
+// - A CRC-32 checksum of everything before it.
+//
+// Numbers and lengths are unsigned LEB128, except for the checksum, which is
+// 4 bytes little-endian.
+
+use std::collections::HashMap;
+
+use crate::{
+  chunk::{Chunk, Constant, Function},
+  debug::Info,
+};
+
+const MAGIC: [u8; 3] = [b'O', b'M', b'A'];
+const VERSION: u8 = 2;
 
 const CONSTANT_STR: u8 = 1;
 const CONSTANT_FUN: u8 = 2;
 
 pub fn emit(function: Function) -> Vec<u8> {
-  let mut bytes = Vec::new();
-
-  bytes.extend([b'O', b'M', b'A', 1]);
-  bytes.extend(emit_function(function));
+  let mut emitter = Emitter::default();
+  emitter.function(function);
 
-  bytes
-}
+  let mut bytes = MAGIC.to_vec();
+  bytes.push(VERSION);
+  emit_string(&mut bytes, env!("CARGO_PKG_VERSION"));
 
-fn emit_chunk(chunk: Chunk) -> Vec<u8> {
-  let mut bytes = Vec::new();
+  emit_uint(&mut bytes, emitter.strings.len());
+  for string in &emitter.strings {
+    emit_string(&mut bytes, string);
+  }
 
-  bytes.extend(emit_data(chunk.data));
+  emit_uint(&mut bytes, emitter.functions.len());
+  for function in emitter.functions {
+    bytes.extend(function);
+  }
 
-  bytes.push(SECTION_CODE);
-  bytes.extend((chunk.code.len() as u64).to_le_bytes());
-  bytes.extend(chunk.code);
+  let checksum = crc32(&bytes);
+  bytes.extend(checksum.to_le_bytes());
 
   bytes
 }
 
-fn emit_data(data: Vec<Constant>) -> Vec<u8> {
-  let mut bytes = Vec::new();
+#[derive(Default)]
+struct Emitter {
+  strings: Vec<String>,
+  // Indices of the strings in the table.
+  indices: HashMap<String, usize>,
+  functions: Vec<Vec<u8>>,
+}
 
-  bytes.push(SECTION_DATA);
-  bytes.extend((data.len() as u64).to_le_bytes());
+impl Emitter {
+  // Adds the function and the functions nested in it to the table, and
+  // returns its index.
+  fn function(&mut self, function: Function) -> usize {
+    let index = self.functions.len();
+    self.functions.push(Vec::new());
+
+    let mut bytes = Vec::new();
+    emit_uint(&mut bytes, function.arity as usize);
+    emit_uint(&mut bytes, function.locals as usize);
+    emit_uint(&mut bytes, function.upvalues.len());
+    for (index, is_local) in function.upvalues {
+      emit_uint(&mut bytes, index as usize);
+      bytes.push(is_local as u8);
+    }
+    self.chunk(&mut bytes, function.chunk);
 
-  for constant in data {
-    bytes.extend(emit_constant(constant));
+    self.functions[index] = bytes;
+    index
   }
 
-  bytes
-}
+  fn chunk(&mut self, bytes: &mut Vec<u8>, chunk: Chunk) {
+    match chunk.info {
+      Some(info) => {
+        bytes.push(1);
+        self.info(bytes, info);
+      }
+      None => bytes.push(0),
+    }
+
+    emit_uint(bytes, chunk.data.len());
+    for constant in chunk.data {
+      match constant {
+        Constant::String(string) => {
+          bytes.push(CONSTANT_STR);
+          let index = self.string(string);
+          emit_uint(bytes, index);
+        }
+        Constant::Function(function) => {
+          bytes.push(CONSTANT_FUN);
+          let index = self.function(function);
+          emit_uint(bytes, index);
+        }
+      }
+    }
 
-fn emit_constant(constant: Constant) -> Vec<u8> {
-  let mut bytes = Vec::new();
+    emit_uint(bytes, chunk.code.len());
+    bytes.extend(chunk.code);
+  }
 
-  match constant {
-    Constant::String(string) => {
-      bytes.push(CONSTANT_STR);
-      bytes.extend((string.bytes().len() as u64).to_le_bytes());
-      bytes.extend(string.bytes());
+  fn info(&mut self, bytes: &mut Vec<u8>, info: Info) {
+    for string in [info.name, info.path] {
+      let index = self.string(string);
+      emit_uint(bytes, index);
     }
-    Constant::Function(function) => {
-      bytes.push(CONSTANT_FUN);
-      bytes.extend(emit_function(function));
+
+    emit_uint(bytes, info.spans.len());
+    for (offset, line, column) in info.spans {
+      emit_uint(bytes, offset);
+      emit_uint(bytes, line);
+      emit_uint(bytes, column);
     }
   }
 
-  bytes
+  fn string(&mut self, string: String) -> usize {
+    if let Some(index) = self.indices.get(&string) {
+      return *index;
+    }
+    self.indices.insert(string.clone(), self.strings.len());
+    self.strings.push(string);
+    self.strings.len() - 1
+  }
 }
 
-fn emit_function(function: Function) -> Vec<u8> {
-  let mut bytes = Vec::new();
-
-  bytes.extend((function.arity as u64).to_le_bytes());
-  bytes.extend(emit_chunk(function.chunk));
-  bytes.extend((function.locals as u64).to_le_bytes());
-  bytes.extend((function.upvalues.len() as u64).to_le_bytes());
-  for (index, is_local) in function.upvalues {
-    bytes.extend((index as u64).to_le_bytes());
-    if is_local {
-      bytes.push(1);
-    } else {
-      bytes.push(0);
+fn emit_string(bytes: &mut Vec<u8>, string: &str) {
+  emit_uint(bytes, string.len());
+  bytes.extend(string.bytes());
+}
+
+fn emit_uint(bytes: &mut Vec<u8>, uint: usize) {
+  let mut uint = uint as u64;
+  loop {
+    let byte = (uint & 0x7f) as u8;
+    uint >>= 7;
+    if uint == 0 {
+      bytes.push(byte);
+      return;
     }
+    bytes.push(byte | 0x80);
   }
+}
 
-  bytes
+// CRC-32 as used by zip and PNG, computed a bit at a time since executables
+// are small.
+fn crc32(bytes: &[u8]) -> u32 {
+  let mut crc = !0u32;
+  for byte in bytes {
+    crc ^= *byte as u32;
+    for _ in 0..8 {
+      crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+    }
+  }
+  !crc
 }
@@ -1,4 +1,4 @@
-use std::{fmt, io, rc::Rc};
+use std::{fmt, io, rc::Rc, slice};
 
 use crate::{lex::Token, source::Span};
 
@@ -7,6 +7,55 @@ pub enum CompileError {
   Parse(ParseError),
   Verify(VerifyError),
   Io(Rc<io::Error>),
+  // Every error found in a file, in source order. Never nested or empty.
+  Multiple(Vec<CompileError>),
+}
+
+impl CompileError {
+  // Collects the errors into one, or returns `None` if there are none.
+  pub fn from_errors(errors: Vec<CompileError>) -> Option<CompileError> {
+    let mut flattened = Vec::new();
+    for error in errors {
+      match error {
+        Self::Multiple(errors) => flattened.extend(errors),
+        error => flattened.push(error),
+      }
+    }
+
+    match flattened.len() {
+      0 => None,
+      1 => flattened.pop(),
+      _ => Some(Self::Multiple(flattened)),
+    }
+  }
+
+  pub fn errors(&self) -> &[CompileError] {
+    match self {
+      Self::Multiple(errors) => errors,
+      error => slice::from_ref(error),
+    }
+  }
+
+  // For multiple errors, the span of the first one.
+  pub fn span(&self) -> Option<&Span> {
+    match self {
+      Self::Parse(error) => Some(error.span()),
+      Self::Verify(error) => Some(error.span()),
+      Self::Io(_) => None,
+      Self::Multiple(errors) => errors[0].span(),
+    }
+  }
+
+  // The error without the source excerpt. For multiple errors, the message of
+  // the first one.
+  pub fn message(&self) -> String {
+    match self {
+      Self::Parse(error) => error.message(),
+      Self::Verify(error) => error.reason().to_string(),
+      Self::Io(error) => error.to_string(),
+      Self::Multiple(errors) => errors[0].message(),
+    }
+  }
 }
 
 impl fmt::Display for CompileError {
@@ -15,6 +64,15 @@ impl fmt::Display for CompileError {
       Self::Parse(error) => write!(f, "{}", error),
       Self::Verify(error) => write!(f, "{}", error),
       Self::Io(error) => write!(f, "{}", error),
+      Self::Multiple(errors) => {
+        for (index, error) in errors.iter().enumerate() {
+          if index > 0 {
+            writeln!(f)?;
+          }
+          write!(f, "{}", error)?;
+        }
+        Ok(())
+      }
     }
   }
 }
@@ -29,7 +87,7 @@ pub struct ParseError {
 impl ParseError {
   pub fn end<const N: usize>(
     span: Span,
-    expected: Option<[u8; N]>,
+    expected: Option<[char; N]>,
   ) -> ParseError {
     ParseError {
       span,
@@ -41,8 +99,8 @@ impl ParseError {
 
   pub fn chars<const N: usize>(
     span: Span,
-    unexpected: u8,
-    expected: Option<[u8; N]>,
+    unexpected: char,
+    expected: Option<[char; N]>,
   ) -> ParseError {
     ParseError {
       span,
@@ -52,6 +110,15 @@ impl ParseError {
     }
   }
 
+  // A number literal with a char that isn't a digit in its radix.
+  pub fn digit(span: Span, unexpected: char, radix: u32) -> ParseError {
+    ParseError {
+      span,
+      unexpected: ParseExpectation::Char(unexpected),
+      expected: Some(ParseExpectation::Digit(radix)),
+    }
+  }
+
   pub fn tokens<const N: usize>(
     span: Span,
     unexpected: Token,
@@ -64,15 +131,23 @@ impl ParseError {
         .map(|expected| ParseExpectation::Tokens(expected.to_vec())),
     }
   }
-}
 
-impl fmt::Display for ParseError {
-  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+  pub fn span(&self) -> &Span {
+    &self.span
+  }
+
+  pub fn message(&self) -> String {
     if let Some(expected) = &self.expected {
-      writeln!(f, "unexpected {}, expected {}", self.unexpected, expected)?;
+      format!("unexpected {}, expected {}", self.unexpected, expected)
     } else {
-      writeln!(f, "unexpected {}", self.unexpected)?;
+      format!("unexpected {}", self.unexpected)
     }
+  }
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "{}", self.message())?;
 
     write!(
       f,
@@ -86,8 +161,8 @@ impl fmt::Display for ParseError {
       self.span.column(),
       self.span.line(),
       self.span.line_content(),
-      "^".repeat(self.span.content().len()),
-      padding = self.span.column() + self.span.content().len(),
+      "^".repeat(self.span.content().chars().count()),
+      padding = self.span.column() + self.span.content().chars().count(),
     )
   }
 }
@@ -96,8 +171,10 @@ impl fmt::Display for ParseError {
 pub enum ParseExpectation {
   Tokens(Vec<Token>),
   Token(Token),
-  Chars(Vec<u8>),
-  Char(u8),
+  Chars(Vec<char>),
+  Char(char),
+  // A digit in the radix.
+  Digit(u32),
 }
 
 impl fmt::Display for ParseExpectation {
@@ -105,14 +182,12 @@ impl fmt::Display for ParseExpectation {
     match self {
       Self::Tokens(tokens) => write_slice(f, tokens),
       Self::Token(token) => write!(f, "{}", token),
-      Self::Chars(bytes) => write_slice(
-        f,
-        &bytes
-          .into_iter()
-          .map(|byte| *byte as char)
-          .collect::<Vec<char>>(),
-      ),
-      Self::Char(byte) => write!(f, "{}", *byte as char),
+      Self::Chars(chars) => write_slice(f, chars),
+      Self::Char(char) => write!(f, "{}", char),
+      Self::Digit(2) => write!(f, "binary digit"),
+      Self::Digit(8) => write!(f, "octal digit"),
+      Self::Digit(16) => write!(f, "hex digit"),
+      Self::Digit(_) => write!(f, "digit"),
     }
   }
 }
@@ -138,10 +213,24 @@ impl VerifyError {
     }
   }
 
-  pub fn multiple_tag_arguments(span: Span) -> Self {
+  pub fn mismatched_alternative_bindings(span: Span) -> Self {
     Self {
       span,
-      reason: VerifyErrorReason::MultipleTagArguments,
+      reason: VerifyErrorReason::MismatchedAlternativeBindings,
+    }
+  }
+
+  pub fn mixed_tag_fields(span: Span) -> Self {
+    Self {
+      span,
+      reason: VerifyErrorReason::MixedTagFields,
+    }
+  }
+
+  pub fn duplicate_tag_field(span: Span) -> Self {
+    Self {
+      span,
+      reason: VerifyErrorReason::DuplicateTagField,
     }
   }
 
@@ -152,33 +241,79 @@ impl VerifyError {
     }
   }
 
+  pub fn integer_out_of_range(span: Span) -> Self {
+    Self {
+      span,
+      reason: VerifyErrorReason::IntegerOutOfRange,
+    }
+  }
+
   pub fn import_argument_not_string(span: Span) -> Self {
     Self {
       span,
       reason: VerifyErrorReason::ImportArgumentNotString,
     }
   }
+
+  pub fn not_granted(span: Span, native: String, capability: String) -> Self {
+    Self {
+      span,
+      reason: VerifyErrorReason::NotGranted(native, capability),
+    }
+  }
+
+  pub fn unknown_type(span: Span) -> Self {
+    Self {
+      span,
+      reason: VerifyErrorReason::UnknownType,
+    }
+  }
+
+  pub fn mismatched_types(span: Span, expected: String, found: String) -> Self {
+    Self {
+      span,
+      reason: VerifyErrorReason::MismatchedTypes(expected, found),
+    }
+  }
+
+  pub fn invalid_operands(span: Span, op: &'static str, types: String) -> Self {
+    Self {
+      span,
+      reason: VerifyErrorReason::InvalidOperands(op, types),
+    }
+  }
+
+  pub fn wrong_argument_count(
+    span: Span,
+    expected: usize,
+    found: usize,
+  ) -> Self {
+    Self {
+      span,
+      reason: VerifyErrorReason::WrongArgumentCount(expected, found),
+    }
+  }
+
+  pub fn unsupported(span: Span, what: &'static str) -> Self {
+    Self {
+      span,
+      reason: VerifyErrorReason::Unsupported(what),
+    }
+  }
+
+  pub fn span(&self) -> &Span {
+    &self.span
+  }
+
+  pub fn reason(&self) -> &VerifyErrorReason {
+    &self.reason
+  }
 }
 
 impl fmt::Display for VerifyError {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    write!(
-      f,
-      "{}
-    --> {}:{}:{}
-     |
- {:>3} | {}
-     | {:>padding$}
- ",
-      self.reason,
-      self.span.path().display(),
-      self.span.line(),
-      self.span.column(),
-      self.span.line(),
-      self.span.line_content(),
-      "^".repeat(self.span.content().len()),
-      padding = self.span.column() + self.span.content().len(),
-    )
+    write!(f, "{}", self.reason)?;
+    write_excerpt(f, &self.span)
   }
 }
 
@@ -186,9 +321,23 @@ impl fmt::Display for VerifyError {
 pub enum VerifyErrorReason {
   InvalidCasePat,
   InvalidAssignee,
-  MultipleTagArguments,
+  MismatchedAlternativeBindings,
+  MixedTagFields,
+  DuplicateTagField,
   ImportArgumentNotString,
   UnresolvedIdentifier,
+  IntegerOutOfRange,
+  UnknownType,
+  // The expected type and the type found.
+  MismatchedTypes(String, String),
+  // The operator and the types of its operands.
+  InvalidOperands(&'static str, String),
+  // The number of parameters and the number of arguments.
+  WrongArgumentCount(usize, usize),
+  // The name of the native and the capability it needs.
+  NotGranted(String, String),
+  // What the code generator can't compile yet.
+  Unsupported(&'static str),
 }
 
 impl fmt::Display for VerifyErrorReason {
@@ -196,19 +345,190 @@ impl fmt::Display for VerifyErrorReason {
     match self {
       Self::InvalidCasePat => write!(f, "invalid pattern for case arm"),
       Self::InvalidAssignee => write!(f, "cannot assign to expression"),
-      Self::MultipleTagArguments => {
-        write!(f, "more than 1 argument passed to tag expression")
+      Self::MismatchedAlternativeBindings => {
+        write!(f, "alternatives in case arm must bind the same identifiers")
       }
+      Self::MixedTagFields => {
+        write!(
+          f,
+          "cannot mix positional and named fields in tag expression"
+        )
+      }
+      Self::DuplicateTagField => write!(f, "field is specified more than once"),
       Self::UnresolvedIdentifier => {
         write!(f, "unresolved identifier")
       }
       Self::ImportArgumentNotString => {
         write!(f, "string must be passed to import")
       }
+      Self::IntegerOutOfRange => {
+        write!(f, "integer literal doesn't fit in 64 bits")
+      }
+      Self::UnknownType => write!(f, "unknown type"),
+      Self::MismatchedTypes(expected, found) => {
+        write!(
+          f,
+          "mismatched types: expected {}, found {}",
+          expected, found
+        )
+      }
+      Self::InvalidOperands(op, types) => {
+        write!(f, "cannot apply `{}` to {}", op, types)
+      }
+      Self::WrongArgumentCount(expected, found) => {
+        write!(f, "expected {} arguments, found {}", expected, found)
+      }
+      Self::NotGranted(native, capability) => {
+        write!(
+          f,
+          "`{}` needs the `{}` capability, which isn't granted",
+          native, capability
+        )
+      }
+      Self::Unsupported(what) => write!(f, "{} aren't supported yet", what),
     }
   }
 }
 
+#[derive(Clone, Debug)]
+pub struct Warning {
+  span: Span,
+  reason: WarningReason,
+}
+
+impl Warning {
+  pub fn non_exhaustive_case(span: Span, missing: String) -> Self {
+    Self {
+      span,
+      reason: WarningReason::NonExhaustiveCase(missing),
+    }
+  }
+
+  pub fn unreachable_pat(span: Span) -> Self {
+    Self {
+      span,
+      reason: WarningReason::UnreachablePat,
+    }
+  }
+
+  pub fn span(&self) -> &Span {
+    &self.span
+  }
+
+  pub fn reason(&self) -> &WarningReason {
+    &self.reason
+  }
+}
+
+impl fmt::Display for Warning {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.reason)?;
+    write_excerpt(f, &self.span)
+  }
+}
+
+#[derive(Clone, Debug)]
+pub enum WarningReason {
+  NonExhaustiveCase(String),
+  UnreachablePat,
+}
+
+impl fmt::Display for WarningReason {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Self::NonExhaustiveCase(missing) => {
+        write!(f, "case is not exhaustive, `{}` is not matched", missing)
+      }
+      Self::UnreachablePat => write!(f, "unreachable pattern"),
+    }
+  }
+}
+
+// A lint is a warning that can be turned off by its rule.
+#[derive(Clone, Debug)]
+pub struct Lint {
+  span: Span,
+  reason: LintReason,
+}
+
+impl Lint {
+  pub fn new(span: Span, reason: LintReason) -> Self {
+    Self { span, reason }
+  }
+
+  pub fn span(&self) -> &Span {
+    &self.span
+  }
+
+  pub fn reason(&self) -> &LintReason {
+    &self.reason
+  }
+}
+
+impl fmt::Display for Lint {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} [{}]", self.reason, self.reason.rule().name())?;
+    write_excerpt(f, &self.span)
+  }
+}
+
+#[derive(Clone, Debug)]
+pub enum LintReason {
+  UnusedBinding(&'static str),
+  UnusedParameter(&'static str),
+  UnreadBinding(&'static str),
+  // The line of the shadowed binding.
+  Shadowing(&'static str, usize),
+  UnusedAssignment(&'static str),
+  UnreachableArm,
+  ConstantCondition,
+}
+
+impl fmt::Display for LintReason {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Self::UnusedBinding(name) => write!(f, "`{}` is never used", name),
+      Self::UnusedParameter(name) => {
+        write!(f, "parameter `{}` is never used", name)
+      }
+      Self::UnreadBinding(name) => {
+        write!(f, "`{}` is assigned to but never read", name)
+      }
+      Self::Shadowing(name, line) => {
+        write!(f, "`{}` shadows the binding on line {}", name, line)
+      }
+      Self::UnusedAssignment(name) => {
+        write!(f, "value assigned to `{}` is never read", name)
+      }
+      Self::UnreachableArm => {
+        write!(f, "unreachable arm, an earlier arm matches every value")
+      }
+      Self::ConstantCondition => write!(f, "condition is a constant"),
+    }
+  }
+}
+
+// Writes the location of the span and the line it's on, with the span
+// underlined.
+fn write_excerpt(f: &mut fmt::Formatter, span: &Span) -> fmt::Result {
+  write!(
+    f,
+    "
+    --> {}:{}:{}
+     |
+ {:>3} | {}
+     | {:>padding$}
+ ",
+    span.path().display(),
+    span.line(),
+    span.column(),
+    span.line(),
+    span.line_content(),
+    "^".repeat(span.content().chars().count()),
+    padding = span.column() + span.content().chars().count(),
+  )
+}
+
 fn write_slice<T>(f: &mut fmt::Formatter, items: &[T]) -> fmt::Result
 where
   T: fmt::Display,
@@ -224,6 +544,52 @@ where
       }
       Ok(())
     })
-    .collect::<Result<_, fmt::Error>>()?;
+    .collect::<Result<(), fmt::Error>>()?;
   Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+  use std::path::Path;
+
+  use super::*;
+  use crate::{parse::Parser, source::Source};
+
+  fn messages(error: &CompileError) -> Vec<String> {
+    error.errors().iter().map(CompileError::message).collect()
+  }
+
+  #[test]
+  fn no_errors_collect_to_none() {
+    assert!(CompileError::from_errors(Vec::new()).is_none());
+  }
+
+  #[test]
+  fn errors_are_collected_flat_and_in_order() {
+    let error = |message: &str| {
+      CompileError::Io(Rc::new(io::Error::other(message.to_string())))
+    };
+    let single = CompileError::from_errors(vec![error("a")]).unwrap();
+    assert!(matches!(single, CompileError::Io(_)));
+
+    let nested = CompileError::from_errors(vec![error("b"), error("c")]);
+    let collected =
+      CompileError::from_errors(vec![error("a"), nested.unwrap(), error("d")])
+        .unwrap();
+    assert_eq!(messages(&collected), ["a", "b", "c", "d"]);
+    assert_eq!(collected.message(), "a");
+  }
+
+  #[test]
+  fn parser_collects_several_errors() {
+    let source = "let w = );\nlet x = [1, 2;\nlet y = 3 +;\n";
+    let source = Source::from_str(source, Path::new("test.oma"));
+    let error = Parser::new(source).parse().unwrap_err();
+    let lines = error
+      .errors()
+      .iter()
+      .map(|error| error.span().unwrap().line())
+      .collect::<Vec<_>>();
+    assert_eq!(lines, [1, 2, 3], "{:?}", messages(&error));
+  }
+}
@@ -0,0 +1,820 @@
+// Exhaustiveness and reachability checking for `case` expressions, based on
+// the usefulness algorithm from Maranget's "Warnings for pattern matching".
+//
+// Oma is dynamically typed, so a subject can be any value, whatever its arms
+// match: a `case` over `true` and `false` can be given `3`. An arm is only
+// unreachable when the arms before it match every value it does, so a
+// catch-all arm is reachable unless another catch-all comes first.
+//
+// Exhaustiveness instead assumes the subject is one of the values its arms
+// are written for. Bools and array lengths have a known set of constructors.
+// Tags are grouped into families: tags matched in the same position of any
+// `case` in the module belong together, so a `case` matching only `Some(v)`
+// is missing `None()` if `None()` is matched alongside `Some(..)` elsewhere.
+// Numbers, strings, null, maps and named tags always need a catch-all arm.
+
+use std::{collections::HashMap, fmt, iter, slice};
+
+use crate::{ast::*, error::Warning};
+
+pub fn check(module: &Module) -> Vec<Warning> {
+  let mut cases = Vec::new();
+  collect_cases(&module.body, &mut cases);
+
+  let mut families = Families::default();
+  for case_expr in &cases {
+    families.group(case_expr.arms.iter().flat_map(|arm| &arm.pats).collect());
+  }
+
+  let mut checker = Checker {
+    families,
+    opaque_count: 0,
+  };
+  let mut warnings = Vec::new();
+  for case_expr in cases {
+    checker.case_expr(case_expr, &mut warnings);
+  }
+  warnings
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Constructor {
+  // A tag with the given number of positional fields.
+  Tag(&'static str, usize),
+  // A tag with at least the given named fields, in order of name.
+  NamedTag(&'static str, Vec<&'static str>),
+  Bool(bool),
+  Null,
+  Int(i64),
+  // The bits of the float, so that constructors can be compared.
+  Float(u64),
+  String(&'static str),
+  // An array of exactly the given length, or of at least that length if the
+  // flag is set.
+  Array(usize, bool),
+  // Covers nothing but the pattern it was created for.
+  Opaque(usize),
+}
+
+impl Constructor {
+  fn arity(&self) -> usize {
+    match self {
+      Self::Tag(_, arity) | Self::Array(arity, _) => *arity,
+      Self::NamedTag(_, names) => names.len(),
+      _ => 0,
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+enum Pattern {
+  Wild,
+  Constructor(Constructor, Vec<Pattern>),
+  // An array pattern with a spread between the prefix and suffix.
+  Slice(Vec<Pattern>, Vec<Pattern>),
+}
+
+impl fmt::Display for Pattern {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Self::Wild | Self::Constructor(Constructor::Opaque(_), _) => {
+        write!(f, "_")
+      }
+      Self::Constructor(Constructor::Tag(tag, _), fields) => {
+        write!(f, "{}(", tag)?;
+        write_patterns(f, fields)?;
+        write!(f, ")")
+      }
+      Self::Constructor(Constructor::NamedTag(tag, names), fields) => {
+        write!(f, "{}(", tag)?;
+        for (index, (name, field)) in names.iter().zip(fields).enumerate() {
+          if index > 0 {
+            write!(f, ", ")?;
+          }
+          write!(f, "{}: {}", name, field)?;
+        }
+        write!(f, ")")
+      }
+      Self::Constructor(Constructor::Bool(bool), _) => write!(f, "{}", bool),
+      Self::Constructor(Constructor::Null, _) => write!(f, "null"),
+      Self::Constructor(Constructor::Int(int), _) => write!(f, "{}", int),
+      Self::Constructor(Constructor::Float(bits), _) => {
+        write!(f, "{:?}", f64::from_bits(*bits))
+      }
+      Self::Constructor(Constructor::String(string), _) => {
+        write!(f, "{:?}", string)
+      }
+      Self::Constructor(Constructor::Array(_, or_more), items) => {
+        write!(f, "[")?;
+        write_patterns(f, items)?;
+        match (or_more, items.is_empty()) {
+          (true, true) => write!(f, "..]"),
+          (true, false) => write!(f, ", ..]"),
+          (false, _) => write!(f, "]"),
+        }
+      }
+      Self::Slice(prefix, suffix) => {
+        write!(f, "[")?;
+        for pattern in prefix {
+          write!(f, "{}, ", pattern)?;
+        }
+        write!(f, "..")?;
+        for pattern in suffix {
+          write!(f, ", {}", pattern)?;
+        }
+        write!(f, "]")
+      }
+    }
+  }
+}
+
+fn write_patterns(f: &mut fmt::Formatter, patterns: &[Pattern]) -> fmt::Result {
+  for (index, pattern) in patterns.iter().enumerate() {
+    if index > 0 {
+      write!(f, ", ")?;
+    }
+    write!(f, "{}", pattern)?;
+  }
+  Ok(())
+}
+
+type TagKey = (&'static str, usize);
+
+// Where a pattern is nested in another, so that tags matched in the same
+// place are grouped.
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
+enum Position {
+  Field(TagKey, usize),
+  NamedField(&'static str, &'static str),
+  Item(usize),
+}
+
+// Union-find over tags matched in the same position.
+#[derive(Default)]
+struct Families {
+  parents: HashMap<TagKey, TagKey>,
+}
+
+impl Families {
+  fn group(&mut self, pats: Vec<&Pat>) {
+    let mut nested: HashMap<Position, Vec<&Pat>> = HashMap::new();
+    let mut previous = None;
+    for pat in pats {
+      match pat {
+        Pat::Tag(tag_pat) => {
+          let tag = tag_pat.tag.content.as_str();
+          match &tag_pat.fields {
+            TagFields::Positional(fields) => {
+              let key = (tag, fields.len());
+              self.parents.entry(key).or_insert(key);
+              if let Some(previous) = previous {
+                self.union(previous, key);
+              }
+              previous = Some(key);
+              for (index, field) in fields.iter().enumerate() {
+                nested
+                  .entry(Position::Field(key, index))
+                  .or_default()
+                  .push(field);
+              }
+            }
+            TagFields::Named(fields) => {
+              for (name, field) in fields {
+                let position = Position::NamedField(tag, name.content.as_str());
+                nested.entry(position).or_default().push(field);
+              }
+            }
+          }
+        }
+        Pat::Array(array_pat) => {
+          for (index, item) in array_pat.items.iter().enumerate() {
+            match item {
+              ArrayPatItem::Pat(pat) => {
+                nested.entry(Position::Item(index)).or_default().push(pat)
+              }
+              // Items after a spread are at no fixed index.
+              ArrayPatItem::Spread(_) => break,
+            }
+          }
+        }
+        _ => {}
+      }
+    }
+
+    for (_, pats) in nested {
+      self.group(pats);
+    }
+  }
+
+  fn find(&self, mut key: TagKey) -> TagKey {
+    while let Some(parent) = self.parents.get(&key) {
+      if *parent == key {
+        break;
+      }
+      key = *parent;
+    }
+    key
+  }
+
+  fn union(&mut self, left: TagKey, right: TagKey) {
+    let left = self.find(left);
+    let right = self.find(right);
+    if left != right {
+      self.parents.insert(left, right);
+    }
+  }
+
+  fn family(&self, key: TagKey) -> Vec<TagKey> {
+    let root = self.find(key);
+    let mut family = self
+      .parents
+      .keys()
+      .filter(|other| self.find(**other) == root)
+      .copied()
+      .collect::<Vec<_>>();
+    if !family.contains(&key) {
+      family.push(key);
+    }
+    family.sort_unstable();
+    family
+  }
+}
+
+// Whether values outside the constructors written in the arms are taken into
+// account.
+#[derive(Clone, Copy)]
+enum World {
+  // Any value can reach the `case`, which decides reachability.
+  Open,
+  // Only values built by the constructors that can appear in each position,
+  // which decides exhaustiveness.
+  Closed,
+}
+
+struct Checker {
+  families: Families,
+  opaque_count: usize,
+}
+
+impl Checker {
+  fn case_expr(&mut self, case_expr: &CaseExpr, warnings: &mut Vec<Warning>) {
+    // Rows of the arms that are guaranteed to match if their patterns do.
+    let mut rows = Vec::new();
+    for arm in &case_expr.arms {
+      let mut arm_rows = Vec::new();
+      for pat in &arm.pats {
+        let pattern = self.lower(pat);
+        let matrix = rows.iter().chain(&arm_rows).cloned().collect::<Vec<_>>();
+        if self
+          .useful(&matrix, slice::from_ref(&pattern), World::Open)
+          .is_none()
+        {
+          warnings.push(Warning::unreachable_pat(pat.span()));
+        }
+        arm_rows.push(vec![pattern]);
+      }
+
+      // An earlier alternative of the same arm shadows later ones even if the
+      // guard fails, but a guarded arm covers nothing for later arms.
+      if arm.guard.is_none() {
+        rows.extend(arm_rows);
+      }
+    }
+
+    if let Some(witness) = self.useful(&rows, &[Pattern::Wild], World::Closed) {
+      warnings.push(Warning::non_exhaustive_case(
+        case_expr.subject.span(),
+        witness[0].to_string(),
+      ));
+    }
+  }
+
+  fn lower(&mut self, pat: &Pat) -> Pattern {
+    match pat {
+      Pat::Ident(_) => Pattern::Wild,
+      Pat::Lit(lit) => Pattern::Constructor(
+        match lit {
+          Lit::Number(NumberLit {
+            number: Number::Int(int),
+            ..
+          }) => Constructor::Int(*int),
+          Lit::Number(NumberLit {
+            number: Number::Float(float),
+            ..
+          }) => Constructor::Float(float.to_bits()),
+          Lit::Bool(BoolLit { bool, .. }) => Constructor::Bool(*bool),
+          Lit::String(StringLit { string, .. }) => {
+            Constructor::String(string.as_str())
+          }
+          Lit::Null(_) => Constructor::Null,
+        },
+        Vec::new(),
+      ),
+      Pat::Tag(tag_pat) => {
+        let tag = tag_pat.tag.content.as_str();
+        match &tag_pat.fields {
+          TagFields::Positional(fields) => Pattern::Constructor(
+            Constructor::Tag(tag, fields.len()),
+            fields.iter().map(|field| self.lower(field)).collect(),
+          ),
+          // Named fields are ordered by name so patterns listing them in
+          // different orders line up.
+          TagFields::Named(fields) => {
+            let mut fields = fields.iter().collect::<Vec<_>>();
+            fields.sort_by_key(|(name, _)| name.content);
+            Pattern::Constructor(
+              Constructor::NamedTag(
+                tag,
+                fields
+                  .iter()
+                  .map(|(name, _)| name.content.as_str())
+                  .collect(),
+              ),
+              fields.iter().map(|(_, field)| self.lower(field)).collect(),
+            )
+          }
+        }
+      }
+      Pat::Array(array_pat) => {
+        let mut prefix = Vec::new();
+        let mut suffix = None;
+        for item in &array_pat.items {
+          match (item, &mut suffix) {
+            (ArrayPatItem::Spread(_), _) => suffix = Some(Vec::new()),
+            (ArrayPatItem::Pat(pat), Some(suffix)) => {
+              suffix.push(self.lower(pat))
+            }
+            (ArrayPatItem::Pat(pat), None) => prefix.push(self.lower(pat)),
+          }
+        }
+        match suffix {
+          Some(suffix) => Pattern::Slice(prefix, suffix),
+          None => Pattern::Constructor(
+            Constructor::Array(prefix.len(), false),
+            prefix,
+          ),
+        }
+      }
+      Pat::Map(_) => {
+        self.opaque_count += 1;
+        Pattern::Constructor(Constructor::Opaque(self.opaque_count), Vec::new())
+      }
+    }
+  }
+
+  // Returns a vector of values matched by `vector` but by none of the `rows`,
+  // if there is one.
+  fn useful(
+    &self,
+    rows: &[Vec<Pattern>],
+    vector: &[Pattern],
+    world: World,
+  ) -> Option<Vec<Pattern>> {
+    let head = match vector.first() {
+      Some(head) => head,
+      None => {
+        return if rows.is_empty() {
+          Some(Vec::new())
+        } else {
+          None
+        }
+      }
+    };
+
+    match head {
+      Pattern::Constructor(constructor, _) => {
+        self.useful_constructor(rows, vector, constructor, world)
+      }
+      Pattern::Slice(..) => {
+        let heads = rows
+          .iter()
+          .map(|row| &row[0])
+          .chain(iter::once(head))
+          .collect::<Vec<_>>();
+        array_signature(&heads)
+          .iter()
+          .filter(|constructor| specialize(vector, constructor).is_some())
+          .find_map(|constructor| {
+            self.useful_constructor(rows, vector, constructor, world)
+          })
+      }
+      Pattern::Wild => {
+        let signature = self.signature(rows);
+        let missing = signature.as_ref().and_then(|signature| {
+          signature.iter().find(|constructor| {
+            !rows.iter().any(|row| {
+              !matches!(row[0], Pattern::Wild)
+                && specialize(row, constructor).is_some()
+            })
+          })
+        });
+
+        // When the rows have every constructor, the wildcard is only useful
+        // if it is for the values of one of them.
+        if let (World::Closed, Some(signature), None) =
+          (world, &signature, missing)
+        {
+          return signature.iter().find_map(|constructor| {
+            self.useful_constructor(rows, vector, constructor, world)
+          });
+        }
+
+        // Otherwise there are values built by none of them, which only the
+        // catch-all rows match. Those rows match values built by any
+        // constructor too, so they decide whether the wildcard is useful.
+        let default = rows
+          .iter()
+          .filter(|row| matches!(row[0], Pattern::Wild))
+          .map(|row| row[1..].to_vec())
+          .collect::<Vec<_>>();
+        let witness = self.useful(&default, &vector[1..], world)?;
+        let head = match missing {
+          Some(constructor) => Pattern::Constructor(
+            constructor.clone(),
+            vec![Pattern::Wild; constructor.arity()],
+          ),
+          None => Pattern::Wild,
+        };
+        Some(iter::once(head).chain(witness).collect())
+      }
+    }
+  }
+
+  fn useful_constructor(
+    &self,
+    rows: &[Vec<Pattern>],
+    vector: &[Pattern],
+    constructor: &Constructor,
+    world: World,
+  ) -> Option<Vec<Pattern>> {
+    let rows = rows
+      .iter()
+      .filter_map(|row| specialize(row, constructor))
+      .collect::<Vec<_>>();
+    let vector = specialize(vector, constructor)?;
+    let mut witness = self.useful(&rows, &vector, world)?;
+    let rest = witness.split_off(constructor.arity());
+    Some(
+      iter::once(Pattern::Constructor(constructor.clone(), witness))
+        .chain(rest)
+        .collect(),
+    )
+  }
+  // Returns every constructor of the first column of the rows, if the
+  // column only has bools, only arrays or only positional tags.
+  fn signature(&self, rows: &[Vec<Pattern>]) -> Option<Vec<Constructor>> {
+    let heads = rows
+      .iter()
+      .map(|row| &row[0])
+      .filter(|pattern| !matches!(pattern, Pattern::Wild))
+      .collect::<Vec<_>>();
+    let first = heads.first()?;
+    match first {
+      Pattern::Constructor(Constructor::Bool(_), _) => heads
+        .iter()
+        .all(|head| {
+          matches!(head, Pattern::Constructor(Constructor::Bool(_), _))
+        })
+        .then(|| vec![Constructor::Bool(true), Constructor::Bool(false)]),
+      Pattern::Constructor(Constructor::Tag(..), _) => {
+        let mut signature = Vec::new();
+        for head in &heads {
+          if let Pattern::Constructor(Constructor::Tag(tag, arity), _) = head {
+            for (tag, arity) in self.families.family((*tag, *arity)) {
+              let constructor = Constructor::Tag(tag, arity);
+              if !signature.contains(&constructor) {
+                signature.push(constructor);
+              }
+            }
+          } else {
+            return None;
+          }
+        }
+        Some(signature)
+      }
+      Pattern::Constructor(Constructor::Array(..), _) | Pattern::Slice(..) => {
+        heads
+          .iter()
+          .all(|head| {
+            matches!(
+              head,
+              Pattern::Constructor(Constructor::Array(..), _)
+                | Pattern::Slice(..)
+            )
+          })
+          .then(|| array_signature(&heads))
+      }
+      _ => None,
+    }
+  }
+}
+
+// Arrays at least as long as every fixed-length pattern and every prefix plus
+// suffix are indistinguishable, so they share the last constructor.
+fn array_signature(heads: &[&Pattern]) -> Vec<Constructor> {
+  let mut min_or_more = 0;
+  for head in heads {
+    match head {
+      Pattern::Constructor(Constructor::Array(len, _), _) => {
+        min_or_more = min_or_more.max(len + 1)
+      }
+      Pattern::Slice(prefix, suffix) => {
+        min_or_more = min_or_more.max(prefix.len() + suffix.len())
+      }
+      _ => {}
+    }
+  }
+  (0..min_or_more)
+    .map(|len| Constructor::Array(len, false))
+    .chain(iter::once(Constructor::Array(min_or_more, true)))
+    .collect()
+}
+
+// Returns the row with its head replaced by the fields of `constructor`, if
+// the head can match values built by it.
+fn specialize(
+  row: &[Pattern],
+  constructor: &Constructor,
+) -> Option<Vec<Pattern>> {
+  let mut specialized = match &row[0] {
+    Pattern::Wild => vec![Pattern::Wild; constructor.arity()],
+    Pattern::Constructor(other, fields) if other == constructor => {
+      fields.clone()
+    }
+    Pattern::Constructor(..) => return None,
+    Pattern::Slice(prefix, suffix) => match constructor {
+      Constructor::Array(len, _) if *len >= prefix.len() + suffix.len() => {
+        let mut fields = prefix.clone();
+        fields.resize(len - suffix.len(), Pattern::Wild);
+        fields.extend(suffix.iter().cloned());
+        fields
+      }
+      _ => return None,
+    },
+  };
+  specialized.extend(row[1..].iter().cloned());
+  Some(specialized)
+}
+
+fn collect_cases<'a>(expr: &'a Expr, cases: &mut Vec<&'a CaseExpr>) {
+  match expr {
+    Expr::Lit(_) | Expr::Ident(_) => {}
+    Expr::Map(map_expr) => {
+      for pair in &map_expr.pairs {
+        match pair {
+          MapExprPair::Spread(value) | MapExprPair::Ident(_, value) => {
+            collect_cases(value, cases)
+          }
+          MapExprPair::Expr(key, value) => {
+            collect_cases(key, cases);
+            collect_cases(value, cases);
+          }
+        }
+      }
+    }
+    Expr::Array(array_expr) => {
+      for item in &array_expr.items {
+        match item {
+          ArrayExprItem::Spread(expr) | ArrayExprItem::Expr(expr) => {
+            collect_cases(expr, cases)
+          }
+        }
+      }
+    }
+    Expr::Lambda(lambda_expr) => collect_cases(&lambda_expr.body, cases),
+    Expr::Tag(tag_expr) => {
+      for field in tag_expr.fields.values() {
+        collect_cases(field, cases);
+      }
+    }
+    Expr::Block(block_expr) => {
+      for expr in &block_expr.exprs {
+        collect_cases(expr, cases);
+      }
+    }
+    Expr::Binary(binary_expr) => {
+      collect_cases(&binary_expr.left, cases);
+      collect_cases(&binary_expr.right, cases);
+    }
+    Expr::Unary(unary_expr) => collect_cases(&unary_expr.operand, cases),
+    Expr::Bind(bind_expr) => collect_cases(&bind_expr.value, cases),
+    Expr::Assign(assign_expr) => {
+      if let AssignExprAssignee::Access(access_expr) = &assign_expr.assignee {
+        collect_access_cases(access_expr, cases);
+      }
+      collect_cases(&assign_expr.value, cases);
+    }
+    Expr::Call(call_expr) => {
+      collect_cases(&call_expr.receiver, cases);
+      for argument in &call_expr.arguments {
+        match argument {
+          CallExprArgument::Spread(expr) | CallExprArgument::Expr(expr) => {
+            collect_cases(expr, cases)
+          }
+        }
+      }
+    }
+    Expr::Access(access_expr) => collect_access_cases(access_expr, cases),
+    Expr::If(if_expr) => {
+      collect_cases(&if_expr.condition, cases);
+      collect_cases(&if_expr.body, cases);
+      if let Some(otherwise) = &if_expr.otherwise {
+        collect_cases(otherwise, cases);
+      }
+    }
+    Expr::Case(case_expr) => {
+      cases.push(case_expr);
+      collect_cases(&case_expr.subject, cases);
+      for arm in &case_expr.arms {
+        if let Some(guard) = &arm.guard {
+          collect_cases(guard, cases);
+        }
+        collect_cases(&arm.expr, cases);
+      }
+    }
+    Expr::For(for_expr) => {
+      collect_cases(&for_expr.iterator, cases);
+      collect_cases(&for_expr.body, cases);
+    }
+    Expr::While(while_expr) => {
+      collect_cases(&while_expr.condition, cases);
+      collect_cases(&while_expr.body, cases);
+    }
+  }
+}
+
+fn collect_access_cases<'a>(
+  access_expr: &'a AccessExpr,
+  cases: &mut Vec<&'a CaseExpr>,
+) {
+  collect_cases(&access_expr.receiver, cases);
+  match &access_expr.field {
+    AccessExprField::Ident(_) => {}
+    AccessExprField::Expr(field) => collect_cases(field, cases),
+    AccessExprField::Slice(start, end) => {
+      for bound in start.iter().chain(end) {
+        collect_cases(bound, cases);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::Path;
+
+  use super::*;
+  use crate::{error::WarningReason, parse::Parser, source::Source};
+
+  // The reasons for the warnings about the only `case` in the source.
+  fn warnings(source: &str) -> Vec<String> {
+    let source = Source::from_str(source, Path::new("test.oma"));
+    let module = Parser::new(source).parse().unwrap();
+    check(&module)
+      .iter()
+      .map(|warning| warning.reason().to_string())
+      .collect()
+  }
+
+  fn unreachable() -> String {
+    WarningReason::UnreachablePat.to_string()
+  }
+
+  fn not_matched(missing: &str) -> String {
+    WarningReason::NonExhaustiveCase(missing.to_string()).to_string()
+  }
+
+  #[test]
+  fn catch_all_after_tag_is_reachable() {
+    assert!(warnings("case x { Some(v) -> v, _ -> 0, }").is_empty());
+  }
+
+  #[test]
+  fn catch_all_after_every_tag_matched_elsewhere_is_reachable() {
+    let source = "case a { Some(v) -> v, None() -> 0, };
+      case x { Some(v) -> v, None() -> 0, _ -> 1, }";
+    assert!(warnings(source).is_empty());
+  }
+
+  #[test]
+  fn catch_all_after_tag_with_other_field_count_is_reachable() {
+    assert!(warnings("case Pair(1, 2) { Pair(a) -> a, _ -> 0, }").is_empty());
+  }
+
+  #[test]
+  fn catch_all_after_bools_is_reachable() {
+    assert!(warnings("case x { true -> 1, false -> 0, _ -> 2, }").is_empty());
+  }
+
+  #[test]
+  fn catch_all_after_array_lengths_is_reachable() {
+    let source = "case x { [] -> 0, [a, ..rest] -> 1, _ -> 2, }";
+    assert!(warnings(source).is_empty());
+  }
+
+  #[test]
+  fn every_tag_of_family_is_exhaustive() {
+    assert!(warnings("case x { Some(v) -> v, None() -> 0, }").is_empty());
+  }
+
+  #[test]
+  fn missing_tag_is_named() {
+    let source = "case a { Some(v) -> v, None() -> 0, };
+      case x { Some(v) -> v, }";
+    assert_eq!(warnings(source), vec![not_matched("None()")]);
+  }
+
+  #[test]
+  fn missing_nested_bool_is_named() {
+    let source = "case x { Some(true) -> 1, None() -> 0, }";
+    assert_eq!(warnings(source), vec![not_matched("Some(false)")]);
+  }
+
+  #[test]
+  fn bools_are_exhaustive() {
+    assert!(warnings("case x { true -> 1, false -> 0, }").is_empty());
+  }
+
+  #[test]
+  fn array_lengths_are_exhaustive() {
+    let source = "case x { [] -> 0, [a, ..rest] -> 1, }";
+    assert!(warnings(source).is_empty());
+  }
+
+  #[test]
+  fn literals_need_catch_all() {
+    assert_eq!(
+      warnings("case x { 1 -> 1, \"a\" -> 2, null -> 3, }"),
+      vec![not_matched("_")]
+    );
+  }
+
+  #[test]
+  fn named_tags_need_catch_all() {
+    assert_eq!(
+      warnings("case x { Point(x: a) -> a, }"),
+      vec![not_matched("_")]
+    );
+  }
+
+  #[test]
+  fn missing_bool_is_named() {
+    assert_eq!(
+      warnings("case x { true -> 1, }"),
+      vec![not_matched("false")]
+    );
+  }
+
+  #[test]
+  fn missing_array_length_is_named() {
+    assert_eq!(
+      warnings("case x { [] -> 1, }"),
+      vec![not_matched("[_, ..]")]
+    );
+  }
+
+  #[test]
+  fn repeated_tag_is_unreachable() {
+    let source = "case x { Some(v) -> v, Some(w) -> w, _ -> 0, }";
+    assert_eq!(warnings(source), vec![unreachable()]);
+  }
+
+  #[test]
+  fn tag_after_catch_all_is_unreachable() {
+    assert_eq!(
+      warnings("case x { _ -> 1, Some(v) -> 2, }"),
+      vec![unreachable()]
+    );
+  }
+
+  #[test]
+  fn field_covered_by_earlier_arm_is_unreachable() {
+    let source = "case x { Some(_) -> 1, Some(false) -> 2, _ -> 3, }";
+    assert_eq!(warnings(source), vec![unreachable()]);
+  }
+
+  #[test]
+  fn array_covered_by_spread_is_unreachable() {
+    let source = "case x { [a, ..rest] -> 1, [b] -> 2, _ -> 3, }";
+    assert_eq!(warnings(source), vec![unreachable()]);
+  }
+
+  #[test]
+  fn named_fields_in_any_order_are_the_same() {
+    let source =
+      "case x { Point(x: a, y: b) -> 1, Point(y: c, x: d) -> 2, _ -> 3, }";
+    assert_eq!(warnings(source), vec![unreachable()]);
+  }
+
+  #[test]
+  fn named_fields_with_fewer_names_are_reachable() {
+    let source = "case x { Point(x: a, y: b) -> 1, Point(x: c) -> 2, _ -> 3, }";
+    assert!(warnings(source).is_empty());
+  }
+
+  #[test]
+  fn guarded_arm_covers_nothing() {
+    let source = "case x { Some(v) if v -> 1, Some(w) -> 2, _ -> 3, }";
+    assert!(warnings(source).is_empty());
+  }
+}
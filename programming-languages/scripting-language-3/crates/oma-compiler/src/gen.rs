@@ -5,42 +5,179 @@ use intern::Intern;
 use crate::{
   ast::*,
   chunk::{Chunk, Function, Op, Opcode, Operand},
+  debug::Info,
   error::{CompileError, VerifyError},
   source::Span,
 };
 
 pub struct Generator {
   registry: Vec<(&'static str, usize)>,
+  // Natives the module may not use, with the capability each needs.
+  denied: Vec<(String, String)>,
+  strict: bool,
   context: Context,
+  symbols: Symbols,
+  // Definitions of each open scope, so their visible span can be set once
+  // the scope ends.
+  scopes: Vec<Vec<usize>>,
+  // Errors that generation carried on after, so all of them are reported.
+  errors: Vec<CompileError>,
+  // Offset of the start of each line of the module, for debug info.
+  lines: Vec<usize>,
+  // Name for the lambda about to be generated, from the binding it's assigned
+  // to.
+  name: Option<&'static String>,
+}
+
+// Bindings and identifier uses recorded during generation, for tooling.
+#[derive(Debug, Default)]
+pub struct Symbols {
+  pub definitions: Vec<Definition>,
+  pub references: Vec<Reference>,
+}
+
+#[derive(Debug)]
+pub struct Definition {
+  pub ident: Ident,
+  pub kind: DefinitionKind,
+  // Span of the construct the binding is visible in.
+  pub scope: Span,
+  // Binding with the same name that was visible where this one was made.
+  pub shadows: Option<Ident>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DefinitionKind {
+  Let,
+  Parameter,
+  CaseArm,
+}
+
+#[derive(Debug)]
+pub struct Reference {
+  pub ident: Ident,
+  pub kind: ReferenceKind,
+  // Binding the identifier resolves to, or `None` for registry natives.
+  pub definition: Option<Ident>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReferenceKind {
+  Read,
+  Write,
+  // A read from within a lambda the binding was captured by.
+  Capture,
+  // The binding site in another alternative of a case arm.
+  Alternative,
 }
 
 impl Generator {
-  pub fn new(registry: Vec<(&'static str, usize)>) -> Generator {
+  pub fn new(registry: Vec<(&'static str, usize)>, strict: bool) -> Generator {
     Generator {
       registry,
+      denied: Vec::new(),
+      strict,
       context: Context::new(),
+      symbols: Symbols::default(),
+      scopes: vec![Vec::new()],
+      errors: Vec::new(),
+      lines: Vec::new(),
+      name: None,
     }
   }
 
-  pub fn generate(self, module: Module) -> Result<Function, CompileError> {
+  // Makes referring to any of the natives an error, rather than resolving to
+  // the native. They are given by name, with the capability each needs.
+  pub fn deny(&mut self, denied: Vec<(String, String)>) {
+    self.denied = denied;
+  }
+
+  pub fn generate(mut self, module: Module) -> Result<Function, CompileError> {
     self.module(module)
   }
 
-  fn module(mut self, module: Module) -> Result<Function, CompileError> {
+  // Generates the module only to record its symbols. Symbols recorded before
+  // an error are kept.
+  pub fn symbols(
+    mut self,
+    module: Module,
+  ) -> (Symbols, Result<(), CompileError>) {
+    let span = module.span();
+    let result = self.module(module).map(|_| ());
+
+    // An error aborts generation inside whatever scopes were open, so let them
+    // run to the end of the module.
+    while !self.scopes.is_empty() {
+      self.close_scope(&span);
+    }
+
+    (self.symbols, result)
+  }
+
+  fn module(&mut self, module: Module) -> Result<Function, CompileError> {
     let locals = count_expr_locals(&module.body) as u64;
+    let span = module.span();
+    self.lines = line_starts(span.source().content());
 
-    self.expr(module.body)?;
+    if let Err(error) = self.expr(module.body) {
+      self.errors.push(error);
+    }
     self.emit_ret();
 
+    self.close_scope(&span);
+
+    if let Some(error) = CompileError::from_errors(mem::take(&mut self.errors))
+    {
+      return Err(error);
+    }
+
+    let context = mem::replace(&mut self.context, Context::new());
     Ok(Function {
       arity: 0,
-      chunk: self.context.chunk,
+      chunk: self.chunk(context, "<module>".to_string(), &span),
       locals,
       upvalues: Vec::new(),
     })
   }
 
+  // Finishes the chunk of a context, attaching its debug info.
+  fn chunk(&self, context: Context, name: String, span: &Span) -> Chunk {
+    let spans = context
+      .spans
+      .iter()
+      .map(|(offset, span)| {
+        let (line, column) = self.position(span);
+        (*offset, line, column)
+      })
+      .collect();
+
+    let mut chunk = context.chunk;
+    chunk.info = Some(Info {
+      name,
+      path: span.path().display().to_string(),
+      spans,
+    });
+    chunk
+  }
+
+  // Returns the line and column of the start of the span, numbered like in
+  // `Span`.
+  fn position(&self, span: &Span) -> (usize, usize) {
+    let offset = span.start();
+    let line = self.lines.partition_point(|start| *start <= offset);
+    let line_start = self.lines[line.max(1) - 1];
+    let column = span.source().content()[line_start..offset].chars().count();
+    (line, column)
+  }
+
   fn expr(&mut self, expr: Expr) -> Result<(), CompileError> {
+    let previous = self.context.span.replace(expr.span());
+    let result = self.expr_kind(expr);
+    self.context.span = previous;
+    result
+  }
+
+  fn expr_kind(&mut self, expr: Expr) -> Result<(), CompileError> {
     match expr {
       Expr::Lit(lit) => self.lit(lit),
       Expr::Ident(ident) => self.ident(ident),
@@ -58,7 +195,11 @@ impl Generator {
       Expr::If(if_expr) => self.if_expr(if_expr),
       Expr::Case(case_expr) => self.case_expr(case_expr),
       Expr::While(while_expr) => self.while_expr(while_expr),
-      _ => unimplemented!(),
+      Expr::For(for_expr) => {
+        self.unsupported(for_expr.span(), "`for` loops");
+        self.emit_nul();
+        Ok(())
+      }
     }
   }
 
@@ -77,7 +218,12 @@ impl Generator {
           self.context.emit(Op::new(Opcode::Set));
           self.context.emit(Op::new(Opcode::Pop));
         }
-        _ => unimplemented!(),
+        MapExprPair::Spread(expr) => {
+          self.unsupported(expr.span(), "spreads in maps");
+        }
+        MapExprPair::Expr(key, _) => {
+          self.unsupported(key.span(), "computed map keys");
+        }
       }
     }
 
@@ -95,7 +241,9 @@ impl Generator {
           self.context.emit(Op::new(Opcode::Apn));
           self.context.emit(Op::new(Opcode::Pop));
         }
-        _ => unimplemented!(),
+        ArrayExprItem::Spread(expr) => {
+          self.unsupported(expr.span(), "spreads in arrays");
+        }
       }
     }
 
@@ -109,33 +257,50 @@ impl Generator {
     let arity = lambda_expr.parameters.len() as u64;
 
     let locals = count_expr_locals(&lambda_expr.body);
+    let span = lambda_expr.span();
+    let name = match self.name.take() {
+      Some(name) => name.to_string(),
+      None => format!("<anonymous@{}>", self.position(&span).0),
+    };
 
     let context = mem::replace(&mut self.context, Context::new());
     self.context = Context::with_parent(context);
+    self.scopes.push(Vec::new());
 
     for parameter in lambda_expr.parameters.iter() {
       match parameter {
-        LambdaExprParameter::Pat(Pat::Ident(ident)) => {
-          self.context.add_local(&ident);
+        LambdaExprParameter::Pat(Pat::Ident(ident), _) => {
+          self.define(ident, DefinitionKind::Parameter);
+        }
+        LambdaExprParameter::Pat(pat, _) => {
+          self.unsupported(pat.span(), "patterns as parameters");
+          for ident in pat.idents() {
+            self.define(&ident, DefinitionKind::Parameter);
+          }
+        }
+        LambdaExprParameter::Spread(ident) => {
+          self.unsupported(ident.span(), "rest parameters");
+          self.define(ident, DefinitionKind::Parameter);
         }
-        _ => unimplemented!(),
       }
     }
 
     self.expr(*lambda_expr.body)?;
     self.emit_ret();
 
-    let context = mem::replace(&mut self.context, Context::new());
-    self.context = *context.parent.unwrap();
+    self.close_scope(&span);
 
-    let chunk = context.chunk;
+    let mut context = mem::replace(&mut self.context, Context::new());
+    self.context = *context.parent.take().unwrap();
 
     let upvalues = context
       .upvalues
-      .into_iter()
+      .iter()
       .map(|upvalue| (upvalue.index as u64, upvalue.is_local))
       .collect();
 
+    let chunk = self.chunk(context, name, &span);
+
     let function = Function {
       arity,
       chunk,
@@ -149,11 +314,36 @@ impl Generator {
   }
 
   fn tag_expr(&mut self, tag_expr: TagExpr) -> Result<(), CompileError> {
-    self.expr(*tag_expr.expr)?;
+    match tag_expr.fields {
+      TagFields::Positional(fields) => {
+        let count = fields.len();
+        for field in fields {
+          self.expr(field)?;
+        }
 
-    self.emit_tag(tag_expr.tag.content);
+        self.emit_tag(tag_expr.tag.content);
 
-    self.context.emit(Op::new(Opcode::Tag));
+        self
+          .context
+          .emit(Op::with_operand(Opcode::Tag, Operand::Usize(count)));
+      }
+      TagFields::Named(fields) => {
+        let count = fields.len();
+        for (name, field) in fields {
+          self.context.emit(Op::with_operand(
+            Opcode::Str,
+            Operand::String(name.content.to_string()),
+          ));
+          self.expr(field)?;
+        }
+
+        self.emit_tag(tag_expr.tag.content);
+
+        self
+          .context
+          .emit(Op::with_operand(Opcode::Tgn, Operand::Usize(count)));
+      }
+    }
 
     Ok(())
   }
@@ -186,6 +376,7 @@ impl Generator {
     &mut self,
     binary_expr: BinaryExpr,
   ) -> Result<(), CompileError> {
+    let span = binary_expr.span();
     self.expr(*binary_expr.left)?;
     self.expr(*binary_expr.right)?;
 
@@ -194,8 +385,17 @@ impl Generator {
       BinaryOp::Subtract => Opcode::Sub,
       BinaryOp::Multiply => Opcode::Mul,
       BinaryOp::Divide => Opcode::Div,
-      BinaryOp::And => unimplemented!(),
-      BinaryOp::Or => unimplemented!(),
+      BinaryOp::Remainder => Opcode::Rem,
+      BinaryOp::Power => Opcode::Pow,
+      BinaryOp::BitAnd => Opcode::Ban,
+      BinaryOp::BitOr => Opcode::Bor,
+      BinaryOp::BitXor => Opcode::Bxr,
+      BinaryOp::ShiftLeft => Opcode::Shl,
+      BinaryOp::ShiftRight => Opcode::Shr,
+      BinaryOp::And | BinaryOp::Or => {
+        self.unsupported(span, "`&&` and `||`");
+        Opcode::Pop
+      }
       BinaryOp::Equal => Opcode::Eql,
       BinaryOp::NotEqual => Opcode::Neq,
       BinaryOp::Greater => Opcode::Gtn,
@@ -221,6 +421,11 @@ impl Generator {
   }
 
   fn bind_expr(&mut self, bind_expr: BindExpr) -> Result<(), CompileError> {
+    if let (Pat::Ident(ident), Expr::Lambda(_)) =
+      (&bind_expr.bindee, &*bind_expr.value)
+    {
+      self.name = Some(ident.content);
+    }
     self.expr(*bind_expr.value)?;
 
     self.bind_expr_pat(bind_expr.bindee)?;
@@ -231,18 +436,36 @@ impl Generator {
   fn bind_expr_pat(&mut self, pat: Pat) -> Result<(), CompileError> {
     match pat {
       Pat::Ident(ident) => {
-        let local = self.context.add_local(&ident);
+        let local = self.define(&ident, DefinitionKind::Let);
         self
           .context
           .emit(Op::with_operand(Opcode::Sav, Operand::Usize(local)));
       }
 
-      Pat::Tag(tag_pat) => {
-        self.context.emit(Op::new(Opcode::Dup));
-        self.context.emit(Op::new(Opcode::Utg));
-        self.bind_expr_pat(*tag_pat.pat)?;
-        self.context.emit(Op::new(Opcode::Pop));
-      }
+      Pat::Tag(tag_pat) => match tag_pat.fields {
+        TagFields::Positional(fields) => {
+          for (index, pat) in fields.into_iter().enumerate() {
+            self.context.emit(Op::new(Opcode::Dup));
+            self
+              .context
+              .emit(Op::with_operand(Opcode::Utg, Operand::Usize(index)));
+            self.bind_expr_pat(pat)?;
+            self.context.emit(Op::new(Opcode::Pop));
+          }
+        }
+        TagFields::Named(fields) => {
+          for (name, pat) in fields {
+            self.context.emit(Op::new(Opcode::Dup));
+            self.context.emit(Op::with_operand(
+              Opcode::Str,
+              Operand::String(name.content.to_string()),
+            ));
+            self.context.emit(Op::new(Opcode::Get));
+            self.bind_expr_pat(pat)?;
+            self.context.emit(Op::new(Opcode::Pop));
+          }
+        }
+      },
 
       Pat::Map(map_pat) => {
         for pair in map_pat.pairs {
@@ -257,11 +480,19 @@ impl Generator {
               self.bind_expr_pat(pat)?;
               self.context.emit(Op::new(Opcode::Pop));
             }
-            _ => unimplemented!(),
+            MapPatPair::Spread(ident) => {
+              self.unsupported(ident.span(), "spreads in map patterns");
+              self.define(&ident, DefinitionKind::Let);
+            }
           }
         }
       }
-      _ => unimplemented!(),
+      pat => {
+        self.unsupported(pat.span(), "literal and array patterns in bindings");
+        for ident in pat.idents() {
+          self.define(&ident, DefinitionKind::Let);
+        }
+      }
     }
 
     Ok(())
@@ -276,6 +507,17 @@ impl Generator {
         self.expr(*assign_expr.value)?;
         self.assign_expr_pat(pat)?;
       }
+      AssignExprAssignee::Access(AccessExpr {
+        receiver,
+        field: AccessExprField::Slice(start, end),
+      }) => {
+        // The range is replaced with the items of the value.
+        self.expr(*receiver)?;
+        self.slice_bounds(start, end)?;
+        self.context.emit(Op::new(Opcode::Rmv));
+        self.expr(*assign_expr.value)?;
+        self.context.emit(Op::new(Opcode::Ins));
+      }
       AssignExprAssignee::Access(access_expr) => {
         self.expr(*access_expr.receiver)?;
 
@@ -289,6 +531,9 @@ impl Generator {
               Operand::String(ident.content.to_string()),
             ));
           }
+          AccessExprField::Slice(..) => {
+            unreachable!("slices are matched above")
+          }
         }
 
         self.context.emit(Op::new(Opcode::Set));
@@ -300,21 +545,42 @@ impl Generator {
 
   fn assign_expr_pat(&mut self, pat: Pat) -> Result<(), CompileError> {
     match pat {
-      Pat::Ident(ident) => {
-        let local = self.context.local(&ident).ok_or(CompileError::Verify(
+      Pat::Ident(ident) => match self.context.local(&ident) {
+        Some(local) => {
+          self.reference(&ident, ReferenceKind::Write);
+          self
+            .context
+            .emit(Op::with_operand(Opcode::Sav, Operand::Usize(local)));
+        }
+        None => self.errors.push(CompileError::Verify(
           VerifyError::unresolved_identifier(ident.span()),
-        ))?;
-        self
-          .context
-          .emit(Op::with_operand(Opcode::Sav, Operand::Usize(local)));
-      }
-
-      Pat::Tag(tag_pat) => {
-        self.context.emit(Op::new(Opcode::Dup));
-        self.context.emit(Op::new(Opcode::Utg));
-        self.assign_expr_pat(*tag_pat.pat)?;
-        self.context.emit(Op::new(Opcode::Pop));
-      }
+        )),
+      },
+
+      Pat::Tag(tag_pat) => match tag_pat.fields {
+        TagFields::Positional(fields) => {
+          for (index, pat) in fields.into_iter().enumerate() {
+            self.context.emit(Op::new(Opcode::Dup));
+            self
+              .context
+              .emit(Op::with_operand(Opcode::Utg, Operand::Usize(index)));
+            self.assign_expr_pat(pat)?;
+            self.context.emit(Op::new(Opcode::Pop));
+          }
+        }
+        TagFields::Named(fields) => {
+          for (name, pat) in fields {
+            self.context.emit(Op::new(Opcode::Dup));
+            self.context.emit(Op::with_operand(
+              Opcode::Str,
+              Operand::String(name.content.to_string()),
+            ));
+            self.context.emit(Op::new(Opcode::Get));
+            self.assign_expr_pat(pat)?;
+            self.context.emit(Op::new(Opcode::Pop));
+          }
+        }
+      },
 
       Pat::Map(map_pat) => {
         for pair in map_pat.pairs {
@@ -329,30 +595,56 @@ impl Generator {
               self.assign_expr_pat(pat)?;
               self.context.emit(Op::new(Opcode::Pop));
             }
-            _ => unimplemented!(),
+            MapPatPair::Spread(ident) => {
+              self.unsupported(ident.span(), "spreads in map patterns");
+            }
           }
         }
       }
-      _ => unimplemented!(),
+      pat => {
+        let span = pat.span();
+        self.unsupported(span, "literal and array patterns in assignments");
+      }
     }
 
     Ok(())
   }
 
+  // `value.name(...)` is a method call, which passes `value` along unless
+  // `name` is a field of a map.
   fn call_expr(&mut self, call_expr: CallExpr) -> Result<(), CompileError> {
-    self.expr(*call_expr.receiver)?;
+    let opcode = match *call_expr.receiver {
+      Expr::Access(AccessExpr {
+        receiver,
+        field: AccessExprField::Ident(ident),
+      }) => {
+        self.expr(*receiver)?;
+        self.context.emit(Op::with_operand(
+          Opcode::Str,
+          Operand::String(ident.content.to_string()),
+        ));
+        Opcode::Ivk
+      }
+      receiver => {
+        self.expr(receiver)?;
+        Opcode::Cal
+      }
+    };
 
     let arity = call_expr.arguments.len();
     for argument in call_expr.arguments {
       match argument {
         CallExprArgument::Expr(expr) => self.expr(expr)?,
-        CallExprArgument::Spread(_) => unimplemented!(),
+        CallExprArgument::Spread(expr) => {
+          self.unsupported(expr.span(), "spreads in calls");
+          self.expr(expr)?;
+        }
       }
     }
 
     self
       .context
-      .emit(Op::with_operand(Opcode::Cal, Operand::Usize(arity)));
+      .emit(Op::with_operand(opcode, Operand::Usize(arity)));
 
     Ok(())
   }
@@ -371,6 +663,11 @@ impl Generator {
           Operand::String(ident.content.to_string()),
         ));
       }
+      AccessExprField::Slice(start, end) => {
+        self.slice_bounds(start, end)?;
+        self.context.emit(Op::new(Opcode::Slc));
+        return Ok(());
+      }
     }
 
     self.context.emit(Op::new(Opcode::Get));
@@ -378,6 +675,23 @@ impl Generator {
     Ok(())
   }
 
+  // Pushes the bounds of a range, with null for a bound that's left out.
+  fn slice_bounds(
+    &mut self,
+    start: Option<Box<Expr>>,
+    end: Option<Box<Expr>>,
+  ) -> Result<(), CompileError> {
+    for bound in [start, end] {
+      match bound {
+        Some(bound) => self.expr(*bound)?,
+        None => {
+          self.emit_nul();
+        }
+      }
+    }
+    Ok(())
+  }
+
   fn if_expr(&mut self, if_expr: IfExpr) -> Result<(), CompileError> {
     self.expr(*if_expr.condition)?;
 
@@ -412,7 +726,7 @@ impl Generator {
     let mut jump_offsets = Vec::new();
     let mut jump_if_offsets = Vec::new();
 
-    for (pat, expr) in case_expr.arms {
+    for arm in case_expr.arms {
       for jump_if_offset in &jump_if_offsets {
         self
           .context
@@ -420,11 +734,52 @@ impl Generator {
       }
       jump_if_offsets.clear();
 
-      self.context.enter_scope();
+      let span = Span::combine(&arm.pats[0].span(), &arm.expr.span());
+      self.enter_scope();
 
-      self.case_expr_pat(&mut jump_if_offsets, Vec::new(), pat)?;
+      // Every alternative binds the same identifiers, so they share locals.
+      for ident in arm.pats[0].idents() {
+        if self.context.scope_local(&ident).is_none() {
+          self.define(&ident, DefinitionKind::CaseArm);
+        }
+      }
 
-      self.expr(expr)?;
+      let alternatives = arm.pats.len();
+      let mut matched_offsets = Vec::new();
+      for (index, pat) in arm.pats.into_iter().enumerate() {
+        if index < alternatives - 1 {
+          let mut alternative_offsets = Vec::new();
+          self.case_expr_pat(&mut alternative_offsets, Vec::new(), pat)?;
+          matched_offsets.push(
+            self
+              .context
+              .emit(Op::with_operand(Opcode::Jmp, Operand::Usize(usize::MAX))),
+          );
+          for offset in alternative_offsets {
+            self
+              .context
+              .patch(offset, Operand::Usize(self.context.len()));
+          }
+        } else {
+          self.case_expr_pat(&mut jump_if_offsets, Vec::new(), pat)?;
+        }
+      }
+      for offset in matched_offsets {
+        self
+          .context
+          .patch(offset, Operand::Usize(self.context.len()));
+      }
+
+      if let Some(guard) = arm.guard {
+        self.expr(guard)?;
+        jump_if_offsets.push(
+          self
+            .context
+            .emit(Op::with_operand(Opcode::Jif, Operand::Usize(usize::MAX))),
+        );
+      }
+
+      self.expr(arm.expr)?;
 
       jump_offsets.push(
         self
@@ -432,7 +787,7 @@ impl Generator {
           .emit(Op::with_operand(Opcode::Jmp, Operand::Usize(usize::MAX))),
       );
 
-      self.context.exit_scope();
+      self.exit_scope(&span);
     }
 
     for jump_if_offset in &jump_if_offsets {
@@ -442,7 +797,12 @@ impl Generator {
     }
     jump_if_offsets.clear();
 
-    self.emit_nul();
+    if self.strict {
+      self.context.emit(Op::new(Opcode::Dup));
+      self.context.emit(Op::new(Opcode::Nma));
+    } else {
+      self.emit_nul();
+    }
 
     for offset in jump_offsets {
       self
@@ -459,12 +819,18 @@ impl Generator {
   fn case_expr_pat(
     &mut self,
     jump_if_offsets: &mut Vec<usize>,
-    path: Vec<String>,
+    path: Vec<PathComponent>,
     pat: Pat,
   ) -> Result<(), CompileError> {
     match pat {
       Pat::Ident(ident) => {
-        let local = self.context.add_local(&ident);
+        let local = self
+          .context
+          .scope_local(&ident)
+          .expect("case arm locals are added before matching");
+        if self.context.definition(&ident).as_ref() != Some(&ident) {
+          self.reference(&ident, ReferenceKind::Alternative);
+        }
         self.context.emit(Op::new(Opcode::Dup));
         self.case_expr_pat_subject(path.clone());
         self
@@ -476,7 +842,14 @@ impl Generator {
       Pat::Tag(tag_pat) => {
         self.context.emit(Op::new(Opcode::Dup));
         self.case_expr_pat_subject(path.clone());
-        self.context.emit(Op::new(Opcode::Gtg));
+        // Positional fields must all be matched, but named fields can be
+        // matched by name, ignoring the rest.
+        match &tag_pat.fields {
+          TagFields::Positional(fields) => self
+            .context
+            .emit(Op::with_operand(Opcode::Gtg, Operand::Usize(fields.len()))),
+          TagFields::Named(_) => self.context.emit(Op::new(Opcode::Gnt)),
+        };
         self.context.emit(Op::with_operand(
           Opcode::Str,
           Operand::String(tag_pat.tag.content.to_string()),
@@ -488,9 +861,35 @@ impl Generator {
             .emit(Op::with_operand(Opcode::Jif, Operand::Usize(usize::MAX))),
         );
 
-        let mut path = path.clone();
-        path.push("__UTG".to_string());
-        self.case_expr_pat(jump_if_offsets, path, *tag_pat.pat)?;
+        match tag_pat.fields {
+          TagFields::Positional(fields) => {
+            for (index, pat) in fields.into_iter().enumerate() {
+              let mut path = path.clone();
+              path.push(PathComponent::TagField(index));
+              self.case_expr_pat(jump_if_offsets, path, pat)?;
+            }
+          }
+          TagFields::Named(fields) => {
+            // Unlike map keys, fields that are there but null still match.
+            for (name, pat) in fields {
+              self.context.emit(Op::new(Opcode::Dup));
+              self.case_expr_pat_subject(path.clone());
+              self.context.emit(Op::with_operand(
+                Opcode::Str,
+                Operand::String(name.content.to_string()),
+              ));
+              self.context.emit(Op::new(Opcode::Has));
+              jump_if_offsets.push(self.context.emit(Op::with_operand(
+                Opcode::Jif,
+                Operand::Usize(usize::MAX),
+              )));
+
+              let mut path = path.clone();
+              path.push(PathComponent::Key(name.content.to_string()));
+              self.case_expr_pat(jump_if_offsets, path, pat)?;
+            }
+          }
+        }
       }
 
       Pat::Map(map_pat) => {
@@ -512,10 +911,66 @@ impl Generator {
               )));
 
               let mut path = path.clone();
-              path.push(ident.content.to_string());
+              path.push(PathComponent::Key(ident.content.to_string()));
               self.case_expr_pat(jump_if_offsets, path, pat)?;
             }
-            _ => unimplemented!(),
+            MapPatPair::Spread(ident) => {
+              self.unsupported(ident.span(), "spreads in map patterns");
+            }
+          }
+        }
+      }
+
+      Pat::Array(array_pat) => {
+        let spread = array_pat
+          .items
+          .iter()
+          .position(|item| matches!(item, ArrayPatItem::Spread(_)));
+        let len = array_pat.items.len() - spread.map_or(0, |_| 1);
+
+        self.context.emit(Op::new(Opcode::Dup));
+        self.case_expr_pat_subject(path.clone());
+        self.context.emit(Op::new(Opcode::Len));
+        if spread.is_some() {
+          self.emit_nul();
+          self.context.emit(Op::new(Opcode::Eql));
+          jump_if_offsets.push(
+            self
+              .context
+              .emit(Op::with_operand(Opcode::Jit, Operand::Usize(usize::MAX))),
+          );
+
+          self.context.emit(Op::new(Opcode::Dup));
+          self.case_expr_pat_subject(path.clone());
+          self.context.emit(Op::new(Opcode::Len));
+          self.emit_int(len);
+          self.context.emit(Op::new(Opcode::Gte));
+        } else {
+          self.emit_int(len);
+          self.context.emit(Op::new(Opcode::Eql));
+        }
+        jump_if_offsets.push(
+          self
+            .context
+            .emit(Op::with_operand(Opcode::Jif, Operand::Usize(usize::MAX))),
+        );
+
+        let prefix = spread.unwrap_or(len);
+        for (index, item) in array_pat.items.into_iter().enumerate() {
+          let mut path = path.clone();
+          match item {
+            ArrayPatItem::Pat(pat) => {
+              path.push(if index < prefix {
+                PathComponent::Index(index)
+              } else {
+                PathComponent::IndexFromEnd(len + 1 - index)
+              });
+              self.case_expr_pat(jump_if_offsets, path, pat)?;
+            }
+            ArrayPatItem::Spread(ident) => {
+              path.push(PathComponent::Rest(prefix, len - prefix));
+              self.case_expr_pat(jump_if_offsets, path, Pat::Ident(ident))?;
+            }
           }
         }
       }
@@ -531,22 +986,45 @@ impl Generator {
             .emit(Op::with_operand(Opcode::Jif, Operand::Usize(usize::MAX))),
         );
       }
-
-      _ => unimplemented!(),
     }
 
     Ok(())
   }
 
-  fn case_expr_pat_subject(&mut self, path: Vec<String>) {
+  fn case_expr_pat_subject(&mut self, path: Vec<PathComponent>) {
     for component in path {
-      if component == "__UTG" {
-        self.context.emit(Op::new(Opcode::Utg));
-      } else {
-        self
-          .context
-          .emit(Op::with_operand(Opcode::Str, Operand::String(component)));
-        self.context.emit(Op::new(Opcode::Get));
+      match component {
+        PathComponent::Key(key) => {
+          self
+            .context
+            .emit(Op::with_operand(Opcode::Str, Operand::String(key)));
+          self.context.emit(Op::new(Opcode::Get));
+        }
+        PathComponent::TagField(index) => {
+          self
+            .context
+            .emit(Op::with_operand(Opcode::Utg, Operand::Usize(index)));
+        }
+        PathComponent::Index(index) => {
+          self.emit_int(index);
+          self.context.emit(Op::new(Opcode::Get));
+        }
+        PathComponent::IndexFromEnd(offset) => {
+          self.context.emit(Op::new(Opcode::Dup));
+          self.context.emit(Op::new(Opcode::Len));
+          self.emit_int(offset);
+          self.context.emit(Op::new(Opcode::Sub));
+          self.context.emit(Op::new(Opcode::Get));
+        }
+        PathComponent::Rest(prefix, suffix) => {
+          self.context.emit(Op::new(Opcode::Dup));
+          self.context.emit(Op::new(Opcode::Len));
+          self.emit_int(suffix);
+          self.context.emit(Op::new(Opcode::Sub));
+          self.emit_int(prefix);
+          self.context.emit(Op::new(Opcode::Swp));
+          self.context.emit(Op::new(Opcode::Slc));
+        }
       }
     }
   }
@@ -577,7 +1055,16 @@ impl Generator {
 
   fn lit(&mut self, lit: Lit) -> Result<(), CompileError> {
     match lit {
-      Lit::Number(NumberLit { number: float, .. }) => self
+      Lit::Number(NumberLit {
+        number: Number::Int(int),
+        ..
+      }) => self
+        .context
+        .emit(Op::with_operand(Opcode::Int, Operand::I64(int))),
+      Lit::Number(NumberLit {
+        number: Number::Float(float),
+        ..
+      }) => self
         .context
         .emit(Op::with_operand(Opcode::Flt, Operand::F64(float))),
       Lit::Bool(BoolLit { bool: true, .. }) => {
@@ -596,25 +1083,89 @@ impl Generator {
 
   fn ident(&mut self, ident: Ident) -> Result<(), CompileError> {
     if let Some(local) = self.context.local(&ident) {
+      self.reference(&ident, ReferenceKind::Read);
       self.emit_lod(local);
     } else if let Some(upvalue) = self.context.upvalue(&ident) {
+      self.reference(&ident, ReferenceKind::Capture);
       self.emit_lou(upvalue);
+    } else if let Some((name, capability)) =
+      self.denied.iter().find(|(name, _)| name == ident.content)
+    {
+      self
+        .errors
+        .push(CompileError::Verify(VerifyError::not_granted(
+          ident.span,
+          name.clone(),
+          capability.clone(),
+        )));
+      self.emit_nul();
     } else if let Some((_, id)) = self
       .registry
       .iter()
       .find(|(name, _)| name == ident.content)
       .copied()
     {
+      self.reference(&ident, ReferenceKind::Read);
       self.emit_nal(id);
     } else {
-      return Err(CompileError::Verify(VerifyError::unresolved_identifier(
-        ident.span,
-      )));
+      // The module won't be emitted, so any value will do to carry on.
+      self.errors.push(CompileError::Verify(
+        VerifyError::unresolved_identifier(ident.span),
+      ));
+      self.emit_nul();
     }
 
     Ok(())
   }
 
+  fn define(&mut self, ident: &Ident, kind: DefinitionKind) -> usize {
+    self.symbols.definitions.push(Definition {
+      ident: ident.clone(),
+      kind,
+      scope: ident.span(),
+      shadows: self.context.definition(ident),
+    });
+    self
+      .scopes
+      .last_mut()
+      .unwrap()
+      .push(self.symbols.definitions.len() - 1);
+    self.context.add_local(ident)
+  }
+
+  // Reports code that can't be compiled yet, and carries on so that the rest
+  // of the module is still checked.
+  fn unsupported(&mut self, span: Span, what: &'static str) {
+    self
+      .errors
+      .push(CompileError::Verify(VerifyError::unsupported(span, what)));
+  }
+
+  fn reference(&mut self, ident: &Ident, kind: ReferenceKind) {
+    self.symbols.references.push(Reference {
+      ident: ident.clone(),
+      kind,
+      definition: self.context.definition(ident),
+    });
+  }
+
+  fn enter_scope(&mut self) {
+    self.context.enter_scope();
+    self.scopes.push(Vec::new());
+  }
+
+  fn exit_scope(&mut self, span: &Span) {
+    self.context.exit_scope();
+    self.close_scope(span);
+  }
+
+  fn close_scope(&mut self, span: &Span) {
+    for index in self.scopes.pop().unwrap() {
+      let definition = &mut self.symbols.definitions[index];
+      definition.scope = Span::combine(&definition.ident.span(), span);
+    }
+  }
+
   fn emit_tag(&mut self, name: &'static String) -> usize {
     self.context.emit(Op::with_operand(
       Opcode::Str,
@@ -650,6 +1201,12 @@ impl Generator {
     self.context.emit(Op::new(Opcode::Ret))
   }
 
+  fn emit_int(&mut self, int: usize) -> usize {
+    self
+      .context
+      .emit(Op::with_operand(Opcode::Int, Operand::I64(int as i64)))
+  }
+
   fn emit_nul(&mut self) -> usize {
     self.context.emit(Op::new(Opcode::Nul))
   }
@@ -661,6 +1218,10 @@ struct Context {
   local_depth: usize,
   upvalues: Vec<Upvalue>,
   chunk: Chunk,
+  // Span of the expression being generated, and the spans ops were emitted
+  // for by the offset of the first op.
+  span: Option<Span>,
+  spans: Vec<(usize, Span)>,
 }
 
 impl Context {
@@ -677,6 +1238,8 @@ impl Context {
       local_depth: 0,
       upvalues: Vec::new(),
       chunk: Chunk::new(),
+      span: None,
+      spans: Vec::new(),
     }
   }
 
@@ -693,12 +1256,22 @@ impl Context {
       local_depth: 0,
       upvalues: Vec::new(),
       chunk: Chunk::new(),
+      span: None,
+      spans: Vec::new(),
     }
   }
 
   fn emit(&mut self, op: Op) -> usize {
-    dbg!(&op);
-    self.chunk.emit(op)
+    let offset = self.chunk.emit(op);
+    if let Some(span) = &self.span {
+      let is_new = self.spans.last().is_none_or(|(_, last)| {
+        (last.start(), last.end()) != (span.start(), span.end())
+      });
+      if is_new {
+        self.spans.push((offset, span.clone()));
+      }
+    }
+    offset
   }
 
   fn patch(&mut self, offset: usize, operand: Operand) {
@@ -720,6 +1293,25 @@ impl Context {
     None
   }
 
+  // Returns the binding an identifier resolves to, searching enclosing
+  // lambdas too.
+  fn definition(&self, ident: &Ident) -> Option<Ident> {
+    self
+      .locals
+      .iter()
+      .rev()
+      .find(|(local_ident, _)| local_ident.content == ident.content)
+      .map(|(local_ident, _)| local_ident.clone())
+      .or_else(|| self.parent.as_ref()?.definition(ident))
+  }
+
+  // Like `local`, but only searches the innermost scope.
+  fn scope_local(&self, ident: &Ident) -> Option<usize> {
+    self.locals.iter().rposition(|(local_ident, depth)| {
+      *depth == self.local_depth && local_ident.content == ident.content
+    })
+  }
+
   fn add_upvalue(&mut self, upvalue: Upvalue) -> usize {
     if let Some(index) = self.upvalues.iter().position(|u| u == &upvalue) {
       index
@@ -782,3 +1374,23 @@ struct Upvalue {
   index: usize,
   is_local: bool,
 }
+
+// A step from a case subject to the part of it matched by a nested pattern.
+#[derive(Clone)]
+enum PathComponent {
+  Key(String),
+  TagField(usize),
+  Index(usize),
+  // Index of an array item counted back from the end, starting at 1.
+  IndexFromEnd(usize),
+  // The items of an array between the given number of leading and trailing
+  // items.
+  Rest(usize, usize),
+}
+
+// Returns the offset of the start of each line.
+fn line_starts(content: &str) -> Vec<usize> {
+  let mut starts = vec![0];
+  starts.extend(content.match_indices('\n').map(|(offset, _)| offset + 1));
+  starts
+}
@@ -1,5 +1,7 @@
 use std::fmt;
 
+use unicode_xid::UnicodeXID;
+
 use crate::{
   error::{CompileError, ParseError},
   source::{Source, Span},
@@ -33,10 +35,10 @@ impl Lexer {
       .peek()
       .expect("`self.start` checked to be within bounds")
     {
-      b'"' => return self.string(),
-      byte if is_digit(byte) => return self.number(),
-      b'_' => return self.ident(),
-      byte if is_alphabetic(byte) => return self.ident(),
+      '"' => return self.string(),
+      char if is_digit(char) => return self.number(),
+      '_' => return self.ident(),
+      char if is_xid_start(char) => return self.ident(),
       _ => {}
     }
 
@@ -46,35 +48,42 @@ impl Lexer {
         .expect("`self.start` checked to be within bounds"),
       self.peek(),
     ) {
-      (b'/', Some(b'/')) => self.comment()?,
-      (b'+', _) => self.build(Token::Plus),
-      (b'-', Some(b'>')) => self.advance_and_build(Token::Arrow),
-      (b'-', _) => self.build(Token::Dash),
-      (b'*', _) => self.build(Token::Star),
-      (b'/', _) => self.build(Token::Slash),
-      (b'_', _) => self.build(Token::Underscore),
-      (b'.', Some(b'.')) => self.advance_and_build(Token::DotDot),
-      (b'.', _) => self.build(Token::Dot),
-      (b',', _) => self.build(Token::Comma),
-      (b':', _) => self.build(Token::Colon),
-      (b';', _) => self.build(Token::Semicolon),
-      (b'(', _) => self.build(Token::OpenParen),
-      (b')', _) => self.build(Token::CloseParen),
-      (b'[', _) => self.build(Token::OpenBracket),
-      (b']', _) => self.build(Token::CloseBracket),
-      (b'{', _) => self.build(Token::OpenBrace),
-      (b'}', _) => self.build(Token::CloseBrace),
-      (b'&', Some(b'&')) => self.advance_and_build(Token::And),
-      (b'|', Some(b'|')) => self.advance_and_build(Token::Or),
-      (b'=', Some(b'=')) => self.advance_and_build(Token::EqualEqual),
-      (b'=', _) => self.build(Token::Equal),
-      (b'!', Some(b'=')) => self.advance_and_build(Token::BangEqual),
-      (b'!', _) => self.build(Token::Bang),
-      (b'>', Some(b'=')) => self.advance_and_build(Token::GreaterEqual),
-      (b'>', _) => self.build(Token::Greater),
-      (b'<', Some(b'=')) => self.advance_and_build(Token::LessEqual),
-      (b'<', _) => self.build(Token::Less),
-      (byte, _) => return Err(self.build_error::<0>(byte, None)),
+      ('/', Some('/')) => self.comment()?,
+      ('+', _) => self.build(Token::Plus),
+      ('-', Some('>')) => self.advance_and_build(Token::Arrow),
+      ('-', _) => self.build(Token::Dash),
+      ('*', Some('*')) => self.advance_and_build(Token::StarStar),
+      ('*', _) => self.build(Token::Star),
+      ('/', _) => self.build(Token::Slash),
+      ('%', _) => self.build(Token::Percent),
+      ('^', _) => self.build(Token::Caret),
+      ('_', _) => self.build(Token::Underscore),
+      ('.', Some('.')) => self.advance_and_build(Token::DotDot),
+      ('.', _) => self.build(Token::Dot),
+      (',', _) => self.build(Token::Comma),
+      (':', _) => self.build(Token::Colon),
+      (';', _) => self.build(Token::Semicolon),
+      ('(', _) => self.build(Token::OpenParen),
+      (')', _) => self.build(Token::CloseParen),
+      ('[', _) => self.build(Token::OpenBracket),
+      (']', _) => self.build(Token::CloseBracket),
+      ('{', _) => self.build(Token::OpenBrace),
+      ('}', _) => self.build(Token::CloseBrace),
+      ('&', Some('&')) => self.advance_and_build(Token::And),
+      ('&', _) => self.build(Token::Ampersand),
+      ('|', Some('|')) => self.advance_and_build(Token::Or),
+      ('|', _) => self.build(Token::Pipe),
+      ('=', Some('=')) => self.advance_and_build(Token::EqualEqual),
+      ('=', _) => self.build(Token::Equal),
+      ('!', Some('=')) => self.advance_and_build(Token::BangEqual),
+      ('!', _) => self.build(Token::Bang),
+      ('>', Some('>')) => self.advance_and_build(Token::GreaterGreater),
+      ('>', Some('=')) => self.advance_and_build(Token::GreaterEqual),
+      ('>', _) => self.build(Token::Greater),
+      ('<', Some('<')) => self.advance_and_build(Token::LessLess),
+      ('<', Some('=')) => self.advance_and_build(Token::LessEqual),
+      ('<', _) => self.build(Token::Less),
+      (char, _) => return Err(self.build_error::<0>(char, None)),
     };
 
     Ok(lexeme)
@@ -82,14 +91,14 @@ impl Lexer {
 
   fn comment(&mut self) -> Result<Lexeme, CompileError> {
     self
-      .expect([b'/'])
+      .expect(['/'])
       .expect("`self.comment` should begin with slash");
 
     loop {
-      if let Some(b'\n') | None = self.peek() {
+      if let Some('\n') | None = self.peek() {
         break;
       }
-      self.advance().expect("`self.peek` returned a byte");
+      self.advance().expect("`self.peek` returned a char");
     }
 
     Ok(self.build(Token::Comment))
@@ -97,52 +106,93 @@ impl Lexer {
 
   fn string(&mut self) -> Result<Lexeme, CompileError> {
     self
-      .expect([b'"'])
+      .expect(['"'])
       .expect("`self.string` should begin with double quote");
 
     loop {
       match self.peek() {
-        Some(b'"') => break,
-        None => return Err(self.build_end_error(Some([b'"']))),
+        Some('"') => break,
+        None => return Err(self.build_end_error(Some(['"']))),
         _ => self.advance(),
       };
     }
 
     self
-      .expect([b'"'])
+      .expect(['"'])
       .expect("closing quote was peeked earlier");
 
     Ok(self.build(Token::String))
   }
 
+  // Lexes an integer in decimal, or in hex, octal or binary with a `0x`, `0o`
+  // or `0b` prefix, or a decimal float with a fraction or exponent. Digits can
+  // be separated by `_`.
   fn number(&mut self) -> Result<Lexeme, CompileError> {
-    let mut has_decimal = false;
+    let radix = match (self.peek(), self.peek_next()) {
+      (Some('0'), Some('x')) => 16,
+      (Some('0'), Some('o')) => 8,
+      (Some('0'), Some('b')) => 2,
+      _ => 10,
+    };
+    if radix != 10 {
+      self.advance();
+      self.advance();
+    }
+    self.digits(radix)?;
 
-    loop {
-      match self.peek() {
-        Some(b'.') if !has_decimal => {
-          has_decimal = true;
+    if radix == 10 {
+      // `1..2` is a range and `1.abs()` a method call, so a fraction needs a
+      // digit after the point.
+      if let (Some('.'), Some(next)) = (self.peek(), self.peek_next()) {
+        if is_digit(next) {
           self.advance();
+          self.digits(10)?;
         }
-        Some(byte) if is_digit(byte) => {
+      }
+      if let Some('e') | Some('E') = self.peek() {
+        self.advance();
+        if let Some('+') | Some('-') = self.peek() {
           self.advance();
         }
-        Some(_) => break,
-        None => break,
+        self.digits(10)?;
       }
     }
 
-    Ok(self.build(Token::Number))
+    match self.peek() {
+      Some(char) if is_xid_continue(char) => {
+        self.advance();
+        Err(self.build_digit_error(char, radix))
+      }
+      _ => Ok(self.build(Token::Number)),
+    }
+  }
+
+  // Lexes at least one digit, with single underscores between digits.
+  fn digits(&mut self, radix: u32) -> Result<(), CompileError> {
+    match self.advance() {
+      Some(char) if char.is_digit(radix) => {}
+      Some(char) => return Err(self.build_digit_error(char, radix)),
+      None => return Err(self.build_end_error::<0>(None)),
+    }
+
+    loop {
+      match (self.peek(), self.peek_next()) {
+        (Some(char), _) if char.is_digit(radix) => {
+          self.advance();
+        }
+        (Some('_'), Some(next)) if next.is_digit(radix) => {
+          self.advance();
+          self.advance();
+        }
+        _ => return Ok(()),
+      }
+    }
   }
 
   fn ident(&mut self) -> Result<Lexeme, CompileError> {
     loop {
       match self.peek() {
-        Some(byte)
-          if !is_digit(byte) && !is_alphabetic(byte) && byte != b'_' =>
-        {
-          break
-        }
+        Some(char) if !is_xid_continue(char) => break,
         None => break,
         _ => self.advance(),
       };
@@ -169,7 +219,7 @@ impl Lexer {
   fn whitespace(&mut self) {
     loop {
       match self.peek() {
-        Some(byte) if is_whitespace(byte) => {
+        Some(char) if is_whitespace(char) => {
           self.advance().expect("`self.peek` returned `Some`");
         }
         _ => break,
@@ -191,15 +241,19 @@ impl Lexer {
 
   fn build_error<const N: usize>(
     &self,
-    unexpected: u8,
-    expected: Option<[u8; N]>,
+    unexpected: char,
+    expected: Option<[char; N]>,
   ) -> CompileError {
     CompileError::Parse(ParseError::chars(self.span(), unexpected, expected))
   }
 
+  fn build_digit_error(&self, unexpected: char, radix: u32) -> CompileError {
+    CompileError::Parse(ParseError::digit(self.span(), unexpected, radix))
+  }
+
   fn build_end_error<const N: usize>(
     &self,
-    expected: Option<[u8; N]>,
+    expected: Option<[char; N]>,
   ) -> CompileError {
     CompileError::Parse(ParseError::end(self.span(), expected))
   }
@@ -208,14 +262,19 @@ impl Lexer {
     Span::new(self.source.clone(), self.start, self.end)
   }
 
-  fn peek(&self) -> Option<u8> {
-    self.source.content().as_bytes().get(self.end).copied()
+  // Spans are still in bytes, so `end` always lands between chars.
+  fn peek(&self) -> Option<char> {
+    self.source.content()[self.end..].chars().next()
+  }
+
+  fn peek_next(&self) -> Option<char> {
+    self.source.content()[self.end..].chars().nth(1)
   }
 
-  fn advance(&mut self) -> Option<u8> {
-    if let Some(byte) = self.peek() {
-      self.end += 1;
-      Some(byte)
+  fn advance(&mut self) -> Option<char> {
+    if let Some(char) = self.peek() {
+      self.end += char.len_utf8();
+      Some(char)
     } else {
       None
     }
@@ -223,14 +282,14 @@ impl Lexer {
 
   fn expect<const N: usize>(
     &mut self,
-    expected: [u8; N],
-  ) -> Result<u8, CompileError> {
-    let byte = self.advance().ok_or(self.build_end_error(Some(expected)))?;
+    expected: [char; N],
+  ) -> Result<char, CompileError> {
+    let char = self.advance().ok_or(self.build_end_error(Some(expected)))?;
 
-    if expected.contains(&byte) {
-      Ok(byte)
+    if expected.contains(&char) {
+      Ok(char)
     } else {
-      Err(self.build_error(byte, Some(expected)))
+      Err(self.build_error(char, Some(expected)))
     }
   }
 }
@@ -263,7 +322,12 @@ pub enum Token {
   Plus,
   Dash,
   Star,
+  StarStar,
   Slash,
+  Percent,
+  Ampersand,
+  Pipe,
+  Caret,
   Dot,
   DotDot,
   Comma,
@@ -277,8 +341,10 @@ pub enum Token {
   BangEqual,
   Greater,
   GreaterEqual,
+  GreaterGreater,
   Less,
   LessEqual,
+  LessLess,
   And,
   Or,
   OpenParen,
@@ -312,7 +378,12 @@ impl fmt::Display for Token {
       Self::Plus => write!(f, "'+'"),
       Self::Dash => write!(f, "'-'"),
       Self::Star => write!(f, "'*'"),
+      Self::StarStar => write!(f, "'**'"),
       Self::Slash => write!(f, "'/'"),
+      Self::Percent => write!(f, "'%'"),
+      Self::Ampersand => write!(f, "'&'"),
+      Self::Pipe => write!(f, "'|'"),
+      Self::Caret => write!(f, "'^'"),
       Self::Dot => write!(f, "'.'"),
       Self::DotDot => write!(f, "'..'"),
       Self::Comma => write!(f, "','"),
@@ -326,8 +397,10 @@ impl fmt::Display for Token {
       Self::BangEqual => write!(f, "'!='k"),
       Self::Greater => write!(f, "'>'"),
       Self::GreaterEqual => write!(f, "'>='"),
+      Self::GreaterGreater => write!(f, "'>>'"),
       Self::Less => write!(f, "'<'"),
       Self::LessEqual => write!(f, "'<='"),
+      Self::LessLess => write!(f, "'<<'"),
       Self::And => write!(f, "'&&'"),
       Self::Or => write!(f, "'||'"),
       Self::OpenParen => write!(f, "'('"),
@@ -350,14 +423,129 @@ impl fmt::Display for Token {
   }
 }
 
-fn is_digit(byte: u8) -> bool {
-  byte >= b'0' && byte <= b'9'
+fn is_digit(char: char) -> bool {
+  char.is_ascii_digit()
 }
 
-fn is_whitespace(byte: u8) -> bool {
-  byte == b'\r' || byte == b' ' || byte == b'\t' || byte == b'\n'
+fn is_whitespace(char: char) -> bool {
+  char == '\r' || char == ' ' || char == '\t' || char == '\n'
 }
 
-fn is_alphabetic(byte: u8) -> bool {
-  byte >= b'a' && byte <= b'z' || byte >= b'A' && byte <= b'Z'
+// Identifiers follow Unicode's XID rules, except that they can also start
+// with `_`.
+fn is_xid_start(char: char) -> bool {
+  UnicodeXID::is_xid_start(char)
+}
+
+fn is_xid_continue(char: char) -> bool {
+  UnicodeXID::is_xid_continue(char)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::Path;
+
+  use super::*;
+
+  fn lex(source: &str) -> Result<Vec<(Token, String)>, CompileError> {
+    let mut lexer = Lexer::new(Source::from_str(source, Path::new("test.oma")));
+    let mut lexemes = Vec::new();
+    loop {
+      let lexeme = lexer.next()?;
+      if lexeme.token() == Token::End {
+        return Ok(lexemes);
+      }
+      lexemes.push((lexeme.token(), lexeme.span().content().to_string()));
+    }
+  }
+
+  fn numbers(source: &str) -> Vec<String> {
+    let lexemes = lex(source).unwrap();
+    assert!(lexemes.iter().all(|(token, _)| *token == Token::Number));
+    lexemes.into_iter().map(|(_, content)| content).collect()
+  }
+
+  fn error(source: &str) -> String {
+    match lex(source) {
+      Err(CompileError::Parse(error)) => error.message(),
+      result => panic!("expected a parse error, found {:?}", result),
+    }
+  }
+
+  #[test]
+  fn prefixed_integers() {
+    assert_eq!(
+      numbers("0xff 0xFF 0o17 0b101"),
+      vec!["0xff", "0xFF", "0o17", "0b101"]
+    );
+  }
+
+  #[test]
+  fn digit_outside_radix() {
+    assert_eq!(error("0xfg"), "unexpected g, expected hex digit");
+    assert_eq!(error("0o8"), "unexpected 8, expected octal digit");
+    assert_eq!(error("0b102"), "unexpected 2, expected binary digit");
+    assert_eq!(error("0x"), "unexpected end of input");
+    assert_eq!(error("0X1"), "unexpected X, expected digit");
+  }
+
+  #[test]
+  fn separators() {
+    assert_eq!(
+      numbers("1_000 0xdead_beef 0b1_0 1_0.0_1 1e1_0"),
+      vec!["1_000", "0xdead_beef", "0b1_0", "1_0.0_1", "1e1_0"]
+    );
+  }
+
+  #[test]
+  fn separator_needs_digits_on_both_sides() {
+    assert_eq!(error("1_"), "unexpected _, expected digit");
+    assert_eq!(error("1__0"), "unexpected _, expected digit");
+    assert_eq!(error("0x_1"), "unexpected _, expected hex digit");
+  }
+
+  #[test]
+  fn exponents() {
+    assert_eq!(
+      numbers("1e3 1E3 1.5e-3 2e+8"),
+      vec!["1e3", "1E3", "1.5e-3", "2e+8"]
+    );
+    assert_eq!(error("1e"), "unexpected end of input");
+    assert_eq!(error("1e+x"), "unexpected x, expected digit");
+  }
+
+  #[test]
+  fn point_needs_digit_after_it() {
+    assert_eq!(
+      lex("1..2 1.abs").unwrap(),
+      vec![
+        (Token::Number, "1".to_string()),
+        (Token::DotDot, "..".to_string()),
+        (Token::Number, "2".to_string()),
+        (Token::Number, "1".to_string()),
+        (Token::Dot, ".".to_string()),
+        (Token::Ident, "abs".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn unicode_identifiers() {
+    assert_eq!(
+      lex("café _x δ1 名前").unwrap(),
+      vec![
+        (Token::Ident, "café".to_string()),
+        (Token::Ident, "_x".to_string()),
+        (Token::Ident, "δ1".to_string()),
+        (Token::Ident, "名前".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn carets_count_chars() {
+    let error = lex("let café = 0x1g;").unwrap_err().to_string();
+    assert!(error.contains("test.oma:1:11"), "{}", error);
+    assert!(error.contains("\n     |            ^^^^\n"), "{}", error);
+  }
 }
@@ -1,35 +1,180 @@
 use std::path::Path;
 
 use self::{
-  emit::emit, error::CompileError, gen::Generator, parse::Parser,
+  ast::{Expr, MapExpr, MapExprPair, Module},
+  cache::Key,
+  emit::emit,
+  gen::Generator,
+  parse::Parser,
   source::Source,
 };
 
-mod ast;
+pub use self::{
+  cache::{Cache, Lookup},
+  error::{CompileError, Lint, Warning},
+};
+
+pub mod ast;
+mod cache;
+pub mod check;
 mod chunk;
 mod debug;
 mod emit;
-mod error;
-mod gen;
+pub mod error;
+pub mod exhaust;
+pub mod gen;
 mod lex;
-mod parse;
-mod source;
+pub mod lint;
+pub mod parse;
+mod pretty;
+pub mod source;
+
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+  // Raise an error when no arm of a `case` matches instead of yielding null.
+  pub strict: bool,
+  // Natives the script isn't granted, by name, with the capability each
+  // needs. Referring to one is an error.
+  pub denied: Vec<(String, String)>,
+}
+
+#[derive(Debug)]
+pub struct Compilation {
+  pub bytes: Vec<u8>,
+  pub warnings: Vec<Warning>,
+}
 
 pub fn compile(
+  path: &Path,
+  registry: Vec<(&'static str, usize)>,
+  options: &Options,
+) -> Result<Compilation, CompileError> {
+  compile_source(Source::from_file(path)?, registry, options)
+}
+
+// Like `compile`, but reuses the executable in the cache if the file, the
+// registry, the options and the compiler are all the same as when it was
+// cached, and otherwise caches the new one. Executables with warnings aren't
+// cached, so that the warnings are reported every time. Also returns how the
+// cache was used.
+pub fn compile_cached(
+  path: &Path,
   registry: Vec<(&'static str, usize)>,
-) -> Result<Vec<u8>, CompileError> {
-  let parser = Parser::new(Source::from_str(
-    "let counter = 0;
-    counter = counter + 1;
-    while counter < 100000 {
-      __console_info(counter);
-      counter = counter + 1;
+  options: &Options,
+  cache: &Cache,
+) -> Result<(Compilation, Lookup), CompileError> {
+  let source = Source::from_file(path)?;
+  let key = Key::new(source.content(), &registry, options);
+  let lookup = match cache.get(path, &key) {
+    (Some(bytes), lookup) => {
+      let warnings = Vec::new();
+      return Ok((Compilation { bytes, warnings }, lookup));
     }
-",
-    Path::new("main.oma"),
-  ));
+    (None, lookup) => lookup,
+  };
+
+  let compilation = compile_source(source, registry, options)?;
+  if compilation.warnings.is_empty() {
+    cache.put(path, &key, &compilation.bytes);
+  }
+  Ok((compilation, lookup))
+}
+
+fn compile_source(
+  source: Source,
+  registry: Vec<(&'static str, usize)>,
+  options: &Options,
+) -> Result<Compilation, CompileError> {
+  let parser = Parser::new(source);
   let module = parser.parse()?;
-  let generator = Generator::new(registry);
+  check::check(&module)?;
+  let warnings = exhaust::check(&module);
+  let mut generator = Generator::new(registry, options.strict);
+  generator.deny(options.denied.clone());
+  let chunk = generator.generate(module)?;
+  Ok(Compilation {
+    bytes: emit(chunk),
+    warnings,
+  })
+}
+
+// Compiles a test file so that its module evaluates to a map of its top-level
+// bindings whose names start with `test_`, and returns their names in order.
+pub fn compile_tests(
+  path: &Path,
+  registry: Vec<(&'static str, usize)>,
+  options: &Options,
+) -> Result<(Compilation, Vec<String>), CompileError> {
+  let parser = Parser::new(Source::from_file(path)?);
+  let mut module = parser.parse()?;
+  check::check(&module)?;
+  let names = export_tests(&mut module);
+  let warnings = exhaust::check(&module);
+  let mut generator = Generator::new(registry, options.strict);
+  generator.deny(options.denied.clone());
   let chunk = generator.generate(module)?;
-  Ok(emit(chunk))
+  let compilation = Compilation {
+    bytes: emit(chunk),
+    warnings,
+  };
+  Ok((compilation, names))
+}
+
+fn export_tests(module: &mut Module) -> Vec<String> {
+  let block = match &mut module.body {
+    Expr::Block(block) => block,
+    _ => unreachable!("modules are blocks"),
+  };
+
+  let mut tests = Vec::new();
+  for expr in &block.exprs {
+    if let Expr::Bind(bind) = expr {
+      for ident in bind.bindee.idents() {
+        let shadowed = tests
+          .iter()
+          .position(|test: &ast::Ident| test.content == ident.content);
+        if let Some(index) = shadowed {
+          tests.remove(index);
+        }
+        if ident.content.starts_with("test_") {
+          tests.push(ident);
+        }
+      }
+    }
+  }
+
+  let names = tests.iter().map(|test| test.content.to_string()).collect();
+  let pairs = tests
+    .into_iter()
+    .map(|test| MapExprPair::Ident(test.clone(), Expr::Ident(test)))
+    .collect();
+  // The map has no source of its own, so it's put with the last binding, which
+  // keeps it from adding a line to coverage.
+  let span = block.exprs.last().map_or(block.span.clone(), Expr::span);
+  block.exprs.push(Expr::Map(MapExpr { span, pairs }));
+  block.has_semi = false;
+  names
+}
+
+// Formats the file in the canonical style, keeping comments.
+pub fn format(path: &Path) -> Result<String, CompileError> {
+  let parser = Parser::new(Source::from_file(path)?);
+  let (module, comments) = parser.parse_with_comments()?;
+  Ok(pretty::print(&module, &comments))
+}
+
+// Checks the file with the given lint rules. Errors that stop the file from
+// compiling are returned instead of lints.
+pub fn lint(
+  path: &Path,
+  registry: Vec<(&'static str, usize)>,
+  rules: &[lint::Rule],
+) -> Result<Vec<Lint>, CompileError> {
+  let parser = Parser::new(Source::from_file(path)?);
+  let module = parser.parse()?;
+  check::check(&module)?;
+  let generator = Generator::new(registry, false);
+  let (symbols, result) = generator.symbols(module.clone());
+  result?;
+  Ok(lint::check(&module, &symbols, rules))
 }
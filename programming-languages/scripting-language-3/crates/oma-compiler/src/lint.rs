@@ -0,0 +1,450 @@
+// Lints for code that compiles but is likely a mistake. They are checked on
+// the AST together with the bindings and references recorded by the
+// generator.
+//
+// Bindings whose names start with an underscore are never reported as unused
+// or shadowing.
+
+use crate::{
+  ast::*,
+  error::{Lint, LintReason},
+  gen::{Definition, DefinitionKind, ReferenceKind, Symbols},
+  source::Span,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rule {
+  UnusedBinding,
+  Shadowing,
+  UnusedAssignment,
+  UnreachableArm,
+  ConstantCondition,
+}
+
+impl Rule {
+  pub const ALL: [Rule; 5] = [
+    Rule::UnusedBinding,
+    Rule::Shadowing,
+    Rule::UnusedAssignment,
+    Rule::UnreachableArm,
+    Rule::ConstantCondition,
+  ];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Self::UnusedBinding => "unused-binding",
+      Self::Shadowing => "shadowing",
+      Self::UnusedAssignment => "unused-assignment",
+      Self::UnreachableArm => "unreachable-arm",
+      Self::ConstantCondition => "constant-condition",
+    }
+  }
+
+  pub fn from_name(name: &str) -> Option<Rule> {
+    Self::ALL.iter().copied().find(|rule| rule.name() == name)
+  }
+}
+
+impl LintReason {
+  pub fn rule(&self) -> Rule {
+    match self {
+      Self::UnusedBinding(_)
+      | Self::UnusedParameter(_)
+      | Self::UnreadBinding(_) => Rule::UnusedBinding,
+      Self::Shadowing(..) => Rule::Shadowing,
+      Self::UnusedAssignment(_) => Rule::UnusedAssignment,
+      Self::UnreachableArm => Rule::UnreachableArm,
+      Self::ConstantCondition => Rule::ConstantCondition,
+    }
+  }
+}
+
+// Checks the module with the given rules, returning lints in source order.
+pub fn check(module: &Module, symbols: &Symbols, rules: &[Rule]) -> Vec<Lint> {
+  let mut walker = Walker::default();
+  walker.expr(&module.body);
+
+  let mut lints = std::mem::take(&mut walker.lints);
+  for definition in &symbols.definitions {
+    if !definition.ident.content.starts_with('_') {
+      lints.extend(definition_lint(definition, symbols));
+    }
+  }
+  for (span, ident) in &walker.assignments {
+    lints.extend(assignment_lint(span, ident, &walker, symbols));
+  }
+
+  lints.retain(|lint| rules.contains(&lint.reason().rule()));
+  lints.sort_by_key(|lint| lint.span().start());
+  lints
+}
+
+fn definition_lint(definition: &Definition, symbols: &Symbols) -> Vec<Lint> {
+  let name = definition.ident.content.as_str();
+  let mut lints = Vec::new();
+
+  if let Some(shadowed) = &definition.shadows {
+    lints.push(Lint::new(
+      definition.ident.span(),
+      LintReason::Shadowing(name, shadowed.span.line()),
+    ));
+  }
+
+  let kinds = reference_kinds(&definition.ident, symbols);
+  let is_read = kinds
+    .iter()
+    .any(|kind| matches!(kind, ReferenceKind::Read | ReferenceKind::Capture));
+  let is_written = kinds.contains(&ReferenceKind::Write);
+  let reason = match (definition.kind, is_read, is_written) {
+    (_, true, _) | (DefinitionKind::CaseArm, ..) => None,
+    (_, false, true) => Some(LintReason::UnreadBinding(name)),
+    (DefinitionKind::Let, false, false) => {
+      Some(LintReason::UnusedBinding(name))
+    }
+    (DefinitionKind::Parameter, false, false) => {
+      Some(LintReason::UnusedParameter(name))
+    }
+  };
+  lints.extend(reason.map(|reason| Lint::new(definition.ident.span(), reason)));
+
+  lints
+}
+
+// An assignment is unused if another assignment to the binding always runs
+// after it before any read, or if no read of the binding follows it, either
+// later in the source or earlier in a loop around the assignment. Bindings
+// captured by a lambda could be read whenever the lambda is called, so they
+// are left out, as are bindings that are never read at all.
+fn assignment_lint(
+  span: &Span,
+  ident: &Ident,
+  walker: &Walker,
+  symbols: &Symbols,
+) -> Option<Lint> {
+  let definition = binding(ident, symbols)?;
+  if definition.content.starts_with('_') {
+    return None;
+  }
+
+  let reads = symbols
+    .references
+    .iter()
+    .filter(|reference| reference.definition.as_ref() == Some(definition))
+    .filter(|reference| reference.kind != ReferenceKind::Write)
+    .filter(|reference| reference.kind != ReferenceKind::Alternative)
+    .collect::<Vec<_>>();
+  if reads.is_empty()
+    || reads
+      .iter()
+      .any(|reference| reference.kind == ReferenceKind::Capture)
+  {
+    return None;
+  }
+
+  // Reads in the value of the next assignment happen before it's written.
+  let is_overwritten = walker.assignments.iter().any(|(next_span, next)| {
+    binding(next, symbols) == Some(definition)
+      && next_span.start() >= span.end()
+      && walker.always_follows(span, next_span)
+      && !reads.iter().any(|reference| {
+        let start = reference.ident.span.start();
+        span.end() <= start && start < next_span.end()
+      })
+  });
+  let is_read_later = reads
+    .iter()
+    .any(|reference| reference.ident.span.start() >= span.end());
+  let is_read_in_loop = walker.loops.iter().any(|loop_span| {
+    contains(loop_span, span)
+      && reads
+        .iter()
+        .any(|reference| contains(loop_span, &reference.ident.span))
+  });
+  if !is_overwritten && (is_read_later || is_read_in_loop) {
+    return None;
+  }
+
+  Some(Lint::new(
+    ident.span(),
+    LintReason::UnusedAssignment(ident.content),
+  ))
+}
+
+// The binding an assignment's identifier resolves to.
+fn binding<'a>(ident: &Ident, symbols: &'a Symbols) -> Option<&'a Ident> {
+  symbols
+    .references
+    .iter()
+    .find(|reference| reference.ident == *ident)?
+    .definition
+    .as_ref()
+}
+
+fn reference_kinds(ident: &Ident, symbols: &Symbols) -> Vec<ReferenceKind> {
+  symbols
+    .references
+    .iter()
+    .filter(|reference| reference.definition.as_ref() == Some(ident))
+    .map(|reference| reference.kind)
+    .collect()
+}
+
+fn contains(outer: &Span, inner: &Span) -> bool {
+  outer.start() <= inner.start() && inner.end() <= outer.end()
+}
+
+// Collects what the symbol based lints need from the AST, and checks the
+// lints that only need the AST.
+#[derive(Default)]
+struct Walker {
+  lints: Vec<Lint>,
+  // Each assignment to an identifier, with the span of the whole assignment.
+  assignments: Vec<(Span, Ident)>,
+  loops: Vec<Span>,
+  // Code that may not run: branches of `if` and `case`, and the right
+  // operands of `and` and `or`.
+  branches: Vec<Span>,
+  lambdas: Vec<Span>,
+}
+
+impl Walker {
+  // Whether the code at `later`, which comes after `earlier` in the source,
+  // runs whenever `earlier` does, before control can go back to before
+  // `earlier`.
+  fn always_follows(&self, earlier: &Span, later: &Span) -> bool {
+    self
+      .branches
+      .iter()
+      .all(|branch| !contains(branch, later) || contains(branch, earlier))
+      && self
+        .loops
+        .iter()
+        .chain(&self.lambdas)
+        .all(|outer| !contains(outer, earlier) || contains(outer, later))
+  }
+
+  fn expr(&mut self, expr: &Expr) {
+    match expr {
+      Expr::Lit(_) | Expr::Ident(_) => {}
+      Expr::Map(map_expr) => {
+        for pair in &map_expr.pairs {
+          match pair {
+            MapExprPair::Spread(value) | MapExprPair::Ident(_, value) => {
+              self.expr(value)
+            }
+            MapExprPair::Expr(key, value) => {
+              self.expr(key);
+              self.expr(value);
+            }
+          }
+        }
+      }
+      Expr::Array(array_expr) => {
+        for item in &array_expr.items {
+          match item {
+            ArrayExprItem::Spread(expr) | ArrayExprItem::Expr(expr) => {
+              self.expr(expr)
+            }
+          }
+        }
+      }
+      Expr::Lambda(lambda_expr) => {
+        self.lambdas.push(lambda_expr.span());
+        self.expr(&lambda_expr.body);
+      }
+      Expr::Tag(tag_expr) => {
+        for field in tag_expr.fields.values() {
+          self.expr(field);
+        }
+      }
+      Expr::Block(block_expr) => {
+        for expr in &block_expr.exprs {
+          self.expr(expr);
+        }
+      }
+      Expr::Binary(binary_expr) => {
+        if let BinaryOp::And | BinaryOp::Or = binary_expr.op {
+          self.branches.push(binary_expr.right.span());
+        }
+        self.expr(&binary_expr.left);
+        self.expr(&binary_expr.right);
+      }
+      Expr::Unary(unary_expr) => self.expr(&unary_expr.operand),
+      Expr::Bind(bind_expr) => self.expr(&bind_expr.value),
+      Expr::Assign(assign_expr) => {
+        match &assign_expr.assignee {
+          AssignExprAssignee::Pat(pat) => {
+            for ident in pat.idents() {
+              self.assignments.push((assign_expr.span(), ident));
+            }
+          }
+          AssignExprAssignee::Access(access_expr) => {
+            self.access_expr(access_expr)
+          }
+        }
+        self.expr(&assign_expr.value);
+      }
+      Expr::Call(call_expr) => {
+        self.expr(&call_expr.receiver);
+        for argument in &call_expr.arguments {
+          match argument {
+            CallExprArgument::Spread(expr) | CallExprArgument::Expr(expr) => {
+              self.expr(expr)
+            }
+          }
+        }
+      }
+      Expr::Access(access_expr) => self.access_expr(access_expr),
+      Expr::If(if_expr) => {
+        self.condition(&if_expr.condition, false);
+        self.branches.push(if_expr.body.span());
+        self.expr(&if_expr.body);
+        if let Some(otherwise) = &if_expr.otherwise {
+          self.branches.push(otherwise.span());
+          self.expr(otherwise);
+        }
+      }
+      Expr::Case(case_expr) => self.case_expr(case_expr),
+      Expr::For(for_expr) => {
+        self.loops.push(for_expr.span());
+        self.expr(&for_expr.iterator);
+        self.expr(&for_expr.body);
+      }
+      Expr::While(while_expr) => {
+        self.loops.push(while_expr.span());
+        // `while true` is how an endless loop is written.
+        self.condition(&while_expr.condition, true);
+        self.expr(&while_expr.body);
+      }
+    }
+  }
+
+  fn access_expr(&mut self, access_expr: &AccessExpr) {
+    self.expr(&access_expr.receiver);
+    match &access_expr.field {
+      AccessExprField::Ident(_) => {}
+      AccessExprField::Expr(field) => self.expr(field),
+      AccessExprField::Slice(start, end) => {
+        for bound in start.iter().chain(end) {
+          self.expr(bound);
+        }
+      }
+    }
+  }
+
+  fn case_expr(&mut self, case_expr: &CaseExpr) {
+    self.expr(&case_expr.subject);
+
+    let mut is_matched = false;
+    for arm in &case_expr.arms {
+      if is_matched {
+        let span = arm.pats.iter().fold(arm.pats[0].span(), |span, pat| {
+          Span::combine(&span, &pat.span())
+        });
+        self.lints.push(Lint::new(span, LintReason::UnreachableArm));
+      }
+      if arm.guard.is_none()
+        && arm.pats.iter().any(|pat| matches!(pat, Pat::Ident(_)))
+      {
+        is_matched = true;
+      }
+
+      self
+        .branches
+        .push(Span::combine(&arm.pats[0].span(), &arm.expr.span()));
+      if let Some(guard) = &arm.guard {
+        self.condition(guard, false);
+      }
+      self.expr(&arm.expr);
+    }
+  }
+
+  fn condition(&mut self, condition: &Expr, allow_true: bool) {
+    match condition {
+      Expr::Lit(Lit::Bool(BoolLit { bool: true, .. })) if allow_true => {}
+      Expr::Lit(lit) => self
+        .lints
+        .push(Lint::new(lit.span(), LintReason::ConstantCondition)),
+      condition => self.expr(condition),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::Path;
+
+  use super::*;
+  use crate::{gen::Generator, parse::Parser, source::Source};
+
+  // The offsets of the unused assignments in the source.
+  fn unused_assignments(source: &str) -> Vec<usize> {
+    let source = Source::from_str(source, Path::new("test.oma"));
+    let module = Parser::new(source).parse().unwrap();
+    let generator = Generator::new(vec![("__console_info", 0)], false);
+    let (symbols, result) = generator.symbols(module.clone());
+    result.unwrap();
+    check(&module, &symbols, &[Rule::UnusedAssignment])
+      .iter()
+      .map(|lint| lint.span().start())
+      .collect()
+  }
+
+  #[test]
+  fn assignment_overwritten_before_read() {
+    let source = "let x = 1; x = 2; x = 3; __console_info(x);";
+    assert_eq!(
+      unused_assignments(source),
+      vec![source.find("x = 2").unwrap()]
+    );
+  }
+
+  #[test]
+  fn assignment_read_by_next_assignment() {
+    let source = "let x = 1; x = 2; x = x + 1; __console_info(x);";
+    assert!(unused_assignments(source).is_empty());
+  }
+
+  #[test]
+  fn assignment_overwritten_in_branch() {
+    let source =
+      "let x = 1; let c = true; x = 2; if c { x = 3; }; __console_info(x);";
+    assert!(unused_assignments(source).is_empty());
+  }
+
+  #[test]
+  fn assignment_in_branch_overwritten_after() {
+    let source =
+      "let x = 1; let c = true; if c { x = 2; }; x = 3; __console_info(x);";
+    assert_eq!(
+      unused_assignments(source),
+      vec![source.find("x = 2").unwrap()]
+    );
+  }
+
+  #[test]
+  fn assignment_overwritten_in_same_branch() {
+    let source =
+      "let x = 0; let c = true; if c { x = 1; x = 2; }; __console_info(x);";
+    assert_eq!(
+      unused_assignments(source),
+      vec![source.find("x = 1").unwrap()]
+    );
+  }
+
+  #[test]
+  fn assignment_in_loop_read_by_condition() {
+    let source =
+      "let x = 1; while x < 3 { x = x + 1; }; x = 5; __console_info(x);";
+    assert!(unused_assignments(source).is_empty());
+  }
+
+  #[test]
+  fn assignment_never_read_after() {
+    let source = "let x = 1; __console_info(x); x = 2;";
+    assert_eq!(
+      unused_assignments(source),
+      vec![source.find("x = 2").unwrap()]
+    );
+  }
+}
@@ -11,6 +11,10 @@ pub struct Parser {
   source: Source,
   lexer: Lexer,
   current: Option<Lexeme>,
+  // Spans of the comments skipped so far, in source order.
+  comments: Vec<Span>,
+  // Errors recovered from so far.
+  errors: Vec<CompileError>,
 }
 
 impl Parser {
@@ -19,13 +23,31 @@ impl Parser {
       lexer: Lexer::new(source.clone()),
       source,
       current: None,
+      comments: Vec::new(),
+      errors: Vec::new(),
     }
   }
 
-  pub fn parse(mut self) -> Result<Module, CompileError> {
-    let module = self.module()?;
-    self.expect([Token::End])?;
-    Ok(module)
+  // Parses the module, reporting every syntax error that was recovered from.
+  pub fn parse(self) -> Result<Module, CompileError> {
+    self.parse_with_comments().map(|(module, _)| module)
+  }
+
+  // Parses the module, also returning the comments that the AST leaves out.
+  pub fn parse_with_comments(
+    mut self,
+  ) -> Result<(Module, Vec<Span>), CompileError> {
+    let module = self.module().and_then(|module| {
+      self.expect([Token::End])?;
+      Ok(module)
+    });
+
+    let module = module.map_err(|error| self.record(error)).ok();
+    match (module, CompileError::from_errors(self.errors)) {
+      (Some(module), None) => Ok((module, self.comments)),
+      (_, Some(error)) => Err(error),
+      (None, None) => unreachable!("the error was recorded"),
+    }
   }
 
   fn module(&mut self) -> Result<Module, CompileError> {
@@ -38,36 +60,32 @@ impl Parser {
     &mut self,
     terminators: [Token; N],
   ) -> Result<Expr, CompileError> {
-    let span = self.peek()?.span().clone();
+    let span = self.recovering_peek().span().clone();
     let mut span = Span::new(span.source().clone(), span.start(), span.end());
 
     let mut exprs = Vec::new();
     let mut has_semi = true;
 
-    while !terminators.contains(&self.peek()?.token()) {
-      let expr = self.expr()?;
-
-      span = Span::combine(&span, &expr.span());
+    loop {
+      let lexeme = self.recovering_peek();
+      if terminators.contains(&lexeme.token()) {
+        break;
+      }
+      if let Token::End = lexeme.token() {
+        return Err(self.build_error(Some(terminators)));
+      }
 
-      if let Token::Semicolon = self.peek()?.token() {
-        self.expect([Token::Semicolon]).unwrap();
-        has_semi = true;
-      } else {
-        match &expr {
-          Expr::If(_) | Expr::While(_) | Expr::For(_) | Expr::Case(_) => {
-            has_semi = false
-          }
-          _ => {
-            if !terminators.contains(&self.peek()?.token()) {
-              return Err(self.build_error(Some(terminators)));
-            } else {
-              has_semi = false;
-            }
-          }
+      match self.statement(terminators) {
+        Ok((expr, semi)) => {
+          span = Span::combine(&span, &expr.span());
+          has_semi = semi;
+          exprs.push(expr);
+        }
+        Err(error) => {
+          self.record(error);
+          self.synchronize(terminators, lexeme.span().start());
         }
       }
-
-      exprs.push(expr);
     }
 
     Ok(Expr::Block(BlockExpr {
@@ -77,6 +95,63 @@ impl Parser {
     }))
   }
 
+  // Parses an expression in a block, returning whether it ended with a
+  // semicolon.
+  fn statement<const N: usize>(
+    &mut self,
+    terminators: [Token; N],
+  ) -> Result<(Expr, bool), CompileError> {
+    let expr = self.expr()?;
+
+    if let Token::Semicolon = self.peek()?.token() {
+      self.expect([Token::Semicolon]).unwrap();
+      return Ok((expr, true));
+    }
+
+    match &expr {
+      Expr::If(_) | Expr::While(_) | Expr::For(_) | Expr::Case(_) => {
+        Ok((expr, false))
+      }
+      _ if terminators.contains(&self.peek()?.token()) => Ok((expr, false)),
+      _ => Err(self.build_error(Some(terminators))),
+    }
+  }
+
+  // Skips past the statement an error occurred in: up to a semicolon, the end
+  // of the block, or a keyword that starts a statement. Brackets opened along
+  // the way are skipped as a whole.
+  fn synchronize<const N: usize>(
+    &mut self,
+    terminators: [Token; N],
+    statement_start: usize,
+  ) {
+    let mut depth = 0usize;
+    loop {
+      let lexeme = self.recovering_peek();
+      match lexeme.token() {
+        Token::End => return,
+        token if depth == 0 && terminators.contains(&token) => return,
+        Token::Semicolon if depth == 0 => {
+          self.advance().unwrap();
+          return;
+        }
+        // A keyword the failed statement started with has to be skipped, or
+        // parsing would never move on.
+        Token::Let | Token::If | Token::Case | Token::For | Token::While
+          if depth == 0 && lexeme.span().start() != statement_start =>
+        {
+          return
+        }
+        Token::OpenParen | Token::OpenBracket | Token::OpenBrace => depth += 1,
+        Token::CloseParen | Token::CloseBracket | Token::CloseBrace => {
+          depth = depth.saturating_sub(1)
+        }
+        _ => {}
+      }
+      self.advance().unwrap();
+    }
+  }
+
   fn expr(&mut self) -> Result<Expr, CompileError> {
     match self.peek()?.token() {
       Token::Let => self.bind_expr(),
@@ -91,17 +166,43 @@ impl Parser {
   fn bind_expr(&mut self) -> Result<Expr, CompileError> {
     self.expect([Token::Let])?;
 
-    if let Expr::Assign(assign_expr) = self.expr()? {
-      if let AssignExprAssignee::Pat(pat) = assign_expr.assignee {
-        Ok(Expr::Bind(BindExpr {
-          bindee: pat,
-          value: assign_expr.value,
-        }))
-      } else {
-        unimplemented!();
+    let expr = self.expr()?;
+    if let Token::Colon = self.peek()?.token() {
+      // The bindee stops short of the annotation, as `:` isn't an operator.
+      let span = expr.span();
+      let pat = Pat::from_expr(expr)
+        .ok_or(CompileError::Verify(VerifyError::invalid_assignee(span)))?;
+      self.expect([Token::Colon]).unwrap();
+      let ty = self.type_expr()?;
+      self.expect([Token::Equal])?;
+      return Ok(Expr::Bind(BindExpr {
+        bindee: pat,
+        ty: Some(ty),
+        value: Box::new(self.expr()?),
+      }));
+    }
+
+    match expr {
+      Expr::Assign(AssignExpr {
+        assignee: AssignExprAssignee::Pat(pat),
+        value,
+      }) => Ok(Expr::Bind(BindExpr {
+        bindee: pat,
+        ty: None,
+        value,
+      })),
+      Expr::Assign(assign_expr) => Err(CompileError::Verify(
+        VerifyError::invalid_assignee(assign_expr.assignee.span()),
+      )),
+      // An `=` would have been parsed as part of an assignment.
+      _ => {
+        let lexeme = self.peek()?;
+        Err(CompileError::Parse(ParseError::tokens(
+          lexeme.span().clone(),
+          lexeme.token(),
+          Some([Token::Colon, Token::Equal]),
+        )))
       }
-    } else {
-      unimplemented!();
     }
   }
 
@@ -158,7 +259,14 @@ impl Parser {
     self.expect([Token::OpenBrace])?;
     let mut arms = Vec::new();
     while self.peek()?.token() != Token::CloseBrace {
-      let pat = self.pat()?;
+      let pats = self.case_expr_pats()?;
+
+      let guard = if let Token::If = self.peek()?.token() {
+        self.expect([Token::If]).unwrap();
+        Some(self.expr()?)
+      } else {
+        None
+      };
 
       self.expect([Token::Arrow])?;
 
@@ -173,7 +281,7 @@ impl Parser {
         expr
       };
 
-      arms.push((pat, expr))
+      arms.push(CaseExprArm { pats, guard, expr })
     }
     let close_brace_lexeme = self
       .expect([Token::CloseBrace])
@@ -186,6 +294,32 @@ impl Parser {
     }))
   }
 
+  // Parses the alternatives of a case arm, e.g. `Ok(v) | Some(v)`.
+  fn case_expr_pats(&mut self) -> Result<Vec<Pat>, CompileError> {
+    let mut exprs = Vec::new();
+    flatten_alternatives(self.expr()?, &mut exprs);
+
+    let mut pats = Vec::new();
+    for expr in exprs {
+      let span = expr.span();
+      pats.push(
+        Pat::from_expr(expr)
+          .ok_or(CompileError::Verify(VerifyError::invalid_case_pat(span)))?,
+      );
+    }
+
+    let idents = bound_names(&pats[0]);
+    for pat in &pats[1..] {
+      if bound_names(pat) != idents {
+        return Err(CompileError::Verify(
+          VerifyError::mismatched_alternative_bindings(pat.span()),
+        ));
+      }
+    }
+
+    Ok(pats)
+  }
+
   fn for_expr(&mut self) -> Result<Expr, CompileError> {
     let for_lexeme = self.expect([Token::For]).unwrap();
 
@@ -251,51 +385,33 @@ impl Parser {
 
         left = match self.advance().unwrap().token() {
           Token::OpenBracket => {
-            let field = AccessExprField::Expr(Box::new(self.expr()?));
+            let field = self.access_expr_field()?;
             self.expect([Token::CloseBracket])?;
             Expr::Access(AccessExpr {
               receiver: Box::new(left),
               field,
             })
           }
-          Token::OpenParen => {
-            let arguments = self.call_expr_arguments()?;
-            let close_paren_lexeme = self.expect([Token::CloseParen]).unwrap();
-            match &left {
-              Expr::Ident(ident) if is_pascal_case(ident.content) => {
-                if arguments.len() == 1 {
-                  let mut arguments = arguments;
-                  let argument = arguments.pop().unwrap();
-                  if let CallExprArgument::Expr(expr) = argument {
-                    Expr::Tag(TagExpr {
-                      span: Span::combine(
-                        &left.span(),
-                        close_paren_lexeme.span(),
-                      ),
-                      tag: ident.clone(),
-                      expr: Box::new(expr),
-                    })
-                  } else {
-                    let span =
-                      Span::combine(&left.span(), close_paren_lexeme.span());
-                    return Err(CompileError::Verify(
-                      VerifyError::multiple_tag_arguments(span),
-                    ));
-                  }
-                } else {
-                  let span =
-                    Span::combine(&left.span(), close_paren_lexeme.span());
-                  return Err(CompileError::Verify(
-                    VerifyError::multiple_tag_arguments(span),
-                  ));
-                }
-              }
-              _ => Expr::Call(CallExpr {
+          Token::OpenParen => match &left {
+            Expr::Ident(ident) if is_pascal_case(ident.content) => {
+              let fields = self.tag_expr_fields()?;
+              let close_paren_lexeme =
+                self.expect([Token::CloseParen]).unwrap();
+              Expr::Tag(TagExpr {
+                span: Span::combine(&left.span(), close_paren_lexeme.span()),
+                tag: ident.clone(),
+                fields,
+              })
+            }
+            _ => {
+              let arguments = self.call_expr_arguments()?;
+              self.expect([Token::CloseParen]).unwrap();
+              Expr::Call(CallExpr {
                 receiver: Box::new(left),
                 arguments,
-              }),
+              })
             }
-          }
+          },
           _ => unreachable!(),
         };
 
@@ -320,12 +436,19 @@ impl Parser {
           Token::Equal => {
             let value = self.expr()?;
             let span = left.span().clone();
-            Expr::Assign(AssignExpr {
-              assignee: AssignExprAssignee::from_expr(left).ok_or(
-                CompileError::Verify(VerifyError::invalid_assignee(span)),
-              )?,
-              value: Box::new(value),
-            })
+            match AssignExprAssignee::from_expr(left) {
+              Some(assignee) => Expr::Assign(AssignExpr {
+                assignee,
+                value: Box::new(value),
+              }),
+              // Carry on with the value so later errors are reported too.
+              None => {
+                self.record(CompileError::Verify(
+                  VerifyError::invalid_assignee(span),
+                ));
+                value
+              }
+            }
           }
           token => Expr::Binary(BinaryExpr {
             op: BinaryOp::from_token(token).unwrap(),
@@ -343,6 +466,27 @@ impl Parser {
     Ok(left)
   }
 
+  // The field between brackets, which is either a key or a range.
+  fn access_expr_field(&mut self) -> Result<AccessExprField, CompileError> {
+    let start = match self.peek()?.token() {
+      Token::DotDot => None,
+      _ => Some(Box::new(self.expr()?)),
+    };
+
+    match (self.peek()?.token(), start) {
+      (Token::DotDot, start) => {
+        self.expect([Token::DotDot]).unwrap();
+        let end = match self.peek()?.token() {
+          Token::CloseBracket => None,
+          _ => Some(Box::new(self.expr()?)),
+        };
+        Ok(AccessExprField::Slice(start, end))
+      }
+      (_, Some(key)) => Ok(AccessExprField::Expr(key)),
+      (_, None) => unreachable!("`..` was peeked"),
+    }
+  }
+
   fn prefix_expr(&mut self) -> Result<Expr, CompileError> {
     if let Some(power) = prefix_binding_power(self.peek()?.token()) {
       let op = self.advance().unwrap();
@@ -385,6 +529,58 @@ impl Parser {
     Ok(arguments)
   }
 
+  // Parses either positional fields `(a, b)` or named fields `(x: a, y: b)`.
+  // Mixing the two is not allowed.
+  fn tag_expr_fields(&mut self) -> Result<TagFields<Expr>, CompileError> {
+    let mut positional = Vec::new();
+    let mut named: Vec<(Ident, Expr)> = Vec::new();
+    while self.peek()?.token() != Token::CloseParen {
+      let expr = self.expr()?;
+
+      match (expr, self.peek()?.token()) {
+        (Expr::Ident(name), Token::Colon) => {
+          self.expect([Token::Colon]).unwrap();
+          let value = self.expr()?;
+          if !positional.is_empty() {
+            return Err(CompileError::Verify(VerifyError::mixed_tag_fields(
+              Span::combine(&name.span(), &value.span()),
+            )));
+          }
+          if named.iter().any(|(other, _)| other.content == name.content) {
+            return Err(CompileError::Verify(
+              VerifyError::duplicate_tag_field(name.span()),
+            ));
+          }
+          named.push((name, value));
+        }
+        (expr, _) => {
+          if !named.is_empty() {
+            return Err(CompileError::Verify(VerifyError::mixed_tag_fields(
+              expr.span(),
+            )));
+          }
+          positional.push(expr);
+        }
+      }
+
+      match self.peek()?.token() {
+        Token::Comma => {
+          self.expect([Token::Comma]).unwrap();
+        }
+        Token::CloseParen => {}
+        _ => {
+          return Err(self.build_error(Some([Token::Comma, Token::CloseParen])))
+        }
+      }
+    }
+
+    if named.is_empty() {
+      Ok(TagFields::Positional(positional))
+    } else {
+      Ok(TagFields::Named(named))
+    }
+  }
+
   fn group_or_lambda_expr(&mut self) -> Result<Expr, CompileError> {
     let open_paren_lexeme = self.peek()?;
     assert_eq!(open_paren_lexeme.token(), Token::OpenParen);
@@ -419,7 +615,7 @@ impl Parser {
   }
 
   fn lambda_expr(&mut self) -> Result<Expr, CompileError> {
-    let (parameters, lambda_head_span) = self.lambda_expr_head()?;
+    let (parameters, return_ty, lambda_head_span) = self.lambda_expr_head()?;
 
     let (body, body_span) = match self.peek()?.token() {
       Token::OpenBrace => {
@@ -441,13 +637,16 @@ impl Parser {
     Ok(Expr::Lambda(LambdaExpr {
       span: Span::combine(&lambda_head_span, &body_span),
       parameters,
+      return_ty,
       body: Box::new(body),
     }))
   }
 
+  // Parses the parameters and the return type, if annotated, up to the arrow.
   fn lambda_expr_head(
     &mut self,
-  ) -> Result<(Vec<LambdaExprParameter>, Span), CompileError> {
+  ) -> Result<(Vec<LambdaExprParameter>, Option<TypeExpr>, Span), CompileError>
+  {
     let open_paren_lexeme = self.expect([Token::OpenParen]).unwrap();
 
     let mut parameters = Vec::new();
@@ -457,7 +656,16 @@ impl Parser {
           self.expect([Token::DotDot]).unwrap();
           LambdaExprParameter::Spread(self.ident()?)
         }
-        _ => LambdaExprParameter::Pat(self.pat()?),
+        _ => {
+          let pat = self.pat()?;
+          let ty = if let Token::Colon = self.peek()?.token() {
+            self.expect([Token::Colon]).unwrap();
+            Some(self.type_expr()?)
+          } else {
+            None
+          };
+          LambdaExprParameter::Pat(pat, ty)
+        }
       };
 
       parameters.push(parameter);
@@ -481,10 +689,18 @@ impl Parser {
       .expect([Token::CloseParen])
       .expect("`self.peek` returned close paren token");
 
+    let return_ty = if let Token::Colon = self.peek()?.token() {
+      self.expect([Token::Colon]).unwrap();
+      Some(self.type_expr()?)
+    } else {
+      None
+    };
+
     let arrow_lexeme = self.expect([Token::Arrow])?;
 
     Ok((
       parameters,
+      return_ty,
       Span::combine(open_paren_lexeme.span(), arrow_lexeme.span()),
     ))
   }
@@ -586,8 +802,163 @@ impl Parser {
     }))
   }
 
+  // Parses a type annotation, which is a type or alternatives separated by
+  // `|`.
+  fn type_expr(&mut self) -> Result<TypeExpr, CompileError> {
+    let mut types = vec![self.type_expr_alternative()?];
+    while let Token::Pipe = self.peek()?.token() {
+      self.expect([Token::Pipe]).unwrap();
+      types.push(self.type_expr_alternative()?);
+    }
+
+    if types.len() == 1 {
+      Ok(types.pop().unwrap())
+    } else {
+      Ok(TypeExpr::Union(types))
+    }
+  }
+
+  fn type_expr_alternative(&mut self) -> Result<TypeExpr, CompileError> {
+    match self.peek()?.token() {
+      Token::Ident => {
+        let ident = self.ident()?;
+        if !is_pascal_case(ident.content) {
+          return Ok(TypeExpr::Name(ident));
+        }
+        // A tag without fields can leave out its parentheses, as in
+        // `Some(int) | None`.
+        if self.peek()?.token() != Token::OpenParen {
+          return Ok(TypeExpr::Tag(TagTypeExpr {
+            span: ident.span(),
+            tag: ident,
+            fields: TagFields::Positional(Vec::new()),
+          }));
+        }
+
+        self.expect([Token::OpenParen]).unwrap();
+        let mut positional = Vec::new();
+        let mut named = Vec::new();
+        while self.peek()?.token() != Token::CloseParen {
+          let start = self.peek()?.span().clone();
+          let ty = self.type_expr()?;
+          match (ty, self.peek()?.token()) {
+            (TypeExpr::Name(name), Token::Colon) => {
+              self.expect([Token::Colon]).unwrap();
+              let ty = self.type_expr()?;
+              if !positional.is_empty() {
+                return Err(CompileError::Verify(
+                  VerifyError::mixed_tag_fields(Span::combine(
+                    &start,
+                    &ty.span(),
+                  )),
+                ));
+              }
+              named.push((name, ty));
+            }
+            (ty, _) => {
+              if !named.is_empty() {
+                return Err(CompileError::Verify(
+                  VerifyError::mixed_tag_fields(ty.span()),
+                ));
+              }
+              positional.push(ty);
+            }
+          }
+
+          match self.peek()?.token() {
+            Token::Comma => {
+              self.expect([Token::Comma]).unwrap();
+            }
+            Token::CloseParen => {}
+            _ => {
+              return Err(
+                self.build_error(Some([Token::Comma, Token::CloseParen])),
+              )
+            }
+          }
+        }
+        let close_paren_lexeme = self.expect([Token::CloseParen]).unwrap();
+
+        let fields = if named.is_empty() {
+          TagFields::Positional(positional)
+        } else {
+          TagFields::Named(named)
+        };
+        Ok(TypeExpr::Tag(TagTypeExpr {
+          span: Span::combine(&ident.span, close_paren_lexeme.span()),
+          tag: ident,
+          fields,
+        }))
+      }
+      Token::Null => {
+        let lexeme = self.expect([Token::Null]).unwrap();
+        let span = lexeme.span();
+        Ok(TypeExpr::Name(Ident {
+          span: span.clone(),
+          content: span.content().intern(),
+        }))
+      }
+      Token::OpenBracket => {
+        let open_bracket_lexeme = self.expect([Token::OpenBracket]).unwrap();
+        let item = self.type_expr()?;
+        let close_bracket_lexeme = self.expect([Token::CloseBracket])?;
+        Ok(TypeExpr::Array(ArrayTypeExpr {
+          span: Span::combine(
+            open_bracket_lexeme.span(),
+            close_bracket_lexeme.span(),
+          ),
+          item: Box::new(item),
+        }))
+      }
+      Token::OpenBrace => {
+        let open_brace_lexeme = self.expect([Token::OpenBrace]).unwrap();
+        let mut fields: Vec<(Ident, TypeExpr)> = Vec::new();
+        while self.peek()?.token() != Token::CloseBrace {
+          let name = self.ident()?;
+          self.expect([Token::Colon])?;
+          let ty = self.type_expr()?;
+          if fields
+            .iter()
+            .any(|(other, _)| other.content == name.content)
+          {
+            return Err(CompileError::Verify(
+              VerifyError::duplicate_tag_field(name.span()),
+            ));
+          }
+          fields.push((name, ty));
+
+          match self.peek()?.token() {
+            Token::Comma => {
+              self.expect([Token::Comma]).unwrap();
+            }
+            Token::CloseBrace => {}
+            _ => {
+              return Err(
+                self.build_error(Some([Token::Comma, Token::CloseBrace])),
+              )
+            }
+          }
+        }
+        let close_brace_lexeme = self.expect([Token::CloseBrace]).unwrap();
+        Ok(TypeExpr::Map(MapTypeExpr {
+          span: Span::combine(
+            open_brace_lexeme.span(),
+            close_brace_lexeme.span(),
+          ),
+          fields,
+        }))
+      }
+      _ => Err(self.build_error(Some([
+        Token::Ident,
+        Token::Null,
+        Token::OpenBracket,
+        Token::OpenBrace,
+      ]))),
+    }
+  }
+
   fn ident(&mut self) -> Result<Ident, CompileError> {
-    let lexeme = self.expect([Token::Ident]).unwrap();
+    let lexeme = self.expect([Token::Ident])?;
     let span = lexeme.span();
     Ok(Ident {
       span: span.clone(),
@@ -599,11 +970,12 @@ impl Parser {
     let lit = match self.peek()?.token() {
       Token::Number => {
         let lexeme = self.expect([Token::Number]).unwrap();
-        let number = lexeme
-          .span()
-          .content()
-          .parse()
-          .expect("invalid number was lexed");
+        let number = number(lexeme.span().content()).unwrap_or_else(|| {
+          self.record(CompileError::Verify(VerifyError::integer_out_of_range(
+            lexeme.span().clone(),
+          )));
+          Number::Int(0)
+        });
         Lit::Number(NumberLit {
           span: lexeme.span().clone(),
           number,
@@ -652,7 +1024,10 @@ impl Parser {
     &mut self,
     expected: Option<[Token; N]>,
   ) -> CompileError {
-    let lexeme = self.advance().unwrap();
+    let lexeme = match self.peek() {
+      Ok(lexeme) => lexeme,
+      Err(error) => return error,
+    };
     CompileError::Parse(ParseError::tokens(
       lexeme.span().clone(),
       lexeme.token(),
@@ -668,6 +1043,7 @@ impl Parser {
     loop {
       let lexeme = self.lexer.next()?;
       if let Token::Comment = lexeme.token() {
+        self.comments.push(lexeme.span().clone());
       } else {
         self.current = Some(lexeme);
         return self.peek();
@@ -675,6 +1051,26 @@ impl Parser {
     }
   }
 
+  // Peeks at the next token, recording lexer errors on the way.
+  fn recovering_peek(&mut self) -> Lexeme {
+    loop {
+      match self.peek() {
+        Ok(lexeme) => return lexeme,
+        Err(error) => self.record(error),
+      }
+    }
+  }
+
+  // Records an error, unless one was already reported at the same place.
+  fn record(&mut self, error: CompileError) {
+    let span = |error: &CompileError| {
+      error.span().map(|span| (span.start(), span.end()))
+    };
+    if self.errors.last().map(span) != Some(span(&error)) {
+      self.errors.push(error);
+    }
+  }
+
   fn advance(&mut self) -> Result<Lexeme, CompileError> {
     self.peek()?;
     Ok(self.current.take().expect("`self.peek` was just called"))
@@ -684,10 +1080,11 @@ impl Parser {
     &mut self,
     expected: [Token; N],
   ) -> Result<Lexeme, CompileError> {
-    let lexeme = self.advance()?;
+    // The unexpected token is left in place for error recovery.
+    let lexeme = self.peek()?;
 
     if expected.contains(&lexeme.token()) {
-      Ok(lexeme)
+      self.advance()
     } else {
       Err(CompileError::Parse(ParseError::tokens(
         lexeme.span().clone(),
@@ -698,14 +1095,62 @@ impl Parser {
   }
 }
 
+fn flatten_alternatives(expr: Expr, exprs: &mut Vec<Expr>) {
+  match expr {
+    Expr::Binary(BinaryExpr {
+      op: BinaryOp::BitOr,
+      left,
+      right,
+    }) => {
+      flatten_alternatives(*left, exprs);
+      flatten_alternatives(*right, exprs);
+    }
+    expr => exprs.push(expr),
+  }
+}
+
+fn bound_names(pat: &Pat) -> Vec<&'static str> {
+  let mut names = pat
+    .idents()
+    .into_iter()
+    .map(|ident| ident.content.as_str())
+    .collect::<Vec<_>>();
+  names.sort_unstable();
+  names.dedup();
+  names
+}
+
 fn prefix_binding_power(token: Token) -> Option<u8> {
   let power = match token {
-    Token::Dash | Token::Bang => 15,
+    Token::Dash | Token::Bang => 23,
     _ => return None,
   };
   Some(power)
 }
 
+// Converts a lexed number literal, or returns `None` if it's an integer that
+// doesn't fit in an `i64`.
+fn number(content: &str) -> Option<Number> {
+  let digits = content.replace('_', "");
+  let radix = match digits.get(..2) {
+    Some("0x") => 16,
+    Some("0o") => 8,
+    Some("0b") => 2,
+    _ => 10,
+  };
+
+  if radix != 10 {
+    i64::from_str_radix(&digits[2..], radix)
+      .ok()
+      .map(Number::Int)
+  } else if digits.contains(['.', 'e', 'E']) {
+    let float = digits.parse().expect("invalid float was lexed");
+    Some(Number::Float(float))
+  } else {
+    digits.parse().ok().map(Number::Int)
+  }
+}
+
 fn infix_binding_power(token: Token) -> Option<(u8, u8)> {
   let power = match token {
     Token::Equal => (1, 2),
@@ -714,9 +1159,16 @@ fn infix_binding_power(token: Token) -> Option<(u8, u8)> {
     Token::Greater | Token::GreaterEqual | Token::Less | Token::LessEqual => {
       (7, 8)
     }
-    Token::Plus | Token::Dash => (9, 10),
-    Token::Star | Token::Slash => (11, 12),
-    Token::Dot => (13, 14),
+    Token::Pipe => (9, 10),
+    Token::Caret => (11, 12),
+    Token::Ampersand => (13, 14),
+    Token::LessLess | Token::GreaterGreater => (15, 16),
+    Token::Plus | Token::Dash => (17, 18),
+    Token::Star | Token::Slash | Token::Percent => (19, 20),
+    Token::Dot => (21, 22),
+    // Right associative, and binds tighter than prefix operators so that
+    // `-2 ** 2` is `-(2 ** 2)`.
+    Token::StarStar => (25, 24),
     _ => return None,
   };
   Some(power)
@@ -724,8 +1176,55 @@ fn infix_binding_power(token: Token) -> Option<(u8, u8)> {
 
 fn postfix_binding_power(token: Token) -> Option<u8> {
   let power = match token {
-    Token::OpenParen | Token::OpenBracket => 16,
+    Token::OpenParen | Token::OpenBracket => 26,
     _ => return None,
   };
   Some(power)
 }
+
+#[cfg(test)]
+mod tests {
+  use std::path::Path;
+
+  use super::*;
+  use crate::{error::VerifyErrorReason, source::Source};
+
+  #[test]
+  fn integer_values() {
+    let ints = ["1_000", "0xff", "0o17", "0b1_01", "0x7fff_ffff_ffff_ffff"]
+      .map(|content| match number(content) {
+        Some(Number::Int(int)) => int,
+        number => panic!("expected an integer, found {:?}", number),
+      });
+    assert_eq!(ints, [1000, 255, 15, 5, i64::MAX]);
+  }
+
+  #[test]
+  fn float_values() {
+    let floats =
+      ["1_0.5", "1.5e-3", "2E+2"].map(|content| match number(content) {
+        Some(Number::Float(float)) => float,
+        number => panic!("expected a float, found {:?}", number),
+      });
+    assert_eq!(floats, [10.5, 0.0015, 200.0]);
+  }
+
+  #[test]
+  fn integer_out_of_range() {
+    let binary = format!("0b1{}", "0".repeat(64));
+    for source in [
+      "9_223_372_036_854_775_808",
+      "0x8000_0000_0000_0000",
+      &binary,
+    ] {
+      let source = Source::from_str(source, Path::new("test.oma"));
+      match Parser::new(source).parse() {
+        Err(CompileError::Verify(error)) => assert!(matches!(
+          error.reason(),
+          VerifyErrorReason::IntegerOutOfRange
+        )),
+        result => panic!("expected an error, found {:?}", result),
+      }
+    }
+  }
+}
@@ -0,0 +1,936 @@
+// Canonical pretty-printer for Oma source, used by `oma fmt`.
+//
+// A module is first turned into a document in the style of Wadler's "A
+// prettier printer": text, line breaks that a group may render as spaces if it
+// fits within the line width, and nesting. Groups containing a hard line break
+// or a line comment always break.
+//
+// The AST has no trivia, so comments are placed by position. Before each item
+// of a block or a delimited list, any comment starting before the item is
+// printed on its own line. A comment following code on the same line is kept at
+// the end of that line. Comments in other positions move to the next place a
+// line can start. Single blank lines between statements are preserved.
+
+use crate::{ast::*, source::Span};
+
+const WIDTH: usize = 80;
+const INDENT: usize = 2;
+
+// The binding power of an expression that can't be an operand without parens.
+const LOOSE: u8 = 0;
+// The binding power of an expression that never needs parens.
+const TIGHT: u8 = u8::MAX;
+
+const PREFIX_POWER: u8 = 23;
+const POSTFIX_POWER: u8 = 26;
+const DOT_POWER: u8 = 21;
+const ASSIGN_POWER: u8 = 1;
+
+pub fn print(module: &Module, comments: &[Span]) -> String {
+  let span = module.span();
+  let mut printer = Printer {
+    content: span.source().content(),
+    comments,
+    next_comment: 0,
+  };
+
+  let mut doc = match &module.body {
+    Expr::Block(block_expr) => printer.statements(block_expr, usize::MAX),
+    expr => printer.expr(expr).0,
+  };
+  if doc.is_empty() {
+    return String::new();
+  }
+  doc = concat(vec![doc, Doc::HardLine]);
+
+  render(&doc)
+}
+
+enum Doc {
+  Text(String),
+  // A space, or a line break if the enclosing group breaks.
+  Line,
+  // Nothing, or a line break if the enclosing group breaks.
+  SoftLine,
+  HardLine,
+  // Text that is only printed if the enclosing group breaks.
+  IfBreak(&'static str),
+  // A line comment, printed at the end of the current line.
+  Suffix(String),
+  Nest(Box<Doc>),
+  // The flag is set if the group contains a hard line break.
+  Group(Box<Doc>, bool),
+  Concat(Vec<Doc>),
+}
+
+impl Doc {
+  fn is_hard(&self) -> bool {
+    match self {
+      Self::HardLine | Self::Suffix(_) => true,
+      Self::Group(_, hard) => *hard,
+      Self::Nest(doc) => doc.is_hard(),
+      Self::Concat(docs) => docs.iter().any(Doc::is_hard),
+      _ => false,
+    }
+  }
+
+  fn is_empty(&self) -> bool {
+    match self {
+      Self::Text(text) => text.is_empty(),
+      Self::Concat(docs) => docs.iter().all(Doc::is_empty),
+      _ => false,
+    }
+  }
+}
+
+fn text(text: impl Into<String>) -> Doc {
+  Doc::Text(text.into())
+}
+
+fn concat(docs: Vec<Doc>) -> Doc {
+  Doc::Concat(docs)
+}
+
+fn nest(doc: Doc) -> Doc {
+  Doc::Nest(Box::new(doc))
+}
+
+fn group(doc: Doc) -> Doc {
+  let hard = doc.is_hard();
+  Doc::Group(Box::new(doc), hard)
+}
+
+// How a delimited list is printed.
+struct Delims {
+  open: &'static str,
+  close: &'static str,
+  // Pad the items with spaces when the list fits on one line.
+  spaced: bool,
+  // Offset of the closing delimiter, if the AST records it.
+  end: Option<usize>,
+}
+
+struct Printer<'a> {
+  content: &'a str,
+  comments: &'a [Span],
+  next_comment: usize,
+}
+
+impl<'a> Printer<'a> {
+  fn statements(&mut self, block_expr: &BlockExpr, end: usize) -> Doc {
+    let mut docs = Vec::new();
+
+    for (index, expr) in block_expr.exprs.iter().enumerate() {
+      let span = expr.span();
+      while let Some(comment) = self.comment_before(span.start()) {
+        self.separate(&mut docs, comment.start());
+        docs.push(text(self.comment_text(comment)));
+      }
+
+      self.separate(&mut docs, self.statement_start(expr));
+      docs.push(self.expr(expr).0);
+
+      let is_last = index + 1 == block_expr.exprs.len();
+      if (is_last && block_expr.has_semi) || (!is_last && needs_semi(expr)) {
+        docs.push(text(";"));
+      }
+
+      let next = block_expr
+        .exprs
+        .get(index + 1)
+        .map_or(end, |expr| expr.span().start());
+      if let Some(comment) = self.trailing_comment(next) {
+        docs.push(Doc::Suffix(self.comment_text(comment)));
+      }
+    }
+
+    while let Some(comment) = self.comment_before(end) {
+      self.separate(&mut docs, comment.start());
+      docs.push(text(self.comment_text(comment)));
+    }
+
+    concat(docs)
+  }
+
+  // Where the statement starts in the source, including the `let` keyword
+  // that isn't part of a binding's span.
+  fn statement_start(&self, expr: &Expr) -> usize {
+    let start = expr.span().start();
+    match expr {
+      Expr::Bind(_) => self.content[..start]
+        .trim_end()
+        .strip_suffix("let")
+        .map_or(start, str::len),
+      _ => start,
+    }
+  }
+
+  // Starts a new line, keeping a blank line if the source had one.
+  fn separate(&self, docs: &mut Vec<Doc>, start: usize) {
+    if !docs.is_empty() {
+      docs.push(Doc::HardLine);
+      // Spans of some expressions stop short of their closing delimiter, so
+      // only the whitespace directly before the item is considered.
+      let gap_start = self.content[..start].trim_end().len();
+      if self.content[gap_start..start].matches('\n').count() > 1 {
+        docs.push(Doc::HardLine);
+      }
+    }
+  }
+
+  fn block(&mut self, expr: &Expr, end: usize) -> Doc {
+    let block_expr = match expr {
+      Expr::Block(block_expr) => block_expr,
+      expr => return self.expr(expr).0,
+    };
+
+    let statements = self.statements(block_expr, end);
+    if statements.is_empty() {
+      return text("{}");
+    }
+    concat(vec![
+      text("{"),
+      nest(concat(vec![Doc::HardLine, statements])),
+      Doc::HardLine,
+      text("}"),
+    ])
+  }
+
+  // Returns the document for an expression along with its left and right
+  // binding powers, which decide whether it needs parens as an operand.
+  fn expr(&mut self, expr: &Expr) -> (Doc, u8, u8) {
+    match expr {
+      Expr::Lit(lit) => (self.lit(lit), TIGHT, TIGHT),
+      Expr::Ident(ident) => (text(ident.content.as_str()), TIGHT, TIGHT),
+      Expr::Map(map_expr) => (self.map_expr(map_expr), TIGHT, TIGHT),
+      Expr::Array(array_expr) => {
+        let doc = self.list(
+          Delims {
+            open: "[",
+            close: "]",
+            spaced: false,
+            end: Some(array_expr.span.end() - 1),
+          },
+          &array_expr.items,
+          |item| match item {
+            ArrayExprItem::Spread(expr) | ArrayExprItem::Expr(expr) => {
+              expr.span().start()
+            }
+          },
+          |printer, item| match item {
+            ArrayExprItem::Spread(expr) => {
+              concat(vec![text(".."), printer.expr(expr).0])
+            }
+            ArrayExprItem::Expr(expr) => printer.expr(expr).0,
+          },
+        );
+        (doc, TIGHT, TIGHT)
+      }
+      Expr::Lambda(lambda_expr) => {
+        (self.lambda_expr(lambda_expr), TIGHT, LOOSE)
+      }
+      Expr::Tag(tag_expr) => {
+        let end = tag_expr.span.end() - 1;
+        let fields = match &tag_expr.fields {
+          TagFields::Positional(fields) => self.list(
+            Delims::parens(Some(end)),
+            fields,
+            |field| field.span().start(),
+            |printer, field| printer.expr(field).0,
+          ),
+          TagFields::Named(fields) => self.list(
+            Delims::parens(Some(end)),
+            fields,
+            |(name, _)| name.span.start(),
+            |printer, (name, field)| {
+              concat(vec![
+                text(format!("{}: ", name.content)),
+                printer.expr(field).0,
+              ])
+            },
+          ),
+        };
+        (
+          concat(vec![text(tag_expr.tag.content.as_str()), fields]),
+          TIGHT,
+          TIGHT,
+        )
+      }
+      Expr::Block(block_expr) => {
+        (self.block(expr, block_expr.span.end()), LOOSE, LOOSE)
+      }
+      Expr::Binary(binary_expr) => self.binary_expr(binary_expr),
+      Expr::Unary(unary_expr) => {
+        let op = match unary_expr.op {
+          UnaryOp::Negate => "-",
+          UnaryOp::Not => "!",
+        };
+        let (operand, _, right) =
+          self.right_operand(&unary_expr.operand, PREFIX_POWER);
+        (
+          concat(vec![text(op), operand]),
+          TIGHT,
+          right.min(PREFIX_POWER),
+        )
+      }
+      Expr::Bind(bind_expr) => {
+        let mut docs = vec![text("let "), self.pat(&bind_expr.bindee)];
+        if let Some(ty) = &bind_expr.ty {
+          docs.push(text(format!(": {}", ty)));
+        }
+        docs.push(text(" = "));
+        docs.push(self.expr(&bind_expr.value).0);
+        let doc = concat(docs);
+        (doc, LOOSE, LOOSE)
+      }
+      Expr::Assign(assign_expr) => {
+        let (assignee, left) = match &assign_expr.assignee {
+          AssignExprAssignee::Pat(pat) => (self.pat(pat), TIGHT),
+          AssignExprAssignee::Access(access_expr) => {
+            let (doc, left, _) = self.access_expr(access_expr);
+            (doc, left)
+          }
+        };
+        let doc =
+          concat(vec![assignee, text(" = "), self.expr(&assign_expr.value).0]);
+        (doc, left.min(ASSIGN_POWER), LOOSE)
+      }
+      Expr::Call(call_expr) => self.call_expr(call_expr),
+      Expr::Access(access_expr) => self.access_expr(access_expr),
+      Expr::If(if_expr) => (self.if_expr(if_expr), LOOSE, LOOSE),
+      Expr::Case(case_expr) => (self.case_expr(case_expr), LOOSE, LOOSE),
+      Expr::For(for_expr) => {
+        let doc = concat(vec![
+          text("for "),
+          self.pat(&for_expr.item),
+          text(" in "),
+          self.expr(&for_expr.iterator).0,
+          text(" "),
+          self.block(&for_expr.body, for_expr.span.end()),
+        ]);
+        (doc, LOOSE, LOOSE)
+      }
+      Expr::While(while_expr) => {
+        let doc = concat(vec![
+          text("while "),
+          self.expr(&while_expr.condition).0,
+          text(" "),
+          self.block(&while_expr.body, while_expr.span.end()),
+        ]);
+        (doc, LOOSE, LOOSE)
+      }
+    }
+  }
+
+  // An operand that is followed by an operator binding with the given power.
+  fn left_operand(&mut self, expr: &Expr, power: u8) -> (Doc, u8, u8) {
+    let (doc, left, right) = self.expr(expr);
+    if right <= power {
+      (parens(doc), TIGHT, TIGHT)
+    } else {
+      (doc, left, right)
+    }
+  }
+
+  // An operand that is preceded by an operator binding with the given power.
+  fn right_operand(&mut self, expr: &Expr, power: u8) -> (Doc, u8, u8) {
+    let (doc, left, right) = self.expr(expr);
+    if left < power {
+      (parens(doc), TIGHT, TIGHT)
+    } else {
+      (doc, left, right)
+    }
+  }
+
+  fn binary_expr(&mut self, binary_expr: &BinaryExpr) -> (Doc, u8, u8) {
+    let mut docs = Vec::new();
+    let powers = self.binary_chain(binary_expr, &mut docs);
+    let rest = docs.split_off(1);
+    let doc = group(concat(vec![docs.pop().unwrap(), nest(concat(rest))]));
+    (doc, powers.0, powers.1)
+  }
+
+  // Collects the operands of a chain of left associative operators with the
+  // same binding power, so that they break together.
+  fn binary_chain(
+    &mut self,
+    binary_expr: &BinaryExpr,
+    docs: &mut Vec<Doc>,
+  ) -> (u8, u8) {
+    let (op, (left_power, right_power)) = binary_op(&binary_expr.op);
+
+    let left = match &*binary_expr.left {
+      Expr::Binary(left_expr)
+        if left_power < right_power
+          && binary_op(&left_expr.op).1 == (left_power, right_power) =>
+      {
+        self.binary_chain(left_expr, docs).0
+      }
+      expr => {
+        let (doc, left, _) = self.left_operand(expr, left_power);
+        docs.push(doc);
+        left
+      }
+    };
+
+    let (right_doc, _, right) =
+      self.right_operand(&binary_expr.right, right_power);
+    docs.push(text(format!(" {}", op)));
+    docs.push(Doc::Line);
+    docs.push(right_doc);
+
+    (left.min(left_power), right.min(right_power))
+  }
+
+  fn access_expr(&mut self, access_expr: &AccessExpr) -> (Doc, u8, u8) {
+    match &access_expr.field {
+      AccessExprField::Ident(ident) => {
+        let (receiver, left, _) =
+          self.left_operand(&access_expr.receiver, DOT_POWER);
+        let doc = concat(vec![receiver, text(format!(".{}", ident.content))]);
+        (doc, left.min(DOT_POWER), TIGHT)
+      }
+      AccessExprField::Expr(field) => {
+        let (receiver, left, _) =
+          self.left_operand(&access_expr.receiver, POSTFIX_POWER);
+        let doc =
+          concat(vec![receiver, text("["), self.expr(field).0, text("]")]);
+        (doc, left, TIGHT)
+      }
+      AccessExprField::Slice(start, end) => {
+        let (receiver, left, _) =
+          self.left_operand(&access_expr.receiver, POSTFIX_POWER);
+        let mut docs = vec![receiver, text("[")];
+        if let Some(start) = start {
+          docs.push(self.expr(start).0);
+        }
+        docs.push(text(".."));
+        if let Some(end) = end {
+          docs.push(self.expr(end).0);
+        }
+        docs.push(text("]"));
+        (concat(docs), left, TIGHT)
+      }
+    }
+  }
+
+  fn call_expr(&mut self, call_expr: &CallExpr) -> (Doc, u8, u8) {
+    let (receiver, left, _) =
+      self.left_operand(&call_expr.receiver, POSTFIX_POWER);
+
+    let argument =
+      |printer: &mut Self, argument: &CallExprArgument| match argument {
+        CallExprArgument::Spread(expr) => {
+          concat(vec![text(".."), printer.expr(expr).0])
+        }
+        CallExprArgument::Expr(expr) => printer.expr(expr).0,
+      };
+
+    // A trailing lambda with a block body stays on the line of the call,
+    // unless a comment has to go between the arguments.
+    let hugs = match call_expr.arguments.last() {
+      Some(CallExprArgument::Expr(Expr::Lambda(lambda_expr))) => {
+        matches!(*lambda_expr.body, Expr::Block(_))
+          && !self.has_comment_before(lambda_expr.body.span().start())
+      }
+      _ => false,
+    };
+    if hugs {
+      let mut docs = vec![receiver, text("(")];
+      for (index, argument_expr) in call_expr.arguments.iter().enumerate() {
+        if index > 0 {
+          docs.push(text(", "));
+        }
+        docs.push(group(argument(self, argument_expr)));
+      }
+      docs.push(text(")"));
+      return (concat(docs), left, TIGHT);
+    }
+
+    let arguments = self.list(
+      Delims::parens(None),
+      &call_expr.arguments,
+      |argument| argument.span().start(),
+      argument,
+    );
+    (concat(vec![receiver, arguments]), left, TIGHT)
+  }
+
+  fn lambda_expr(&mut self, lambda_expr: &LambdaExpr) -> Doc {
+    let parameters = self.list(
+      Delims::parens(None),
+      &lambda_expr.parameters,
+      |parameter| match parameter {
+        LambdaExprParameter::Spread(ident) => ident.span.start(),
+        LambdaExprParameter::Pat(pat, _) => pat.span().start(),
+      },
+      |printer, parameter| match parameter {
+        LambdaExprParameter::Spread(ident) => {
+          text(format!("..{}", ident.content))
+        }
+        LambdaExprParameter::Pat(pat, None) => printer.pat(pat),
+        LambdaExprParameter::Pat(pat, Some(ty)) => {
+          concat(vec![printer.pat(pat), text(format!(": {}", ty))])
+        }
+      },
+    );
+    let arrow = match &lambda_expr.return_ty {
+      Some(ty) => format!(": {} -> ", ty),
+      None => " -> ".to_string(),
+    };
+
+    let body = match &*lambda_expr.body {
+      Expr::Block(_) => self.block(&lambda_expr.body, lambda_expr.span.end()),
+      // A map would be read back as a block.
+      Expr::Map(_) => parens(self.expr(&lambda_expr.body).0),
+      expr => self.expr(expr).0,
+    };
+
+    concat(vec![parameters, text(arrow), body])
+  }
+
+  fn if_expr(&mut self, if_expr: &IfExpr) -> Doc {
+    let body_end = if_expr
+      .otherwise
+      .as_ref()
+      .map_or(if_expr.span.end(), |otherwise| otherwise.span().start());
+
+    let mut docs = vec![
+      text("if "),
+      self.expr(&if_expr.condition).0,
+      text(" "),
+      self.block(&if_expr.body, body_end),
+    ];
+    if let Some(otherwise) = &if_expr.otherwise {
+      docs.push(text(" else "));
+      docs.push(match &**otherwise {
+        Expr::If(if_expr) => self.if_expr(if_expr),
+        otherwise => self.block(otherwise, if_expr.span.end()),
+      });
+    }
+    concat(docs)
+  }
+
+  fn case_expr(&mut self, case_expr: &CaseExpr) -> Doc {
+    let mut arms = Vec::new();
+    for (index, arm) in case_expr.arms.iter().enumerate() {
+      let start = arm.pats[0].span().start();
+      while let Some(comment) = self.comment_before(start) {
+        arms.push(Doc::HardLine);
+        arms.push(text(self.comment_text(comment)));
+      }
+
+      let mut docs = Vec::new();
+      for (index, pat) in arm.pats.iter().enumerate() {
+        if index > 0 {
+          docs.push(text(" | "));
+        }
+        docs.push(self.pat(pat));
+      }
+      if let Some(guard) = &arm.guard {
+        docs.push(text(" if "));
+        docs.push(self.expr(guard).0);
+      }
+      docs.push(text(" -> "));
+
+      let next = case_expr
+        .arms
+        .get(index + 1)
+        .map_or(case_expr.span.end(), |arm| arm.pats[0].span().start());
+      match &arm.expr {
+        Expr::Block(_) => docs.push(self.block(&arm.expr, next)),
+        // A map would be read back as a block.
+        Expr::Map(_) => {
+          docs.push(parens(self.expr(&arm.expr).0));
+          docs.push(text(","));
+        }
+        expr => {
+          docs.push(self.expr(expr).0);
+          docs.push(text(","));
+        }
+      }
+      if let Some(comment) = self.trailing_comment(next) {
+        docs.push(Doc::Suffix(self.comment_text(comment)));
+      }
+
+      arms.push(Doc::HardLine);
+      arms.push(group(concat(docs)));
+    }
+
+    while let Some(comment) = self.comment_before(case_expr.span.end()) {
+      arms.push(Doc::HardLine);
+      arms.push(text(self.comment_text(comment)));
+    }
+
+    if arms.is_empty() {
+      return concat(vec![
+        text("case "),
+        self.expr(&case_expr.subject).0,
+        text(" {}"),
+      ]);
+    }
+    concat(vec![
+      text("case "),
+      self.expr(&case_expr.subject).0,
+      text(" {"),
+      nest(concat(arms)),
+      Doc::HardLine,
+      text("}"),
+    ])
+  }
+
+  fn map_expr(&mut self, map_expr: &MapExpr) -> Doc {
+    self.list(
+      Delims::braces(map_expr.span.end() - 1),
+      &map_expr.pairs,
+      |pair| match pair {
+        MapExprPair::Spread(expr) | MapExprPair::Expr(expr, _) => {
+          expr.span().start()
+        }
+        MapExprPair::Ident(field, _) => field.span.start(),
+      },
+      |printer, pair| match pair {
+        MapExprPair::Spread(expr) => {
+          concat(vec![text(".."), printer.expr(expr).0])
+        }
+        MapExprPair::Expr(field, value) => concat(vec![
+          text("["),
+          printer.expr(field).0,
+          text("]: "),
+          printer.expr(value).0,
+        ]),
+        MapExprPair::Ident(field, Expr::Ident(value)) if field == value => {
+          text(field.content.as_str())
+        }
+        MapExprPair::Ident(field, value) => concat(vec![
+          text(format!("{}: ", field.content)),
+          printer.expr(value).0,
+        ]),
+      },
+    )
+  }
+
+  fn pat(&mut self, pat: &Pat) -> Doc {
+    match pat {
+      Pat::Lit(lit) => self.lit(lit),
+      Pat::Ident(ident) => text(ident.content.as_str()),
+      Pat::Tag(tag_pat) => {
+        let end = tag_pat.span.end() - 1;
+        let fields = match &tag_pat.fields {
+          TagFields::Positional(fields) => self.list(
+            Delims::parens(Some(end)),
+            fields,
+            |field| field.span().start(),
+            |printer, field| printer.pat(field),
+          ),
+          TagFields::Named(fields) => self.list(
+            Delims::parens(Some(end)),
+            fields,
+            |(name, _)| name.span.start(),
+            |printer, (name, field)| {
+              concat(vec![
+                text(format!("{}: ", name.content)),
+                printer.pat(field),
+              ])
+            },
+          ),
+        };
+        concat(vec![text(tag_pat.tag.content.as_str()), fields])
+      }
+      Pat::Map(map_pat) => self.list(
+        Delims::braces(map_pat.span.end() - 1),
+        &map_pat.pairs,
+        |pair| match pair {
+          MapPatPair::Spread(ident) | MapPatPair::Ident(ident, _) => {
+            ident.span.start()
+          }
+        },
+        |printer, pair| match pair {
+          MapPatPair::Spread(ident) => text(format!("..{}", ident.content)),
+          MapPatPair::Ident(field, Pat::Ident(value)) if field == value => {
+            text(field.content.as_str())
+          }
+          MapPatPair::Ident(field, value) => concat(vec![
+            text(format!("{}: ", field.content)),
+            printer.pat(value),
+          ]),
+        },
+      ),
+      Pat::Array(array_pat) => self.list(
+        Delims {
+          open: "[",
+          close: "]",
+          spaced: false,
+          end: Some(array_pat.span.end() - 1),
+        },
+        &array_pat.items,
+        |item| match item {
+          ArrayPatItem::Spread(ident) => ident.span.start(),
+          ArrayPatItem::Pat(pat) => pat.span().start(),
+        },
+        |printer, item| match item {
+          ArrayPatItem::Spread(ident) => text(format!("..{}", ident.content)),
+          ArrayPatItem::Pat(pat) => printer.pat(pat),
+        },
+      ),
+    }
+  }
+
+  fn lit(&self, lit: &Lit) -> Doc {
+    text(lit.span().content())
+  }
+
+  // Prints a comma separated list that is broken one item per line, with a
+  // trailing comma, if it doesn't fit.
+  fn list<T>(
+    &mut self,
+    delims: Delims,
+    items: &[T],
+    start: impl Fn(&T) -> usize,
+    mut item: impl FnMut(&mut Self, &T) -> Doc,
+  ) -> Doc {
+    let mut docs = Vec::new();
+    for (index, list_item) in items.iter().enumerate() {
+      let item_start = start(list_item);
+      while let Some(comment) = self.comment_before(item_start) {
+        docs.push(text(self.comment_text(comment)));
+        docs.push(Doc::HardLine);
+      }
+
+      docs.push(item(self, list_item));
+
+      let next = items.get(index + 1).map(&start).or(delims.end);
+      if index + 1 < items.len() {
+        docs.push(text(","));
+      } else {
+        docs.push(Doc::IfBreak(","));
+      }
+      if let Some(comment) = next.and_then(|next| self.trailing_comment(next)) {
+        docs.push(Doc::Suffix(self.comment_text(comment)));
+      }
+      if index + 1 < items.len() {
+        docs.push(Doc::Line);
+      }
+    }
+
+    if let Some(end) = delims.end {
+      while let Some(comment) = self.comment_before(end) {
+        if !docs.is_empty() {
+          docs.push(Doc::HardLine);
+        }
+        docs.push(text(self.comment_text(comment)));
+      }
+    }
+
+    if docs.is_empty() {
+      return text(format!("{}{}", delims.open, delims.close));
+    }
+
+    let line = || {
+      if delims.spaced {
+        Doc::Line
+      } else {
+        Doc::SoftLine
+      }
+    };
+    group(concat(vec![
+      text(delims.open),
+      nest(concat(vec![line(), concat(docs)])),
+      line(),
+      text(delims.close),
+    ]))
+  }
+
+  fn has_comment_before(&self, offset: usize) -> bool {
+    self
+      .comments
+      .get(self.next_comment)
+      .is_some_and(|comment| comment.start() < offset)
+  }
+
+  fn comment_before(&mut self, offset: usize) -> Option<&'a Span> {
+    let comment = self.comments.get(self.next_comment)?;
+    if comment.start() < offset {
+      self.next_comment += 1;
+      Some(comment)
+    } else {
+      None
+    }
+  }
+
+  // Takes the next comment if it follows code on its line.
+  fn trailing_comment(&mut self, offset: usize) -> Option<&'a Span> {
+    let comment = self.comments.get(self.next_comment)?;
+    let line_start = self.content[..comment.start()]
+      .rfind('\n')
+      .map_or(0, |index| index + 1);
+    if comment.start() < offset
+      && !self.content[line_start..comment.start()].trim().is_empty()
+    {
+      self.next_comment += 1;
+      Some(comment)
+    } else {
+      None
+    }
+  }
+
+  fn comment_text(&self, comment: &Span) -> String {
+    comment.content().trim_end().to_string()
+  }
+}
+
+impl Delims {
+  fn parens(end: Option<usize>) -> Delims {
+    Delims {
+      open: "(",
+      close: ")",
+      spaced: false,
+      end,
+    }
+  }
+
+  fn braces(end: usize) -> Delims {
+    Delims {
+      open: "{",
+      close: "}",
+      spaced: true,
+      end: Some(end),
+    }
+  }
+}
+
+fn parens(doc: Doc) -> Doc {
+  concat(vec![text("("), doc, text(")")])
+}
+
+// Expressions ending in a block don't need a semicolon to be followed by
+// another expression.
+fn needs_semi(expr: &Expr) -> bool {
+  !matches!(
+    expr,
+    Expr::If(_) | Expr::While(_) | Expr::For(_) | Expr::Case(_)
+  )
+}
+
+// Mirrors the infix binding powers of the parser.
+fn binary_op(op: &BinaryOp) -> (&'static str, (u8, u8)) {
+  match op {
+    BinaryOp::And => ("&&", (3, 4)),
+    BinaryOp::Or => ("||", (3, 4)),
+    BinaryOp::Equal => ("==", (5, 6)),
+    BinaryOp::NotEqual => ("!=", (5, 6)),
+    BinaryOp::Greater => (">", (7, 8)),
+    BinaryOp::GreaterEqual => (">=", (7, 8)),
+    BinaryOp::Less => ("<", (7, 8)),
+    BinaryOp::LessEqual => ("<=", (7, 8)),
+    BinaryOp::BitOr => ("|", (9, 10)),
+    BinaryOp::BitXor => ("^", (11, 12)),
+    BinaryOp::BitAnd => ("&", (13, 14)),
+    BinaryOp::ShiftLeft => ("<<", (15, 16)),
+    BinaryOp::ShiftRight => (">>", (15, 16)),
+    BinaryOp::Add => ("+", (17, 18)),
+    BinaryOp::Subtract => ("-", (17, 18)),
+    BinaryOp::Multiply => ("*", (19, 20)),
+    BinaryOp::Divide => ("/", (19, 20)),
+    BinaryOp::Remainder => ("%", (19, 20)),
+    BinaryOp::Power => ("**", (25, 24)),
+  }
+}
+
+fn render(doc: &Doc) -> String {
+  let mut output = String::new();
+  let mut column = 0;
+  // Indentation is written with the first text on a line, so blank lines
+  // don't end in whitespace.
+  let mut indent_pending = None;
+  let mut suffixes = Vec::new();
+
+  let mut stack = vec![(0, false, doc)];
+  while let Some((indent, flat, doc)) = stack.pop() {
+    match doc {
+      Doc::Text(content) => {
+        if let Some(indent) = indent_pending.take() {
+          output.push_str(&" ".repeat(indent));
+          column = indent;
+        }
+        output.push_str(content);
+        column += content.chars().count();
+      }
+      Doc::Line | Doc::SoftLine | Doc::IfBreak(_) if flat => {
+        if let Doc::Line = doc {
+          output.push(' ');
+          column += 1;
+        }
+      }
+      Doc::IfBreak(content) => {
+        output.push_str(content);
+        column += content.len();
+      }
+      Doc::Line | Doc::SoftLine | Doc::HardLine => {
+        for suffix in suffixes.drain(..) {
+          output.push(' ');
+          output.push_str(suffix);
+        }
+        output.push('\n');
+        column = 0;
+        indent_pending = Some(indent);
+      }
+      Doc::Suffix(content) => suffixes.push(content),
+      Doc::Nest(doc) => stack.push((indent + INDENT, flat, doc)),
+      Doc::Group(doc, hard) => {
+        let flat =
+          flat || (!*hard && fits(WIDTH.saturating_sub(column), doc, &stack));
+        stack.push((indent, flat, doc));
+      }
+      Doc::Concat(docs) => {
+        for doc in docs.iter().rev() {
+          stack.push((indent, flat, doc));
+        }
+      }
+    }
+  }
+
+  for suffix in suffixes {
+    output.push(' ');
+    output.push_str(suffix);
+  }
+  output
+}
+
+// Checks whether a group printed flat, followed by the rest of the document up
+// to the next line break, fits in the remaining width.
+fn fits(width: usize, doc: &Doc, rest: &[(usize, bool, &Doc)]) -> bool {
+  let mut width = width as isize;
+  let mut rest = rest.iter().rev();
+  let mut stack = vec![(true, doc)];
+
+  loop {
+    let (flat, doc) = match stack.pop() {
+      Some(entry) => entry,
+      None => match rest.next() {
+        Some((_, flat, doc)) => (*flat, *doc),
+        None => return true,
+      },
+    };
+
+    match doc {
+      Doc::Text(content) => width -= content.chars().count() as isize,
+      Doc::Line if flat => width -= 1,
+      Doc::SoftLine | Doc::IfBreak(_) if flat => {}
+      Doc::IfBreak(content) => width -= content.len() as isize,
+      Doc::Line | Doc::SoftLine => return true,
+      Doc::HardLine | Doc::Suffix(_) => return !flat,
+      Doc::Nest(doc) => stack.push((flat, doc)),
+      Doc::Group(doc, hard) => stack.push((flat && !*hard, doc)),
+      Doc::Concat(docs) => {
+        for doc in docs.iter().rev() {
+          stack.push((flat, doc));
+        }
+      }
+    }
+
+    if width < 0 {
+      return false;
+    }
+  }
+}
@@ -75,6 +75,10 @@ impl Span {
     self.end - self.start
   }
 
+  pub fn is_empty(&self) -> bool {
+    self.start == self.end
+  }
+
   pub fn line(&self) -> usize {
     let mut count = 1;
     let mut offset = self.start;
@@ -89,16 +93,11 @@ impl Span {
     unreachable!();
   }
 
+  // Counts chars rather than bytes, so it matches what an editor shows.
   pub fn column(&self) -> usize {
-    let mut offset = self.start;
-    let lines = self.source.content().split("\n");
-    for line in lines {
-      if offset < line.len() + 1 {
-        return offset;
-      }
-      offset -= line.len() + 1;
-    }
-    unreachable!();
+    let before = &self.source.content()[..self.start];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    before[line_start..].chars().count()
   }
 }
 
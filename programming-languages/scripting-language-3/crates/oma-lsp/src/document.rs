@@ -0,0 +1,155 @@
+use std::path::Path;
+
+use oma_compiler::{
+  check,
+  error::CompileError,
+  exhaust,
+  gen::{Generator, Symbols},
+  parse::Parser,
+  source::{Source, Span},
+};
+
+pub struct Document {
+  text: String,
+  diagnostics: Vec<Diagnostic>,
+  // Kept from the last version that parsed, so navigation still works while
+  // the document is being edited.
+  symbols: Option<Symbols>,
+}
+
+pub struct Diagnostic {
+  pub start: usize,
+  pub end: usize,
+  pub severity: Severity,
+  pub message: String,
+}
+
+#[derive(Clone, Copy)]
+pub enum Severity {
+  Error = 1,
+  Warning = 2,
+}
+
+impl Document {
+  pub fn new() -> Document {
+    Document {
+      text: String::new(),
+      diagnostics: Vec::new(),
+      symbols: None,
+    }
+  }
+
+  pub fn diagnostics(&self) -> &[Diagnostic] {
+    &self.diagnostics
+  }
+
+  pub fn symbols(&self) -> Option<&Symbols> {
+    self.symbols.as_ref()
+  }
+
+  pub fn update(
+    &mut self,
+    text: String,
+    path: &Path,
+    registry: &[(&'static str, usize)],
+  ) {
+    self.text = text;
+
+    let (diagnostics, symbols) = analyze(&self.text, path, registry);
+
+    self.diagnostics = diagnostics;
+    if symbols.is_some() {
+      self.symbols = symbols;
+    }
+  }
+
+  // Converts a byte offset to a zero-based line and UTF-16 column.
+  pub fn position(&self, offset: usize) -> (usize, usize) {
+    let offset = offset.min(self.text.len());
+    let before = &self.text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    let line = before.matches('\n').count();
+    let column = before[line_start..].encode_utf16().count();
+    (line, column)
+  }
+
+  pub fn offset(&self, line: usize, column: usize) -> usize {
+    let mut line_start = 0;
+    for _ in 0..line {
+      match self.text[line_start..].find('\n') {
+        Some(index) => line_start += index + 1,
+        None => return self.text.len(),
+      }
+    }
+
+    let mut units = 0;
+    for (index, char) in self.text[line_start..].char_indices() {
+      if units >= column || char == '\n' {
+        return line_start + index;
+      }
+      units += char.len_utf16();
+    }
+    self.text.len()
+  }
+}
+
+fn analyze(
+  text: &str,
+  path: &Path,
+  registry: &[(&'static str, usize)],
+) -> (Vec<Diagnostic>, Option<Symbols>) {
+  let mut diagnostics = Vec::new();
+
+  let module = match Parser::new(Source::from_str(text, path)).parse() {
+    Ok(module) => module,
+    Err(error) => {
+      diagnostics.extend(Diagnostic::from_errors(&error));
+      return (diagnostics, None);
+    }
+  };
+
+  if let Err(error) = check::check(&module) {
+    diagnostics.extend(Diagnostic::from_errors(&error));
+  }
+
+  for warning in exhaust::check(&module) {
+    diagnostics.push(Diagnostic::new(
+      warning.span(),
+      Severity::Warning,
+      warning.reason().to_string(),
+    ));
+  }
+
+  let generator = Generator::new(registry.to_vec(), false);
+  let (symbols, result) = generator.symbols(module);
+  if let Err(error) = result {
+    diagnostics.extend(Diagnostic::from_errors(&error));
+  }
+
+  (diagnostics, Some(symbols))
+}
+
+impl Diagnostic {
+  fn new(span: &Span, severity: Severity, message: String) -> Diagnostic {
+    Diagnostic {
+      start: span.start(),
+      end: span.end(),
+      severity,
+      message,
+    }
+  }
+
+  fn from_errors(error: &CompileError) -> Vec<Diagnostic> {
+    error
+      .errors()
+      .iter()
+      .filter_map(|error| {
+        Some(Diagnostic::new(
+          error.span()?,
+          Severity::Error,
+          error.message(),
+        ))
+      })
+      .collect()
+  }
+}
@@ -0,0 +1,39 @@
+use std::{io, process};
+
+use oma::prelude::PRELUDE;
+
+use self::server::Server;
+
+mod document;
+mod server;
+mod transport;
+
+fn main() -> io::Result<()> {
+  let mut registry = Vec::new();
+  for (index, native) in PRELUDE.iter().enumerate() {
+    registry.push((native.name, index));
+  }
+
+  let mut server = Server::new(registry);
+
+  let stdin = io::stdin();
+  let mut reader = stdin.lock();
+  let stdout = io::stdout();
+  let mut writer = stdout.lock();
+
+  while let Some(message) = transport::read(&mut reader)? {
+    for response in server.handle(message) {
+      transport::write(&mut writer, &response)?;
+    }
+
+    if server.exit() {
+      break;
+    }
+  }
+
+  // Exiting without a shutdown request is an error according to the protocol.
+  if !server.shutdown() {
+    process::exit(1);
+  }
+  Ok(())
+}
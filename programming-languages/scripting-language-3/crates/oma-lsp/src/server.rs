@@ -0,0 +1,306 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use oma_compiler::{ast::Ident, gen::Symbols};
+use serde_json::{json, Value};
+
+use crate::document::Document;
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+const COMPLETION_FUNCTION: u8 = 3;
+const COMPLETION_VARIABLE: u8 = 6;
+
+pub struct Server {
+  registry: Vec<(&'static str, usize)>,
+  documents: HashMap<String, Document>,
+  shutdown: bool,
+  exit: bool,
+}
+
+impl Server {
+  pub fn new(registry: Vec<(&'static str, usize)>) -> Server {
+    Server {
+      registry,
+      documents: HashMap::new(),
+      shutdown: false,
+      exit: false,
+    }
+  }
+
+  pub fn exit(&self) -> bool {
+    self.exit
+  }
+
+  pub fn shutdown(&self) -> bool {
+    self.shutdown
+  }
+
+  // Handles a request or notification, returning the messages to send back.
+  pub fn handle(&mut self, message: Value) -> Vec<Value> {
+    let method = message["method"].as_str().unwrap_or_default();
+    let params = &message["params"];
+
+    let id = match message.get("id") {
+      Some(id) => id.clone(),
+      None => return self.notification(method, params),
+    };
+
+    let result = match method {
+      "initialize" => Ok(json!({
+        "capabilities": {
+          "textDocumentSync": 1,
+          "definitionProvider": true,
+          "referencesProvider": true,
+          "hoverProvider": true,
+          "completionProvider": {},
+        },
+        "serverInfo": { "name": "oma-lsp" },
+      })),
+      "shutdown" => {
+        self.shutdown = true;
+        Ok(Value::Null)
+      }
+      "textDocument/definition" => self.definition(params),
+      "textDocument/references" => self.references(params),
+      "textDocument/hover" => self.hover(params),
+      "textDocument/completion" => self.completion(params),
+      _ => Err((METHOD_NOT_FOUND, format!("unknown method: {}", method))),
+    };
+
+    vec![match result {
+      Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+      Err((code, message)) => json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+      }),
+    }]
+  }
+
+  fn notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+    let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+    let text = match method {
+      "exit" => {
+        self.exit = true;
+        return Vec::new();
+      }
+      "textDocument/didOpen" => params["textDocument"]["text"].as_str(),
+      "textDocument/didChange" => params["contentChanges"]
+        .as_array()
+        .and_then(|changes| changes.last())
+        .and_then(|change| change["text"].as_str()),
+      "textDocument/didClose" => {
+        self.documents.remove(uri);
+        return vec![publish_diagnostics(uri, Vec::new())];
+      }
+      _ => None,
+    };
+
+    let text = match text {
+      Some(text) => text.to_string(),
+      None => return Vec::new(),
+    };
+
+    let document = self
+      .documents
+      .entry(uri.to_string())
+      .or_insert_with(Document::new);
+    document.update(text, &uri_to_path(uri), &self.registry);
+
+    let diagnostics = document
+      .diagnostics()
+      .iter()
+      .map(|diagnostic| {
+        json!({
+          "range": range(document, diagnostic.start, diagnostic.end),
+          "severity": diagnostic.severity as u8,
+          "source": "oma",
+          "message": diagnostic.message,
+        })
+      })
+      .collect();
+    vec![publish_diagnostics(uri, diagnostics)]
+  }
+
+  fn definition(&self, params: &Value) -> Result<Value, (i64, String)> {
+    let (uri, document, offset) = self.position(params)?;
+    let symbols = match document.symbols() {
+      Some(symbols) => symbols,
+      None => return Ok(Value::Null),
+    };
+
+    Ok(match target(symbols, offset) {
+      Some(Target::Definition(ident)) => location(uri, document, &ident),
+      _ => Value::Null,
+    })
+  }
+
+  fn references(&self, params: &Value) -> Result<Value, (i64, String)> {
+    let (uri, document, offset) = self.position(params)?;
+    let symbols = match document.symbols() {
+      Some(symbols) => symbols,
+      None => return Ok(Value::Null),
+    };
+    let include_declaration = params["context"]["includeDeclaration"]
+      .as_bool()
+      .unwrap_or(true);
+
+    let definition = match target(symbols, offset) {
+      Some(Target::Definition(ident)) => ident,
+      _ => return Ok(Value::Null),
+    };
+
+    let mut locations = Vec::new();
+    if include_declaration {
+      locations.push(location(uri, document, &definition));
+    }
+    for reference in &symbols.references {
+      if let Some(other) = &reference.definition {
+        if same_span(other, &definition) {
+          locations.push(location(uri, document, &reference.ident));
+        }
+      }
+    }
+    Ok(Value::Array(locations))
+  }
+
+  fn hover(&self, params: &Value) -> Result<Value, (i64, String)> {
+    let (_, document, offset) = self.position(params)?;
+    let symbols = match document.symbols() {
+      Some(symbols) => symbols,
+      None => return Ok(Value::Null),
+    };
+
+    let contents = match target(symbols, offset) {
+      Some(Target::Definition(ident)) => {
+        // Both are counted from 1, as they're read by people.
+        let span = ident.span();
+        format!(
+          "```oma\n{}\n```\nbound at line {}, column {}",
+          span.line_content().trim(),
+          span.line(),
+          span.column() + 1,
+        )
+      }
+      Some(Target::Native(ident)) => {
+        format!("native lambda `{}`", ident.content)
+      }
+      None => return Ok(Value::Null),
+    };
+
+    Ok(json!({
+      "contents": { "kind": "markdown", "value": contents },
+    }))
+  }
+
+  fn completion(&self, params: &Value) -> Result<Value, (i64, String)> {
+    let (_, document, offset) = self.position(params)?;
+
+    let mut items = Vec::new();
+    let mut names = Vec::new();
+    if let Some(symbols) = document.symbols() {
+      // Later definitions shadow earlier ones, so visit them in reverse.
+      for definition in symbols.definitions.iter().rev() {
+        let name = definition.ident.content.as_str();
+        if definition.ident.span().end() <= offset
+          && definition.scope.start() <= offset
+          && offset <= definition.scope.end()
+          && !names.contains(&name)
+        {
+          names.push(name);
+          items.push(json!({ "label": name, "kind": COMPLETION_VARIABLE }));
+        }
+      }
+    }
+    for (name, _) in &self.registry {
+      if !names.contains(name) {
+        items.push(json!({ "label": name, "kind": COMPLETION_FUNCTION }));
+      }
+    }
+
+    Ok(Value::Array(items))
+  }
+
+  // Resolves the document and byte offset a request refers to.
+  fn position<'a>(
+    &'a self,
+    params: &'a Value,
+  ) -> Result<(&'a str, &'a Document, usize), (i64, String)> {
+    let uri = params["textDocument"]["uri"]
+      .as_str()
+      .ok_or_else(|| (INVALID_PARAMS, "missing document".to_string()))?;
+    let document = self
+      .documents
+      .get(uri)
+      .ok_or_else(|| (INVALID_PARAMS, format!("unknown document: {}", uri)))?;
+    let line = params["position"]["line"].as_u64().unwrap_or_default();
+    let column = params["position"]["character"].as_u64().unwrap_or_default();
+    Ok((
+      uri,
+      document,
+      document.offset(line as usize, column as usize),
+    ))
+  }
+}
+
+enum Target {
+  Definition(Ident),
+  Native(Ident),
+}
+
+// Finds what the identifier at the offset refers to.
+fn target(symbols: &Symbols, offset: usize) -> Option<Target> {
+  let contains = |ident: &Ident| {
+    let span = ident.span();
+    span.start() <= offset && offset <= span.end()
+  };
+
+  if let Some(reference) = symbols
+    .references
+    .iter()
+    .find(|reference| contains(&reference.ident))
+  {
+    return Some(match &reference.definition {
+      Some(definition) => Target::Definition(definition.clone()),
+      None => Target::Native(reference.ident.clone()),
+    });
+  }
+
+  symbols
+    .definitions
+    .iter()
+    .find(|definition| contains(&definition.ident))
+    .map(|definition| Target::Definition(definition.ident.clone()))
+}
+
+fn same_span(left: &Ident, right: &Ident) -> bool {
+  let (left, right) = (left.span(), right.span());
+  left.start() == right.start() && left.end() == right.end()
+}
+
+fn location(uri: &str, document: &Document, ident: &Ident) -> Value {
+  let span = ident.span();
+  json!({ "uri": uri, "range": range(document, span.start(), span.end()) })
+}
+
+fn range(document: &Document, start: usize, end: usize) -> Value {
+  let (start_line, start_column) = document.position(start);
+  let (end_line, end_column) = document.position(end);
+  json!({
+    "start": { "line": start_line, "character": start_column },
+    "end": { "line": end_line, "character": end_column },
+  })
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Value>) -> Value {
+  json!({
+    "jsonrpc": "2.0",
+    "method": "textDocument/publishDiagnostics",
+    "params": { "uri": uri, "diagnostics": diagnostics },
+  })
+}
+
+fn uri_to_path(uri: &str) -> PathBuf {
+  PathBuf::from(uri.strip_prefix("file://").unwrap_or(uri))
+}
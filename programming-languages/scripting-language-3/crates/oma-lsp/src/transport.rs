@@ -0,0 +1,47 @@
+use std::io::{self, BufRead, Write};
+
+use serde_json::Value;
+
+// Reads a message framed with a `Content-Length` header. Returns `None` once
+// the input is closed.
+pub fn read(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+  let mut length = None;
+  loop {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+      return Ok(None);
+    }
+
+    let line = line.trim_end();
+    if line.is_empty() {
+      break;
+    }
+
+    if let Some((name, value)) = line.split_once(':') {
+      if name.eq_ignore_ascii_case("content-length") {
+        length = value.trim().parse::<usize>().ok();
+      }
+    }
+  }
+
+  let length = length.ok_or_else(|| {
+    io::Error::new(io::ErrorKind::InvalidData, "missing content length")
+  })?;
+  let mut content = vec![0; length];
+  reader.read_exact(&mut content)?;
+
+  serde_json::from_slice(&content)
+    .map(Some)
+    .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+pub fn write(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+  let content = message.to_string();
+  write!(
+    writer,
+    "Content-Length: {}\r\n\r\n{}",
+    content.len(),
+    content
+  )?;
+  writer.flush()
+}
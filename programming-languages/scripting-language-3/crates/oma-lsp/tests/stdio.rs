@@ -0,0 +1,244 @@
+use std::{
+  io::{BufRead, BufReader, Read, Write},
+  process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+
+use serde_json::{json, Value};
+
+const URI: &str = "file:///tmp/main.oma";
+const SOURCE: &str = "let square = (x) -> x * x;\n__console_info(square(2));\n";
+
+struct Client {
+  child: Child,
+  stdin: ChildStdin,
+  stdout: BufReader<ChildStdout>,
+  id: u64,
+}
+
+impl Client {
+  fn start() -> Client {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_oma-lsp"))
+      .stdin(Stdio::piped())
+      .stdout(Stdio::piped())
+      .spawn()
+      .unwrap();
+    let stdin = child.stdin.take().unwrap();
+    let stdout = BufReader::new(child.stdout.take().unwrap());
+    Client {
+      child,
+      stdin,
+      stdout,
+      id: 0,
+    }
+  }
+
+  fn send(&mut self, message: Value) {
+    let content = message.to_string();
+    write!(
+      self.stdin,
+      "Content-Length: {}\r\n\r\n{}",
+      content.len(),
+      content
+    )
+    .unwrap();
+    self.stdin.flush().unwrap();
+  }
+
+  fn receive(&mut self) -> Value {
+    let mut length = 0;
+    loop {
+      let mut line = String::new();
+      self.stdout.read_line(&mut line).unwrap();
+      let line = line.trim_end();
+      if line.is_empty() {
+        break;
+      }
+      if let Some(value) = line.strip_prefix("Content-Length:") {
+        length = value.trim().parse().unwrap();
+      }
+    }
+    let mut content = vec![0; length];
+    self.stdout.read_exact(&mut content).unwrap();
+    serde_json::from_slice(&content).unwrap()
+  }
+
+  fn request(&mut self, method: &str, params: Value) -> Value {
+    self.id += 1;
+    let id = self.id;
+    self.send(json!({
+      "jsonrpc": "2.0",
+      "id": id,
+      "method": method,
+      "params": params,
+    }));
+    let response = self.receive();
+    assert_eq!(response["id"], id);
+    response["result"].clone()
+  }
+
+  fn notify(&mut self, method: &str, params: Value) {
+    self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+  }
+
+  fn open(&mut self, text: &str) -> Value {
+    self.notify(
+      "textDocument/didOpen",
+      json!({
+        "textDocument": {
+          "uri": URI,
+          "languageId": "oma",
+          "version": 1,
+          "text": text,
+        },
+      }),
+    );
+    let notification = self.receive();
+    assert_eq!(notification["method"], "textDocument/publishDiagnostics");
+    notification["params"]["diagnostics"].clone()
+  }
+
+  fn at(&mut self, method: &str, line: u64, character: u64) -> Value {
+    self.request(
+      method,
+      json!({
+        "textDocument": { "uri": URI },
+        "position": { "line": line, "character": character },
+        "context": { "includeDeclaration": true },
+      }),
+    )
+  }
+
+  fn finish(mut self) {
+    assert_eq!(self.request("shutdown", Value::Null), Value::Null);
+    self.notify("exit", Value::Null);
+    assert!(self.child.wait().unwrap().success());
+  }
+}
+
+fn range(line: u64, start: u64, end: u64) -> Value {
+  json!({
+    "start": { "line": line, "character": start },
+    "end": { "line": line, "character": end },
+  })
+}
+
+#[test]
+fn session() {
+  let mut client = Client::start();
+
+  let initialize = client.request("initialize", json!({ "capabilities": {} }));
+  let capabilities = &initialize["capabilities"];
+  assert_eq!(capabilities["definitionProvider"], true);
+  assert_eq!(capabilities["hoverProvider"], true);
+  client.notify("initialized", json!({}));
+
+  assert_eq!(client.open(SOURCE), json!([]));
+
+  // `square` in the call refers to its binding on the first line.
+  let definition = client.at("textDocument/definition", 1, 16);
+  assert_eq!(definition["uri"], URI);
+  assert_eq!(definition["range"], range(0, 4, 10));
+
+  let references = client.at("textDocument/references", 0, 5);
+  let ranges = references
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|location| location["range"].clone())
+    .collect::<Vec<_>>();
+  assert_eq!(ranges, [range(0, 4, 10), range(1, 15, 21)]);
+
+  let hover = client.at("textDocument/hover", 1, 16);
+  let hover = hover["contents"]["value"].as_str().unwrap();
+  assert!(hover.contains("let square = (x) -> x * x;"));
+  assert!(hover.ends_with("bound at line 1, column 5"));
+
+  let hover = client.at("textDocument/hover", 1, 3);
+  assert_eq!(hover["contents"]["value"], "native lambda `__console_info`");
+
+  // `x` is only in scope inside the lambda.
+  let completion = client.at("textDocument/completion", 1, 0);
+  let labels = completion
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|item| item["label"].as_str().unwrap())
+    .collect::<Vec<_>>();
+  assert!(labels.contains(&"square"));
+  assert!(labels.contains(&"__console_info"));
+  assert!(!labels.contains(&"x"));
+
+  client.finish();
+}
+
+#[test]
+fn unsupported_code_is_reported() {
+  let mut client = Client::start();
+  client.request("initialize", json!({ "capabilities": {} }));
+
+  let diagnostics = client.open("let x;\n");
+  assert_eq!(diagnostics[0]["range"], range(0, 5, 6));
+  assert_eq!(diagnostics[0]["severity"], 1);
+
+  let diagnostics = client.open("let a = true;\na && a;\n");
+  assert_eq!(diagnostics[0]["range"], range(1, 0, 6));
+  assert_eq!(
+    diagnostics[0]["message"],
+    "`&&` and `||` aren't supported yet"
+  );
+
+  // The server is still up.
+  let hover = client.at("textDocument/hover", 1, 0);
+  assert!(hover["contents"]["value"]
+    .as_str()
+    .unwrap()
+    .ends_with("bound at line 1, column 5"));
+
+  client.finish();
+}
+
+#[test]
+fn bindings_are_followed_into_lambdas() {
+  let mut client = Client::start();
+  client.request("initialize", json!({ "capabilities": {} }));
+
+  let source = "let base = 10;\n\
+                let add = (x) -> x + base;\n\
+                let nested = () -> () -> base;\n\
+                let shadow = (base) -> base;\n\
+                __console_info(add(base));\n";
+  assert_eq!(client.open(source), json!([]));
+
+  // `base` in a lambda, and in a lambda in a lambda, is the module's.
+  let definition = client.at("textDocument/definition", 1, 22);
+  assert_eq!(definition["range"], range(0, 4, 8));
+  let definition = client.at("textDocument/definition", 2, 26);
+  assert_eq!(definition["range"], range(0, 4, 8));
+
+  // The parameter that shadows it is another binding.
+  let definition = client.at("textDocument/definition", 3, 24);
+  assert_eq!(definition["range"], range(3, 14, 18));
+
+  let references = client.at("textDocument/references", 0, 5);
+  let ranges = references
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|location| location["range"].clone())
+    .collect::<Vec<_>>();
+  assert_eq!(
+    ranges,
+    [
+      range(0, 4, 8),
+      range(1, 21, 25),
+      range(2, 25, 29),
+      range(4, 19, 23),
+    ]
+  );
+
+  // Finding references from a use in a lambda gives the same.
+  let from_lambda = client.at("textDocument/references", 1, 23);
+  assert_eq!(from_lambda, references);
+
+  client.finish();
+}
@@ -0,0 +1,23 @@
+use std::path::Path;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use oma_compiler::{compile, Options};
+use shu::{NativeLambdaRegistry, Vm};
+
+fn arithmetic(c: &mut Criterion) {
+  let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("benches/arithmetic.oma");
+  let bytes = compile(&path, Vec::new(), &Options::default())
+    .expect("failed to compile")
+    .bytes;
+
+  c.bench_function("arithmetic", |b| {
+    b.iter(|| {
+      Vm::new()
+        .eval(NativeLambdaRegistry::new(), &bytes)
+        .expect("failed to run")
+    })
+  });
+}
+
+criterion_group!(benches, arithmetic);
+criterion_main!(benches);
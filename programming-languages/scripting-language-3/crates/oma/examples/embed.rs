@@ -0,0 +1,57 @@
+use std::path::Path;
+
+use oma::prelude::{METHODS, PRELUDE};
+use oma_compiler::{compile, Options};
+use shu::{NativeLambdaRegistry, Value, Vm};
+
+fn main() {
+  let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../examples/embed.oma");
+
+  let mut names = PRELUDE.iter().map(|native| native.name).collect::<Vec<_>>();
+  names.push("__array_map");
+  let registry = names
+    .iter()
+    .enumerate()
+    .map(|(index, name)| (*name, index))
+    .collect();
+  let compilation = compile(&path, registry, &Options::default()).expect("failed to compile");
+
+  let mut registry = NativeLambdaRegistry::new();
+  for native in PRELUDE {
+    registry.add(native.name, native.lambda);
+  }
+  for (kind, name, method) in METHODS {
+    registry.add_method(kind, name, method);
+  }
+  // Takes `[array, lambda]` and calls the lambda on each item of the array.
+  registry.add_with_context("__array_map", |context, argument| {
+    let array = argument.get(&Value::Int(0)).unwrap_or(Value::Null);
+    let lambda = argument.get(&Value::Int(1)).unwrap_or(Value::Null);
+    let len = array
+      .as_array()
+      .map(|array| array.borrow().len())
+      .unwrap_or(0);
+
+    let result = Value::array();
+    for index in 0..len {
+      let item = array.get(&Value::Int(index as i64)).unwrap_or(Value::Null);
+      let value = context.call(&lambda, &[item])?;
+      result.as_array().unwrap().borrow_mut().push(&value);
+    }
+    Ok(result)
+  });
+
+  let mut vm = Vm::new();
+  let exports = match vm.eval(registry, &compilation.bytes) {
+    Ok(exports) => exports,
+    Err(error) => return eprintln!("{}", error),
+  };
+
+  for (name, argument) in &[("square", 7), ("counter", 2)] {
+    let lambda = exports.get(&vm.intern(name)).unwrap_or(Value::Null);
+    match vm.call(&lambda, &[Value::Int(*argument)]) {
+      Ok(result) => println!("{}({}) = {}", name, argument, result),
+      Err(error) => eprintln!("{}", error),
+    }
+  }
+}
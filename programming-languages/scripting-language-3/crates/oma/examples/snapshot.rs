@@ -0,0 +1,62 @@
+use std::path::Path;
+
+use oma::prelude::{METHODS, PRELUDE};
+use oma_compiler::{compile, Options};
+use shu::{NativeLambdaRegistry, Vm};
+
+// Steps between snapshots, which is small to restore in as many states as
+// possible.
+const STEPS: usize = 25;
+
+fn main() {
+  let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../examples/snapshot.oma");
+
+  let names = PRELUDE
+    .iter()
+    .enumerate()
+    .map(|(index, native)| (native.name, index))
+    .collect();
+  let compilation = compile(&path, names, &Options::default()).expect("failed to compile");
+  let executable = compilation.bytes;
+
+  let mut vm = Vm::new();
+  if let Err(error) = vm.load(registry(), &executable) {
+    return eprintln!("{}", error);
+  }
+
+  // Runs a few steps at a time, and then continues in a new VM from a
+  // snapshot of the old one, as if the process had restarted.
+  let mut restores = 0;
+  loop {
+    for _ in 0..STEPS {
+      match vm.step() {
+        Ok(false) => {}
+        Ok(true) => return println!("restored {} times", restores),
+        Err(error) => return eprintln!("{}", error),
+      }
+    }
+
+    let snapshot = match vm.snapshot() {
+      Ok(snapshot) => snapshot,
+      Err(error) => return eprintln!("{}", error),
+    };
+    vm = Vm::new();
+    if let Err(error) = vm.restore(registry(), &executable, &snapshot) {
+      return eprintln!("{}", error);
+    }
+    restores += 1;
+  }
+}
+
+// Natives must be registered the same way each time for snapshots to refer
+// to them.
+fn registry() -> NativeLambdaRegistry {
+  let mut registry = NativeLambdaRegistry::new();
+  for native in PRELUDE {
+    registry.add(native.name, native.lambda);
+  }
+  for (kind, name, method) in METHODS {
+    registry.add_method(kind, name, method);
+  }
+  registry
+}
@@ -0,0 +1,262 @@
+use shu::{Context, Error, Reason, Trace, Value};
+
+use crate::prelude::Method;
+
+// Natives that `oma test` adds for test files. Like other natives, they take
+// a single argument, so `assert_eq` is passed an array of the actual value
+// followed by the expected one.
+pub const ASSERTIONS: [(&str, Method); 3] = [
+  ("assert", assert),
+  ("assert_eq", assert_eq),
+  ("assert_error", assert_error),
+];
+
+fn error(reason: Reason) -> Error {
+  Error::new(reason, Trace::new())
+}
+
+// Fails unless the value is true.
+fn assert(_: &mut Context, value: Value) -> Result<Value, Error> {
+  match value {
+    Value::Bool(true) => Ok(Value::Null),
+    value => Err(error(Reason::AssertionFailed(value))),
+  }
+}
+
+// Fails unless the values are equal by structure.
+fn assert_eq(_: &mut Context, argument: Value) -> Result<Value, Error> {
+  let items = argument
+    .as_array()
+    .map(|array| array.borrow().items().to_vec())
+    .ok_or_else(|| error(Reason::Type))?;
+  match items.as_slice() {
+    [actual, expected] if actual.equals(expected) => Ok(Value::Null),
+    [actual, expected] => Err(error(Reason::NotEqual(actual.clone(), expected.clone()))),
+    _ => Err(error(Reason::WrongArity)),
+  }
+}
+
+// Calls the lambda with no arguments, and fails unless it raises an error.
+fn assert_error(context: &mut Context, lambda: Value) -> Result<Value, Error> {
+  match context.call(&lambda, &[]) {
+    Ok(_) => Err(error(Reason::NoError)),
+    Err(_) => Ok(Value::Null),
+  }
+}
+
+// Renders the value the same way each time, for test reports: strings are
+// quoted and map keys sorted, unlike when values are printed. Arrays and maps
+// already being rendered further up are shown as `...`.
+pub fn render(value: &Value) -> String {
+  let mut rendered = String::new();
+  render_within(value, &mut Vec::new(), &mut rendered);
+  rendered
+}
+
+fn render_within(value: &Value, visiting: &mut Vec<usize>, rendered: &mut String) {
+  let address = match container_address(value) {
+    Some(address) if visiting.contains(&address) => return rendered.push_str("..."),
+    address => address,
+  };
+  visiting.extend(address);
+
+  match value {
+    Value::String(string) => rendered.push_str(&format!("{:?}", string.as_str())),
+    Value::Array(array) => {
+      rendered.push('[');
+      for (index, item) in array.borrow().items().iter().enumerate() {
+        if index > 0 {
+          rendered.push_str(", ");
+        }
+        render_within(item, visiting, rendered);
+      }
+      rendered.push(']');
+    }
+    Value::Map(map) => {
+      rendered.push('{');
+      for (index, (key, value)) in sorted_entries(value).iter().enumerate() {
+        if index > 0 {
+          rendered.push(',');
+        }
+        rendered.push_str(&format!(" {}: ", map_key(key)));
+        render_within(value, visiting, rendered);
+      }
+      rendered.push_str(if map.borrow().entries().next().is_some() {
+        " }"
+      } else {
+        "}"
+      });
+    }
+    Value::Tagged(tagged) => {
+      rendered.push_str(&format!("{}(", tagged.tag()));
+      for (index, field) in tagged.fields().iter().enumerate() {
+        if index > 0 {
+          rendered.push_str(", ");
+        }
+        if let Some(name) = tagged.names().get(index) {
+          rendered.push_str(&format!("{}: ", name));
+        }
+        render_within(field, visiting, rendered);
+      }
+      rendered.push(')');
+    }
+    value => rendered.push_str(&value.to_string()),
+  }
+
+  if address.is_some() {
+    visiting.pop();
+  }
+}
+
+// A place where the actual value differs from the expected one. The path
+// leads there from the values compared, like `.b[1]`, and is empty if they
+// differ as a whole. A side is missing if only the other has an item or field
+// there.
+#[derive(Debug, PartialEq)]
+pub struct Difference {
+  pub path: String,
+  pub expected: Option<String>,
+  pub actual: Option<String>,
+}
+
+// Finds where the values differ, looking into arrays, maps and tagged values
+// of the same kind rather than reporting them whole.
+pub fn differences(actual: &Value, expected: &Value) -> Vec<Difference> {
+  let mut differences = Vec::new();
+  differences_within(
+    actual,
+    expected,
+    String::new(),
+    &mut Vec::new(),
+    &mut differences,
+  );
+  differences
+}
+
+fn differences_within(
+  actual: &Value,
+  expected: &Value,
+  path: String,
+  visiting: &mut Vec<(usize, usize)>,
+  differences: &mut Vec<Difference>,
+) {
+  if actual.equals(expected) {
+    return;
+  }
+  if let (Some(left), Some(right)) = (container_address(actual), container_address(expected)) {
+    if visiting.contains(&(left, right)) {
+      return;
+    }
+    visiting.push((left, right));
+  }
+
+  let mut pairs = Vec::new();
+  match (actual, expected) {
+    (Value::Array(left), Value::Array(right)) => {
+      let (left, right) = (
+        left.borrow().items().to_vec(),
+        right.borrow().items().to_vec(),
+      );
+      for index in 0..left.len().max(right.len()) {
+        pairs.push((
+          format!("{}[{}]", path, index),
+          left.get(index).cloned(),
+          right.get(index).cloned(),
+        ));
+      }
+    }
+    (Value::Map(_), Value::Map(_)) => {
+      let (left, right) = (sorted_entries(actual), sorted_entries(expected));
+      let mut keys = left
+        .iter()
+        .chain(&right)
+        .map(|(key, _)| key.clone())
+        .collect::<Vec<_>>();
+      keys.sort();
+      keys.dedup();
+      for key in keys {
+        let find = |entries: &[(String, Value)]| {
+          entries
+            .iter()
+            .find(|(other, _)| *other == key)
+            .map(|(_, value)| value.clone())
+        };
+        let path = match map_key(&key) {
+          key if key.starts_with('"') => format!("{}[{}]", path, key),
+          key => format!("{}.{}", path, key),
+        };
+        pairs.push((path, find(&left), find(&right)));
+      }
+    }
+    (Value::Tagged(left), Value::Tagged(right))
+      if left.tag() == right.tag()
+        && left.names() == right.names()
+        && left.fields().len() == right.fields().len() =>
+    {
+      for (index, (field, other)) in left.fields().iter().zip(right.fields()).enumerate() {
+        let name = left
+          .names()
+          .get(index)
+          .map_or_else(|| index.to_string(), |name| name.to_string());
+        pairs.push((
+          format!("{}.{}", path, name),
+          Some(field.clone()),
+          Some(other.clone()),
+        ));
+      }
+    }
+    _ => {
+      differences.push(Difference {
+        path,
+        expected: Some(render(expected)),
+        actual: Some(render(actual)),
+      });
+      return;
+    }
+  }
+
+  for (path, actual, expected) in pairs {
+    match (actual, expected) {
+      (Some(actual), Some(expected)) => {
+        differences_within(&actual, &expected, path, visiting, differences)
+      }
+      (actual, expected) => differences.push(Difference {
+        path,
+        expected: expected.as_ref().map(render),
+        actual: actual.as_ref().map(render),
+      }),
+    }
+  }
+}
+
+fn sorted_entries(map: &Value) -> Vec<(String, Value)> {
+  let map = map.as_map().unwrap().borrow();
+  let mut entries = map
+    .entries()
+    .map(|(key, value)| (key.as_string().unwrap().to_string(), value.clone()))
+    .collect::<Vec<_>>();
+  entries.sort_by(|(left, _), (right, _)| left.cmp(right));
+  entries
+}
+
+// Map keys are shown bare if they could be written as field names.
+fn map_key(key: &str) -> String {
+  let mut chars = key.chars();
+  let is_name = chars
+    .next()
+    .is_some_and(|char| char == '_' || char.is_alphabetic())
+    && chars.all(|char| char == '_' || char.is_alphanumeric());
+  if is_name {
+    key.to_string()
+  } else {
+    format!("{:?}", key)
+  }
+}
+
+fn container_address(value: &Value) -> Option<usize> {
+  match value {
+    Value::Array(array) => Some(&**array as *const _ as usize),
+    Value::Map(map) => Some(&**map as *const _ as usize),
+    _ => None,
+  }
+}
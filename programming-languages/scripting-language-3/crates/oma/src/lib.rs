@@ -1 +1,2 @@
+pub mod assert;
 pub mod prelude;
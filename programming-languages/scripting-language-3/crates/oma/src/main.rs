@@ -1,30 +1,428 @@
 #![cfg_attr(rustfmt, rustfmt_skip)]
 
-use shu::{NativeLambdaRegistry, Vm};
-use oma::prelude::PRELUDE;
-use oma_compiler::compile;
+use std::{cell::RefCell, env, fs, path::{Path, PathBuf}, process, rc::Rc};
+
+use shu::{Capabilities, Coverage, Error, NativeLambdaRegistry, Overflow, Reason, Value, Vm};
+use oma::{assert::{differences, render, Difference, ASSERTIONS}, prelude::{METHODS, PRELUDE}};
+use oma_compiler::{compile_cached, compile_tests, format, lint, lint::Rule, Cache, CompileError, Lookup, Options};
+
+const USAGE: &str = "usage: oma run [--strict] [--promote] [--explain] [--sandbox] [--grant <capability>]... <file>
+       oma fmt [--check] <file>...
+       oma lint [--allow <rule>]... <file>
+       oma test [--filter <text>] [--format text|tap|json] [--coverage] [<dir>]";
+
+const CACHE_DIR: &str = ".oma-cache";
 
 fn main() {
+  let args = env::args().skip(1).collect::<Vec<_>>();
+  match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+    ["run", flags @ .., path] => {
+      let mut options = Options::default();
+      let mut overflow = Overflow::Error;
+      let mut explain = false;
+      // Granting any capability sandboxes the script, as does `--sandbox`.
+      let mut capabilities = None;
+      let mut flags = flags.iter();
+      while let Some(flag) = flags.next() {
+        match *flag {
+          "--strict" => options.strict = true,
+          "--promote" => overflow = Overflow::Promote,
+          "--explain" => explain = true,
+          "--sandbox" => { capabilities.get_or_insert_with(Capabilities::new); },
+          "--grant" => {
+            let capability = flags.next().unwrap_or_else(|| usage());
+            capabilities.get_or_insert_with(Capabilities::new).grant(capability);
+          },
+          _ => usage(),
+        }
+      }
+      run(Path::new(path), options, overflow, capabilities, explain);
+    },
+    ["fmt", args @ ..] => {
+      let check = args.contains(&"--check");
+      let paths = args.iter().filter(|arg| **arg != "--check").collect::<Vec<_>>();
+      if paths.is_empty() || paths.iter().any(|path| path.starts_with("--")) {
+        usage();
+      }
+      fmt(&paths, check);
+    },
+    ["lint", flags @ .., path] => {
+      let mut rules = Rule::ALL.to_vec();
+      let mut flags = flags.iter();
+      while let Some(flag) = flags.next() {
+        match (*flag, flags.next().and_then(|name| Rule::from_name(name))) {
+          ("--allow", Some(rule)) => rules.retain(|r| *r != rule),
+          _ => usage(),
+        }
+      }
+      check(Path::new(path), &rules);
+    },
+    ["test", args @ ..] => {
+      let mut options = TestOptions { filter: None, format: Format::Text, coverage: false };
+      let mut root = None;
+      let mut args = args.iter();
+      while let Some(arg) = args.next() {
+        match *arg {
+          "--filter" => options.filter = Some(*args.next().unwrap_or_else(|| usage())),
+          "--coverage" => options.coverage = true,
+          "--format" => options.format = match args.next().copied() {
+            Some("text") => Format::Text,
+            Some("tap") => Format::Tap,
+            Some("json") => Format::Json,
+            _ => usage(),
+          },
+          path if !path.starts_with("--") && root.is_none() => root = Some(path),
+          _ => usage(),
+        }
+      }
+      test(Path::new(root.unwrap_or(".")), &options);
+    },
+    _ => usage(),
+  }
+}
+
+// Compiles the file, or loads it from the cache in `.oma-cache` if it hasn't
+// changed, and runs it. With `explain`, says which it was.
+fn run(path: &Path, mut options: Options, overflow: Overflow, capabilities: Option<Capabilities>, explain: bool) {
   let mut registry = Vec::new();
-  for (index, (name, _)) in PRELUDE.iter().enumerate() {
-    registry.push((*name, index));
+  for (index, native) in PRELUDE.iter().enumerate() {
+    registry.push((native.name, index));
+  }
+
+  let natives = natives();
+  if let Some(capabilities) = &capabilities {
+    options.denied = natives.denied(capabilities);
+  }
+
+  let cache = Cache::new(CACHE_DIR);
+  let compilation = match compile_cached(path, registry, &options, &cache) {
+    Ok((compilation, lookup)) => {
+      if explain {
+        match lookup {
+          Lookup::Hit => eprintln!("cache hit: {}", path.display()),
+          lookup => eprintln!("cache miss: {} ({})", path.display(), lookup),
+        }
+      }
+      compilation
+    },
+    Err(error) => report(&error),
+  };
+
+  for warning in &compilation.warnings {
+    eprintln!("warning: {}", warning);
+  }
+
+  let mut vm = Vm::with_overflow(overflow);
+  if let Some(capabilities) = capabilities {
+    vm.sandbox(capabilities);
+  }
+  if let Err(error) = vm.run(natives, &compilation.bytes) {
+    eprintln!("{}", error);
+    process::exit(1);
+  }
+}
+
+// Formats the files in place, or with `check` only reports the ones that
+// aren't formatted.
+fn fmt(paths: &[&&str], check: bool) {
+  let mut unformatted = false;
+  for path in paths {
+    let path = Path::new(path);
+    let formatted = match format(path) {
+      Ok(formatted) => formatted,
+      Err(error) => report(&error),
+    };
+
+    let original = fs::read_to_string(path).unwrap_or_default();
+    if formatted == original {
+      continue;
+    }
+
+    if check {
+      eprintln!("not formatted: {}", path.display());
+      unformatted = true;
+    } else if let Err(error) = fs::write(path, formatted) {
+      eprintln!("error: {}: {}", path.display(), error);
+      process::exit(1);
+    }
+  }
+
+  if unformatted {
+    process::exit(1);
+  }
+}
+
+fn check(path: &Path, rules: &[Rule]) {
+  let registry = PRELUDE.iter().enumerate().map(|(index, native)| (native.name, index)).collect();
+  let lints = match lint(path, registry, rules) {
+    Ok(lints) => lints,
+    Err(error) => report(&error),
+  };
+
+  for lint in &lints {
+    eprintln!("warning: {}", lint);
+  }
+  if !lints.is_empty() {
+    process::exit(1);
+  }
+}
+
+struct TestOptions<'a> {
+  // Only tests whose names contain this are run.
+  filter: Option<&'a str>,
+  format: Format,
+  // Write the lines that ran to `lcov.info`, and summarize them.
+  coverage: bool,
+}
+
+#[derive(Clone, Copy)]
+enum Format {
+  Text,
+  Tap,
+  Json,
+}
+
+// Runs each test of each test file under the root in a new VM, so tests can't
+// affect each other through the module's bindings.
+fn test(root: &Path, options: &TestOptions) {
+  let format = options.format;
+  let mut paths = Vec::new();
+  discover(root, &mut paths);
+  paths.sort();
+
+  // The assertions are added after the prelude, as in `run_test`.
+  let names = PRELUDE.iter().map(|native| native.name).chain(ASSERTIONS.iter().map(|(name, _)| *name));
+  let registry = names.enumerate().map(|(index, name)| (name, index)).collect::<Vec<_>>();
+
+  if let Format::Tap = format {
+    println!("TAP version 13");
+  }
+
+  let coverage = Rc::new(RefCell::new(Coverage::new()));
+  let (mut passed, mut failed) = (0, 0);
+  for path in &paths {
+    let (compilation, names) = match compile_tests(path, registry.clone(), &Options::default()) {
+      Ok(compiled) => compiled,
+      Err(error) => {
+        for error in error.errors() {
+          eprintln!("error: {}", error);
+        }
+        failed += 1;
+        continue;
+      },
+    };
+
+    for warning in &compilation.warnings {
+      eprintln!("warning: {}", warning);
+    }
+
+    for name in names.iter().filter(|name| options.filter.is_none_or(|filter| name.contains(filter))) {
+      let coverage = if options.coverage { Some(&coverage) } else { None };
+      let result = run_test(&compilation.bytes, name, coverage);
+      if result.is_ok() {
+        passed += 1;
+      } else {
+        failed += 1;
+      }
+      report_test(format, passed + failed, &path.display().to_string(), name, &result);
+    }
+  }
+
+  match format {
+    Format::Text => println!("\n{} passed, {} failed", passed, failed),
+    Format::Tap => println!("1..{}", passed + failed),
+    Format::Json => println!("{{\"passed\":{},\"failed\":{}}}", passed, failed),
+  }
+  if options.coverage {
+    report_coverage(&coverage.borrow(), format);
+  }
+  if failed > 0 {
+    process::exit(1);
+  }
+}
+
+// Collects the test files under the path, or the path itself if it's a file.
+fn discover(path: &Path, paths: &mut Vec<PathBuf>) {
+  if path.is_file() {
+    paths.push(path.to_path_buf());
+    return;
   }
 
-  let bytes = match compile(registry) {
-    Ok(bytes) => bytes,
+  let entries = match fs::read_dir(path) {
+    Ok(entries) => entries,
     Err(error) => {
-      eprintln!("error: {}", error);
-      std::process::exit(1);
+      eprintln!("error: {}: {}", path.display(), error);
+      process::exit(1);
     },
   };
+  for entry in entries.flatten() {
+    let name = entry.file_name().to_string_lossy().into_owned();
+    let path = entry.path();
+    if path.is_dir() && !name.starts_with('.') {
+      discover(&path, paths);
+    } else if name.ends_with("_test.oma") {
+      paths.push(path);
+    }
+  }
+}
 
+fn run_test(executable: &[u8], name: &str, coverage: Option<&Rc<RefCell<Coverage>>>) -> Result<(), Error> {
+  let mut vm = Vm::new();
+  if let Some(coverage) = coverage {
+    vm.record_coverage(Rc::clone(coverage));
+  }
+  let mut registry = natives();
+  for (name, lambda) in ASSERTIONS {
+    registry.add_with_context(name, lambda);
+  }
+
+  let tests = vm.eval(registry, executable)?;
+  let key = vm.intern(name);
+  let test = tests.get(&key).unwrap_or(Value::Null);
+  vm.call(&test, &[])?;
+  Ok(())
+}
+
+fn report_test(format: Format, number: usize, path: &str, name: &str, result: &Result<(), Error>) {
+  let error = match (format, result) {
+    (Format::Text, Ok(())) => return println!("ok   {} {}", path, name),
+    (Format::Tap, Ok(())) => return println!("ok {} - {} {}", number, path, name),
+    (Format::Json, Ok(())) => return println!("{{\"file\":{},\"test\":{},\"ok\":true}}", json(path), json(name)),
+    (_, Err(error)) => error,
+  };
+
+  let frames = error.trace().frames().iter().rev().map(ToString::to_string).collect::<Vec<_>>();
+  // Unequal values are rendered apart from the message, so that maps come out
+  // the same way each time.
+  let (message, values) = match error.reason() {
+    Reason::NotEqual(actual, expected) => (
+      String::from("assertion failed: values aren't equal"),
+      Some((render(actual), render(expected), differences(actual, expected))),
+    ),
+    reason => (reason.to_string(), None),
+  };
+
+  match format {
+    Format::Text => {
+      println!("FAIL {} {}", path, name);
+      println!("  error: {}", message);
+      for difference in values.iter().flat_map(|(_, _, differences)| differences) {
+        let indent = if difference.path.is_empty() { "  " } else { "    " };
+        if !difference.path.is_empty() {
+          println!("  {}", difference.path);
+        }
+        match &difference.expected {
+          Some(expected) => println!("{}- expected: {}", indent, expected),
+          None => println!("{}- expected: nothing", indent),
+        }
+        match &difference.actual {
+          Some(actual) => println!("{}+ actual:   {}", indent, actual),
+          None => println!("{}+ actual:   nothing", indent),
+        }
+      }
+      for frame in &frames {
+        println!("  at {}", frame);
+      }
+    },
+    Format::Tap => {
+      println!("not ok {} - {} {}", number, path, name);
+      println!("  ---");
+      println!("  message: {}", json(&message));
+      if let Some((actual, expected, differences)) = &values {
+        println!("  expected: {}", json(expected));
+        println!("  actual: {}", json(actual));
+        println!("  differences: [{}]", differences.iter().map(json_difference).collect::<Vec<_>>().join(", "));
+      }
+      println!("  at: [{}]", frames.iter().map(|frame| json(frame)).collect::<Vec<_>>().join(", "));
+      println!("  ...");
+    },
+    Format::Json => {
+      print!("{{\"file\":{},\"test\":{},\"ok\":false", json(path), json(name));
+      print!(",\"message\":{}", json(&message));
+      if let Some((actual, expected, differences)) = &values {
+        print!(",\"expected\":{},\"actual\":{}", json(expected), json(actual));
+        print!(",\"differences\":[{}]", differences.iter().map(json_difference).collect::<Vec<_>>().join(","));
+      }
+      println!(",\"trace\":[{}]}}", frames.iter().map(|frame| json(frame)).collect::<Vec<_>>().join(","));
+    },
+  }
+}
+
+// A side that's missing is null.
+fn json_difference(difference: &Difference) -> String {
+  let side = |side: &Option<String>| side.as_deref().map_or_else(|| String::from("null"), json);
+  format!(
+    "{{\"path\":{},\"expected\":{},\"actual\":{}}}",
+    json(&difference.path),
+    side(&difference.expected),
+    side(&difference.actual),
+  )
+}
+
+// Writes the coverage to `lcov.info`, and prints how much of each file ran,
+// to stderr unless the results are text, so they stay machine-readable.
+fn report_coverage(coverage: &Coverage, format: Format) {
+  let mut lcov = String::new();
+  let mut summary = vec![String::from("coverage:")];
+  for (path, file) in coverage.files() {
+    lcov.push_str(&format!("SF:{}\n", path));
+    for (line, hits) in &file.lines {
+      lcov.push_str(&format!("DA:{},{}\n", line, hits));
+    }
+    lcov.push_str(&format!("LH:{}\nLF:{}\nend_of_record\n", file.lines_hit(), file.lines.len()));
+
+    let percent = 100.0 * file.lines_hit() as f64 / file.lines.len().max(1) as f64;
+    summary.push(format!("  {:5.1}% {:>4}/{:<4} {}", percent, file.lines_hit(), file.lines.len(), path));
+  }
+
+  if let Err(error) = fs::write("lcov.info", lcov) {
+    eprintln!("error: lcov.info: {}", error);
+    process::exit(1);
+  }
+  for line in summary {
+    match format {
+      Format::Text => println!("{}", line),
+      _ => eprintln!("{}", line),
+    }
+  }
+}
+
+// Quotes the string for JSON, which TAP's YAML blocks also accept.
+fn json(string: &str) -> String {
+  let mut quoted = String::from("\"");
+  for char in string.chars() {
+    match char {
+      '"' => quoted.push_str("\\\""),
+      '\\' => quoted.push_str("\\\\"),
+      '\n' => quoted.push_str("\\n"),
+      char if char.is_control() => quoted.push_str(&format!("\\u{:04x}", char as u32)),
+      char => quoted.push(char),
+    }
+  }
+  quoted.push('"');
+  quoted
+}
+
+fn natives() -> NativeLambdaRegistry {
   let mut registry = NativeLambdaRegistry::new();
-  for (_, lambda) in PRELUDE {
-    registry.add(lambda);
+  for native in PRELUDE {
+    let id = registry.add(native.name, native.lambda);
+    registry.require(id, native.capability);
+  }
+  for (kind, name, method) in METHODS {
+    registry.add_method(kind, name, method);
   }
+  registry
+}
 
-  let mut vm = Vm::new();
-  if let Err(error) = vm.run(registry, &bytes) {
-    eprintln!("{}", error);
+fn report(error: &CompileError) -> ! {
+  for error in error.errors() {
+    eprintln!("error: {}", error);
   }
+  process::exit(1);
+}
+
+fn usage() -> ! {
+  eprintln!("{}", USAGE);
+  process::exit(2);
 }
@@ -1,8 +1,39 @@
-use shu::Value;
+use std::convert::TryInto;
 
-pub const PRELUDE: [(&'static str, fn(Value) -> Value); 2] = [
-  ("__console_info", console::info),
-  ("__console_error", console::error),
+use shu::{Context, Error, Kind, Reason, Trace, Value};
+
+pub struct Native {
+  pub name: &'static str,
+  // The capability a VM must be granted to call it.
+  pub capability: &'static str,
+  pub lambda: fn(Value) -> Value,
+}
+
+pub const PRELUDE: [Native; 2] = [
+  Native {
+    name: "__console_info",
+    capability: "io.console",
+    lambda: console::info,
+  },
+  Native {
+    name: "__console_error",
+    capability: "io.console",
+    lambda: console::error,
+  },
+];
+
+pub type Method = fn(&mut Context, Value) -> Result<Value, Error>;
+
+// Methods are passed an array of the receiver followed by the arguments.
+pub const METHODS: [(Kind, &str, Method); 8] = [
+  (Kind::String, "len", string::len),
+  (Kind::Array, "len", array::len),
+  (Kind::Array, "push", array::push),
+  (Kind::Array, "add", array::add),
+  (Kind::Map, "keys", map::keys),
+  (Kind::Map, "meta", map::meta),
+  (Kind::Map, "set_meta", map::set_meta),
+  (Kind::Tagged, "tag", tagged::tag),
 ];
 
 mod console {
@@ -18,3 +49,104 @@ mod console {
     value
   }
 }
+
+// Checks that a method was passed exactly `N` values, counting the receiver.
+fn arguments<const N: usize>(argument: &Value) -> Result<[Value; N], Error> {
+  let items = argument
+    .as_array()
+    .map(|array| array.borrow().items().to_vec())
+    .ok_or_else(|| error(Reason::Type))?;
+  items.try_into().map_err(|_| error(Reason::WrongArity))
+}
+
+fn error(reason: Reason) -> Error {
+  Error::new(reason, Trace::new())
+}
+
+mod string {
+  use shu::{Context, Error, Reason, Value};
+
+  use super::{arguments, error};
+
+  pub fn len(_: &mut Context, argument: Value) -> Result<Value, Error> {
+    let [string] = arguments(&argument)?;
+    let string = string.as_string().ok_or_else(|| error(Reason::Type))?;
+    Ok(Value::Int(string.chars().count() as i64))
+  }
+}
+
+mod array {
+  use shu::{Context, Error, Reason, Value};
+
+  use super::{arguments, error};
+
+  pub fn len(_: &mut Context, argument: Value) -> Result<Value, Error> {
+    let [array] = arguments(&argument)?;
+    let array = array.as_array().ok_or_else(|| error(Reason::Type))?;
+    let len = array.borrow().len();
+    Ok(Value::Int(len as i64))
+  }
+
+  // Appends the item and returns the array.
+  pub fn push(_: &mut Context, argument: Value) -> Result<Value, Error> {
+    let [array_value, item] = arguments(&argument)?;
+    let array = array_value.as_array().ok_or_else(|| error(Reason::Type))?;
+    array.borrow_mut().push(&item);
+    Ok(array_value)
+  }
+
+  // Concatenates two arrays into a new one, which is what `+` does.
+  pub fn add(_: &mut Context, argument: Value) -> Result<Value, Error> {
+    let [left, right] = arguments(&argument)?;
+    let result = Value::array();
+    for array in &[left, right] {
+      let array = array.as_array().ok_or_else(|| error(Reason::Type))?;
+      for item in array.borrow().items() {
+        result.as_array().unwrap().borrow_mut().push(item);
+      }
+    }
+    Ok(result)
+  }
+}
+
+mod map {
+  use shu::{Context, Error, Reason, Value};
+
+  use super::{arguments, error};
+
+  pub fn keys(_: &mut Context, argument: Value) -> Result<Value, Error> {
+    let [map] = arguments(&argument)?;
+    let map = map.as_map().ok_or_else(|| error(Reason::Type))?;
+    let keys = Value::array();
+    for key in map.borrow().keys() {
+      keys.as_array().unwrap().borrow_mut().push(&key);
+    }
+    Ok(keys)
+  }
+
+  pub fn meta(_: &mut Context, argument: Value) -> Result<Value, Error> {
+    let [map] = arguments(&argument)?;
+    let map = map.as_map().ok_or_else(|| error(Reason::Type))?;
+    let meta = map.borrow().meta().cloned();
+    Ok(meta.map(Value::Map).unwrap_or(Value::Null))
+  }
+
+  // Sets the meta map, or removes it if given null, and returns the map.
+  pub fn set_meta(_: &mut Context, argument: Value) -> Result<Value, Error> {
+    let [map, meta] = arguments(&argument)?;
+    map.set_meta(&meta).map_err(error)?;
+    Ok(map)
+  }
+}
+
+mod tagged {
+  use shu::{Context, Error, Reason, Value};
+
+  use super::{arguments, error};
+
+  pub fn tag(_: &mut Context, argument: Value) -> Result<Value, Error> {
+    let [tagged] = arguments(&argument)?;
+    let tagged = tagged.as_tagged().ok_or_else(|| error(Reason::Type))?;
+    Ok(Value::String(tagged.tag().clone()))
+  }
+}
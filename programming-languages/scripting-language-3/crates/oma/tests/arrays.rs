@@ -0,0 +1,52 @@
+mod common;
+
+use common::eval;
+
+#[test]
+fn negative_index_counts_from_end() {
+  assert_eq!(eval("let a = [1, 2, 3]; a[-1]").unwrap(), "3");
+}
+
+#[test]
+fn slice_between_indices() {
+  assert_eq!(eval("let a = [1, 2, 3, 4]; a[1..3]").unwrap(), "[2, 3]");
+}
+
+#[test]
+fn slice_from_start() {
+  assert_eq!(eval("let a = [1, 2, 3, 4]; a[..2]").unwrap(), "[1, 2]");
+}
+
+#[test]
+fn slice_to_end_from_negative_index() {
+  assert_eq!(eval("let a = [1, 2, 3, 4]; a[-2..]").unwrap(), "[3, 4]");
+}
+
+#[test]
+fn set_at_length_grows_array() {
+  assert_eq!(eval("let a = [1, 2]; a[2] = 3; a").unwrap(), "[1, 2, 3]");
+}
+
+#[test]
+fn set_past_length_is_error() {
+  let error = eval("let a = [1, 2]; a[3] = 4; a").unwrap_err();
+  assert!(error.contains("invalid key: 3"), "{}", error);
+}
+
+#[test]
+fn set_empty_slice_inserts() {
+  let source = "let a = [1, 2, 3]; a[1..1] = [8, 9]; a";
+  assert_eq!(eval(source).unwrap(), "[1, 8, 9, 2, 3]");
+}
+
+#[test]
+fn set_slice_to_empty_array_removes() {
+  let source = "let a = [1, 2, 3, 4]; a[1..3] = []; a";
+  assert_eq!(eval(source).unwrap(), "[1, 4]");
+}
+
+#[test]
+fn backwards_range_is_error() {
+  let error = eval("let a = [1, 2, 3]; a[2..1]").unwrap_err();
+  assert!(error.contains("invalid range: 2..1"), "{}", error);
+}
@@ -0,0 +1,154 @@
+use std::{
+  cell::{Cell, RefCell},
+  future::Future,
+  path::Path,
+  pin::Pin,
+  rc::Rc,
+  task::{Context, Poll, Waker},
+};
+
+use oma::prelude::{METHODS, PRELUDE};
+use oma_compiler::{compile, Options};
+use shu::{NativeLambdaRegistry, Value, Vm};
+
+// Runs `examples/async.oma`, then its `worker` on three fibers sleeping for
+// different times, and checks that they wake in the order of their deadlines.
+#[test]
+fn fibers_wake_in_deadline_order() {
+  let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../examples/async.oma");
+
+  let mut names = PRELUDE.iter().map(|native| native.name).collect::<Vec<_>>();
+  names.push("__sleep");
+  let registry = names
+    .iter()
+    .enumerate()
+    .map(|(index, name)| (*name, index))
+    .collect();
+  let compilation = compile(&path, registry, &Options::default()).unwrap();
+
+  let runtime = Runtime::default();
+  let log = Rc::new(RefCell::new(Vec::new()));
+
+  let mut registry = NativeLambdaRegistry::new();
+  for native in PRELUDE {
+    let log = Rc::clone(&log);
+    registry.add(native.name, move |value: Value| {
+      log.borrow_mut().push(value.to_string());
+      value
+    });
+  }
+  for (kind, name, method) in METHODS {
+    registry.add_method(kind, name, method);
+  }
+  // Sleeps for a number of ticks of the runtime's clock, and returns the time
+  // it woke at.
+  let clock = Rc::clone(&runtime.clock);
+  registry.add_async("__sleep", move |ticks| {
+    let ticks = match ticks {
+      Value::Int(ticks) => ticks as u64,
+      _ => 0,
+    };
+    let sleep = Sleep::new(&clock, ticks);
+    async move { Ok(Value::Int(sleep.await as i64)) }
+  });
+
+  let mut vm = Vm::new();
+  let exports = runtime
+    .block_on(vm.eval_async(registry, &compilation.bytes))
+    .unwrap();
+  assert_eq!(*log.borrow(), ["[module woke at, 1]"]);
+  log.borrow_mut().clear();
+
+  let worker = exports.get(&vm.intern("worker")).unwrap();
+  for (index, (name, ticks)) in [("a", 3), ("b", 1), ("c", 2)].iter().enumerate() {
+    let arguments = [vm.intern(name), Value::Int(*ticks)];
+    assert_eq!(vm.spawn(&worker, &arguments).unwrap(), index);
+  }
+
+  let results = runtime
+    .block_on(vm.join())
+    .into_iter()
+    .map(|result| result.unwrap().to_string())
+    .collect::<Vec<_>>();
+  // Results are in spawn order, whatever order the fibers finished in.
+  assert_eq!(results, ["a", "b", "c"]);
+  assert_eq!(
+    *log.borrow(),
+    [
+      "[a, sleeps for, 3]",
+      "[b, sleeps for, 1]",
+      "[c, sleeps for, 2]",
+      "[b, woke at, 2]",
+      "[c, woke at, 3]",
+      "[a, woke at, 4]",
+    ]
+  );
+  assert_eq!(runtime.clock.now.get(), 4);
+}
+
+// A single-threaded executor with a virtual clock, so that runs are
+// deterministic. Whenever the future can't make progress, the clock jumps to
+// the earliest timer instead of waiting for it.
+#[derive(Default)]
+struct Runtime {
+  clock: Rc<Clock>,
+}
+
+#[derive(Default)]
+struct Clock {
+  now: Cell<u64>,
+  // The deadlines of the pending sleeps, with their wakers.
+  timers: RefCell<Vec<(u64, Waker)>>,
+}
+
+impl Runtime {
+  fn block_on<F: Future>(&self, future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+      if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+        return output;
+      }
+
+      let mut timers = self.clock.timers.borrow_mut();
+      let next = timers
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, (deadline, _))| *deadline)
+        .map(|(index, _)| index)
+        .expect("the future is waiting on nothing");
+      let (deadline, waker) = timers.remove(next);
+      self.clock.now.set(self.clock.now.get().max(deadline));
+      waker.wake();
+    }
+  }
+}
+
+struct Sleep {
+  clock: Rc<Clock>,
+  deadline: u64,
+}
+
+impl Sleep {
+  fn new(clock: &Rc<Clock>, ticks: u64) -> Sleep {
+    Sleep {
+      clock: Rc::clone(clock),
+      deadline: clock.now.get() + ticks,
+    }
+  }
+}
+
+impl Future for Sleep {
+  type Output = u64;
+
+  fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u64> {
+    let now = self.clock.now.get();
+    if now >= self.deadline {
+      Poll::Ready(now)
+    } else {
+      let timer = (self.deadline, cx.waker().clone());
+      self.clock.timers.borrow_mut().push(timer);
+      Poll::Pending
+    }
+  }
+}
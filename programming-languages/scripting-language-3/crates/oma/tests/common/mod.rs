@@ -0,0 +1,78 @@
+// Helpers shared by the integration tests.
+
+#![allow(dead_code)]
+
+use std::{
+  env, fs,
+  path::PathBuf,
+  process,
+  sync::atomic::{AtomicUsize, Ordering},
+};
+
+use oma::prelude::{METHODS, PRELUDE};
+use oma_compiler::{compile, Compilation, Options};
+use shu::{NativeLambdaRegistry, Value, Vm};
+
+// Writes the source to a file of its own in the temporary directory, as the
+// compiler reads from files.
+pub fn source_file(source: &str) -> PathBuf {
+  static COUNT: AtomicUsize = AtomicUsize::new(0);
+  let count = COUNT.fetch_add(1, Ordering::Relaxed);
+  let path = env::temp_dir().join(format!("oma-test-{}-{}.oma", process::id(), count));
+  fs::write(&path, source).unwrap();
+  path
+}
+
+// Writes the file to a directory of its own in the temporary directory, for
+// commands that run on directories.
+pub fn test_dir(name: &str, source: &str) -> PathBuf {
+  static COUNT: AtomicUsize = AtomicUsize::new(0);
+  let count = COUNT.fetch_add(1, Ordering::Relaxed);
+  let dir = env::temp_dir().join(format!("oma-test-dir-{}-{}", process::id(), count));
+  fs::create_dir_all(&dir).unwrap();
+  fs::write(dir.join(name), source).unwrap();
+  dir
+}
+
+pub fn registry() -> Vec<(&'static str, usize)> {
+  PRELUDE
+    .iter()
+    .enumerate()
+    .map(|(index, native)| (native.name, index))
+    .collect()
+}
+
+pub fn natives() -> NativeLambdaRegistry {
+  let mut registry = NativeLambdaRegistry::new();
+  for native in PRELUDE {
+    registry.add(native.name, native.lambda);
+  }
+  for (kind, name, method) in METHODS {
+    registry.add_method(kind, name, method);
+  }
+  registry
+}
+
+pub fn compile_source(source: &str, options: &Options) -> Result<Compilation, String> {
+  let path = source_file(source);
+  let compilation = compile(&path, registry(), options);
+  fs::remove_file(&path).unwrap();
+  compilation.map_err(|error| error.to_string())
+}
+
+// Compiles and runs the source, and returns what its module evaluates to, or
+// the error that stopped it.
+pub fn eval(source: &str) -> Result<String, String> {
+  let compilation = compile_source(source, &Options::default())?;
+  let mut vm = Vm::new();
+  let value = vm
+    .eval(natives(), &compilation.bytes)
+    .map_err(|error| error.to_string())?;
+  Ok(value.to_string())
+}
+
+// Like `eval`, but returns the value itself, and panics on errors.
+pub fn eval_value(source: &str) -> Value {
+  let compilation = compile_source(source, &Options::default()).unwrap();
+  Vm::new().eval(natives(), &compilation.bytes).unwrap()
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use std::{fs, process::Command};
+
+use common::test_dir;
+
+#[test]
+fn lcov_reports_lines_of_uncalled_lambda() {
+  let source = "let unused = (x) -> {
+  let y = x + 1;
+  y * 2
+};
+let test_runs = () -> {
+  assert(true);
+};
+";
+  let dir = test_dir("cover_test.oma", source);
+  let output = Command::new(env!("CARGO_BIN_EXE_oma"))
+    .current_dir(&dir)
+    .args(["test", "--coverage", "."])
+    .output()
+    .unwrap();
+  let lcov = fs::read_to_string(dir.join("lcov.info"));
+  fs::remove_dir_all(&dir).unwrap();
+
+  assert!(output.status.success());
+  assert_eq!(
+    lcov.unwrap(),
+    "\
+SF:./cover_test.oma
+DA:1,1
+DA:2,0
+DA:3,0
+DA:5,1
+DA:6,1
+LH:3
+LF:5
+end_of_record
+"
+  );
+  let stdout = String::from_utf8(output.stdout).unwrap();
+  assert!(
+    stdout.contains("60.0%    3/5    ./cover_test.oma"),
+    "{}",
+    stdout
+  );
+}
@@ -0,0 +1,63 @@
+// Executables written by the compiler and read back by the VM.
+
+mod common;
+
+use common::{compile_source, eval, natives};
+use oma_compiler::Options;
+use shu::Vm;
+
+#[test]
+fn long_strings_are_read_back() {
+  // Longer than 127 bytes, so its length takes two LEB128 bytes.
+  let long = "a".repeat(200);
+  let source = format!("let long = \"{0}\"; [long == \"{0}\", long.len()]", long);
+  assert_eq!(eval(&source).unwrap(), "[true, 200]");
+}
+
+#[test]
+fn strings_are_written_once() {
+  let long = "b".repeat(200);
+  let once = compile_source(&format!("\"{}\"", long), &Options::default()).unwrap();
+  let source = format!("[\"{0}\", \"{0}\", \"{0}\"]", long);
+  let thrice = compile_source(&source, &Options::default()).unwrap();
+  assert!(thrice.bytes.len() < once.bytes.len() + long.len());
+  let result = Vm::new().eval(natives(), &thrice.bytes).unwrap();
+  assert_eq!(result.to_string(), format!("[{0}, {0}, {0}]", long));
+}
+
+#[test]
+fn nested_functions_are_read_back() {
+  let source = "let f = (a) -> (b) -> (c) -> a + b + c; f(1)(2)(3)";
+  assert_eq!(eval(source).unwrap(), "6");
+}
+
+#[test]
+fn many_functions_are_read_back() {
+  // More functions and constants than fit in one LEB128 byte.
+  let lambdas = (0..200)
+    .map(|index| format!("() -> {}", index))
+    .collect::<Vec<_>>()
+    .join(", ");
+  let source = format!("let fs = [{}]; [fs.len(), fs[0](), fs[199]()]", lambdas);
+  assert_eq!(eval(&source).unwrap(), "[200, 0, 199]");
+}
+
+#[test]
+fn spans_are_read_back() {
+  // Lines and columns past 127 take two LEB128 bytes.
+  let source = format!("{}{}1 / 0", "\n".repeat(300), " ".repeat(200));
+  let compilation = compile_source(&source, &Options::default()).unwrap();
+  let error = Vm::new().eval(natives(), &compilation.bytes).unwrap_err();
+  let trace = error.trace().to_string();
+  assert!(trace.contains(":301:200)"), "{}", trace);
+}
+
+#[test]
+fn changed_byte_fails_checksum() {
+  let compilation = compile_source("[1, 2, 3]", &Options::default()).unwrap();
+  let mut bytes = compilation.bytes;
+  let middle = bytes.len() / 2;
+  bytes[middle] ^= 1;
+  let error = Vm::new().eval(natives(), &bytes).unwrap_err();
+  assert!(error.to_string().contains("matching checksum"), "{}", error);
+}
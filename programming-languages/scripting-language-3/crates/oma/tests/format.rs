@@ -0,0 +1,114 @@
+mod common;
+
+use std::{cell::RefCell, fs, path::Path, rc::Rc};
+
+use common::source_file;
+use oma::{
+  assert::ASSERTIONS,
+  prelude::{METHODS, PRELUDE},
+};
+use shu::{NativeLambdaRegistry, Value, Vm};
+
+// Natives the examples are run with, besides the prelude and the assertions,
+// which are called by the hosts in `crates/oma/examples`.
+const HOST_NATIVES: [&str; 2] = ["__sleep", "__array_map"];
+
+fn examples() -> Vec<(String, String)> {
+  let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../examples");
+  let mut examples = fs::read_dir(dir)
+    .unwrap()
+    .map(|entry| entry.unwrap().path())
+    .filter(|path| path.extension().is_some_and(|extension| extension == "oma"))
+    .map(|path| {
+      let name = path.file_name().unwrap().to_string_lossy().to_string();
+      (name, fs::read_to_string(path).unwrap())
+    })
+    .collect::<Vec<_>>();
+  examples.sort();
+  assert!(!examples.is_empty());
+  examples
+}
+
+fn format(source: &str) -> String {
+  let path = source_file(source);
+  let formatted = oma_compiler::format(&path);
+  fs::remove_file(&path).unwrap();
+  formatted.unwrap()
+}
+
+// Runs the source and each of its tests, with natives that log what they're
+// passed, and returns the log.
+fn run(source: &str) -> Vec<String> {
+  let names = PRELUDE
+    .iter()
+    .map(|native| native.name)
+    .chain(HOST_NATIVES)
+    .chain(ASSERTIONS.iter().map(|(name, _)| *name));
+  let path = source_file(source);
+  let compilation = oma_compiler::compile_tests(
+    &path,
+    names
+      .enumerate()
+      .map(|(index, name)| (name, index))
+      .collect(),
+    &Default::default(),
+  );
+  fs::remove_file(&path).unwrap();
+  let (compilation, tests) = compilation.unwrap();
+
+  let log = Rc::new(RefCell::new(Vec::new()));
+  let mut registry = NativeLambdaRegistry::new();
+  for name in PRELUDE.iter().map(|native| native.name).chain(["__sleep"]) {
+    let log = Rc::clone(&log);
+    registry.add(name, move |value: Value| {
+      log.borrow_mut().push(format!("{}({})", name, value));
+      value
+    });
+  }
+  registry.add_with_context("__array_map", |context, argument| {
+    let array = argument.get(&Value::Int(0)).unwrap_or(Value::Null);
+    let lambda = argument.get(&Value::Int(1)).unwrap_or(Value::Null);
+    let result = Value::array();
+    for item in array.as_array().unwrap().borrow().items().to_vec() {
+      let value = context.call(&lambda, &[item])?;
+      result.as_array().unwrap().borrow_mut().push(&value);
+    }
+    Ok(result)
+  });
+  for (name, lambda) in ASSERTIONS {
+    registry.add_with_context(name, lambda);
+  }
+  for (kind, name, method) in METHODS {
+    registry.add_method(kind, name, method);
+  }
+
+  let mut vm = Vm::new();
+  let exports = vm.eval(registry, &compilation.bytes).unwrap();
+  for name in tests {
+    let test = exports.get(&vm.intern(&name)).unwrap();
+    let result = match vm.call(&test, &[]) {
+      Ok(value) => value.to_string(),
+      Err(error) => error.reason().to_string(),
+    };
+    log.borrow_mut().push(format!("{} -> {}", name, result));
+  }
+  let log = log.borrow().clone();
+  log
+}
+
+#[test]
+fn formatting_is_idempotent() {
+  for (name, source) in examples() {
+    let once = format(&source);
+    assert_eq!(format(&once), once, "formatting {} again changed it", name);
+  }
+}
+
+#[test]
+fn formatting_keeps_behaviour() {
+  for (name, source) in examples() {
+    let log = run(&source);
+    assert!(!log.is_empty(), "{} did nothing", name);
+    assert_eq!(run(&format(&source)), log, "formatting {} changed it", name);
+  }
+}
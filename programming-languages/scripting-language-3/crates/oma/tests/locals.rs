@@ -0,0 +1,82 @@
+// Case arms bind locals wherever a case expression appears, and the frame
+// must have a slot for each of them.
+
+mod common;
+
+use common::eval;
+
+#[test]
+fn case_in_array_item() {
+  assert_eq!(
+    eval("let p = 3; [5, case p { a -> a, }]").unwrap(),
+    "[5, 3]"
+  );
+}
+
+#[test]
+fn case_in_map_value() {
+  assert_eq!(
+    eval("let p = 3; { k: case p { a -> a, } }").unwrap(),
+    "{k: 3}"
+  );
+}
+
+#[test]
+fn case_in_tag_field() {
+  assert_eq!(
+    eval("let p = 3; Some(case p { a -> a, })").unwrap(),
+    "Some(3)"
+  );
+}
+
+#[test]
+fn case_as_call_receiver() {
+  assert_eq!(eval("(case 1 { a -> (x) -> x, })(5)").unwrap(), "5");
+}
+
+#[test]
+fn case_in_call_argument() {
+  let source = "let f = (x) -> x; let p = 3; f(case p { a -> a, })";
+  assert_eq!(eval(source).unwrap(), "3");
+}
+
+#[test]
+fn case_in_binary_operands() {
+  let source = "let p = 3; (case p { a -> a, }) + (case p { b -> b, })";
+  assert_eq!(eval(source).unwrap(), "6");
+}
+
+#[test]
+fn case_in_unary_operand() {
+  assert_eq!(eval("let p = 3; -(case p { a -> a, })").unwrap(), "-3");
+}
+
+#[test]
+fn case_in_bound_value() {
+  let source = "let p = 3; let q = case p { a -> a, }; let r = 4; [q, r]";
+  assert_eq!(eval(source).unwrap(), "[3, 4]");
+}
+
+#[test]
+fn case_in_assigned_value() {
+  let source = "let p = 3; let q = 0; q = case p { a -> a + 1, }; q";
+  assert_eq!(eval(source).unwrap(), "4");
+}
+
+#[test]
+fn case_in_index() {
+  let source = "let items = [7, 8]; let p = 1; items[case p { a -> a, }]";
+  assert_eq!(eval(source).unwrap(), "8");
+}
+
+#[test]
+fn case_in_if_condition() {
+  let source = "let p = true; if case p { a -> a, } { 1 } else { 2 }";
+  assert_eq!(eval(source).unwrap(), "1");
+}
+
+#[test]
+fn case_in_case_subject() {
+  let source = "let p = 3; case case p { a -> a, } { b -> [b], }";
+  assert_eq!(eval(source).unwrap(), "[3]");
+}
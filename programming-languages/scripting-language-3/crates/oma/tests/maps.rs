@@ -0,0 +1,21 @@
+mod common;
+
+use common::eval;
+
+#[test]
+fn built_key_finds_constant_key() {
+  let source = "let m = {key: 1}; let k = \"ke\" + \"y\"; m[k]";
+  assert_eq!(eval(source).unwrap(), "1");
+}
+
+#[test]
+fn constant_key_finds_built_key() {
+  let source = "let m = {}; m[\"ke\" + \"y\"] = 2; m.key";
+  assert_eq!(eval(source).unwrap(), "2");
+}
+
+#[test]
+fn built_keys_set_the_same_entry() {
+  let source = "let m = {}; m[\"k\" + \"1\"] = 1; m[\"k\" + \"1\"] = 2; m";
+  assert_eq!(eval(source).unwrap(), "{k1: 2}");
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use common::eval;
+
+#[test]
+fn method_gets_receiver_as_first_argument() {
+  assert_eq!(eval("[1, 2, 3].len()").unwrap(), "3");
+  assert_eq!(eval("let a = [1]; a.push(2); a").unwrap(), "[1, 2]");
+}
+
+#[test]
+fn field_is_called_without_map() {
+  assert_eq!(eval("let m = { f: (x) -> x + 1 }; m.f(1)").unwrap(), "2");
+}
+
+#[test]
+fn missing_method_is_error() {
+  let error = eval("[1].nothing()").unwrap_err();
+  assert!(error.contains("no method `nothing` for array"), "{}", error);
+}
+
+#[test]
+fn method_is_looked_up_along_meta_chain() {
+  let source = "
+    let Base = { name: (self) -> \"base\", size: (self) -> self.n };
+    let Middle = { name: (self) -> \"middle\" }.set_meta(Base);
+    let m = { n: 3 }.set_meta(Middle);
+    [m.name(), m.size()]";
+  assert_eq!(eval(source).unwrap(), "[middle, 3]");
+}
+
+#[test]
+fn meta_falls_back_to_map_methods() {
+  let source = "let m = { a: 1 }.set_meta({}); m.keys()";
+  assert_eq!(eval(source).unwrap(), "[a]");
+}
+
+#[test]
+fn meta_cycle_is_error() {
+  let source = "let a = {}; let b = {}.set_meta(a); a.set_meta(b)";
+  let error = eval(source).unwrap_err();
+  assert!(error.contains("own chain of meta maps"), "{}", error);
+}
+
+#[test]
+fn operators_call_methods() {
+  let source = "
+    let Money = {
+      add: (self, other) -> ({ cents: self.cents + other.cents }).set_meta(self.meta()),
+      mul: (self, by) -> ({ cents: self.cents * by }).set_meta(self.meta()),
+    };
+    let price = { cents: 150 }.set_meta(Money);
+    [(price + price).cents, (price * 3).cents]";
+  assert_eq!(eval(source).unwrap(), "[300, 450]");
+}
+
+#[test]
+fn equality_calls_eql_on_either_operand() {
+  let source = "
+    let One = { eql: (self, other) -> other == 1 };
+    let m = {}.set_meta(One);
+    [m == 1, 1 == m, m != 1, 1 != m, 2 == m]";
+  assert_eq!(eval(source).unwrap(), "[true, true, false, false, false]");
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use std::{fs, process::Command};
+
+use common::{compile_source, natives, source_file};
+use oma_compiler::Options;
+use shu::{Error, Overflow, Reason, Value, Vm};
+
+const MAX_PLUS_ONE: &str = "9223372036854775807 + 1";
+
+fn eval(source: &str, overflow: Overflow) -> Result<Value, Error> {
+  let compilation = compile_source(source, &Options::default()).unwrap();
+  Vm::with_overflow(overflow).eval(natives(), &compilation.bytes)
+}
+
+// Whether evaluating the source fails for the reason.
+fn fails(source: &str, overflow: Overflow, reason: fn(&Reason) -> bool) -> bool {
+  eval(source, overflow).is_err_and(|error| reason(error.reason()))
+}
+
+#[test]
+fn overflow_is_error() {
+  let overflow = |reason: &Reason| matches!(reason, Reason::Overflow);
+  assert!(fails(MAX_PLUS_ONE, Overflow::Error, overflow));
+  assert!(fails("-9223372036854775807 - 2", Overflow::Error, overflow));
+}
+
+#[test]
+fn overflow_is_promoted() {
+  let value = eval(MAX_PLUS_ONE, Overflow::Promote).unwrap();
+  assert!(matches!(value, Value::BigInt(_)), "{:?}", value);
+  assert_eq!(value.to_string(), "9223372036854775808");
+  let value = eval("(-9223372036854775807 - 1) / -1", Overflow::Promote).unwrap();
+  assert_eq!(value.to_string(), "9223372036854775808");
+}
+
+#[test]
+fn promoted_result_that_fits_is_demoted() {
+  for source in [
+    "(9223372036854775807 + 1) - 1",
+    "let big = 2 ** 70; big / (2 ** 69) + 9223372036854775805",
+    "-(9223372036854775807 + 1) + 1",
+  ] {
+    let value = eval(source, Overflow::Promote).unwrap();
+    assert!(matches!(value, Value::Int(_)), "{}: {:?}", source, value);
+  }
+  let value = eval("(9223372036854775807 + 1) - 1", Overflow::Promote).unwrap();
+  assert!(matches!(value, Value::Int(i64::MAX)), "{:?}", value);
+}
+
+#[test]
+fn division_by_zero_is_error() {
+  let by_zero = |reason: &Reason| matches!(reason, Reason::DivisionByZero);
+  for overflow in [Overflow::Error, Overflow::Promote] {
+    for source in ["1 / 0", "1 % 0", "-5 % 0"] {
+      assert!(fails(source, overflow, by_zero), "{}", source);
+    }
+  }
+  for source in ["(2 ** 70) / 0", "(2 ** 70) % 0"] {
+    assert!(fails(source, Overflow::Promote, by_zero), "{}", source);
+  }
+}
+
+#[test]
+fn run_promotes_with_flag() {
+  let path = source_file(&format!("__console_info({});", MAX_PLUS_ONE));
+  let run = |flags: &[&str]| {
+    Command::new(env!("CARGO_BIN_EXE_oma"))
+      .current_dir(path.parent().unwrap())
+      .arg("run")
+      .args(flags)
+      .arg(&path)
+      .output()
+      .unwrap()
+  };
+  let promoted = run(&["--promote"]);
+  let failed = run(&[]);
+  fs::remove_file(&path).unwrap();
+
+  assert!(promoted.status.success());
+  assert_eq!(
+    String::from_utf8(promoted.stdout).unwrap(),
+    "9223372036854775808\n"
+  );
+  assert!(!failed.status.success());
+  let stderr = String::from_utf8(failed.stderr).unwrap();
+  assert!(stderr.contains("integer overflow"), "{}", stderr);
+}
@@ -0,0 +1,157 @@
+mod common;
+
+use std::{
+  fs,
+  path::Path,
+  process::{Command, Output},
+};
+
+use common::{eval_value, test_dir};
+use oma::assert::{differences, render, Difference};
+
+const TESTS: &str = "
+let test_passes = () -> {
+  assert_eq([[1, \"a\"], [1, \"a\"]]);
+};
+
+let test_map_differs = () -> {
+  assert_eq([({ b: \"x\", a: 1, c: [1, 2] }), ({ c: [1, 2], a: 1, b: \"y\" })]);
+};
+
+let test_fails = () -> {
+  assert(1 == 2);
+};
+";
+
+fn oma_test(dir: &Path, args: &[&str]) -> (Output, String) {
+  let output = Command::new(env!("CARGO_BIN_EXE_oma"))
+    .arg("test")
+    .args(args)
+    .arg(dir)
+    .output()
+    .unwrap();
+  let stdout = String::from_utf8(output.stdout.clone()).unwrap();
+  (output, stdout.replace(&dir.display().to_string(), "<dir>"))
+}
+
+#[test]
+fn runs_each_test_and_reports_differences() {
+  let dir = test_dir("sample_test.oma", TESTS);
+  let (output, stdout) = oma_test(&dir, &[]);
+  fs::remove_dir_all(&dir).unwrap();
+
+  assert!(!output.status.success());
+  assert_eq!(
+    stdout,
+    "\
+ok   <dir>/sample_test.oma test_passes
+FAIL <dir>/sample_test.oma test_map_differs
+  error: assertion failed: values aren't equal
+  .b
+    - expected: \"y\"
+    + actual:   \"x\"
+  at assert_eq (native)
+  at test_map_differs (<dir>/sample_test.oma:7:2)
+FAIL <dir>/sample_test.oma test_fails
+  error: assertion failed: expected true, found false
+  at assert (native)
+  at test_fails (<dir>/sample_test.oma:11:2)
+
+1 passed, 2 failed
+"
+  );
+}
+
+#[test]
+fn filter_runs_matching_tests() {
+  let dir = test_dir("sample_test.oma", TESTS);
+  let (output, stdout) = oma_test(&dir, &["--filter", "pass"]);
+  fs::remove_dir_all(&dir).unwrap();
+
+  assert!(output.status.success());
+  assert_eq!(
+    stdout,
+    "ok   <dir>/sample_test.oma test_passes\n\n1 passed, 0 failed\n"
+  );
+}
+
+#[test]
+fn tap_output() {
+  let dir = test_dir("sample_test.oma", TESTS);
+  let (_, stdout) = oma_test(&dir, &["--format", "tap", "--filter", "s"]);
+  fs::remove_dir_all(&dir).unwrap();
+
+  assert_eq!(
+    stdout,
+    "\
+TAP version 13
+ok 1 - <dir>/sample_test.oma test_passes
+not ok 2 - <dir>/sample_test.oma test_map_differs
+  ---
+  message: \"assertion failed: values aren't equal\"
+  expected: \"{ a: 1, b: \\\"y\\\", c: [1, 2] }\"
+  actual: \"{ a: 1, b: \\\"x\\\", c: [1, 2] }\"
+  differences: [{\"path\":\".b\",\"expected\":\"\\\"y\\\"\",\"actual\":\"\\\"x\\\"\"}]
+  at: [\"assert_eq (native)\", \"test_map_differs (<dir>/sample_test.oma:7:2)\"]
+  ...
+not ok 3 - <dir>/sample_test.oma test_fails
+  ---
+  message: \"assertion failed: expected true, found false\"
+  at: [\"assert (native)\", \"test_fails (<dir>/sample_test.oma:11:2)\"]
+  ...
+1..3
+"
+  );
+}
+
+#[test]
+fn json_output() {
+  let dir = test_dir("sample_test.oma", TESTS);
+  let (_, stdout) = oma_test(&dir, &["--format", "json", "--filter", "map"]);
+  fs::remove_dir_all(&dir).unwrap();
+
+  let lines = stdout.lines().collect::<Vec<_>>();
+  assert_eq!(
+    lines,
+    [
+      "{\"file\":\"<dir>/sample_test.oma\",\"test\":\"test_map_differs\",\"ok\":false,\
+       \"message\":\"assertion failed: values aren't equal\",\
+       \"expected\":\"{ a: 1, b: \\\"y\\\", c: [1, 2] }\",\
+       \"actual\":\"{ a: 1, b: \\\"x\\\", c: [1, 2] }\",\
+       \"differences\":[{\"path\":\".b\",\"expected\":\"\\\"y\\\"\",\"actual\":\"\\\"x\\\"\"}],\
+       \"trace\":[\"assert_eq (native)\",\"test_map_differs (<dir>/sample_test.oma:7:2)\"]}",
+      "{\"passed\":0,\"failed\":1}",
+    ]
+  );
+}
+
+#[test]
+fn render_quotes_strings_and_sorts_keys() {
+  let source =
+    "let m = { z: \"a b\", a: [null, 1.5] }; m[\"two words\"] = Some(x: {}); m.self = m; m";
+  assert_eq!(
+    render(&eval_value(source)),
+    "{ a: [null, 1.5], self: ..., \"two words\": Some(x: {}), z: \"a b\" }"
+  );
+}
+
+#[test]
+fn differences_name_the_items_and_fields_that_differ() {
+  let actual = eval_value("[{ a: 1, b: [1, 2] }, Pair(1, \"x\"), Some(1)]");
+  let expected = eval_value("[{ b: [1, 3], c: 2, a: 1 }, Pair(1, \"y\"), None(), 4]");
+  let difference = |path: &str, expected: Option<&str>, actual: Option<&str>| Difference {
+    path: path.to_string(),
+    expected: expected.map(str::to_string),
+    actual: actual.map(str::to_string),
+  };
+  assert_eq!(
+    differences(&actual, &expected),
+    vec![
+      difference("[0].b[1]", Some("3"), Some("2")),
+      difference("[0].c", Some("2"), None),
+      difference("[1].1", Some("\"y\""), Some("\"x\"")),
+      difference("[2]", Some("None()"), Some("Some(1)")),
+      difference("[3]", Some("4"), None),
+    ]
+  );
+}
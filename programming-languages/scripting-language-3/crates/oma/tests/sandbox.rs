@@ -0,0 +1,109 @@
+mod common;
+
+use common::compile_source;
+use oma::prelude::{METHODS, PRELUDE};
+use oma_compiler::Options;
+use shu::{
+  Capabilities, Context, Error, Kind, NativeLambdaRegistry, Overflow, Reason, Trace, Value, Vm,
+};
+
+// The prelude with the capabilities its natives need, and a method needing
+// one as well.
+fn natives() -> NativeLambdaRegistry {
+  let mut registry = NativeLambdaRegistry::new();
+  for native in PRELUDE {
+    let id = registry.add(native.name, native.lambda);
+    registry.require(id, native.capability);
+  }
+  for (kind, name, method) in METHODS {
+    registry.add_method(kind, name, method);
+  }
+  registry.add_method(Kind::Int, "twice", twice);
+  registry.require_method(Kind::Int, "twice", "math.twice");
+  registry
+}
+
+fn twice(_: &mut Context, arguments: Value) -> Result<Value, Error> {
+  let int = arguments.as_array().unwrap().borrow().get(&Value::Int(0));
+  int
+    .unwrap()
+    .mul(&Value::Int(2), Overflow::Error)
+    .map_err(|reason| Error::new(reason, Trace::new()))
+}
+
+fn granted(capabilities: &[&str]) -> Capabilities {
+  let mut granted = Capabilities::new();
+  for capability in capabilities {
+    granted.grant(capability);
+  }
+  granted
+}
+
+fn run(source: &str, capabilities: Capabilities) -> Result<Value, Error> {
+  let compilation = compile_source(source, &Options::default()).unwrap();
+  let mut vm = Vm::new();
+  vm.sandbox(capabilities);
+  vm.eval(natives(), &compilation.bytes)
+}
+
+#[test]
+fn denied_native_is_compile_error_naming_it() {
+  let options = Options {
+    denied: natives().denied(&Capabilities::new()),
+    ..Options::default()
+  };
+  let error = compile_source("__console_info(1)", &options).unwrap_err();
+  assert!(
+    error.contains("`__console_info` needs the `io.console` capability, which isn't granted"),
+    "{}",
+    error
+  );
+}
+
+#[test]
+fn granted_native_compiles() {
+  let options = Options {
+    denied: natives().denied(&granted(&["io"])),
+    ..Options::default()
+  };
+  assert!(compile_source("__console_info(1)", &options).is_ok());
+}
+
+#[test]
+fn native_not_granted_fails_when_loaded() {
+  let error = run("let info = __console_info; 1", Capabilities::new()).unwrap_err();
+  assert!(matches!(error.reason(), Reason::NotGranted(capability) if capability == "io.console"));
+}
+
+#[test]
+fn native_granted_by_parent_runs() {
+  let value = run("__console_info(1); 2", granted(&["io"])).unwrap();
+  assert_eq!(value.to_string(), "2");
+}
+
+#[test]
+fn native_granted_by_name_prefix_fails() {
+  let error = run("__console_info(1)", granted(&["io.consol"])).unwrap_err();
+  assert!(matches!(error.reason(), Reason::NotGranted(_)));
+}
+
+#[test]
+fn method_not_granted_fails_when_called() {
+  let error = run("3.twice()", granted(&["math.plus"])).unwrap_err();
+  assert!(matches!(error.reason(), Reason::NotGranted(capability) if capability == "math.twice"));
+  let value = run("\"hey\".len()", Capabilities::new()).unwrap();
+  assert_eq!(value.to_string(), "3");
+}
+
+#[test]
+fn method_granted_runs() {
+  let value = run("3.twice()", granted(&["math"])).unwrap();
+  assert_eq!(value.to_string(), "6");
+}
+
+#[test]
+fn vm_without_sandbox_runs_everything() {
+  let compilation = compile_source("__console_info(1); 3.twice()", &Options::default()).unwrap();
+  let value = Vm::new().eval(natives(), &compilation.bytes).unwrap();
+  assert_eq!(value.to_string(), "6");
+}
@@ -0,0 +1,198 @@
+mod common;
+
+use std::{cell::RefCell, fs, rc::Rc};
+
+use common::{compile_source, natives, registry, source_file};
+use oma::prelude::{METHODS, PRELUDE};
+use oma_compiler::{compile, Options};
+use shu::{Expected, NativeLambdaRegistry, Overflow, Reason, Vm};
+
+type Output = Rc<RefCell<Vec<String>>>;
+
+// Compiles the source with a `print` native after the prelude, which the
+// registries from `printing` record into.
+fn compile_printing(source: &str) -> Vec<u8> {
+  let path = source_file(source);
+  let mut names = registry();
+  names.push(("print", names.len()));
+  let compilation = compile(&path, names, &Options::default());
+  fs::remove_file(&path).unwrap();
+  compilation.unwrap().bytes
+}
+
+fn printing(output: &Output) -> NativeLambdaRegistry {
+  let mut registry = natives();
+  let output = Rc::clone(output);
+  registry.add("print", move |value| {
+    output.borrow_mut().push(value.to_string());
+    value
+  });
+  registry
+}
+
+// Runs the executable to the end, promoting integers that overflow so that
+// big integers are snapshotted too, and returns what it printed and its value.
+fn run(executable: &[u8]) -> (Vec<String>, String) {
+  let output = Output::default();
+  let value = Vm::with_overflow(Overflow::Promote)
+    .eval(printing(&output), executable)
+    .unwrap();
+  let printed = output.borrow().clone();
+  (printed, value.to_string())
+}
+
+// Runs the executable for the given number of steps, and then continues in a
+// new VM from a snapshot. Returns `None` if it was done before that.
+fn run_restoring(executable: &[u8], steps: usize) -> Option<(Vec<String>, String)> {
+  let output = Output::default();
+  let mut vm = Vm::with_overflow(Overflow::Promote);
+  vm.load(printing(&output), executable).unwrap();
+  for _ in 0..steps {
+    if vm.step().unwrap() {
+      return None;
+    }
+  }
+  let snapshot = vm.snapshot().unwrap();
+
+  let mut vm = Vm::with_overflow(Overflow::Promote);
+  vm.restore(printing(&output), executable, &snapshot)
+    .unwrap();
+  let value = vm.resume().unwrap();
+  let printed = output.borrow().clone();
+  Some((printed, value.to_string()))
+}
+
+// Checks that restoring from a snapshot after any number of steps prints and
+// returns the same as running without stopping.
+fn assert_restores(source: &str) {
+  let executable = compile_printing(source);
+  let expected = run(&executable);
+  let mut steps = 0;
+  while let Some(restored) = run_restoring(&executable, steps) {
+    assert_eq!(restored, expected, "restored after {} steps", steps);
+    steps += 1;
+  }
+  assert!(steps > 1);
+}
+
+#[test]
+fn restored_run_prints_the_same() {
+  assert_restores(
+    "
+    let total = 0;
+    let index = 0;
+    while index < 4 {
+      total = total + index * index;
+      print([index, total, \"so far\"]);
+      index = index + 1;
+    }
+    Done(total, 2 ** 70)",
+  );
+}
+
+#[test]
+fn cycle_is_kept() {
+  assert_restores(
+    "
+    let map = { name: \"loop\" };
+    map.self = map;
+    print(map.self.self.name);
+    map.self.name = \"changed\";
+    [map.name, map.self.self.self.name]",
+  );
+}
+
+#[test]
+fn shared_array_still_aliases() {
+  assert_restores(
+    "
+    let shared = [1];
+    let pair = { left: shared, right: shared };
+    pair.left.push(2);
+    print(pair.right);
+    pair.right.push(3);
+    [shared, pair.left, pair.right.len()]",
+  );
+}
+
+#[test]
+fn open_upvalue_is_shared_with_its_frame() {
+  assert_restores(
+    "
+    let count = 1;
+    let read = () -> count;
+    print(read());
+    count = count + 10;
+    let seen = read();
+    count = count + 100;
+    [seen, read(), count]",
+  );
+}
+
+#[test]
+fn closed_upvalue_keeps_its_value() {
+  assert_restores(
+    "
+    let counter = () -> {
+      let count = [0];
+      let start = 10;
+      () -> {
+        count[0] = count[0] + 1;
+        start + count[0]
+      }
+    };
+    let next = counter();
+    let other = counter();
+    print(next());
+    [next(), next(), other()]",
+  );
+}
+
+#[test]
+fn snapshot_of_other_executable_is_rejected() {
+  let executable = compile_source("let a = [1, 2]; a.push(3); a", &Options::default())
+    .unwrap()
+    .bytes;
+  let other = compile_source("let a = [1, 2]; a.push(4); a", &Options::default())
+    .unwrap()
+    .bytes;
+
+  let mut vm = Vm::new();
+  vm.load(natives(), &executable).unwrap();
+  vm.step().unwrap();
+  let snapshot = vm.snapshot().unwrap();
+
+  let error = Vm::new().restore(natives(), &other, &snapshot).unwrap_err();
+  match error.reason() {
+    Reason::Snapshot(error) => assert!(matches!(error.expected(), Expected::Executable)),
+    reason => panic!("unexpected reason: {}", reason),
+  }
+}
+
+#[test]
+fn native_missing_from_registry_is_rejected() {
+  let executable = compile_printing("let log = print; log(1); log(2)");
+  let output = Output::default();
+  let mut vm = Vm::new();
+  vm.load(printing(&output), &executable).unwrap();
+  while output.borrow().is_empty() {
+    vm.step().unwrap();
+  }
+  let snapshot = vm.snapshot().unwrap();
+
+  // The same natives, but without `print`.
+  let mut registry = NativeLambdaRegistry::new();
+  for native in PRELUDE {
+    registry.add(native.name, native.lambda);
+  }
+  for (kind, name, method) in METHODS {
+    registry.add_method(kind, name, method);
+  }
+  let error = Vm::new()
+    .restore(registry, &executable, &snapshot)
+    .unwrap_err();
+  match error.reason() {
+    Reason::Snapshot(error) => assert!(matches!(error.expected(), Expected::NativeLambda)),
+    reason => panic!("unexpected reason: {}", reason),
+  }
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use common::{compile_source, eval, natives};
+use oma_compiler::Options;
+use shu::Vm;
+
+#[test]
+fn named_pattern_matches_subset_of_fields() {
+  let source = "case Point(x: 1, y: 2) { Point(x: a) -> a, _ -> null, }";
+  assert_eq!(eval(source).unwrap(), "1");
+}
+
+#[test]
+fn named_pattern_matches_fields_in_any_order() {
+  let source = "case Point(x: 1, y: 2) { Point(y: b, x: a) -> [a, b], _ -> null, }";
+  assert_eq!(eval(source).unwrap(), "[1, 2]");
+}
+
+#[test]
+fn named_pattern_needs_its_fields() {
+  let source = "case Point(x: 1, y: 2) { Point(z: a) -> a, _ -> \"no\", }";
+  assert_eq!(eval(source).unwrap(), "no");
+}
+
+#[test]
+fn named_pattern_needs_named_fields() {
+  let source = "case Point(1, 2) { Point(x: a) -> a, _ -> \"no\", }";
+  assert_eq!(eval(source).unwrap(), "no");
+}
+
+#[test]
+fn named_pattern_needs_same_tag() {
+  let source = "case Size(x: 1) { Point(x: a) -> a, _ -> \"no\", }";
+  assert_eq!(eval(source).unwrap(), "no");
+}
+
+#[test]
+fn positional_pattern_needs_same_number_of_fields() {
+  let source = "case Pair(1, 2) { Pair(a) -> a, _ -> \"no\", }";
+  assert_eq!(eval(source).unwrap(), "no");
+}
+
+#[test]
+fn named_pattern_checks_fields() {
+  let source =
+    "case Point(x: 1, y: 2) { Point(x: 2) -> \"two\", Point(x: 1) -> \"one\", _ -> null, }";
+  assert_eq!(eval(source).unwrap(), "one");
+}
+
+#[test]
+fn named_pattern_matches_null_fields() {
+  let source = "case Point(x: null, y: 2) { Point(x: a, y: b) -> [a, b], _ -> \"no\", }";
+  assert_eq!(eval(source).unwrap(), "[null, 2]");
+}
+
+#[test]
+fn strict_case_without_matching_arm_names_subject() {
+  let options = Options {
+    strict: true,
+    ..Options::default()
+  };
+  let source = "case Point(1, \"a\") { Size(w) -> w, }";
+  let compilation = compile_source(source, &options).unwrap();
+  let error = Vm::new().eval(natives(), &compilation.bytes).unwrap_err();
+  assert!(
+    error
+      .to_string()
+      .contains("no matching arm for: Point(1, a)"),
+    "{}",
+    error
+  );
+}
@@ -1,8 +1,13 @@
-use shu::Vm;
+use std::{env, fs};
+
+use shu::{NativeLambdaRegistry, Vm};
 
 fn main() {
+  let path = env::args().nth(1).expect("usage: sandbox <executable>");
+  let executable = fs::read(path).expect("failed to read executable");
+
   let mut vm = Vm::new();
-  if let Err(error) = vm.run() {
+  if let Err(error) = vm.run(NativeLambdaRegistry::new(), &executable) {
     eprintln!("{}", error);
   }
 }
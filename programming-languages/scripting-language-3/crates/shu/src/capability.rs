@@ -0,0 +1,69 @@
+use std::collections::BTreeSet;
+
+// Named capabilities granted to a script, such as `io.fs.read` or `clock`.
+// Names are paths separated by dots, and granting one grants every capability
+// under it, so `io` grants both `io.fs.read` and `io.net`.
+#[derive(Clone, Debug, Default)]
+pub struct Capabilities {
+  granted: BTreeSet<String>,
+}
+
+impl Capabilities {
+  // Grants nothing, so only natives that need no capability can be used.
+  pub fn new() -> Capabilities {
+    Capabilities::default()
+  }
+
+  pub fn grant(&mut self, capability: &str) {
+    self.granted.insert(capability.to_string());
+  }
+
+  pub fn allows(&self, capability: &str) -> bool {
+    self.granted.iter().any(|granted| {
+      capability == granted
+        || capability
+          .strip_prefix(granted.as_str())
+          .is_some_and(|rest| rest.starts_with('.'))
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn granted(capabilities: &[&str]) -> Capabilities {
+    let mut granted = Capabilities::new();
+    for capability in capabilities {
+      granted.grant(capability);
+    }
+    granted
+  }
+
+  #[test]
+  fn grants_nothing_by_default() {
+    assert!(!Capabilities::new().allows("io"));
+  }
+
+  #[test]
+  fn grants_capabilities_under_it() {
+    let granted = granted(&["io"]);
+    assert!(granted.allows("io"));
+    assert!(granted.allows("io.net"));
+    assert!(granted.allows("io.fs.read"));
+    assert!(!granted.allows("clock"));
+  }
+
+  #[test]
+  fn does_not_grant_names_it_is_a_prefix_of() {
+    assert!(!granted(&["io.consol"]).allows("io.console"));
+    assert!(!granted(&["io"]).allows("iox"));
+  }
+
+  #[test]
+  fn does_not_grant_capabilities_above_it() {
+    let granted = granted(&["io.fs.read"]);
+    assert!(!granted.allows("io.fs"));
+    assert!(!granted.allows("io.fs.write"));
+  }
+}
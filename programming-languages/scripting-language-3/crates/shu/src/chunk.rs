@@ -44,14 +44,15 @@ unsafe impl Trace for Chunk {
 
 #[derive(Debug, Finalize, Trace)]
 pub enum Constant {
-  String(String),
+  // The id of the string in the interner.
+  String(usize),
   Function(Function),
 }
 
 impl Constant {
-  pub fn as_str(&self) -> Option<&str> {
-    if let Constant::String(string) = self {
-      Some(string.as_str())
+  pub fn as_string_id(&self) -> Option<usize> {
+    if let Constant::String(id) = self {
+      Some(*id)
     } else {
       None
     }
@@ -0,0 +1,87 @@
+use std::{
+  collections::{BTreeMap, HashMap},
+  rc::Rc,
+};
+
+use crate::chunk::Chunk;
+
+// Records which lines of source ran, from the spans of the ops that did. It
+// can be shared by several VMs to add up the lines they ran.
+#[derive(Debug, Default)]
+pub struct Coverage {
+  files: BTreeMap<Rc<String>, FileCoverage>,
+  // Chunks by their address, which holding on to them keeps unique.
+  chunks: HashMap<usize, ChunkCoverage>,
+}
+
+#[derive(Debug, Default)]
+pub struct FileCoverage {
+  // How many times each line with code ran, which is the most times any op
+  // on it ran, added up over the chunks loaded from the file.
+  pub lines: BTreeMap<usize, u64>,
+}
+
+impl FileCoverage {
+  pub fn lines_hit(&self) -> usize {
+    self.lines.values().filter(|hits| **hits > 0).count()
+  }
+}
+
+#[derive(Debug)]
+struct ChunkCoverage {
+  _chunk: Rc<Chunk>,
+  // How many times each op ran, by its offset.
+  ops: HashMap<usize, u64>,
+  // How many times each line ran in the chunk.
+  lines: HashMap<usize, u64>,
+}
+
+impl Coverage {
+  pub fn new() -> Coverage {
+    Coverage::default()
+  }
+
+  // The files that ran, or were loaded and didn't, ordered by path.
+  pub fn files(&self) -> impl Iterator<Item = (&str, &FileCoverage)> {
+    self.files.iter().map(|(path, file)| (path.as_str(), file))
+  }
+
+  // Adds the lines of the chunks, so lines that never run are reported.
+  pub(crate) fn add(&mut self, chunks: &[Rc<Chunk>]) {
+    for chunk in chunks {
+      self.chunk(chunk);
+      for (_, span) in chunk.info().iter().flat_map(|info| &info.spans) {
+        let file = self.files.entry(Rc::clone(&span.path)).or_default();
+        file.lines.entry(span.line).or_insert(0);
+      }
+    }
+  }
+
+  pub(crate) fn hit(&mut self, chunk: &Rc<Chunk>, offset: usize) {
+    let span = match chunk.info().and_then(|info| info.span(offset)) {
+      Some(span) => span,
+      None => return,
+    };
+
+    let chunk = self.chunk(chunk);
+    let hits = chunk.ops.entry(offset).or_insert(0);
+    *hits += 1;
+    let line = chunk.lines.entry(span.line).or_insert(0);
+    if *hits > *line {
+      *line = *hits;
+      let file = self.files.entry(span.path).or_default();
+      *file.lines.entry(span.line).or_insert(0) += 1;
+    }
+  }
+
+  fn chunk(&mut self, chunk: &Rc<Chunk>) -> &mut ChunkCoverage {
+    self
+      .chunks
+      .entry(Rc::as_ptr(chunk) as usize)
+      .or_insert_with(|| ChunkCoverage {
+        _chunk: Rc::clone(chunk),
+        ops: HashMap::new(),
+        lines: HashMap::new(),
+      })
+  }
+}
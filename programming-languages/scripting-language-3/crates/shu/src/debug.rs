@@ -2,16 +2,25 @@ use std::{fmt, rc::Rc};
 
 #[derive(Clone, Debug)]
 pub struct Info {
-  pub spans: Vec<Rc<Span>>,
+  pub name: Rc<String>,
+  // Spans by the offset of the first op they cover, in order of offset. Each
+  // span covers the ops up to the next one.
+  pub spans: Vec<(usize, Rc<Span>)>,
 }
 
 impl Info {
+  pub fn name(&self) -> &Rc<String> {
+    &self.name
+  }
+
   pub fn span(&self, offset: usize) -> Option<Span> {
-    self.spans.get(offset).map(|span| (&**span).clone())
+    let index = self.spans.partition_point(|(start, _)| *start <= offset);
+    let (_, span) = self.spans.get(index.checked_sub(1)?)?;
+    Some((**span).clone())
   }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Span {
   pub line: usize,
   pub column: usize,
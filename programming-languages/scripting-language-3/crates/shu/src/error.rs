@@ -1,6 +1,10 @@
-use std::fmt;
+use std::{error, fmt, rc::Rc};
 
-use crate::{debug::Span, parse::ParseError, value::Value};
+use crate::{
+  debug::Span,
+  parse::ParseError,
+  value::{Kind, Value},
+};
 
 #[derive(Debug)]
 pub struct Error {
@@ -12,6 +16,22 @@ impl Error {
   pub fn new(reason: Reason, trace: Trace) -> Error {
     Error { reason, trace }
   }
+
+  pub fn reason(&self) -> &Reason {
+    &self.reason
+  }
+
+  pub fn trace(&self) -> &Trace {
+    &self.trace
+  }
+
+  pub fn category(&self) -> Category {
+    self.reason.category()
+  }
+
+  pub(crate) fn set_trace(&mut self, trace: Trace) {
+    self.trace = trace;
+  }
 }
 
 impl fmt::Display for Error {
@@ -22,10 +42,31 @@ impl fmt::Display for Error {
   }
 }
 
+impl error::Error for Error {
+  fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+    match &self.reason {
+      Reason::Parse(error) | Reason::Snapshot(error) => Some(error),
+      _ => None,
+    }
+  }
+}
+
+// Whether an error is the executable's fault or the program's.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Category {
+  // The executable is corrupt or wasn't produced by a matching compiler, or
+  // a snapshot is corrupt or doesn't match the executable.
+  Executable,
+  // The program did something invalid while running, like adding a string to
+  // a number.
+  User,
+}
+
 #[derive(Debug)]
 pub enum Reason {
   // Compiler errors
   Parse(ParseError),
+  Snapshot(ParseError),
   InvalidCode(usize),
   InvalidOpcode(u8),
   InvalidData(usize),
@@ -37,16 +78,69 @@ pub enum Reason {
   // User errors
   Type,
   InvalidKey(Value),
+  InvalidRange(Value, Value),
   InvalidTag(Value),
   ValueNotCallable(Value),
+  NoMethod(Kind, Value),
+  // A meta map that would end up in its own chain of meta maps.
+  MetaCycle,
   WrongArity,
+  Overflow,
+  DivisionByZero,
+  NegativeShift,
+  NoMatchingArm(Value),
+  CannotSuspend,
+  CannotSnapshot,
+  // The capability a native lambda needs, which the VM wasn't granted.
+  NotGranted(String),
+  // Assertions made by tests
+  AssertionFailed(Value),
+  // The actual value, then the expected one.
+  NotEqual(Value, Value),
+  NoError,
+}
+
+impl Reason {
+  pub fn category(&self) -> Category {
+    match self {
+      Reason::Parse(_)
+      | Reason::Snapshot(_)
+      | Reason::InvalidCode(_)
+      | Reason::InvalidOpcode(_)
+      | Reason::InvalidData(_)
+      | Reason::InvalidUpvalue(_)
+      | Reason::InvalidNativeLambda(_)
+      | Reason::WrongConstantType
+      | Reason::EmptyFrameStack
+      | Reason::EmptyStack => Category::Executable,
+      Reason::Type
+      | Reason::InvalidKey(_)
+      | Reason::InvalidRange(..)
+      | Reason::InvalidTag(_)
+      | Reason::ValueNotCallable(_)
+      | Reason::NoMethod(..)
+      | Reason::MetaCycle
+      | Reason::WrongArity
+      | Reason::Overflow
+      | Reason::DivisionByZero
+      | Reason::NegativeShift
+      | Reason::NoMatchingArm(_)
+      | Reason::CannotSuspend
+      | Reason::CannotSnapshot
+      | Reason::NotGranted(_)
+      | Reason::AssertionFailed(_)
+      | Reason::NotEqual(..)
+      | Reason::NoError => Category::User,
+    }
+  }
 }
 
 impl fmt::Display for Reason {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     match self {
       // Compiler errors
-      Reason::Parse(_) => write!(f, "corrupt executable"),
+      Reason::Parse(error) => write!(f, "corrupt executable: {}", error),
+      Reason::Snapshot(error) => write!(f, "corrupt snapshot: {}", error),
       Reason::InvalidCode(offset) => write!(f, "tried to access invalid code: {}", offset),
       Reason::InvalidOpcode(code) => write!(f, "opcode is invalid: {}", code),
       Reason::InvalidData(constant) => write!(f, "tried to access invalid constant: {}", constant),
@@ -58,44 +152,111 @@ impl fmt::Display for Reason {
       // User errors
       Reason::Type => write!(f, "invalid type"),
       Reason::InvalidTag(value) => write!(f, "invalid tag: {:?}", value),
-      Reason::InvalidKey(key) => write!(f, "invalid key: {:?}", key),
+      Reason::InvalidKey(key) => write!(f, "invalid key: {}", key),
+      Reason::InvalidRange(start, end) => write!(f, "invalid range: {}..{}", start, end),
       Reason::ValueNotCallable(value) => write!(f, "value not callable: {:?}", value),
+      Reason::NoMethod(kind, name) => write!(f, "no method `{}` for {}", name, kind),
+      Reason::MetaCycle => write!(f, "a map can't be in its own chain of meta maps"),
       Reason::WrongArity => write!(f, "wrong arity"),
+      Reason::Overflow => write!(f, "integer overflow"),
+      Reason::DivisionByZero => write!(f, "division by zero"),
+      Reason::NegativeShift => write!(f, "shift by negative amount"),
+      Reason::NoMatchingArm(subject) => write!(f, "no matching arm for: {}", subject),
+      Reason::CannotSuspend => write!(f, "cannot wait on an async native outside an async run"),
+      Reason::CannotSnapshot => write!(f, "cannot snapshot a fiber in the middle of a native call"),
+      Reason::NotGranted(capability) => {
+        write!(
+          f,
+          "native lambda needs the `{}` capability, which isn't granted",
+          capability
+        )
+      }
+      // Assertions made by tests
+      Reason::AssertionFailed(value) => {
+        write!(f, "assertion failed: expected true, found {}", value)
+      }
+      Reason::NotEqual(actual, expected) => {
+        write!(
+          f,
+          "assertion failed: expected {}, found {}",
+          expected, actual
+        )
+      }
+      Reason::NoError => write!(f, "assertion failed: expected an error"),
     }
   }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Trace {
   // List of frames when error occured.
   // From the least recent call to the most recent call.
-  spans: Vec<Option<Span>>,
+  frames: Vec<TraceFrame>,
 }
 
 impl Trace {
   pub fn new() -> Trace {
-    Trace { spans: Vec::new() }
+    Trace { frames: Vec::new() }
+  }
+
+  pub fn push(&mut self, frame: TraceFrame) {
+    self.frames.push(frame)
   }
 
-  pub fn push(&mut self, span: Option<Span>) {
-    self.spans.push(span)
+  pub fn frames(&self) -> &[TraceFrame] {
+    &self.frames
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.frames.is_empty()
   }
 }
 
 impl fmt::Display for Trace {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    for (index, span) in self.spans.iter().rev().enumerate() {
-      let separator = if index == self.spans.len() - 1 {
-        ""
-      } else {
-        "\n"
-      };
-      if let Some(span) = span {
-        write!(f, "  in {}{}", span, separator)?;
-      } else {
-        write!(f, "  in <<unknown>>{}", separator)?;
+    // Runs of the same frame, which recursion leaves, are printed once.
+    let mut runs: Vec<(&TraceFrame, usize)> = Vec::new();
+    for frame in self.frames.iter().rev() {
+      match runs.last_mut() {
+        Some((last, count)) if *last == frame => *count += 1,
+        _ => runs.push((frame, 1)),
+      }
+    }
+
+    for (index, (frame, count)) in runs.iter().enumerate() {
+      if index > 0 {
+        writeln!(f)?;
+      }
+      write!(f, "  at {}", frame)?;
+      if *count > 1 {
+        write!(f, "\n  ... repeated {} more times", count - 1)?;
       }
     }
     Ok(())
   }
 }
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TraceFrame {
+  Lambda {
+    name: Rc<String>,
+    // Where the lambda was, or `None` if the executable has no debug info.
+    span: Option<Span>,
+  },
+  Native {
+    name: Rc<String>,
+  },
+}
+
+impl fmt::Display for TraceFrame {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      TraceFrame::Lambda {
+        name,
+        span: Some(span),
+      } => write!(f, "{} ({})", name, span),
+      TraceFrame::Lambda { name, span: None } => write!(f, "{}", name),
+      TraceFrame::Native { name } => write!(f, "{} (native)", name),
+    }
+  }
+}
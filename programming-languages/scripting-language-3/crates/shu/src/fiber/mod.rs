@@ -1,27 +1,95 @@
-use std::{mem, rc::Rc};
+use std::{
+  cell::RefCell,
+  mem,
+  rc::Rc,
+  task::{self, Poll},
+};
 
 use gc::{Gc, GcCell};
 
 use crate::{
   chunk::{Chunk, Function, Op},
-  error::{Error, Reason},
+  coverage::Coverage,
+  error::{Error, Reason, Trace, TraceFrame},
+  intern::Interner,
   opcode::Opcode,
   registry::NativeLambdaRegistry,
-  value::{Lambda, Tagged, Upvalue, Value},
+  value::{
+    Array, Lambda, NativeCall, NativeFuture, NativeLambda, Overflow, Tagged, Upvalue, Value,
+  },
 };
 
-use self::{frame::Frame, stack::Stack};
+pub use self::snapshot::Module;
+
+use self::{
+  frame::Frame,
+  packed::Packed,
+  snapshot::{Reader, Writer},
+  stack::Stack,
+};
 
 mod frame;
+mod packed;
+mod snapshot;
 mod stack;
 
+// The operators with a fast path in `Packed` try it first, and unpack their
+// operands only if it doesn't apply.
 macro_rules! binary {
+  ($self:expr, $op:ident, $fast_op:ident) => {{
+    let right = $self.stack_pop_packed()?;
+    let left = $self.stack_pop_packed()?;
+    if let Some(result) = Packed::$fast_op(&left, &right) {
+      $self.stack.push_packed(result);
+      return Ok(());
+    }
+    let right = right.into_value();
+    let result = left
+      .into_value()
+      .$op(&right)
+      .ok_or_else(|| $self.build_error(Reason::Type))?;
+    $self.stack.push(result);
+    Ok(())
+  }};
+}
+
+macro_rules! arithmetic {
+  ($self:expr, $op:ident, $fast_op:ident) => {{
+    let right = $self.stack_pop_packed()?;
+    let left = $self.stack_pop_packed()?;
+    if let Some(result) = Packed::$fast_op(&left, &right) {
+      $self.stack.push_packed(result);
+      return Ok(());
+    }
+    let right = right.into_value();
+    let left = left.into_value();
+    let result = match $self.protocol(&left, stringify!($op))? {
+      Some(method) => $self.call_method(&method, left, vec![right])?,
+      None => left
+        .$op(&right, $self.overflow)
+        .map_err(|reason| $self.build_error(reason))?,
+    };
+    $self.stack.push(result);
+    Ok(())
+  }};
+  ($self:expr, $op:ident) => {{
+    let right = $self.stack_pop()?;
+    let left = $self.stack_pop()?;
+    let result = left
+      .$op(&right, $self.overflow)
+      .map_err(|reason| $self.build_error(reason))?;
+    $self.stack.push(result);
+    Ok(())
+  }};
+}
+
+macro_rules! bitwise {
   ($self:expr, $op:ident) => {{
     let right = $self.stack_pop()?;
     let left = $self.stack_pop()?;
     let result = left
       .$op(&right)
-      .ok_or_else(|| $self.build_error(Reason::Type))?;
+      .map_err(|reason| $self.build_error(reason))?;
     $self.stack.push(result);
     Ok(())
   }};
@@ -38,37 +106,164 @@ macro_rules! unary {
 
 pub struct Fiber {
   registry: Rc<NativeLambdaRegistry>,
+  interner: Rc<RefCell<Interner>>,
+  overflow: Overflow,
   chunk: Rc<Chunk>,
   ip: usize,
   bp: usize,
   stack: Stack,
+  // Native lambdas being called, with the number of frames below them.
+  natives: Vec<(usize, Gc<NativeLambda>)>,
+  // The async native the fiber is suspended on, with the future it returned.
+  waiting: Option<(Gc<NativeLambda>, NativeFuture)>,
+  // Where the ops that run are recorded, if anywhere.
+  coverage: Option<Rc<RefCell<Coverage>>>,
+  // How many frames at the bottom of the stack are left out of traces, which
+  // are those of the finished module while the host calls into it.
+  hidden_frames: usize,
 }
 
 impl Fiber {
-  pub fn new(registry: Rc<NativeLambdaRegistry>, function: Function) -> Fiber {
-    let mut stack = Stack::new();
-
-    stack.push(Value::Lambda(Gc::new(Lambda::new(
+  pub fn new(
+    registry: Rc<NativeLambdaRegistry>,
+    interner: Rc<RefCell<Interner>>,
+    overflow: Overflow,
+    function: Function,
+  ) -> Fiber {
+    let lambda = Gc::new(Lambda::new(
       function.arity as usize,
       Rc::clone(&function.chunk),
       function.locals as usize,
       Vec::new(),
-    ))));
+    ));
+    Fiber::start(registry, interner, overflow, lambda, &[])
+  }
+
+  // Creates a fiber that calls the lambda, which is done once it returns like
+  // a module is.
+  pub fn with_lambda(
+    registry: Rc<NativeLambdaRegistry>,
+    interner: Rc<RefCell<Interner>>,
+    overflow: Overflow,
+    lambda: &Value,
+    arguments: &[Value],
+  ) -> Result<Fiber, Error> {
+    let lambda = match lambda {
+      Value::Lambda(lambda) => Gc::clone(lambda),
+      value => {
+        let reason = Reason::ValueNotCallable(value.clone());
+        return Err(Error::new(reason, Trace::new()));
+      }
+    };
+    if arguments.len() != lambda.arity() {
+      return Err(Error::new(Reason::WrongArity, Trace::new()));
+    }
+    Ok(Fiber::start(
+      registry, interner, overflow, lambda, arguments,
+    ))
+  }
 
-    for _ in 0..function.locals {
+  fn start(
+    registry: Rc<NativeLambdaRegistry>,
+    interner: Rc<RefCell<Interner>>,
+    overflow: Overflow,
+    lambda: Gc<Lambda>,
+    arguments: &[Value],
+  ) -> Fiber {
+    let mut stack = Stack::new();
+
+    stack.push(Value::Lambda(Gc::clone(&lambda)));
+    for argument in arguments {
+      stack.push(argument.clone());
+    }
+    for _ in 0..lambda.locals() {
       stack.push(Value::Null);
     }
 
     Fiber {
       registry,
-      chunk: Rc::clone(&function.chunk),
+      interner,
+      overflow,
+      chunk: Rc::clone(lambda.chunk()),
       ip: 0,
       bp: 0,
       stack,
+      natives: Vec::new(),
+      waiting: None,
+      coverage: None,
+      hidden_frames: 0,
+    }
+  }
+
+  // Restores a fiber from a snapshot of one running the module.
+  pub fn restore(
+    registry: Rc<NativeLambdaRegistry>,
+    interner: Rc<RefCell<Interner>>,
+    overflow: Overflow,
+    module: &Module,
+    snapshot: &[u8],
+  ) -> Result<Fiber, Error> {
+    Reader::new(snapshot, module, &registry, &interner)
+      .read(Rc::clone(&registry), Rc::clone(&interner), overflow)
+      .map_err(|error| Error::new(Reason::Snapshot(error), Trace::new()))
+  }
+
+  // Writes the fiber to bytes, which `restore` continues from the same op.
+  // The fiber must be paused between steps, rather than waiting on an async
+  // native.
+  pub fn snapshot(&self, module: &Module) -> Result<Vec<u8>, Error> {
+    Writer::new(self, module).write()
+  }
+
+  pub fn record_coverage(&mut self, coverage: Rc<RefCell<Coverage>>) {
+    self.coverage = Some(coverage);
+  }
+
+  // Runs until the fiber is done. Fails if it calls an async native, since
+  // there is nothing to wait on the future with.
+  pub fn run(&mut self) -> Result<(), Error> {
+    while !self.advance()? {}
+    Ok(())
+  }
+
+  // Steps once like `run` does, and returns whether the fiber is done.
+  pub fn advance(&mut self) -> Result<bool, Error> {
+    match self.step()? {
+      Status::Running => Ok(false),
+      Status::Done => Ok(true),
+      Status::Yield => Err(self.suspend_error()),
+    }
+  }
+
+  // Runs until the fiber is done, or until it waits on an async native whose
+  // future isn't ready, in which case the future will wake the task.
+  pub fn poll_run(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Error>> {
+    loop {
+      if let Some((native, future)) = &mut self.waiting {
+        let result = match future.as_mut().poll(cx) {
+          Poll::Ready(result) => result,
+          Poll::Pending => return Poll::Pending,
+        };
+        let native = Gc::clone(native);
+        self.waiting = None;
+        match result {
+          Ok(value) => self.stack.push(value),
+          Err(error) => return Poll::Ready(Err(self.native_error(&native, error))),
+        }
+      }
+
+      match self.step() {
+        Ok(Status::Done) => return Poll::Ready(Ok(())),
+        Ok(_) => {}
+        Err(error) => return Poll::Ready(Err(error)),
+      }
     }
   }
 
   pub fn step(&mut self) -> Result<Status, Error> {
+    if let Some(coverage) = &self.coverage {
+      coverage.borrow_mut().hit(&self.chunk, self.ip);
+    }
     let op = self.next_op()?;
     let opcode = op
       .opcode()
@@ -106,6 +301,12 @@ impl Fiber {
       Opcode::Mul => self.mul(),
       Opcode::Div => self.div(),
       Opcode::Rem => self.rem(),
+      Opcode::Pow => self.pow(),
+      Opcode::Ban => self.ban(),
+      Opcode::Bor => self.bor(),
+      Opcode::Bxr => self.bxr(),
+      Opcode::Shl => self.shl(),
+      Opcode::Shr => self.shr(),
       Opcode::Neg => self.neg(),
       Opcode::Eql => self.eql(),
       Opcode::Neq => self.neq(),
@@ -118,12 +319,21 @@ impl Fiber {
       Opcode::Get => self.get(),
       Opcode::Set => self.set(),
       Opcode::Apn => self.apn(),
+      Opcode::Len => self.len(),
+      Opcode::Slc => self.slc(),
+      Opcode::Rmv => self.rmv(),
+      Opcode::Ins => self.ins(),
       // Tagged operations
-      Opcode::Tag => self.tag(),
-      Opcode::Utg => self.utg(),
-      Opcode::Gtg => self.gtg(),
+      Opcode::Tag => self.tag(op.operand() as usize),
+      Opcode::Tgn => self.tgn(op.operand() as usize),
+      Opcode::Utg => self.utg(op.operand() as usize),
+      Opcode::Gtg => self.gtg(op.operand() as usize),
+      Opcode::Gnt => self.gnt(),
+      Opcode::Has => self.has(),
+      Opcode::Nma => self.nma(),
       // Lambda operations
-      Opcode::Cal => self.cal(op.operand() as usize),
+      Opcode::Cal => return self.cal(op.operand() as usize).map(|_| self.status()),
+      Opcode::Ivk => return self.ivk(op.operand() as usize).map(|_| self.status()),
       Opcode::Ret => return self.ret(),
     }?;
     Ok(Status::Running)
@@ -157,16 +367,20 @@ impl Fiber {
   }
 
   fn str(&mut self, index: usize) -> Result<(), Error> {
-    let string = self
+    let id = self
       .chunk
       .constant(index as usize)
       .ok_or_else(|| self.build_error(Reason::InvalidData(index)))?
-      .as_str()
-      .ok_or_else(|| self.build_error(Reason::WrongConstantType))?
-      // TODO: Don't copy string on each use
-      .to_string();
+      .as_string_id()
+      .ok_or_else(|| self.build_error(Reason::WrongConstantType))?;
+    let string = self
+      .interner
+      .borrow()
+      .get(id)
+      .map(|symbol| Gc::clone(symbol.as_gc()))
+      .ok_or_else(|| self.build_error(Reason::InvalidData(index)))?;
 
-    self.stack.push(Value::String(Gc::new(string)));
+    self.stack.push(Value::String(string));
 
     Ok(())
   }
@@ -210,6 +424,10 @@ impl Fiber {
   }
 
   fn nal(&mut self, id: usize) -> Result<(), Error> {
+    if let Some(capability) = self.registry.missing(id) {
+      let reason = Reason::NotGranted(capability.to_string());
+      return Err(self.build_error(reason));
+    }
     let native_lambda = self
       .registry
       .get(id)
@@ -233,10 +451,7 @@ impl Fiber {
         Err(self.build_error(Reason::InvalidUpvalue(index)))
       }
     } else {
-      let lambda = self
-        .stack_get(self.bp)?
-        .as_lambda()
-        .ok_or_else(|| self.build_error(Reason::WrongConstantType))?;
+      let lambda = self.current_lambda()?;
       let upvalue = lambda
         .upvalue(index)
         .ok_or_else(|| self.build_error(Reason::InvalidUpvalue(index)))?;
@@ -245,34 +460,33 @@ impl Fiber {
   }
 
   fn pop(&mut self) -> Result<(), Error> {
-    self.stack_pop()?;
+    self.stack_pop_packed()?;
     Ok(())
   }
 
   fn lod(&mut self, local: usize) -> Result<(), Error> {
-    let value = self.stack_get(self.bp + local)?.clone();
-    self.stack.push(value);
-    Ok(())
+    self
+      .stack
+      .load(self.bp + local)
+      .ok_or_else(|| self.build_error(Reason::EmptyStack))
   }
 
   fn sav(&mut self, local: usize) -> Result<(), Error> {
-    let value = self.stack_last()?.clone();
-    self.stack_set(self.bp + local, value)?;
-    Ok(())
+    self
+      .stack
+      .store(self.bp + local)
+      .ok_or_else(|| self.build_error(Reason::EmptyStack))
   }
 
   fn lou(&mut self, index: usize) -> Result<(), Error> {
-    let lambda = self
-      .stack_get(self.bp)?
-      .as_lambda()
-      .ok_or_else(|| self.build_error(Reason::WrongConstantType))?;
+    let lambda = self.current_lambda()?;
 
     let upvalue = lambda
       .upvalue(index)
       .ok_or_else(|| self.build_error(Reason::InvalidUpvalue(index)))?;
 
     let value = match &*upvalue.borrow() {
-      Upvalue::Open(index) => self.stack_get(*index)?.clone(),
+      Upvalue::Open(index) => self.stack_get(*index)?,
       Upvalue::Closed(value) => value.clone(),
     };
 
@@ -281,17 +495,14 @@ impl Fiber {
   }
 
   fn sau(&mut self, index: usize) -> Result<(), Error> {
-    let lambda = self
-      .stack_get(self.bp)?
-      .as_lambda()
-      .ok_or_else(|| self.build_error(Reason::WrongConstantType))?;
+    let lambda = self.current_lambda()?;
 
     let upvalue = lambda
       .upvalue(index)
       .cloned()
       .ok_or_else(|| self.build_error(Reason::InvalidUpvalue(index)))?;
 
-    let value = self.stack_last()?.clone();
+    let value = self.stack_last()?;
 
     let mut upvalue_mut = upvalue.borrow_mut();
     match &mut *upvalue_mut {
@@ -315,76 +526,106 @@ impl Fiber {
   }
 
   fn jit(&mut self, offset: usize) -> Result<(), Error> {
-    if let Value::Bool(true) = self.stack_pop()? {
+    if let Some(true) = self.stack_pop_packed()?.as_bool() {
       self.ip = offset;
     }
     Ok(())
   }
 
   fn jif(&mut self, offset: usize) -> Result<(), Error> {
-    if let Value::Bool(false) = self.stack_pop()? {
+    if let Some(false) = self.stack_pop_packed()?.as_bool() {
       self.ip = offset;
     }
     Ok(())
   }
 
   fn dup(&mut self) -> Result<(), Error> {
-    let value = self.stack_last()?.clone();
-    self.stack.push(value);
-
-    Ok(())
+    let top = self.stack.len().wrapping_sub(1);
+    self
+      .stack
+      .load(top)
+      .ok_or_else(|| self.build_error(Reason::EmptyStack))
   }
 
   fn swp(&mut self) -> Result<(), Error> {
-    let second = self.stack_pop()?;
-    let first = self.stack_pop()?;
+    let second = self.stack_pop_packed()?;
+    let first = self.stack_pop_packed()?;
 
-    self.stack.push(second);
-    self.stack.push(first);
+    self.stack.push_packed(second);
+    self.stack.push_packed(first);
 
     Ok(())
   }
 
   fn rot(&mut self) -> Result<(), Error> {
-    let third = self.stack_pop()?;
-    let second = self.stack_pop()?;
-    let first = self.stack_pop()?;
+    let third = self.stack_pop_packed()?;
+    let second = self.stack_pop_packed()?;
+    let first = self.stack_pop_packed()?;
 
-    self.stack.push(third);
-    self.stack.push(first);
-    self.stack.push(second);
+    self.stack.push_packed(third);
+    self.stack.push_packed(first);
+    self.stack.push_packed(second);
 
     Ok(())
   }
 
   fn add(&mut self) -> Result<(), Error> {
-    binary!(self, add)
+    arithmetic!(self, add, add)
   }
 
   fn sub(&mut self) -> Result<(), Error> {
-    binary!(self, sub)
+    arithmetic!(self, sub, sub)
   }
 
   fn mul(&mut self) -> Result<(), Error> {
-    binary!(self, mul)
+    arithmetic!(self, mul, mul)
   }
 
   fn div(&mut self) -> Result<(), Error> {
-    binary!(self, div)
+    arithmetic!(self, div, div)
   }
 
   fn rem(&mut self) -> Result<(), Error> {
-    binary!(self, rem)
+    arithmetic!(self, rem, rem)
+  }
+
+  fn pow(&mut self) -> Result<(), Error> {
+    arithmetic!(self, pow)
+  }
+
+  fn ban(&mut self) -> Result<(), Error> {
+    bitwise!(self, bit_and)
+  }
+
+  fn bor(&mut self) -> Result<(), Error> {
+    bitwise!(self, bit_or)
+  }
+
+  fn bxr(&mut self) -> Result<(), Error> {
+    bitwise!(self, bit_xor)
+  }
+
+  fn shl(&mut self) -> Result<(), Error> {
+    arithmetic!(self, shl)
+  }
+
+  fn shr(&mut self) -> Result<(), Error> {
+    arithmetic!(self, shr)
   }
 
   fn neg(&mut self) -> Result<(), Error> {
-    unary!(self, neg)
+    let value = self.stack_pop()?;
+    let result = value
+      .neg(self.overflow)
+      .map_err(|reason| self.build_error(reason))?;
+    self.stack.push(result);
+    Ok(())
   }
 
   fn eql(&mut self) -> Result<(), Error> {
     let right = self.stack_pop()?;
     let left = self.stack_pop()?;
-    let result = left.eql(&right);
+    let result = self.equals(left, right)?;
     self.stack.push(result);
     Ok(())
   }
@@ -392,25 +633,40 @@ impl Fiber {
   fn neq(&mut self) -> Result<(), Error> {
     let right = self.stack_pop()?;
     let left = self.stack_pop()?;
-    let result = left.neq(&right);
+    let result = self
+      .equals(left, right)?
+      .not()
+      .ok_or_else(|| self.build_error(Reason::Type))?;
     self.stack.push(result);
     Ok(())
   }
 
+  // Equality is symmetric, so `eql` is called on whichever operand has it,
+  // trying the left one first.
+  fn equals(&mut self, left: Value, right: Value) -> Result<Value, Error> {
+    if let Some(method) = self.protocol(&left, "eql")? {
+      return self.call_method(&method, left, vec![right]);
+    }
+    match self.protocol(&right, "eql")? {
+      Some(method) => self.call_method(&method, right, vec![left]),
+      None => Ok(left.eql(&right)),
+    }
+  }
+
   fn gtn(&mut self) -> Result<(), Error> {
-    binary!(self, gtn)
+    binary!(self, gtn, gt)
   }
 
   fn gte(&mut self) -> Result<(), Error> {
-    binary!(self, gte)
+    binary!(self, gte, ge)
   }
 
   fn ltn(&mut self) -> Result<(), Error> {
-    binary!(self, ltn)
+    binary!(self, ltn, lt)
   }
 
   fn lte(&mut self) -> Result<(), Error> {
-    binary!(self, lte)
+    binary!(self, lte, le)
   }
 
   fn not(&mut self) -> Result<(), Error> {
@@ -459,44 +715,185 @@ impl Fiber {
     Ok(())
   }
 
-  fn tag(&mut self) -> Result<(), Error> {
+  // Pushes the length of the value if it is an array and null otherwise.
+  fn len(&mut self) -> Result<(), Error> {
+    let value = self.stack_pop()?;
+    let len = value
+      .as_array()
+      .map(|array| Value::Int(array.borrow().len() as i64))
+      .unwrap_or(Value::Null);
+    self.stack.push(len);
+    Ok(())
+  }
+
+  fn slc(&mut self) -> Result<(), Error> {
+    let end = self.stack_pop()?;
+    let start = self.stack_pop()?;
+    let array_value = self.stack_pop()?;
+    let array = array_value
+      .as_array()
+      .ok_or_else(|| self.build_error(Reason::Type))?;
+
+    let slice = array
+      .borrow()
+      .slice(&start, &end)
+      .ok_or_else(|| self.build_error(Reason::InvalidRange(start, end)))?;
+
+    self.stack.push(Value::Array(Gc::new(GcCell::new(slice))));
+
+    Ok(())
+  }
+
+  // Removes a range from an array, pushing the array back along with the
+  // index the range started at, so that `ins` can replace the range.
+  fn rmv(&mut self) -> Result<(), Error> {
+    let end = self.stack_pop()?;
+    let start = self.stack_pop()?;
+    let array_value = self.stack_pop()?;
+    let array = array_value
+      .as_array()
+      .ok_or_else(|| self.build_error(Reason::Type))?;
+
+    let index = array
+      .borrow_mut()
+      .remove(&start, &end)
+      .ok_or_else(|| self.build_error(Reason::InvalidRange(start, end)))?;
+
+    self.stack.push(array_value);
+    self.stack.push(Value::Int(index as i64));
+
+    Ok(())
+  }
+
+  // Inserts the items of an array into another array, pushing the items.
+  fn ins(&mut self) -> Result<(), Error> {
+    let items_value = self.stack_pop()?;
+    let index = self.stack_pop()?;
+    let array_value = self.stack_pop()?;
+    let array = array_value
+      .as_array()
+      .ok_or_else(|| self.build_error(Reason::Type))?;
+    // The items are copied first, as they can be the array itself.
+    let items = items_value
+      .as_array()
+      .map(|items| items.borrow().items().to_vec())
+      .ok_or_else(|| self.build_error(Reason::Type))?;
+
+    array
+      .borrow_mut()
+      .insert(&index, items)
+      .ok_or_else(|| self.build_error(Reason::InvalidKey(index)))?;
+
+    self.stack.push(items_value);
+
+    Ok(())
+  }
+
+  fn tag(&mut self, count: usize) -> Result<(), Error> {
     let tag_value = self.stack_pop()?;
     let tag = tag_value
       .as_string()
       .ok_or_else(|| self.build_error(Reason::InvalidTag(tag_value.clone())))?;
 
-    let value = self.stack_pop()?;
+    // Corrupt code can have any count, so the fields aren't allocated up
+    // front, and running out of values on the stack is the error.
+    let mut fields = Vec::new();
+    for _ in 0..count {
+      fields.push(self.stack_pop()?);
+    }
+    fields.reverse();
 
     self
       .stack
-      .push(Value::Tagged(Gc::new(Tagged::new(Gc::clone(tag), value))));
+      .push(Value::Tagged(Gc::new(Tagged::new(Gc::clone(tag), fields))));
+
+    Ok(())
+  }
+
+  fn tgn(&mut self, count: usize) -> Result<(), Error> {
+    let tag_value = self.stack_pop()?;
+    let tag = tag_value
+      .as_string()
+      .ok_or_else(|| self.build_error(Reason::InvalidTag(tag_value.clone())))?;
+
+    let mut names = Vec::new();
+    let mut fields = Vec::new();
+    for _ in 0..count {
+      fields.push(self.stack_pop()?);
+      let name_value = self.stack_pop()?;
+      let name = name_value
+        .as_string()
+        .ok_or_else(|| self.build_error(Reason::InvalidKey(name_value.clone())))?;
+      names.push(Gc::clone(name));
+    }
+    names.reverse();
+    fields.reverse();
+
+    self.stack.push(Value::Tagged(Gc::new(Tagged::with_names(
+      Gc::clone(tag),
+      names,
+      fields,
+    ))));
 
     Ok(())
   }
 
-  fn utg(&mut self) -> Result<(), Error> {
+  fn utg(&mut self, index: usize) -> Result<(), Error> {
     let value = self.stack_pop()?;
     let tagged = value
       .as_tagged()
       .ok_or_else(|| self.build_error(Reason::Type))?;
-    self.stack.push(tagged.value().clone());
+    let field = tagged
+      .field(index)
+      .ok_or_else(|| self.build_error(Reason::InvalidKey(Value::Int(index as i64))))?;
+    self.stack.push(field.clone());
     Ok(())
   }
 
-  fn gtg(&mut self) -> Result<(), Error> {
+  // Pushes the tag of the value if it is a tagged value with the given number
+  // of fields and null otherwise.
+  fn gtg(&mut self, count: usize) -> Result<(), Error> {
     let value = self.stack_pop()?;
     let tag = value
       .as_tagged()
+      .filter(|tagged| tagged.fields().len() == count)
       .map(|tagged| Value::String(Gc::clone(tagged.tag())))
       .unwrap_or(Value::Null);
     self.stack.push(tag);
     Ok(())
   }
 
+  // Like `gtg`, but for values with named fields, however many there are.
+  fn gnt(&mut self) -> Result<(), Error> {
+    let value = self.stack_pop()?;
+    let tag = value
+      .as_tagged()
+      .filter(|tagged| !tagged.names().is_empty())
+      .map(|tagged| Value::String(Gc::clone(tagged.tag())))
+      .unwrap_or(Value::Null);
+    self.stack.push(tag);
+    Ok(())
+  }
+
+  // Pushes whether a tagged value has a field with the given name, which
+  // tells a null field apart from a missing one.
+  fn has(&mut self) -> Result<(), Error> {
+    let name = self.stack_pop()?;
+    let value = self.stack_pop()?;
+    let has = value.as_tagged().is_some_and(|tagged| tagged.has(&name));
+    self.stack.push(Value::Bool(has));
+    Ok(())
+  }
+
+  fn nma(&mut self) -> Result<(), Error> {
+    let subject = self.stack_pop()?;
+    Err(self.build_error(Reason::NoMatchingArm(subject)))
+  }
+
   fn cal(&mut self, arity: usize) -> Result<(), Error> {
     let bp = self.stack.len() - arity - 1;
 
-    let value = self.stack_get(bp)?.clone();
+    let value = self.stack_get(bp)?;
     match &value {
       Value::Lambda(lambda) => {
         if arity != lambda.arity() {
@@ -522,10 +919,23 @@ impl Fiber {
         }
 
         let argument = self.stack_pop()?;
-        let result = native_lambda.call(argument);
+        self
+          .natives
+          .push((self.stack.frame_count(), Gc::clone(native_lambda)));
+        let call = native_lambda.call(&mut Context { fiber: self }, argument);
+        self.natives.pop();
         self.stack_pop()?; // pop the native lambda off as well.
 
-        self.stack.push(result);
+        match call {
+          NativeCall::Done(result) => {
+            let result = result.map_err(|error| self.native_error(native_lambda, error))?;
+            self.stack.push(result);
+          }
+          // The result is pushed once the future resolves.
+          NativeCall::Pending(future) => {
+            self.waiting = Some((Gc::clone(native_lambda), future));
+          }
+        }
       }
       _ => return Err(self.build_error(Reason::ValueNotCallable(value.clone()))),
     }
@@ -533,6 +943,182 @@ impl Fiber {
     Ok(())
   }
 
+  // Calls a method on the receiver, which is below the name and arguments on
+  // the stack. A map's own fields are called like lambdas stored anywhere
+  // else, without the receiver. Other methods are passed the receiver before
+  // the arguments.
+  fn ivk(&mut self, arity: usize) -> Result<(), Error> {
+    let mut arguments = Vec::new();
+    for _ in 0..arity {
+      arguments.push(self.stack_pop()?);
+    }
+    arguments.reverse();
+    let name = self.stack_pop()?;
+    let receiver = self.stack_pop()?;
+
+    let field = receiver.as_map().and_then(|map| map.borrow().field(&name));
+    if let Some(field) = field {
+      return self.push_call(field, arguments);
+    }
+
+    let method = self
+      .method(&receiver, &name)?
+      .ok_or_else(|| self.build_error(Reason::NoMethod(receiver.kind(), name.clone())))?;
+    arguments.insert(0, receiver);
+    match method {
+      // Natives take a single argument, so they get an array.
+      Value::NativeLambda(_) => {
+        let arguments = Value::Array(Gc::new(GcCell::new(Array::with_items(arguments))));
+        self.push_call(method, vec![arguments])
+      }
+      method => self.push_call(method, arguments),
+    }
+  }
+
+  fn push_call(&mut self, lambda: Value, arguments: Vec<Value>) -> Result<(), Error> {
+    let arity = arguments.len();
+    self.stack.push(lambda);
+    for argument in arguments {
+      self.stack.push(argument);
+    }
+    self.cal(arity)
+  }
+
+  // Looks a method up in the chain of meta maps of the receiver, and then in
+  // the methods registered for its kind, which fails if the method needs a
+  // capability the VM wasn't granted.
+  fn method(&self, receiver: &Value, name: &Value) -> Result<Option<Value>, Error> {
+    let mut meta = receiver
+      .as_map()
+      .and_then(|map| map.borrow().meta().cloned());
+    while let Some(map) = meta {
+      let map = map.borrow();
+      if let Some(method) = map.field(name) {
+        return Ok(Some(method));
+      }
+      meta = map.meta().cloned();
+    }
+
+    let name = match name.as_string() {
+      Some(name) => name,
+      None => return Ok(None),
+    };
+    if let Some(capability) = self.registry.missing_method(receiver.kind(), name) {
+      let reason = Reason::NotGranted(capability.to_string());
+      return Err(self.build_error(reason));
+    }
+    Ok(
+      self
+        .registry
+        .method(receiver.kind(), name)
+        .map(|method| Value::NativeLambda(Gc::clone(method))),
+    )
+  }
+
+  // The method an operator calls instead of its built-in behaviour, which is
+  // only looked up for values that aren't primitive.
+  fn protocol(&self, value: &Value, name: &str) -> Result<Option<Value>, Error> {
+    if value.kind().is_primitive() {
+      return Ok(None);
+    }
+    let symbol = self.interner.borrow_mut().intern(name);
+    self.method(value, &Value::String(Gc::clone(symbol.as_gc())))
+  }
+
+  // Calls a method like `ivk`, but runs it to completion.
+  fn call_method(
+    &mut self,
+    method: &Value,
+    receiver: Value,
+    mut arguments: Vec<Value>,
+  ) -> Result<Value, Error> {
+    arguments.insert(0, receiver);
+    match method {
+      Value::NativeLambda(_) => {
+        let arguments = Value::Array(Gc::new(GcCell::new(Array::with_items(arguments))));
+        self.call(method, &[arguments])
+      }
+      method => self.call(method, &arguments),
+    }
+  }
+
+  // Calls the lambda with the arguments on top of the stack, and runs until it
+  // returns. The fiber can be in the middle of running another lambda, such as
+  // when a native lambda calls back into Oma, and continues from where it was
+  // afterwards.
+  // Calls the lambda from the host, leaving the frames below it out of the
+  // traces of its errors, as nothing there called it.
+  pub fn call_from_host(&mut self, lambda: &Value, arguments: &[Value]) -> Result<Value, Error> {
+    let hidden_frames = self.hidden_frames;
+    // The frame the fiber is in is saved below the lambda's.
+    self.hidden_frames = self.stack.frame_count() + 1;
+    let result = self.call(lambda, arguments);
+    self.hidden_frames = hidden_frames;
+    result
+  }
+
+  pub fn call(&mut self, lambda: &Value, arguments: &[Value]) -> Result<Value, Error> {
+    let len = self.stack.len();
+    let depth = self.stack.frame_count();
+
+    self.stack.push(lambda.clone());
+    for argument in arguments {
+      self.stack.push(argument.clone());
+    }
+
+    let result = self.cal(arguments.len()).and_then(|_| {
+      while self.waiting.is_none() && self.stack.frame_count() > depth {
+        self.step()?;
+      }
+      // The native calling back into Oma can't be suspended, so neither can
+      // the lambda it called.
+      if self.waiting.is_some() {
+        return Err(self.suspend_error());
+      }
+      self.stack_pop()
+    });
+
+    if result.is_err() {
+      self.waiting = None;
+      self.unwind(len, depth);
+    }
+    result
+  }
+
+  // The value the module returned, once the fiber is done.
+  pub fn result(&self) -> Result<Value, Error> {
+    self.stack_last()
+  }
+
+  // Closes the upvalues still pointing into the stack, so that lambdas
+  // capturing the finished module's bindings can run on other fibers.
+  pub fn close_upvalues(&mut self) {
+    self.stack.close_upvalues();
+  }
+
+  fn status(&self) -> Status {
+    if self.waiting.is_some() {
+      Status::Yield
+    } else {
+      Status::Running
+    }
+  }
+
+  // Drops the frames and values an interrupted call left behind, restoring
+  // the fiber to how it was before the call.
+  fn unwind(&mut self, len: usize, depth: usize) {
+    while self.stack.frame_count() > depth {
+      if let Some(frame) = self.stack.pop_frame() {
+        self.chunk = frame.chunk;
+        self.ip = frame.ip;
+        self.bp = frame.bp;
+      }
+    }
+    while self.stack.len() > len {
+      self.stack.close_upvalue();
+    }
+  }
+
   fn ret(&mut self) -> Result<Status, Error> {
     if self.stack.is_frames_empty() {
       Ok(Status::Done)
@@ -540,7 +1126,7 @@ impl Fiber {
       // The return value will be at the top of the stack when a lambda completes its
       // executation. We save it temporarily and pop the rest of the stack up to the
       // base pointer.
-      let return_value = self.stack_pop()?;
+      let return_value = self.stack_pop_packed()?;
       while self.stack.len() > self.bp {
         // TODO: Close multiple upvalues at the same time.
         self
@@ -548,7 +1134,7 @@ impl Fiber {
           .close_upvalue()
           .ok_or_else(|| self.build_error(Reason::EmptyStack))?;
       }
-      self.stack.push(return_value);
+      self.stack.push_packed(return_value);
 
       // We then restore the values from the previous frame.
       let frame = self
@@ -563,6 +1149,14 @@ impl Fiber {
     }
   }
 
+  // The lambda being run, which is at the base of its frame.
+  fn current_lambda(&self) -> Result<Gc<Lambda>, Error> {
+    match &self.stack_get(self.bp)? {
+      Value::Lambda(lambda) => Ok(Gc::clone(lambda)),
+      _ => Err(self.build_error(Reason::WrongConstantType)),
+    }
+  }
+
   fn stack_pop(&mut self) -> Result<Value, Error> {
     self
       .stack
@@ -570,14 +1164,21 @@ impl Fiber {
       .ok_or_else(|| self.build_error(Reason::EmptyStack))
   }
 
-  fn stack_last(&self) -> Result<&Value, Error> {
+  fn stack_pop_packed(&mut self) -> Result<Packed, Error> {
+    self
+      .stack
+      .pop_packed()
+      .ok_or_else(|| self.build_error(Reason::EmptyStack))
+  }
+
+  fn stack_last(&self) -> Result<Value, Error> {
     self
       .stack
       .last()
       .ok_or_else(|| self.build_error(Reason::EmptyStack))
   }
 
-  fn stack_get(&self, local: usize) -> Result<&Value, Error> {
+  fn stack_get(&self, local: usize) -> Result<Value, Error> {
     self
       .stack
       .get(local)
@@ -591,11 +1192,60 @@ impl Fiber {
       .ok_or_else(|| self.build_error(Reason::EmptyStack))
   }
 
+  // The error for an async native called where the fiber can't wait on it,
+  // traced from the native.
+  fn suspend_error(&mut self) -> Error {
+    match self.waiting.take() {
+      Some((native, _)) => {
+        let error = Error::new(Reason::CannotSuspend, Trace::new());
+        self.native_error(&native, error)
+      }
+      None => self.build_error(Reason::CannotSuspend),
+    }
+  }
+
+  // Errors the native made itself are traced from the native.
+  fn native_error(&mut self, native: &Gc<NativeLambda>, mut error: Error) -> Error {
+    if error.trace().is_empty() {
+      self
+        .natives
+        .push((self.stack.frame_count(), Gc::clone(native)));
+      error.set_trace(self.build_trace());
+      self.natives.pop();
+    }
+    error
+  }
+
   fn build_error(&self, reason: Reason) -> Error {
-    let mut trace = self.stack.build_trace();
-    // TODO: `self.ip` doesn't reflect current op, but next op, so `- 1` is required to get current op.
-    trace.push(self.chunk.info().and_then(|info| info.span(self.ip - 1)));
-    Error::new(reason, trace)
+    Error::new(reason, self.build_trace())
+  }
+
+  // Traces the frames on the stack and the current one, with the natives
+  // being called in between.
+  fn build_trace(&self) -> Trace {
+    let frames = self.stack.frames();
+    let mut trace = Trace::new();
+    let mut natives = self
+      .natives
+      .iter()
+      .filter(|(below, _)| *below >= self.hidden_frames)
+      .peekable();
+    for (depth, frame) in frames.iter().enumerate().skip(self.hidden_frames) {
+      // TODO: `ip` doesn't reflect current op, but next op, so `- 1` is required to get current op.
+      trace.push(lambda_frame(&frame.chunk, frame.ip - 1));
+      while let Some((_, native)) = natives.next_if(|(below, _)| *below == depth) {
+        trace.push(TraceFrame::Native {
+          name: Rc::clone(native.name()),
+        });
+      }
+    }
+    trace.push(lambda_frame(&self.chunk, self.ip - 1));
+    for (_, native) in natives {
+      trace.push(TraceFrame::Native {
+        name: Rc::clone(native.name()),
+      });
+    }
+    trace
   }
 
   fn next_op(&mut self) -> Result<Op, Error> {
@@ -609,6 +1259,37 @@ impl Fiber {
   }
 }
 
+fn lambda_frame(chunk: &Chunk, offset: usize) -> TraceFrame {
+  match chunk.info() {
+    Some(info) => TraceFrame::Lambda {
+      name: Rc::clone(info.name()),
+      span: info.span(offset),
+    },
+    None => TraceFrame::Lambda {
+      name: Rc::new("<<unknown>>".to_string()),
+      span: None,
+    },
+  }
+}
+
+// Gives a native lambda access to the fiber that called it.
+pub struct Context<'a> {
+  fiber: &'a mut Fiber,
+}
+
+impl Context<'_> {
+  // Returns the string interned, like `Vm::intern`.
+  pub fn intern(&mut self, string: &str) -> Value {
+    let symbol = self.fiber.interner.borrow_mut().intern(string);
+    Value::String(Gc::clone(symbol.as_gc()))
+  }
+
+  // Calls an Oma or native lambda and returns its result.
+  pub fn call(&mut self, lambda: &Value, arguments: &[Value]) -> Result<Value, Error> {
+    self.fiber.call(lambda, arguments)
+  }
+}
+
 pub enum Status {
   Done,
   Running,
@@ -0,0 +1,591 @@
+// Values on the operand stack are packed into 8 bytes with NaN-boxing. Floats
+// are stored as themselves, with every NaN made the same positive quiet NaN.
+// That leaves the negative NaNs free, so a word with the sign and exponent
+// bits set and a non-zero tag in the next 4 bits holds another kind of value,
+// with a 48 bit payload:
+//
+//   sign  exponent      tag   payload
+//   1     11111111111   tttt  48 bits
+//
+// Null, bools and integers that fit in 48 bits are stored in the payload, so
+// moving them around doesn't touch the garbage collector. Other values store
+// the address of their `Gc`, including its root bit, which fits as long as
+// user space addresses are at most 48 bits. With a larger address space, values
+// whose address doesn't fit are moved to a table of spilled values instead,
+// and the payload holds their index in it. Integers too wide for the payload
+// are boxed in a `Gc<i64>`.
+//
+// A packed value owns its `Gc` the same way a `Value` does: it keeps the `Gc`
+// rooted, and it unroots it when dropped.
+
+use std::{
+  cell::RefCell,
+  fmt,
+  marker::PhantomData,
+  mem::{self, ManuallyDrop},
+  ptr,
+};
+
+use gc::{Gc, GcCell, Trace};
+
+use crate::{
+  intern::Str,
+  value::{Array, BigInt, Lambda, Map, NativeLambda, Tagged, Value},
+};
+
+const BOXED: u64 = 0xfff0_0000_0000_0000;
+const QUIET_NAN: u64 = 0x7ff8_0000_0000_0000;
+const TAG_SHIFT: u32 = 48;
+const TAG: u64 = 0xf << TAG_SHIFT;
+const PAYLOAD: u64 = (1 << TAG_SHIFT) - 1;
+
+const NULL: u64 = 0;
+const FALSE: u64 = 0;
+const TRUE: u64 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Tag {
+  Null = 1,
+  Bool,
+  Int,
+  WideInt,
+  BigInt,
+  String,
+  Array,
+  Map,
+  Tagged,
+  Lambda,
+  NativeLambda,
+  // A value in the table of spilled values.
+  Spilled,
+}
+
+impl Tag {
+  fn from_bits(bits: u64) -> Option<Tag> {
+    let tag = match bits {
+      1 => Tag::Null,
+      2 => Tag::Bool,
+      3 => Tag::Int,
+      4 => Tag::WideInt,
+      5 => Tag::BigInt,
+      6 => Tag::String,
+      7 => Tag::Array,
+      8 => Tag::Map,
+      9 => Tag::Tagged,
+      10 => Tag::Lambda,
+      11 => Tag::NativeLambda,
+      12 => Tag::Spilled,
+      _ => return None,
+    };
+    Some(tag)
+  }
+
+  // Whether the payload is the address of a `Gc`.
+  fn is_gc(self) -> bool {
+    !matches!(self, Tag::Null | Tag::Bool | Tag::Int | Tag::Spilled)
+  }
+}
+
+thread_local! {
+  // Values whose address doesn't fit in the payload. They're only ever
+  // packed on address spaces larger than 48 bits.
+  static SPILLED: RefCell<Spilled> = RefCell::new(Spilled::default());
+}
+
+#[derive(Default)]
+struct Spilled {
+  values: Vec<Value>,
+  // Indices of the values that were taken out, which are null until reused.
+  free: Vec<usize>,
+}
+
+impl Spilled {
+  fn insert(&mut self, value: Value) -> usize {
+    match self.free.pop() {
+      Some(index) => {
+        self.values[index] = value;
+        index
+      }
+      None => {
+        self.values.push(value);
+        self.values.len() - 1
+      }
+    }
+  }
+
+  fn take(&mut self, index: usize) -> Value {
+    self.free.push(index);
+    mem::replace(&mut self.values[index], Value::Null)
+  }
+}
+
+// The marker keeps packed values on the thread their `Gc` belongs to, like
+// `Value`, which the table of spilled values relies on too.
+pub struct Packed(u64, PhantomData<Value>);
+
+impl Packed {
+  pub fn null() -> Packed {
+    Packed::boxed(Tag::Null, NULL)
+  }
+
+  pub fn bool(bool: bool) -> Packed {
+    Packed::boxed(Tag::Bool, if bool { TRUE } else { FALSE })
+  }
+
+  pub fn int(int: i64) -> Packed {
+    let payload = int as u64 & PAYLOAD;
+    if sign_extend(payload) == int {
+      Packed::boxed(Tag::Int, payload)
+    } else {
+      Packed::gc(Tag::WideInt, Gc::new(int), |gc| Value::Int(*gc))
+    }
+  }
+
+  pub fn float(float: f64) -> Packed {
+    if float.is_nan() {
+      Packed::from_bits(QUIET_NAN)
+    } else {
+      Packed::from_bits(float.to_bits())
+    }
+  }
+
+  pub fn as_bool(&self) -> Option<bool> {
+    match self.tag() {
+      Some(Tag::Bool) => Some(self.payload() == TRUE),
+      _ => None,
+    }
+  }
+
+  // Only integers stored inline are returned, since this is for fast paths.
+  pub fn as_int(&self) -> Option<i64> {
+    match self.tag() {
+      Some(Tag::Int) => Some(sign_extend(self.payload())),
+      _ => None,
+    }
+  }
+
+  pub fn as_float(&self) -> Option<f64> {
+    match self.tag() {
+      None => Some(f64::from_bits(self.0)),
+      Some(_) => None,
+    }
+  }
+
+  pub fn to_value(&self) -> Value {
+    (*self.value()).clone()
+  }
+
+  // Unpacks the value, handing its root over to the `Value` instead of
+  // rooting it again.
+  pub fn into_value(self) -> Value {
+    match self.tag() {
+      Some(Tag::Spilled) => {
+        let value = SPILLED.with(|spilled| spilled.borrow_mut().take(self.payload() as usize));
+        mem::forget(self);
+        value
+      }
+      Some(tag) if tag.is_gc() && tag != Tag::WideInt => {
+        let value = ManuallyDrop::into_inner(self.value());
+        mem::forget(self);
+        value
+      }
+      _ => self.to_value(),
+    }
+  }
+
+  fn from_bits(bits: u64) -> Packed {
+    Packed(bits, PhantomData)
+  }
+
+  fn boxed(tag: Tag, payload: u64) -> Packed {
+    Packed::from_bits(BOXED | (tag as u64) << TAG_SHIFT | payload)
+  }
+
+  // Packs the `Gc`, or spills it as the value `unpack` makes of it if its
+  // address doesn't fit.
+  fn gc<T: Trace>(tag: Tag, gc: Gc<T>, unpack: fn(Gc<T>) -> Value) -> Packed {
+    debug_assert_eq!(mem::size_of::<Gc<T>>(), mem::size_of::<usize>());
+    // SAFETY: a `Gc` of a sized type is a single pointer.
+    let address = unsafe { mem::transmute_copy::<Gc<T>, usize>(&gc) } as u64;
+    if address & !PAYLOAD == 0 {
+      mem::forget(gc);
+      Packed::boxed(tag, address)
+    } else {
+      Packed::spill(unpack(gc))
+    }
+  }
+
+  fn spill(value: Value) -> Packed {
+    let index = SPILLED.with(|spilled| spilled.borrow_mut().insert(value));
+    Packed::boxed(Tag::Spilled, index as u64)
+  }
+
+  // Packs a new root of the same `Gc`.
+  //
+  // # Safety
+  //
+  // The payload must be the address of a `Gc<T>`.
+  unsafe fn clone_gc<T: Trace + 'static>(&self, tag: Tag) -> Packed {
+    // The new root has the same address, with the root bit set like ours.
+    mem::forget(Gc::clone(&*self.as_gc::<T>()));
+    Packed::boxed(tag, self.payload())
+  }
+
+  fn tag(&self) -> Option<Tag> {
+    if self.0 & BOXED == BOXED {
+      Tag::from_bits((self.0 & TAG) >> TAG_SHIFT)
+    } else {
+      None
+    }
+  }
+
+  fn payload(&self) -> u64 {
+    self.0 & PAYLOAD
+  }
+
+  // Copies the `Gc` out of the payload without rooting it, so it must not be
+  // dropped unless the packed value is forgotten.
+  //
+  // # Safety
+  //
+  // The payload must be the address of a `Gc<T>`.
+  unsafe fn as_gc<T: Trace>(&self) -> ManuallyDrop<Gc<T>> {
+    let address = self.payload() as usize;
+    ManuallyDrop::new(mem::transmute_copy::<usize, Gc<T>>(&address))
+  }
+
+  // Unpacks the value without rooting it, with the same caveat as `as_gc`.
+  fn value(&self) -> ManuallyDrop<Value> {
+    // SAFETY: each tag is only packed with the matching `Gc`, and spilled
+    // values stay in the table until the packed value is dropped.
+    unsafe {
+      let value = match self.tag() {
+        None => Value::Float(f64::from_bits(self.0)),
+        Some(Tag::Spilled) => {
+          SPILLED.with(|spilled| ptr::read(&spilled.borrow().values[self.payload() as usize]))
+        }
+        Some(Tag::Null) => Value::Null,
+        Some(Tag::Bool) => Value::Bool(self.payload() == TRUE),
+        Some(Tag::Int) => Value::Int(sign_extend(self.payload())),
+        Some(Tag::WideInt) => Value::Int(**self.as_gc::<i64>()),
+        Some(Tag::BigInt) => Value::BigInt(ManuallyDrop::into_inner(self.as_gc())),
+        Some(Tag::String) => Value::String(ManuallyDrop::into_inner(self.as_gc())),
+        Some(Tag::Array) => Value::Array(ManuallyDrop::into_inner(self.as_gc())),
+        Some(Tag::Map) => Value::Map(ManuallyDrop::into_inner(self.as_gc())),
+        Some(Tag::Tagged) => Value::Tagged(ManuallyDrop::into_inner(self.as_gc())),
+        Some(Tag::Lambda) => Value::Lambda(ManuallyDrop::into_inner(self.as_gc())),
+        Some(Tag::NativeLambda) => Value::NativeLambda(ManuallyDrop::into_inner(self.as_gc())),
+      };
+      ManuallyDrop::new(value)
+    }
+  }
+}
+
+// Fast paths for the VM's operators, which give `None` for anything but two
+// inline integers or two floats, leaving the rest to `Value`.
+macro_rules! arithmetic {
+  ($op:ident, $checked_op:ident, $trait:ident) => {
+    pub fn $op(left: &Packed, right: &Packed) -> Option<Packed> {
+      if let (Some(left), Some(right)) = (left.as_int(), right.as_int()) {
+        left.$checked_op(right).map(Packed::int)
+      } else if let (Some(left), Some(right)) = (left.as_float(), right.as_float()) {
+        Some(Packed::float(std::ops::$trait::$op(left, right)))
+      } else {
+        None
+      }
+    }
+  };
+}
+
+macro_rules! comparison {
+  ($op:ident) => {
+    pub fn $op(left: &Packed, right: &Packed) -> Option<Packed> {
+      if let (Some(left), Some(right)) = (left.as_int(), right.as_int()) {
+        Some(Packed::bool(left.$op(&right)))
+      } else if let (Some(left), Some(right)) = (left.as_float(), right.as_float()) {
+        Some(Packed::bool(left.$op(&right)))
+      } else {
+        None
+      }
+    }
+  };
+}
+
+impl Packed {
+  arithmetic!(add, checked_add, Add);
+  arithmetic!(sub, checked_sub, Sub);
+  arithmetic!(mul, checked_mul, Mul);
+  arithmetic!(div, checked_div, Div);
+  arithmetic!(rem, checked_rem, Rem);
+
+  comparison!(gt);
+  comparison!(ge);
+  comparison!(lt);
+  comparison!(le);
+}
+
+impl From<Value> for Packed {
+  fn from(value: Value) -> Packed {
+    // `Value` can't be moved out of since it implements `Drop`, so its `Gc`
+    // is read out instead, and the value forgotten.
+    let value = ManuallyDrop::new(value);
+    // SAFETY: the value is never used or dropped after its `Gc` is read.
+    unsafe {
+      match &*value {
+        Value::Null => Packed::null(),
+        Value::Bool(bool) => Packed::bool(*bool),
+        Value::Int(int) => Packed::int(*int),
+        Value::Float(float) => Packed::float(*float),
+        Value::BigInt(gc) => Packed::gc(Tag::BigInt, ptr::read(gc), Value::BigInt),
+        Value::String(gc) => Packed::gc(Tag::String, ptr::read(gc), Value::String),
+        Value::Array(gc) => Packed::gc(Tag::Array, ptr::read(gc), Value::Array),
+        Value::Map(gc) => Packed::gc(Tag::Map, ptr::read(gc), Value::Map),
+        Value::Tagged(gc) => Packed::gc(Tag::Tagged, ptr::read(gc), Value::Tagged),
+        Value::Lambda(gc) => Packed::gc(Tag::Lambda, ptr::read(gc), Value::Lambda),
+        Value::NativeLambda(gc) => {
+          Packed::gc(Tag::NativeLambda, ptr::read(gc), Value::NativeLambda)
+        }
+      }
+    }
+  }
+}
+
+impl Clone for Packed {
+  fn clone(&self) -> Packed {
+    // SAFETY: each tag is only packed with the matching `Gc`.
+    unsafe {
+      match self.tag() {
+        Some(Tag::WideInt) => self.clone_gc::<i64>(Tag::WideInt),
+        Some(Tag::BigInt) => self.clone_gc::<BigInt>(Tag::BigInt),
+        Some(Tag::String) => self.clone_gc::<Str>(Tag::String),
+        Some(Tag::Array) => self.clone_gc::<GcCell<Array>>(Tag::Array),
+        Some(Tag::Map) => self.clone_gc::<GcCell<Map>>(Tag::Map),
+        Some(Tag::Tagged) => self.clone_gc::<Tagged>(Tag::Tagged),
+        Some(Tag::Lambda) => self.clone_gc::<Lambda>(Tag::Lambda),
+        Some(Tag::NativeLambda) => self.clone_gc::<NativeLambda>(Tag::NativeLambda),
+        Some(Tag::Spilled) => Packed::spill(self.to_value()),
+        _ => Packed::from_bits(self.0),
+      }
+    }
+  }
+}
+
+impl Drop for Packed {
+  fn drop(&mut self) {
+    match self.tag() {
+      // SAFETY: the packed value owns the `Gc`, and this is its last use.
+      Some(Tag::WideInt) => unsafe { ManuallyDrop::drop(&mut self.as_gc::<i64>()) },
+      // The table is gone if the thread is exiting, along with its values.
+      Some(Tag::Spilled) => {
+        let index = self.payload() as usize;
+        let value = SPILLED.try_with(|spilled| spilled.borrow_mut().take(index));
+        drop(value);
+      }
+      Some(tag) if tag.is_gc() => unsafe { ManuallyDrop::drop(&mut self.value()) },
+      _ => {}
+    }
+  }
+}
+
+impl fmt::Debug for Packed {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt::Debug::fmt(&*self.value(), f)
+  }
+}
+
+fn sign_extend(payload: u64) -> i64 {
+  ((payload << (64 - TAG_SHIFT)) as i64) >> (64 - TAG_SHIFT)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::rc::Rc;
+
+  use super::*;
+  use crate::{
+    chunk::Chunk,
+    value::{Overflow, Upvalue},
+  };
+
+  // A native lambda that holds `probe` until it's collected.
+  fn native(probe: &Rc<()>) -> Value {
+    let probe = Rc::clone(probe);
+    Value::NativeLambda(Gc::new(NativeLambda::new("probe", move |value| {
+      let _ = &probe;
+      value
+    })))
+  }
+
+  // A value of each kind with a `Gc`, which all reach a native lambda holding
+  // `probe`, besides strings, big integers and wide integers.
+  fn gc_values(probe: &Rc<()>) -> Vec<(Tag, Value)> {
+    let string = || Gc::new(Str::new("tag".to_string()));
+    let map = Map::new();
+    let map = Value::Map(Gc::new(GcCell::new(map)));
+    map.set(Value::String(string()), native(probe)).unwrap();
+    let chunk = Rc::new(Chunk {
+      info: None,
+      data: Box::new([]),
+      code: Box::new([]),
+    });
+    let upvalue = Gc::new(GcCell::new(Upvalue::Closed(native(probe))));
+    vec![
+      (Tag::WideInt, Value::Int(1 << 47)),
+      (Tag::WideInt, Value::Int(i64::MIN)),
+      (
+        Tag::BigInt,
+        Value::Int(i64::MAX)
+          .add(&Value::Int(1), Overflow::Promote)
+          .unwrap(),
+      ),
+      (Tag::String, Value::String(string())),
+      (
+        Tag::Array,
+        Value::Array(Gc::new(GcCell::new(Array::with_items(vec![native(probe)])))),
+      ),
+      (Tag::Map, map),
+      (
+        Tag::Tagged,
+        Value::Tagged(Gc::new(Tagged::new(string(), vec![native(probe)]))),
+      ),
+      (
+        Tag::Lambda,
+        Value::Lambda(Gc::new(Lambda::new(0, chunk, 0, vec![upvalue]))),
+      ),
+      (Tag::NativeLambda, native(probe)),
+    ]
+  }
+
+  fn assert_same(left: &Value, right: &Value) {
+    assert_eq!(format!("{:?}", left), format!("{:?}", right));
+  }
+
+  // Whether the value reaches the native lambda holding the probe.
+  fn holds_probe(tag: Tag) -> bool {
+    !matches!(tag, Tag::WideInt | Tag::BigInt | Tag::String)
+  }
+
+  #[test]
+  fn inline_values_round_trip() {
+    let values = [
+      (Tag::Null, Value::Null),
+      (Tag::Bool, Value::Bool(false)),
+      (Tag::Bool, Value::Bool(true)),
+      (Tag::Int, Value::Int(0)),
+      (Tag::Int, Value::Int((1 << 47) - 1)),
+      (Tag::Int, Value::Int(-(1 << 47))),
+    ];
+    for (tag, value) in values {
+      let packed = Packed::from(value.clone());
+      assert_eq!(packed.tag(), Some(tag));
+      assert_same(&packed.to_value(), &value);
+      assert_same(&packed.clone().into_value(), &value);
+    }
+    assert_eq!(Packed::int(-(1 << 47)).as_int(), Some(-(1 << 47)));
+    assert_eq!(Packed::int(1 << 47).as_int(), None);
+    assert_eq!(Packed::int(-(1 << 47) - 1).tag(), Some(Tag::WideInt));
+  }
+
+  #[test]
+  fn floats_round_trip() {
+    for float in [
+      0.0,
+      -0.0,
+      1.5,
+      f64::INFINITY,
+      f64::NEG_INFINITY,
+      f64::MIN,
+      f64::MAX,
+    ] {
+      let packed = Packed::float(float);
+      assert_eq!(packed.tag(), None);
+      assert_eq!(packed.as_float().unwrap().to_bits(), float.to_bits());
+    }
+  }
+
+  #[test]
+  fn nans_are_canonical() {
+    let nans = [
+      f64::NAN,
+      -f64::NAN,
+      f64::from_bits(0xfff8_0000_0000_0001),
+      f64::from_bits(0xfffc_0000_dead_beef),
+    ];
+    for nan in nans {
+      let packed = Packed::from(Value::Float(nan));
+      assert_eq!(packed.0, QUIET_NAN);
+      assert_eq!(packed.tag(), None);
+      assert!(packed.as_float().unwrap().is_nan());
+    }
+  }
+
+  #[test]
+  fn gc_values_round_trip() {
+    let probe = Rc::new(());
+    for (tag, value) in gc_values(&probe) {
+      let packed = Packed::from(value.clone());
+      assert_eq!(packed.tag(), Some(tag));
+      assert_same(&packed.to_value(), &value);
+      assert_same(&packed.clone().to_value(), &value);
+      assert_same(&packed.into_value(), &value);
+    }
+  }
+
+  #[test]
+  fn clones_share_the_gc() {
+    let array = Value::Array(Gc::new(GcCell::new(Array::new())));
+    let packed = Packed::from(array);
+    let clone = packed.clone();
+    packed
+      .to_value()
+      .as_array()
+      .unwrap()
+      .borrow_mut()
+      .push(&Value::Int(1));
+    drop(packed);
+    assert_eq!(clone.to_value().to_string(), "[1]");
+  }
+
+  #[test]
+  fn packed_values_keep_their_gc_rooted_until_dropped() {
+    let probe = Rc::new(());
+    for (tag, value) in gc_values(&probe) {
+      let packed = Packed::from(value);
+      let clone = packed.clone();
+      let unpacked = packed.clone().into_value();
+      drop(packed);
+      gc::force_collect();
+      if holds_probe(tag) {
+        assert!(Rc::strong_count(&probe) > 1, "{:?} was collected", tag);
+      }
+      drop(clone);
+      drop(unpacked);
+    }
+    gc::force_collect();
+    assert_eq!(Rc::strong_count(&probe), 1);
+  }
+
+  #[test]
+  fn spilled_values_round_trip_and_drop() {
+    let probe = Rc::new(());
+    for (tag, value) in gc_values(&probe) {
+      let debug = format!("{:?}", value);
+      let packed = Packed::spill(value);
+      assert_eq!(packed.tag(), Some(Tag::Spilled));
+      let clone = packed.clone();
+      assert_ne!(clone.payload(), packed.payload());
+      assert_eq!(format!("{:?}", clone.into_value()), debug);
+      gc::force_collect();
+      if holds_probe(tag) {
+        assert!(Rc::strong_count(&probe) > 1, "{:?} was collected", tag);
+      }
+      assert_eq!(format!("{:?}", packed.to_value()), debug);
+    }
+    gc::force_collect();
+    assert_eq!(Rc::strong_count(&probe), 1);
+    SPILLED.with(|spilled| {
+      let spilled = spilled.borrow();
+      assert_eq!(spilled.free.len(), spilled.values.len());
+    });
+  }
+}
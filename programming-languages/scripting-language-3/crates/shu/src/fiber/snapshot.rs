@@ -0,0 +1,713 @@
+// A snapshot is a paused fiber written to bytes, so that it can be restored
+// in another process and continue from the same op. It has four parts:
+//
+// - A header, and the fingerprint of the executable the fiber was running.
+//   Chunks are referred to by their index in the executable, so a snapshot
+//   can only be restored together with the same executable.
+// - The objects on the heap, each with an id that is its index in the list.
+//   Values refer to objects by id, which keeps sharing and cycles intact.
+//   Arrays, maps and upvalues are listed empty, and every other object only
+//   refers to objects listed before it.
+// - The contents of the arrays, maps and upvalues, in the order they were
+//   listed, which can refer to any object.
+// - The fiber itself: its frames, the values on its stack and its open
+//   upvalues.
+//
+// Native lambdas are referred to by their id in the registry, or by kind and
+// name for methods, so they must be registered the same way to restore it.
+
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use gc::{Gc, GcCell};
+
+use crate::{
+  chunk::{Chunk, Function},
+  error::{Error, Reason, Trace},
+  intern::{Interner, Str},
+  parse::{Expected, ParseError},
+  registry::{NativeId, NativeLambdaRegistry},
+  value::{Array, Kind, Lambda, Map, NativeLambda, Overflow, Tagged, Upvalue, Value},
+};
+
+use super::{frame::Frame, stack::Stack, Fiber};
+
+const HEADER: [u8; 4] = [b'O', b'M', b'S', 1];
+
+const VALUE_NULL: u8 = 0;
+const VALUE_FALSE: u8 = 1;
+const VALUE_TRUE: u8 = 2;
+const VALUE_INT: u8 = 3;
+const VALUE_FLOAT: u8 = 4;
+const VALUE_OBJECT: u8 = 5;
+
+const OBJECT_STRING: u8 = 1;
+// An interned string, which is interned again when restored.
+const OBJECT_SYMBOL: u8 = 2;
+const OBJECT_BIG_INT: u8 = 3;
+const OBJECT_ARRAY: u8 = 4;
+const OBJECT_MAP: u8 = 5;
+const OBJECT_UPVALUE: u8 = 6;
+const OBJECT_TAGGED: u8 = 7;
+const OBJECT_LAMBDA: u8 = 8;
+const OBJECT_NATIVE_LAMBDA: u8 = 9;
+const OBJECT_METHOD: u8 = 10;
+
+const UPVALUE_OPEN: u8 = 0;
+const UPVALUE_CLOSED: u8 = 1;
+
+// The executable a fiber runs, with its chunks numbered in the order the
+// parser reads them.
+pub struct Module {
+  fingerprint: u64,
+  chunks: Vec<Rc<Chunk>>,
+}
+
+impl Module {
+  pub fn new(executable: &[u8], function: &Function) -> Module {
+    let mut chunks = Vec::new();
+    collect_chunks(&function.chunk, &mut chunks);
+    Module {
+      fingerprint: fingerprint(executable),
+      chunks,
+    }
+  }
+
+  pub(crate) fn chunks(&self) -> &[Rc<Chunk>] {
+    &self.chunks
+  }
+
+  fn chunk(&self, index: u64) -> Option<&Rc<Chunk>> {
+    self.chunks.get(index as usize)
+  }
+}
+
+fn collect_chunks(chunk: &Rc<Chunk>, chunks: &mut Vec<Rc<Chunk>>) {
+  chunks.push(Rc::clone(chunk));
+  for constant in chunk.data.iter() {
+    if let Some(function) = constant.as_function() {
+      collect_chunks(&function.chunk, chunks);
+    }
+  }
+}
+
+// FNV-1a, which is stable across processes and Rust versions, unlike the
+// standard library's hasher.
+fn fingerprint(bytes: &[u8]) -> u64 {
+  bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+    (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+  })
+}
+
+enum Cell {
+  Array(Gc<GcCell<Array>>),
+  Map(Gc<GcCell<Map>>),
+  Upvalue(Gc<GcCell<Upvalue>>),
+}
+
+pub struct Writer<'a> {
+  fiber: &'a Fiber,
+  module: &'a Module,
+  chunks: HashMap<*const Chunk, u64>,
+  objects: Vec<u8>,
+  // Ids of the objects listed so far, by address.
+  ids: HashMap<usize, u64>,
+  // The arrays, maps and upvalues listed so far, whose contents are written
+  // after the objects.
+  cells: Vec<Cell>,
+}
+
+impl<'a> Writer<'a> {
+  pub fn new(fiber: &'a Fiber, module: &'a Module) -> Writer<'a> {
+    let chunks = module
+      .chunks
+      .iter()
+      .enumerate()
+      .map(|(index, chunk)| (Rc::as_ptr(chunk), index as u64))
+      .collect();
+    Writer {
+      fiber,
+      module,
+      chunks,
+      objects: Vec::new(),
+      ids: HashMap::new(),
+      cells: Vec::new(),
+    }
+  }
+
+  pub fn write(mut self) -> Result<Vec<u8>, Error> {
+    // Only a fiber between ops of its own can be snapshot, as natives being
+    // called and futures being waited on can't be.
+    if !self.fiber.natives.is_empty() || self.fiber.waiting.is_some() {
+      return Err(Error::new(Reason::CannotSnapshot, Trace::new()));
+    }
+
+    let stack = &self.fiber.stack;
+    let mut fiber = Vec::new();
+    self.frame(&mut fiber, &self.fiber.chunk, self.fiber.ip, self.fiber.bp)?;
+    write_u64(&mut fiber, stack.frame_count() as u64);
+    for frame in stack.frames() {
+      self.frame(&mut fiber, &frame.chunk, frame.ip, frame.bp)?;
+    }
+    write_u64(&mut fiber, stack.len() as u64);
+    for value in stack.values() {
+      self.value(&mut fiber, &value)?;
+    }
+    write_u64(&mut fiber, stack.upvalues().len() as u64);
+    for upvalue in stack.upvalues() {
+      let id = self.upvalue(upvalue);
+      write_u64(&mut fiber, id);
+    }
+
+    // Writing the contents of a cell can list more cells.
+    let mut contents = Vec::new();
+    let mut index = 0;
+    while index < self.cells.len() {
+      let cell = match &self.cells[index] {
+        Cell::Array(array) => Cell::Array(Gc::clone(array)),
+        Cell::Map(map) => Cell::Map(Gc::clone(map)),
+        Cell::Upvalue(upvalue) => Cell::Upvalue(Gc::clone(upvalue)),
+      };
+      self.contents(&mut contents, &cell)?;
+      index += 1;
+    }
+
+    let mut bytes = HEADER.to_vec();
+    write_u64(&mut bytes, self.module.fingerprint);
+    write_u64(&mut bytes, self.ids.len() as u64);
+    bytes.extend(self.objects);
+    bytes.extend(contents);
+    bytes.extend(fiber);
+    Ok(bytes)
+  }
+
+  fn frame(
+    &self,
+    bytes: &mut Vec<u8>,
+    chunk: &Rc<Chunk>,
+    ip: usize,
+    bp: usize,
+  ) -> Result<(), Error> {
+    write_u64(bytes, self.chunk(chunk)?);
+    write_u64(bytes, ip as u64);
+    write_u64(bytes, bp as u64);
+    Ok(())
+  }
+
+  fn chunk(&self, chunk: &Rc<Chunk>) -> Result<u64, Error> {
+    self
+      .chunks
+      .get(&Rc::as_ptr(chunk))
+      .copied()
+      .ok_or_else(|| Error::new(Reason::CannotSnapshot, Trace::new()))
+  }
+
+  fn contents(&mut self, bytes: &mut Vec<u8>, cell: &Cell) -> Result<(), Error> {
+    match cell {
+      Cell::Array(array) => {
+        let array = array.borrow();
+        write_u64(bytes, array.len() as u64);
+        for item in array.items() {
+          self.value(bytes, item)?;
+        }
+      }
+      Cell::Map(map) => {
+        let map = map.borrow();
+        let entries = map.entries().collect::<Vec<_>>();
+        write_u64(bytes, entries.len() as u64);
+        for (key, value) in entries {
+          self.value(bytes, &key)?;
+          self.value(bytes, value)?;
+        }
+        match map.meta() {
+          Some(meta) => self.value(bytes, &Value::Map(Gc::clone(meta)))?,
+          None => bytes.push(VALUE_NULL),
+        }
+      }
+      Cell::Upvalue(upvalue) => match &*upvalue.borrow() {
+        Upvalue::Open(index) => {
+          bytes.push(UPVALUE_OPEN);
+          write_u64(bytes, *index as u64);
+        }
+        Upvalue::Closed(value) => {
+          bytes.push(UPVALUE_CLOSED);
+          self.value(bytes, value)?;
+        }
+      },
+    }
+    Ok(())
+  }
+
+  fn value(&mut self, bytes: &mut Vec<u8>, value: &Value) -> Result<(), Error> {
+    match value {
+      Value::Null => bytes.push(VALUE_NULL),
+      Value::Bool(false) => bytes.push(VALUE_FALSE),
+      Value::Bool(true) => bytes.push(VALUE_TRUE),
+      Value::Int(int) => {
+        bytes.push(VALUE_INT);
+        bytes.extend(&int.to_le_bytes());
+      }
+      Value::Float(float) => {
+        bytes.push(VALUE_FLOAT);
+        bytes.extend(&float.to_le_bytes());
+      }
+      value => {
+        let id = self.object(value)?;
+        bytes.push(VALUE_OBJECT);
+        write_u64(bytes, id);
+      }
+    }
+    Ok(())
+  }
+
+  // Lists the object the value refers to if it isn't yet, after the objects
+  // it refers to, and returns its id.
+  fn object(&mut self, value: &Value) -> Result<u64, Error> {
+    let address = match value {
+      Value::BigInt(int) => address(int),
+      Value::String(string) => return Ok(self.string(string)),
+      Value::Array(array) => address(array),
+      Value::Map(map) => address(map),
+      Value::Tagged(tagged) => address(tagged),
+      Value::Lambda(lambda) => address(lambda),
+      Value::NativeLambda(native) => address(native),
+      Value::Null | Value::Bool(_) | Value::Int(_) | Value::Float(_) => unreachable!(),
+    };
+    if let Some(id) = self.ids.get(&address) {
+      return Ok(*id);
+    }
+
+    let mut object = Vec::new();
+    match value {
+      Value::BigInt(int) => {
+        object.push(OBJECT_BIG_INT);
+        write_bytes(&mut object, &int.to_signed_bytes_le());
+      }
+      Value::Array(array) => {
+        object.push(OBJECT_ARRAY);
+        self.cells.push(Cell::Array(Gc::clone(array)));
+      }
+      Value::Map(map) => {
+        object.push(OBJECT_MAP);
+        self.cells.push(Cell::Map(Gc::clone(map)));
+      }
+      Value::Tagged(tagged) => self.tagged(&mut object, tagged)?,
+      Value::Lambda(lambda) => self.lambda(&mut object, lambda)?,
+      Value::NativeLambda(native) => self.native_lambda(&mut object, native)?,
+      _ => unreachable!(),
+    }
+    Ok(self.push(address, object))
+  }
+
+  fn tagged(&mut self, object: &mut Vec<u8>, tagged: &Tagged) -> Result<(), Error> {
+    let tag = self.string(tagged.tag());
+    let names = tagged
+      .names()
+      .iter()
+      .map(|name| self.string(name))
+      .collect::<Vec<_>>();
+    let mut fields = Vec::new();
+    for field in tagged.fields() {
+      self.value(&mut fields, field)?;
+    }
+
+    object.push(OBJECT_TAGGED);
+    write_u64(object, tag);
+    write_u64(object, names.len() as u64);
+    for name in names {
+      write_u64(object, name);
+    }
+    write_u64(object, tagged.fields().len() as u64);
+    object.extend(fields);
+    Ok(())
+  }
+
+  fn lambda(&mut self, object: &mut Vec<u8>, lambda: &Lambda) -> Result<(), Error> {
+    let chunk = self.chunk(lambda.chunk())?;
+    let upvalues = lambda
+      .upvalues()
+      .iter()
+      .map(|upvalue| self.upvalue(upvalue))
+      .collect::<Vec<_>>();
+
+    object.push(OBJECT_LAMBDA);
+    write_u64(object, chunk);
+    write_u64(object, lambda.arity() as u64);
+    write_u64(object, lambda.locals() as u64);
+    write_u64(object, upvalues.len() as u64);
+    for upvalue in upvalues {
+      write_u64(object, upvalue);
+    }
+    Ok(())
+  }
+
+  fn native_lambda(
+    &mut self,
+    object: &mut Vec<u8>,
+    native: &Gc<NativeLambda>,
+  ) -> Result<(), Error> {
+    match self.fiber.registry.find(native) {
+      Some(NativeId::Lambda(id)) => {
+        object.push(OBJECT_NATIVE_LAMBDA);
+        write_u64(object, id as u64);
+      }
+      Some(NativeId::Method(kind, name)) => {
+        object.push(OBJECT_METHOD);
+        object.push(kind_code(kind));
+        write_bytes(object, name.as_bytes());
+      }
+      None => return Err(Error::new(Reason::CannotSnapshot, Trace::new())),
+    }
+    Ok(())
+  }
+
+  fn string(&mut self, string: &Gc<Str>) -> u64 {
+    let address = address(string);
+    if let Some(id) = self.ids.get(&address) {
+      return *id;
+    }
+    let interned = self.fiber.interner.borrow().is_interned(string);
+    let mut object = vec![if interned {
+      OBJECT_SYMBOL
+    } else {
+      OBJECT_STRING
+    }];
+    write_bytes(&mut object, string.as_bytes());
+    self.push(address, object)
+  }
+
+  fn upvalue(&mut self, upvalue: &Gc<GcCell<Upvalue>>) -> u64 {
+    let address = address(upvalue);
+    if let Some(id) = self.ids.get(&address) {
+      return *id;
+    }
+    self.cells.push(Cell::Upvalue(Gc::clone(upvalue)));
+    self.push(address, vec![OBJECT_UPVALUE])
+  }
+
+  fn push(&mut self, address: usize, object: Vec<u8>) -> u64 {
+    let id = self.ids.len() as u64;
+    self.ids.insert(address, id);
+    self.objects.extend(object);
+    id
+  }
+}
+
+enum Object {
+  Value(Value),
+  Upvalue(Gc<GcCell<Upvalue>>),
+}
+
+pub struct Reader<'a> {
+  bytes: &'a [u8],
+  current: usize,
+  module: &'a Module,
+  registry: &'a NativeLambdaRegistry,
+  interner: &'a RefCell<Interner>,
+  objects: Vec<Object>,
+}
+
+impl<'a> Reader<'a> {
+  pub fn new(
+    bytes: &'a [u8],
+    module: &'a Module,
+    registry: &'a NativeLambdaRegistry,
+    interner: &'a RefCell<Interner>,
+  ) -> Reader<'a> {
+    Reader {
+      bytes,
+      current: 0,
+      module,
+      registry,
+      interner,
+      objects: Vec::new(),
+    }
+  }
+
+  pub fn read(
+    mut self,
+    registry: Rc<NativeLambdaRegistry>,
+    interner: Rc<RefCell<Interner>>,
+    overflow: Overflow,
+  ) -> Result<Fiber, ParseError> {
+    if self.advance::<4>()? != HEADER {
+      return Err(ParseError::new(0, Expected::SnapshotHeader));
+    }
+    let offset = self.current;
+    if self.u64()? != self.module.fingerprint {
+      return Err(ParseError::new(offset, Expected::Executable));
+    }
+
+    let len = self.u64()?;
+    for _ in 0..len {
+      let object = self.object()?;
+      self.objects.push(object);
+    }
+    for index in 0..self.objects.len() {
+      self.contents(index)?;
+    }
+
+    let (chunk, ip, bp) = self.frame()?;
+    let frames = (0..self.u64()?)
+      .map(|_| {
+        let (chunk, ip, bp) = self.frame()?;
+        Ok(Frame { chunk, ip, bp })
+      })
+      .collect::<Result<Vec<_>, ParseError>>()?;
+    let values = (0..self.u64()?)
+      .map(|_| self.value())
+      .collect::<Result<Vec<_>, ParseError>>()?;
+    let upvalues = (0..self.u64()?)
+      .map(|_| self.upvalue())
+      .collect::<Result<Vec<_>, ParseError>>()?;
+
+    Ok(Fiber {
+      registry,
+      interner,
+      overflow,
+      chunk,
+      ip,
+      bp,
+      stack: Stack::from_parts(frames, values, upvalues),
+      natives: Vec::new(),
+      waiting: None,
+      coverage: None,
+      hidden_frames: 0,
+    })
+  }
+
+  fn frame(&mut self) -> Result<(Rc<Chunk>, usize, usize), ParseError> {
+    let chunk = self.chunk()?;
+    let ip = self.u64()? as usize;
+    let bp = self.u64()? as usize;
+    Ok((chunk, ip, bp))
+  }
+
+  fn chunk(&mut self) -> Result<Rc<Chunk>, ParseError> {
+    let index = self.u64()?;
+    self
+      .module
+      .chunk(index)
+      .cloned()
+      .ok_or_else(|| self.error(Expected::Chunk))
+  }
+
+  fn object(&mut self) -> Result<Object, ParseError> {
+    let value = match self.advance::<1>()? {
+      [OBJECT_STRING] => Value::String(Gc::new(Str::new(self.string()?))),
+      [OBJECT_SYMBOL] => {
+        let string = self.string()?;
+        let symbol = self.interner.borrow_mut().intern(&string);
+        Value::String(Gc::clone(symbol.as_gc()))
+      }
+      [OBJECT_BIG_INT] => {
+        let bytes = self.bytes()?;
+        Value::big(num_bigint::BigInt::from_signed_bytes_le(bytes))
+      }
+      [OBJECT_ARRAY] => Value::array(),
+      [OBJECT_MAP] => Value::map(),
+      // Filled in with the other contents.
+      [OBJECT_UPVALUE] => {
+        let upvalue = Upvalue::Closed(Value::Null);
+        return Ok(Object::Upvalue(Gc::new(GcCell::new(upvalue))));
+      }
+      [OBJECT_TAGGED] => {
+        let tag = self.object_string()?;
+        let names = (0..self.u64()?)
+          .map(|_| self.object_string())
+          .collect::<Result<Vec<_>, ParseError>>()?;
+        let fields = (0..self.u64()?)
+          .map(|_| self.value())
+          .collect::<Result<Vec<_>, ParseError>>()?;
+        Value::Tagged(Gc::new(Tagged::with_names(tag, names, fields)))
+      }
+      [OBJECT_LAMBDA] => {
+        let chunk = self.chunk()?;
+        let arity = self.u64()? as usize;
+        let locals = self.u64()? as usize;
+        let upvalues = (0..self.u64()?)
+          .map(|_| self.upvalue())
+          .collect::<Result<Vec<_>, ParseError>>()?;
+        Value::Lambda(Gc::new(Lambda::new(arity, chunk, locals, upvalues)))
+      }
+      [OBJECT_NATIVE_LAMBDA] => {
+        let id = self.u64()? as usize;
+        // A sandboxed VM can't be given natives it wasn't granted this way.
+        let native = self
+          .registry
+          .get(id)
+          .filter(|_| self.registry.missing(id).is_none());
+        let native = native.ok_or_else(|| self.error(Expected::NativeLambda))?;
+        Value::NativeLambda(Gc::clone(native))
+      }
+      [OBJECT_METHOD] => {
+        let [code] = self.advance::<1>()?;
+        let name = self.string()?;
+        let native = kind_from_code(code).and_then(|kind| self.registry.method(kind, &name));
+        let native = native.ok_or_else(|| self.error(Expected::NativeLambda))?;
+        Value::NativeLambda(Gc::clone(native))
+      }
+      _ => return Err(self.error(Expected::Object)),
+    };
+    Ok(Object::Value(value))
+  }
+
+  fn contents(&mut self, index: usize) -> Result<(), ParseError> {
+    match &self.objects[index] {
+      Object::Value(Value::Array(array)) => {
+        let array = Gc::clone(array);
+        for _ in 0..self.u64()? {
+          let item = self.value()?;
+          array.borrow_mut().push(&item);
+        }
+      }
+      Object::Value(Value::Map(map)) => {
+        let map = Value::Map(Gc::clone(map));
+        for _ in 0..self.u64()? {
+          let key = self.value()?;
+          let value = self.value()?;
+          map
+            .set(key, value)
+            .ok_or_else(|| self.error(Expected::Value))?;
+        }
+        let meta = self.value()?;
+        map
+          .set_meta(&meta)
+          .map_err(|_| self.error(Expected::Value))?;
+      }
+      Object::Upvalue(upvalue) => {
+        let upvalue = Gc::clone(upvalue);
+        let contents = match self.advance::<1>()? {
+          [UPVALUE_OPEN] => Upvalue::Open(self.u64()? as usize),
+          [UPVALUE_CLOSED] => Upvalue::Closed(self.value()?),
+          _ => return Err(self.error(Expected::Value)),
+        };
+        *upvalue.borrow_mut() = contents;
+      }
+      Object::Value(_) => {}
+    }
+    Ok(())
+  }
+
+  fn value(&mut self) -> Result<Value, ParseError> {
+    let value = match self.advance::<1>()? {
+      [VALUE_NULL] => Value::Null,
+      [VALUE_FALSE] => Value::Bool(false),
+      [VALUE_TRUE] => Value::Bool(true),
+      [VALUE_INT] => Value::Int(i64::from_le_bytes(self.advance::<8>()?)),
+      [VALUE_FLOAT] => Value::Float(f64::from_le_bytes(self.advance::<8>()?)),
+      [VALUE_OBJECT] => {
+        let id = self.reference()?;
+        match &self.objects[id] {
+          Object::Value(value) => value.clone(),
+          Object::Upvalue(_) => return Err(self.error(Expected::Object)),
+        }
+      }
+      _ => return Err(self.error(Expected::Value)),
+    };
+    Ok(value)
+  }
+
+  fn object_string(&mut self) -> Result<Gc<Str>, ParseError> {
+    let id = self.reference()?;
+    match &self.objects[id] {
+      Object::Value(Value::String(string)) => Ok(Gc::clone(string)),
+      _ => Err(self.error(Expected::Object)),
+    }
+  }
+
+  fn upvalue(&mut self) -> Result<Gc<GcCell<Upvalue>>, ParseError> {
+    let id = self.reference()?;
+    match &self.objects[id] {
+      Object::Upvalue(upvalue) => Ok(Gc::clone(upvalue)),
+      _ => Err(self.error(Expected::Object)),
+    }
+  }
+
+  // Reads the id of an object listed before.
+  fn reference(&mut self) -> Result<usize, ParseError> {
+    let id = self.u64()? as usize;
+    if id < self.objects.len() {
+      Ok(id)
+    } else {
+      Err(self.error(Expected::Object))
+    }
+  }
+
+  fn string(&mut self) -> Result<String, ParseError> {
+    let bytes = self.bytes()?.to_vec();
+    String::from_utf8(bytes).map_err(|_| self.error(Expected::Utf8))
+  }
+
+  fn bytes(&mut self) -> Result<&'a [u8], ParseError> {
+    let len = self.u64()? as usize;
+    let bytes = self
+      .bytes
+      .get(self.current..self.current.saturating_add(len))
+      .ok_or_else(|| self.error(Expected::Bytes(len)))?;
+    self.current += len;
+    Ok(bytes)
+  }
+
+  fn u64(&mut self) -> Result<u64, ParseError> {
+    Ok(u64::from_le_bytes(self.advance::<8>()?))
+  }
+
+  fn advance<const N: usize>(&mut self) -> Result<[u8; N], ParseError> {
+    let mut bytes = [0u8; N];
+    bytes.clone_from_slice(
+      self
+        .bytes
+        .get(self.current..self.current + N)
+        .ok_or_else(|| self.error(Expected::Bytes(N)))?,
+    );
+    self.current += N;
+    Ok(bytes)
+  }
+
+  fn error(&self, expected: Expected) -> ParseError {
+    ParseError::new(self.current, expected)
+  }
+}
+
+fn write_u64(bytes: &mut Vec<u8>, int: u64) {
+  bytes.extend(&int.to_le_bytes());
+}
+
+fn write_bytes(bytes: &mut Vec<u8>, slice: &[u8]) {
+  write_u64(bytes, slice.len() as u64);
+  bytes.extend(slice);
+}
+
+fn address<T: gc::Trace + ?Sized>(gc: &Gc<T>) -> usize {
+  &**gc as *const T as *const () as usize
+}
+
+fn kind_code(kind: Kind) -> u8 {
+  match kind {
+    Kind::Null => 0,
+    Kind::Bool => 1,
+    Kind::Int => 2,
+    Kind::Float => 3,
+    Kind::String => 4,
+    Kind::Array => 5,
+    Kind::Map => 6,
+    Kind::Tagged => 7,
+    Kind::Lambda => 8,
+  }
+}
+
+fn kind_from_code(code: u8) -> Option<Kind> {
+  let kind = match code {
+    0 => Kind::Null,
+    1 => Kind::Bool,
+    2 => Kind::Int,
+    3 => Kind::Float,
+    4 => Kind::String,
+    5 => Kind::Array,
+    6 => Kind::Map,
+    7 => Kind::Tagged,
+    8 => Kind::Lambda,
+    _ => return None,
+  };
+  Some(kind)
+}
@@ -1,16 +1,13 @@
 use gc::{Gc, GcCell};
 
-use crate::{
-  error::Trace,
-  value::{Upvalue, Value},
-};
+use crate::value::{Upvalue, Value};
 
-use super::frame::Frame;
+use super::{frame::Frame, packed::Packed};
 
 #[derive(Debug)]
 pub struct Stack {
   frames: Vec<Frame>,
-  pub values: Vec<Value>,
+  values: Vec<Packed>,
   // TODO: Change this to use a linked list for faster insertion?
   upvalues: Vec<Gc<GcCell<Upvalue>>>,
 }
@@ -24,29 +21,69 @@ impl Stack {
     }
   }
 
+  // Rebuilds a stack from its parts, as a snapshot has them.
+  pub fn from_parts(
+    frames: Vec<Frame>,
+    values: Vec<Value>,
+    upvalues: Vec<Gc<GcCell<Upvalue>>>,
+  ) -> Stack {
+    Stack {
+      frames,
+      values: values.into_iter().map(Packed::from).collect(),
+      upvalues,
+    }
+  }
+
   pub fn push(&mut self, value: Value) {
-    self.values.push(value);
+    self.values.push(Packed::from(value));
   }
 
   pub fn pop(&mut self) -> Option<Value> {
-    self.values.pop()
+    self.values.pop().map(Packed::into_value)
   }
 
-  pub fn last(&self) -> Option<&Value> {
-    self.values.last()
+  pub fn last(&self) -> Option<Value> {
+    self.values.last().map(Packed::to_value)
   }
 
-  pub fn get(&self, local: usize) -> Option<&Value> {
-    self.values.get(local)
+  pub fn get(&self, local: usize) -> Option<Value> {
+    self.values.get(local).map(Packed::to_value)
   }
 
   pub fn set(&mut self, local: usize, value: Value) -> Option<()> {
-    if let Some(local_value) = self.values.get_mut(local) {
-      *local_value = value;
-      Some(())
-    } else {
-      None
-    }
+    *self.values.get_mut(local)? = Packed::from(value);
+    Some(())
+  }
+
+  pub fn push_packed(&mut self, value: Packed) {
+    self.values.push(value);
+  }
+
+  pub fn pop_packed(&mut self) -> Option<Packed> {
+    self.values.pop()
+  }
+
+  // Pushes a copy of the value at the index, without unpacking it.
+  pub fn load(&mut self, index: usize) -> Option<()> {
+    let value = self.values.get(index)?.clone();
+    self.values.push(value);
+    Some(())
+  }
+
+  // Copies the value on top of the stack to the index, without unpacking it.
+  pub fn store(&mut self, index: usize) -> Option<()> {
+    let value = self.values.last()?.clone();
+    *self.values.get_mut(index)? = value;
+    Some(())
+  }
+
+  pub fn values(&self) -> impl Iterator<Item = Value> + '_ {
+    self.values.iter().map(Packed::to_value)
+  }
+
+  // The upvalues still pointing into the stack.
+  pub fn upvalues(&self) -> &[Gc<GcCell<Upvalue>>] {
+    &self.upvalues
   }
 
   pub fn len(&self) -> usize {
@@ -61,6 +98,14 @@ impl Stack {
     self.frames.pop()
   }
 
+  pub fn frames(&self) -> &[Frame] {
+    &self.frames
+  }
+
+  pub fn frame_count(&self) -> usize {
+    self.frames.len()
+  }
+
   pub fn is_frames_empty(&self) -> bool {
     self.frames.is_empty()
   }
@@ -88,11 +133,7 @@ impl Stack {
   // closes the upvalue (setting it to be the popped value).
   // TODO: Improve searching by keeping `self.upvalues` sorted.
   pub fn close_upvalue(&mut self) -> Option<()> {
-    let value = if let Some(value) = self.values.pop() {
-      value
-    } else {
-      return None;
-    };
+    let value = self.values.pop()?.into_value();
 
     let index = self.values.len();
 
@@ -110,12 +151,15 @@ impl Stack {
     Some(())
   }
 
-  // Adds the current stack's context onto the trace
-  pub fn build_trace(&self) -> Trace {
-    let mut trace = Trace::new();
-    for frame in self.frames.iter() {
-      trace.push(frame.chunk.info().and_then(|info| info.span(frame.ip - 1)))
+  // Closes every open upvalue with the value it points to, leaving the stack
+  // as it is.
+  pub fn close_upvalues(&mut self) {
+    let values = &self.values;
+    for upvalue in self.upvalues.drain(..) {
+      let index = upvalue.borrow().as_open();
+      if let Some(value) = index.and_then(|index| values.get(index)) {
+        *upvalue.borrow_mut() = Upvalue::Closed(value.to_value());
+      }
     }
-    trace
   }
 }
@@ -0,0 +1,177 @@
+use std::{
+  cell::Cell,
+  collections::{hash_map::DefaultHasher, HashMap},
+  fmt,
+  hash::{Hash, Hasher},
+  ops::Deref,
+};
+
+use gc::{unsafe_empty_trace, Finalize, Gc, Trace};
+
+// Interns strings so that equal strings share one allocation. Constant
+// strings are interned when an executable is loaded, and hosts can intern
+// others. Strings built while running aren't, so that the table only grows
+// with the code that's loaded.
+#[derive(Default)]
+pub struct Interner {
+  // Symbols by id. Chunks refer to their constant strings by id, as a chunk
+  // can be dropped while the garbage collector runs, when dropping a `Gc` isn't
+  // allowed.
+  symbols: Vec<Symbol>,
+  ids: HashMap<String, usize>,
+}
+
+impl Interner {
+  pub fn new() -> Interner {
+    Interner::default()
+  }
+
+  pub fn intern(&mut self, string: &str) -> Symbol {
+    let id = self.id(string);
+    self.symbols[id].clone()
+  }
+
+  // Interns the string and returns its id.
+  pub fn id(&mut self, string: &str) -> usize {
+    match self.ids.get(string) {
+      Some(id) => *id,
+      None => self.insert(Gc::new(Str::new(string.to_string()))),
+    }
+  }
+
+  pub fn get(&self, id: usize) -> Option<&Symbol> {
+    self.symbols.get(id)
+  }
+
+  // Returns whether the string is the interned one, rather than one that's
+  // only equal to it.
+  pub fn is_interned(&self, string: &Gc<Str>) -> bool {
+    self
+      .ids
+      .get(string.as_str())
+      .is_some_and(|id| Gc::ptr_eq(self.symbols[*id].as_gc(), string))
+  }
+
+  fn insert(&mut self, string: Gc<Str>) -> usize {
+    self.ids.insert(string.to_string(), self.symbols.len());
+    self.symbols.push(Symbol(string));
+    self.symbols.len() - 1
+  }
+}
+
+// The string of a string value. Its hash is worked out the first time it's
+// used as a map key and kept, so that looking it up again doesn't read its
+// contents, which for interned strings means they're only read once.
+#[derive(Finalize)]
+pub struct Str {
+  string: String,
+  hash: Cell<Option<u64>>,
+}
+
+impl Str {
+  pub fn new(string: String) -> Str {
+    Str {
+      string,
+      hash: Cell::new(None),
+    }
+  }
+
+  fn hash(&self) -> u64 {
+    if let Some(hash) = self.hash.get() {
+      return hash;
+    }
+    let mut hasher = DefaultHasher::new();
+    self.string.hash(&mut hasher);
+    let hash = hasher.finish();
+    self.hash.set(Some(hash));
+    hash
+  }
+}
+
+unsafe impl Trace for Str {
+  unsafe_empty_trace!();
+}
+
+impl Deref for Str {
+  type Target = String;
+
+  fn deref(&self) -> &String {
+    &self.string
+  }
+}
+
+impl PartialEq for Str {
+  fn eq(&self, other: &Str) -> bool {
+    self.string == other.string
+  }
+}
+
+impl fmt::Debug for Str {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{:?}", self.string)
+  }
+}
+
+impl fmt::Display for Str {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.string)
+  }
+}
+
+// A string used as a map key. Keys are hashed by the hash their string keeps,
+// and compared by address first, then by that hash, so that their contents are
+// only read when two different strings hash the same.
+#[derive(Clone, Debug, Finalize, Trace)]
+pub struct Symbol(Gc<Str>);
+
+impl Symbol {
+  pub fn new(string: Gc<Str>) -> Symbol {
+    Symbol(string)
+  }
+
+  pub fn as_gc(&self) -> &Gc<Str> {
+    &self.0
+  }
+}
+
+impl Deref for Symbol {
+  type Target = str;
+
+  fn deref(&self) -> &str {
+    self.0.as_str()
+  }
+}
+
+impl PartialEq for Symbol {
+  fn eq(&self, other: &Symbol) -> bool {
+    Gc::ptr_eq(&self.0, &other.0) || (self.0.hash() == other.0.hash() && self.0 == other.0)
+  }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    state.write_u64(self.0.hash())
+  }
+}
+
+impl fmt::Display for Symbol {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.0)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn only_the_interned_string_is_interned() {
+    let mut interner = Interner::new();
+    let symbol = interner.intern("key");
+    assert!(interner.is_interned(symbol.as_gc()));
+    assert!(!interner.is_interned(&Gc::new(Str::new("key".to_string()))));
+    assert!(!interner.is_interned(&Gc::new(Str::new("other".to_string()))));
+  }
+}
@@ -1,10 +1,25 @@
-pub use self::{config::Config, registry::NativeLambdaRegistry, value::Value, vm::Vm};
+pub use self::{
+  capability::Capabilities,
+  config::Config,
+  coverage::{Coverage, FileCoverage},
+  debug::Span,
+  error::{Category, Error, Reason, Trace, TraceFrame},
+  fiber::Context,
+  intern::Str,
+  parse::{Expected, ParseError},
+  registry::NativeLambdaRegistry,
+  value::{Kind, Overflow, Value},
+  vm::Vm,
+};
 
+mod capability;
 mod chunk;
 mod config;
+mod coverage;
 mod debug;
 mod error;
 mod fiber;
+mod intern;
 mod opcode;
 mod parse;
 mod registry;
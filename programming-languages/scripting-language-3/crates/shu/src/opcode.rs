@@ -54,6 +54,23 @@ pub enum Opcode {
   // Lambda operations
   Cal,
   Ret,
+  // Opcodes added since the first version of the format. New ones go last,
+  // so that the ones before keep their numbers.
+  Pow,
+  Ban,
+  Bor,
+  Bxr,
+  Shl,
+  Shr,
+  Tgn,
+  Gnt,
+  Has,
+  Len,
+  Slc,
+  Nma,
+  Rmv,
+  Ins,
+  Ivk,
 }
 
 impl Opcode {
@@ -70,7 +87,12 @@ impl Opcode {
     | Opcode::Jmp
     | Opcode::Jit
     | Opcode::Jif
-    | Opcode::Cal = self
+    | Opcode::Tag
+    | Opcode::Tgn
+    | Opcode::Utg
+    | Opcode::Gtg
+    | Opcode::Cal
+    | Opcode::Ivk = self
     {
       true
     } else {
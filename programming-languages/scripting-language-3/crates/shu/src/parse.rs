@@ -1,6 +1,18 @@
-use std::rc::Rc;
-
-use crate::chunk::{Chunk, Constant, Function};
+use std::{error, fmt, rc::Rc};
+
+use crate::{
+  chunk::{Chunk, Constant, Function},
+  debug::{Info, Span},
+  intern::Interner,
+  opcode::Opcode,
+};
+
+// Version 1 of the format nests functions in the constants of the chunks
+// using them, and writes every number in 8 bytes. Its code is upgraded to the
+// current opcodes as it's read. Version 2, which the compiler writes, is
+// described in its `emit` module.
+const VERSION_1: u8 = 1;
+const VERSION_2: u8 = 2;
 
 const SECTION_INFO: u8 = 1;
 const SECTION_DATA: u8 = 2;
@@ -9,37 +21,295 @@ const SECTION_CODE: u8 = 3;
 const CONSTANT_STR: u8 = 1;
 const CONSTANT_FUN: u8 = 2;
 
+// The opcodes of version 1 by number, and whether they had an operand. Tag
+// operations had none, as tagged values had a single field.
+const V1_OPCODES: [(Opcode, bool); 43] = [
+  (Opcode::Nul, false),
+  (Opcode::Tru, false),
+  (Opcode::Fls, false),
+  (Opcode::Flt, true),
+  (Opcode::Int, true),
+  (Opcode::Str, true),
+  (Opcode::Arr, false),
+  (Opcode::Map, false),
+  (Opcode::Lmd, true),
+  (Opcode::Nal, true),
+  (Opcode::Pop, false),
+  (Opcode::Lod, true),
+  (Opcode::Sav, true),
+  (Opcode::Lou, true),
+  (Opcode::Sau, true),
+  (Opcode::Clu, false),
+  (Opcode::Jmp, true),
+  (Opcode::Jit, true),
+  (Opcode::Jif, true),
+  (Opcode::Dup, false),
+  (Opcode::Swp, false),
+  (Opcode::Rot, false),
+  (Opcode::Add, false),
+  (Opcode::Sub, false),
+  (Opcode::Mul, false),
+  (Opcode::Div, false),
+  (Opcode::Rem, false),
+  (Opcode::Neg, false),
+  (Opcode::Eql, false),
+  (Opcode::Neq, false),
+  (Opcode::Gtn, false),
+  (Opcode::Gte, false),
+  (Opcode::Ltn, false),
+  (Opcode::Lte, false),
+  (Opcode::Not, false),
+  (Opcode::Get, false),
+  (Opcode::Set, false),
+  (Opcode::Apn, false),
+  (Opcode::Tag, false),
+  (Opcode::Utg, false),
+  (Opcode::Gtg, false),
+  (Opcode::Cal, true),
+  (Opcode::Ret, false),
+];
+
 pub struct Parser<'a> {
   bytes: &'a [u8],
   current: usize,
+  // Constant strings are interned as they're parsed.
+  interner: &'a mut Interner,
 }
 
 impl<'a> Parser<'a> {
-  pub fn new(bytes: &'a [u8]) -> Parser<'a> {
-    Parser { bytes, current: 0 }
+  pub fn new(bytes: &'a [u8], interner: &'a mut Interner) -> Parser<'a> {
+    Parser {
+      bytes,
+      current: 0,
+      interner,
+    }
   }
 
   pub fn parse(mut self) -> Result<Function, ParseError> {
-    self.expect([b'O', b'M', b'A', 1])?;
+    self.expect([b'O', b'M', b'A'], Expected::Header)?;
+
+    match self.advance::<1>()? {
+      [VERSION_1] => self.function(),
+      [VERSION_2] => self.executable(),
+      [version] => Err(ParseError {
+        offset: self.current - 1,
+        expected: Expected::Version(version),
+      }),
+    }
+  }
 
-    self.function()
+  // Reads a version 2 executable, after the format version.
+  fn executable(&mut self) -> Result<Function, ParseError> {
+    self.checksum()?;
+
+    // The version of the compiler that wrote the executable, which is only
+    // there for people inspecting it.
+    self.leb128_string()?;
+
+    let strings = (0..self.leb128()?)
+      .map(|_| self.leb128_string().map(Rc::new))
+      .collect::<Result<Vec<_>, ParseError>>()?;
+
+    let len = self.leb128()? as usize;
+    let records = (0..len)
+      .map(|index| self.function_record(index, len, &strings))
+      .collect::<Result<Vec<_>, ParseError>>()?;
+    if self.current != self.bytes.len() {
+      return Err(self.error(Expected::Checksum));
+    }
+
+    // Functions only refer to functions after them, so building them from
+    // the last one means the functions they refer to are built already.
+    let mut functions: Vec<Function> = Vec::with_capacity(len);
+    for record in records.into_iter().rev() {
+      let data = record
+        .data
+        .into_iter()
+        .map(|constant| match constant {
+          RecordConstant::String(id) => Constant::String(id),
+          RecordConstant::Function(index) => Constant::Function(functions[len - 1 - index].clone()),
+        })
+        .collect();
+      functions.push(Function {
+        arity: record.arity,
+        chunk: Rc::new(Chunk {
+          info: record.info,
+          data,
+          code: record.code,
+        }),
+        locals: record.locals,
+        upvalues: record.upvalues,
+      });
+    }
+    functions
+      .pop()
+      .ok_or_else(|| self.error(Expected::Function))
+  }
+
+  // Checks the checksum at the end of the executable, and leaves it out of
+  // the bytes left to parse.
+  fn checksum(&mut self) -> Result<(), ParseError> {
+    let offset = self
+      .bytes
+      .len()
+      .checked_sub(4)
+      .filter(|offset| *offset >= self.current)
+      .ok_or_else(|| self.error(Expected::Checksum))?;
+    let (bytes, checksum) = self.bytes.split_at(offset);
+    if crc32(bytes).to_le_bytes() != checksum {
+      return Err(ParseError {
+        offset,
+        expected: Expected::Checksum,
+      });
+    }
+    self.bytes = bytes;
+    Ok(())
+  }
+
+  fn function_record(
+    &mut self,
+    index: usize,
+    len: usize,
+    strings: &[Rc<String>],
+  ) -> Result<FunctionRecord, ParseError> {
+    let arity = self.leb128()?;
+    let locals = self.leb128()?;
+    let upvalues = (0..self.leb128()?)
+      .map(|_| {
+        let index = self.leb128()?;
+        match self.advance::<1>()? {
+          [0] => Ok((index, false)),
+          [1] => Ok((index, true)),
+          _ => Err(self.error(Expected::Bool)),
+        }
+      })
+      .collect::<Result<Vec<_>, ParseError>>()?;
+
+    let info = match self.advance::<1>()? {
+      [0] => None,
+      [1] => {
+        let name = self.string_index(strings)?;
+        let path = self.string_index(strings)?;
+        let spans = (0..self.leb128()?)
+          .map(|_| {
+            let offset = self.leb128()? as usize;
+            let span = Span {
+              line: self.leb128()? as usize,
+              column: self.leb128()? as usize,
+              path: Rc::clone(&path),
+            };
+            Ok((offset, Rc::new(span)))
+          })
+          .collect::<Result<Vec<_>, ParseError>>()?;
+        Some(Info { name, spans })
+      }
+      _ => return Err(self.error(Expected::Bool)),
+    };
+
+    let data = (0..self.leb128()?)
+      .map(|_| match self.advance::<1>()? {
+        [CONSTANT_STR] => {
+          let string = self.string_index(strings)?;
+          Ok(RecordConstant::String(self.interner.id(&string)))
+        }
+        [CONSTANT_FUN] => match self.leb128()? as usize {
+          function if function > index && function < len => Ok(RecordConstant::Function(function)),
+          _ => Err(self.error(Expected::Function)),
+        },
+        _ => Err(self.error(Expected::Constant)),
+      })
+      .collect::<Result<Vec<_>, ParseError>>()?;
+
+    let len = self.leb128()? as usize;
+    let code = self.slice(len)?.into();
+    self.current += len;
+
+    Ok(FunctionRecord {
+      arity,
+      locals,
+      upvalues,
+      info,
+      data,
+      code,
+    })
+  }
+
+  fn string_index(&mut self, strings: &[Rc<String>]) -> Result<Rc<String>, ParseError> {
+    let index = self.leb128()? as usize;
+    strings
+      .get(index)
+      .cloned()
+      .ok_or_else(|| self.error(Expected::String))
+  }
+
+  fn leb128_string(&mut self) -> Result<String, ParseError> {
+    let len = self.leb128()? as usize;
+
+    let bytes = self.slice(len)?.to_vec();
+    let string = String::from_utf8(bytes).map_err(|_| self.error(Expected::Utf8))?;
+    self.current += len;
+
+    Ok(string)
+  }
+
+  fn leb128(&mut self) -> Result<u64, ParseError> {
+    let start = self.current;
+    let mut uint = 0u64;
+    for shift in (0..64).step_by(7) {
+      let [byte] = self.advance::<1>()?;
+      uint |= ((byte & 0x7f) as u64) << shift;
+      if byte & 0x80 == 0 {
+        return Ok(uint);
+      }
+    }
+    Err(ParseError {
+      offset: start,
+      expected: Expected::Leb128,
+    })
   }
 
   fn chunk(&mut self) -> Result<Chunk, ParseError> {
-    let info = if self.peek()? == [SECTION_INFO] {
-      unimplemented!();
+    let mut info = if self.peek()? == [SECTION_INFO] {
+      Some(self.info()?)
     } else {
       None
     };
 
     let data = self.data()?;
+    let start = self.current + 9;
     let code = self.code()?;
+    let code = upgrade(&code, info.as_mut()).map_err(|offset| ParseError {
+      offset: start + offset,
+      expected: Expected::Op,
+    })?;
 
     Ok(Chunk { info, data, code })
   }
 
+  fn info(&mut self) -> Result<Info, ParseError> {
+    self.expect([SECTION_INFO], Expected::Section("info"))?;
+
+    let name = Rc::new(self.string()?);
+    let path = Rc::new(self.string()?);
+
+    let len = u64::from_le_bytes(self.advance::<8>()?) as usize;
+    let spans = (0..len)
+      .map(|_| {
+        let offset = u64::from_le_bytes(self.advance::<8>()?) as usize;
+        let span = Span {
+          line: u64::from_le_bytes(self.advance::<8>()?) as usize,
+          column: u64::from_le_bytes(self.advance::<8>()?) as usize,
+          path: Rc::clone(&path),
+        };
+        Ok((offset, Rc::new(span)))
+      })
+      .collect::<Result<Vec<_>, ParseError>>()?;
+
+    Ok(Info { name, spans })
+  }
+
   fn data(&mut self) -> Result<Box<[Constant]>, ParseError> {
-    self.expect([SECTION_DATA])?;
+    self.expect([SECTION_DATA], Expected::Section("data"))?;
 
     let len = u64::from_le_bytes(self.advance::<8>()?) as usize;
 
@@ -49,15 +319,11 @@ impl<'a> Parser<'a> {
   }
 
   fn code(&mut self) -> Result<Box<[u8]>, ParseError> {
-    self.expect([SECTION_CODE])?;
+    self.expect([SECTION_CODE], Expected::Section("code"))?;
 
     let len = u64::from_le_bytes(self.advance::<8>()?) as usize;
 
-    let code = self
-      .bytes
-      .get(self.current..self.current + len)
-      .ok_or(ParseError {})?
-      .into();
+    let code = self.slice(len)?.into();
     self.current += len;
 
     Ok(code)
@@ -65,29 +331,33 @@ impl<'a> Parser<'a> {
 
   fn constant(&mut self) -> Result<Constant, ParseError> {
     match self.peek::<1>()? {
-      [CONSTANT_STR] => self.constant_string().map(Constant::String),
+      [CONSTANT_STR] => {
+        let string = self.constant_string()?;
+        Ok(Constant::String(self.interner.id(&string)))
+      }
       [CONSTANT_FUN] => self.constant_function().map(Constant::Function),
-      _ => Err(ParseError {}),
+      _ => Err(self.error(Expected::Constant)),
     }
   }
 
   fn constant_string(&mut self) -> Result<String, ParseError> {
-    self.expect([CONSTANT_STR])?;
+    self.expect([CONSTANT_STR], Expected::Constant)?;
+
+    self.string()
+  }
 
+  fn string(&mut self) -> Result<String, ParseError> {
     let len = u64::from_le_bytes(self.advance::<8>()?) as usize;
 
-    let bytes = self
-      .bytes
-      .get(self.current..self.current + len)
-      .ok_or(ParseError {})?
-      .to_vec();
+    let bytes = self.slice(len)?.to_vec();
+    let string = String::from_utf8(bytes).map_err(|_| self.error(Expected::Utf8))?;
     self.current += len;
 
-    String::from_utf8(bytes).map_err(|_| ParseError {})
+    Ok(string)
   }
 
   fn constant_function(&mut self) -> Result<Function, ParseError> {
-    self.expect([CONSTANT_FUN])?;
+    self.expect([CONSTANT_FUN], Expected::Constant)?;
 
     self.function()
   }
@@ -105,10 +375,10 @@ impl<'a> Parser<'a> {
       .map(|_| {
         Ok((
           u64::from_le_bytes(self.advance::<8>()?),
-          match self.advance::<1>()? {
-            [0] => false,
-            [1] => true,
-            _ => return Err(ParseError {}),
+          match self.peek::<1>()? {
+            [0] => self.advance::<1>().map(|_| false)?,
+            [1] => self.advance::<1>().map(|_| true)?,
+            _ => return Err(self.error(Expected::Bool)),
           },
         ))
       })
@@ -122,19 +392,16 @@ impl<'a> Parser<'a> {
     })
   }
 
-  fn expect<const N: usize>(&mut self, expected: [u8; N]) -> Result<[u8; N], ParseError> {
-    let mut bytes = [0u8; N];
-    bytes.clone_from_slice(
-      self
-        .bytes
-        .get(self.current..self.current + N)
-        .ok_or(ParseError {})?,
-    );
-    self.current += N;
-    if bytes == expected {
+  fn expect<const N: usize>(
+    &mut self,
+    bytes: [u8; N],
+    expected: Expected,
+  ) -> Result<[u8; N], ParseError> {
+    if self.peek::<N>()? == bytes {
+      self.current += N;
       Ok(bytes)
     } else {
-      Err(ParseError {})
+      Err(self.error(expected))
     }
   }
 
@@ -146,15 +413,295 @@ impl<'a> Parser<'a> {
 
   fn peek<const N: usize>(&mut self) -> Result<[u8; N], ParseError> {
     let mut bytes = [0u8; N];
-    bytes.clone_from_slice(
-      self
-        .bytes
-        .get(self.current..self.current + N)
-        .ok_or(ParseError {})?,
-    );
+    bytes.clone_from_slice(self.slice(N)?);
     Ok(bytes)
   }
+
+  fn slice(&self, len: usize) -> Result<&'a [u8], ParseError> {
+    // A corrupt length can be large enough to overflow the offset.
+    self
+      .current
+      .checked_add(len)
+      .and_then(|end| self.bytes.get(self.current..end))
+      .ok_or_else(|| self.error(Expected::Bytes(len)))
+  }
+
+  fn error(&self, expected: Expected) -> ParseError {
+    ParseError {
+      offset: self.current,
+      expected,
+    }
+  }
+}
+
+// Rewrites version 1 code with the current opcodes, giving tag operations the
+// operands that mean what they did then. That moves the ops after them, so
+// jumps are written with 8-byte operands, to know where ops end up before
+// their targets are, and span offsets are moved along. Returns the offset of
+// the op that can't be read if there is one.
+fn upgrade(code: &[u8], info: Option<&mut Info>) -> Result<Box<[u8]>, usize> {
+  // The ops as (offset, opcode, operand), and where they are moved to.
+  let mut ops = Vec::new();
+  let mut offsets = Vec::new();
+  let mut offset = 0;
+  let mut new_offset = 0;
+  while offset < code.len() {
+    let byte = code[offset];
+    let (opcode, has_operand) = *V1_OPCODES.get((byte >> 2) as usize).ok_or(offset)?;
+    let size = if has_operand { 1 << (byte & 0b11) } else { 0 };
+    let operand = code
+      .get(offset + 1..offset + 1 + size)
+      .ok_or(offset)?
+      .iter()
+      .rev()
+      .fold(0u64, |operand, byte| operand << 8 | *byte as u64);
+    let operand = match opcode {
+      // Tagged values were built from one field, and unwrapped by getting it.
+      Opcode::Tag | Opcode::Gtg => Some((1, 1)),
+      Opcode::Utg => Some((0, 1)),
+      Opcode::Jmp | Opcode::Jit | Opcode::Jif => Some((operand, 8)),
+      _ if has_operand => Some((operand, size)),
+      _ => None,
+    };
+    ops.push((offset, opcode, operand));
+    offsets.push((offset, new_offset));
+    offset += 1 + size;
+    new_offset += 1 + operand.map_or(0, |(_, size)| size);
+  }
+  offsets.push((offset, new_offset));
+  let moved = |offset: u64| -> Option<usize> {
+    let index = offsets
+      .binary_search_by_key(&(offset as usize), |(old, _)| *old)
+      .ok()?;
+    Some(offsets[index].1)
+  };
+
+  let mut upgraded = Vec::with_capacity(new_offset);
+  for (offset, opcode, operand) in ops {
+    let (operand, size) = match (opcode, operand) {
+      (Opcode::Jmp | Opcode::Jit | Opcode::Jif, Some((target, size))) => {
+        (moved(target).ok_or(offset)? as u64, size)
+      }
+      (_, Some(operand)) => operand,
+      (_, None) => {
+        upgraded.push((opcode as u8) << 2);
+        continue;
+      }
+    };
+    upgraded.push((opcode as u8) << 2 | size.trailing_zeros() as u8);
+    upgraded.extend(&operand.to_le_bytes()[..size]);
+  }
+
+  if let Some(info) = info {
+    for (offset, _) in &mut info.spans {
+      *offset = moved(*offset as u64).ok_or(code.len())?;
+    }
+  }
+
+  Ok(upgraded.into())
+}
+
+// A version 2 function as it's read, before the functions it refers to are
+// built.
+struct FunctionRecord {
+  arity: u64,
+  locals: u64,
+  upvalues: Vec<(u64, bool)>,
+  info: Option<Info>,
+  data: Vec<RecordConstant>,
+  code: Box<[u8]>,
+}
+
+enum RecordConstant {
+  // The id of the string in the interner.
+  String(usize),
+  // The index of the function in the function table.
+  Function(usize),
+}
+
+// CRC-32 as used by zip and PNG, which the compiler writes at the end of an
+// executable.
+fn crc32(bytes: &[u8]) -> u32 {
+  let mut crc = !0u32;
+  for byte in bytes {
+    crc ^= *byte as u32;
+    for _ in 0..8 {
+      crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+    }
+  }
+  !crc
 }
 
 #[derive(Debug)]
-pub struct ParseError {}
+pub struct ParseError {
+  offset: usize,
+  expected: Expected,
+}
+
+impl ParseError {
+  pub(crate) fn new(offset: usize, expected: Expected) -> ParseError {
+    ParseError { offset, expected }
+  }
+
+  // The offset of the byte where parsing failed.
+  pub fn offset(&self) -> usize {
+    self.offset
+  }
+
+  pub fn expected(&self) -> &Expected {
+    &self.expected
+  }
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "expected {} at byte {}", self.expected, self.offset)
+  }
+}
+
+impl error::Error for ParseError {}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expected {
+  // The magic number at the start of an executable.
+  Header,
+  // A format version the parser can read, rather than the one found.
+  Version(u8),
+  // A checksum matching the executable, at its end.
+  Checksum,
+  // An unsigned LEB128 number that fits in 64 bits.
+  Leb128,
+  // The index of a string in the string table.
+  String,
+  // The index of a function after the current one in the function table.
+  Function,
+  Section(&'static str),
+  Constant,
+  Bool,
+  Utf8,
+  // The executable ended with fewer than this many bytes left.
+  Bytes(usize),
+  // The magic number and format version at the start of a snapshot.
+  SnapshotHeader,
+  // A snapshot of the executable being restored, rather than another one.
+  Executable,
+  Value,
+  // An id of an object defined earlier in the snapshot, of the right kind.
+  Object,
+  // The index of a chunk in the executable.
+  Chunk,
+  // The id or method name of a registered native lambda.
+  NativeLambda,
+  // A whole version 1 op with a known opcode, and a jump to the start of one.
+  Op,
+}
+
+impl fmt::Display for Expected {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Expected::Header => write!(f, "executable header"),
+      Expected::Version(version) => {
+        write!(
+          f,
+          "format version {} or {} (found {})",
+          VERSION_1, VERSION_2, version
+        )
+      }
+      Expected::Checksum => write!(f, "matching checksum"),
+      Expected::Leb128 => write!(f, "LEB128 number"),
+      Expected::String => write!(f, "string index"),
+      Expected::Function => write!(f, "function index"),
+      Expected::Section(section) => write!(f, "{} section", section),
+      Expected::Constant => write!(f, "constant"),
+      Expected::Bool => write!(f, "bool"),
+      Expected::Utf8 => write!(f, "valid UTF-8"),
+      Expected::Bytes(len) => write!(f, "{} more bytes", len),
+      Expected::SnapshotHeader => write!(f, "snapshot header"),
+      Expected::Executable => write!(f, "snapshot of this executable"),
+      Expected::Value => write!(f, "value"),
+      Expected::Object => write!(f, "object"),
+      Expected::Chunk => write!(f, "chunk"),
+      Expected::NativeLambda => write!(f, "registered native lambda"),
+      Expected::Op => write!(f, "version 1 op"),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(bytes: &[u8]) -> Result<Function, ParseError> {
+    Parser::new(bytes, &mut Interner::new()).parse()
+  }
+
+  // The start of a version 1 executable, up to the length of the name of its
+  // top-level function.
+  fn version_1() -> Vec<u8> {
+    let mut bytes = b"OMA".to_vec();
+    bytes.push(VERSION_1);
+    bytes.extend(0u64.to_le_bytes());
+    bytes.push(SECTION_INFO);
+    bytes
+  }
+
+  #[test]
+  fn truncated_executable() {
+    let mut bytes = version_1();
+    bytes.extend(5u64.to_le_bytes());
+    bytes.extend(b"ma");
+
+    let error = parse(&bytes).unwrap_err();
+    assert_eq!(error.expected(), &Expected::Bytes(5));
+    assert_eq!(error.offset(), 21);
+  }
+
+  #[test]
+  fn length_overflowing_offset() {
+    let mut bytes = version_1();
+    bytes.extend(u64::MAX.to_le_bytes());
+
+    let error = parse(&bytes).unwrap_err();
+    assert_eq!(error.expected(), &Expected::Bytes(u64::MAX as usize));
+  }
+
+  #[test]
+  fn upgrade_moves_jumps_and_spans_past_tag_operands() {
+    // `Tag`, a jump to `Ret` with a 1-byte operand, then `Ret`.
+    let code = [38 << 2, 16 << 2, 3, 42 << 2];
+    let path = Rc::new("main.oma".to_string());
+    let span = |line| {
+      Rc::new(Span {
+        line,
+        column: 0,
+        path: Rc::clone(&path),
+      })
+    };
+    let mut info = Info {
+      name: Rc::new("<module>".to_string()),
+      spans: vec![(0, span(1)), (1, span(2)), (3, span(3))],
+    };
+
+    let upgraded = upgrade(&code, Some(&mut info)).unwrap();
+    let mut expected = vec![(Opcode::Tag as u8) << 2, 1, (Opcode::Jmp as u8) << 2 | 3];
+    expected.extend(11u64.to_le_bytes());
+    expected.push((Opcode::Ret as u8) << 2);
+    assert_eq!(&*upgraded, &expected[..]);
+    let offsets = info.spans.iter().map(|(offset, _)| *offset);
+    assert_eq!(offsets.collect::<Vec<_>>(), vec![0, 2, 11]);
+  }
+
+  #[test]
+  fn upgrade_rejects_unknown_opcode_and_jump_into_op() {
+    assert_eq!(upgrade(&[0, 43 << 2], None).unwrap_err(), 1);
+    // A jump to the operand of an `Int`.
+    let code = [16 << 2, 4, 0, 4 << 2, 7];
+    assert_eq!(upgrade(&code, None).unwrap_err(), 0);
+  }
+
+  #[test]
+  fn executable_without_header() {
+    let error = parse(b"OM").unwrap_err();
+    assert_eq!(error.expected(), &Expected::Bytes(3));
+  }
+}
@@ -1,27 +1,206 @@
+use std::{collections::HashMap, future::Future};
+
 use gc::Gc;
 
-use crate::value::{NativeLambda, Value};
+use crate::{
+  capability::Capabilities,
+  error::Error,
+  fiber::Context,
+  value::{Kind, NativeLambda, Value},
+};
 
 pub struct NativeLambdaRegistry {
   native_lambdas: Vec<Gc<NativeLambda>>,
+  methods: HashMap<(Kind, String), Gc<NativeLambda>>,
+  // The capability each native lambda needs, by id. The rest need none.
+  capabilities: HashMap<usize, String>,
+  // The capability each method needs, like `capabilities`.
+  method_capabilities: HashMap<(Kind, String), String>,
+  // What the VM running the registry was granted, if it's sandboxed.
+  granted: Option<Capabilities>,
 }
 
 impl NativeLambdaRegistry {
   pub fn new() -> NativeLambdaRegistry {
     NativeLambdaRegistry {
       native_lambdas: Vec::new(),
+      methods: HashMap::new(),
+      capabilities: HashMap::new(),
+      method_capabilities: HashMap::new(),
+      granted: None,
     }
   }
 
-  pub fn add<F>(&mut self, lambda: F) -> usize
+  // The name is only used in stack traces, natives are looked up by the id
+  // this returns.
+  pub fn add<F>(&mut self, name: &str, lambda: F) -> usize
   where
     F: Fn(Value) -> Value + 'static,
   {
-    self.native_lambdas.push(Gc::new(NativeLambda::new(lambda)));
+    self
+      .native_lambdas
+      .push(Gc::new(NativeLambda::new(name, lambda)));
+    self.native_lambdas.len() - 1
+  }
+
+  // Adds a native lambda that can call Oma lambdas through the context, for
+  // example to apply a callback to each item of an array.
+  pub fn add_with_context<F>(&mut self, name: &str, lambda: F) -> usize
+  where
+    F: Fn(&mut Context, Value) -> Result<Value, Error> + 'static,
+  {
+    self
+      .native_lambdas
+      .push(Gc::new(NativeLambda::with_context(name, lambda)));
+    self.native_lambdas.len() - 1
+  }
+
+  // Adds a native lambda that returns a future, such as one doing I/O. The
+  // fiber calling it is suspended until the future resolves, and the VM runs
+  // other fibers meanwhile.
+  pub fn add_async<F, R>(&mut self, name: &str, lambda: F) -> usize
+  where
+    F: Fn(Value) -> R + 'static,
+    R: Future<Output = Result<Value, Error>> + 'static,
+  {
+    self
+      .native_lambdas
+      .push(Gc::new(NativeLambda::with_future(name, lambda)));
     self.native_lambdas.len() - 1
   }
 
   pub fn get(&self, id: usize) -> Option<&Gc<NativeLambda>> {
     self.native_lambdas.get(id)
   }
+
+  // Makes the native lambda need the capability, so a VM sandboxed with
+  // `Vm::sandbox` refuses to load it unless the capability is granted.
+  pub fn require(&mut self, id: usize, capability: &str) {
+    self.capabilities.insert(id, capability.to_string());
+  }
+
+  pub fn capability(&self, id: usize) -> Option<&str> {
+    self.capabilities.get(&id).map(String::as_str)
+  }
+
+  // The names of the native lambdas that need a capability which isn't
+  // granted, with the capability each needs, which is what the compiler is
+  // told to reject. Methods are left out, as which one a call ends up at is
+  // only known when it's made.
+  pub fn denied(&self, capabilities: &Capabilities) -> Vec<(String, String)> {
+    let mut denied = self
+      .capabilities
+      .iter()
+      .filter(|(_, capability)| !capabilities.allows(capability))
+      .map(|(id, capability)| {
+        let name = self.native_lambdas[*id].name();
+        (name.to_string(), capability.clone())
+      })
+      .collect::<Vec<_>>();
+    denied.sort();
+    denied
+  }
+
+  pub(crate) fn grant(&mut self, capabilities: Capabilities) {
+    self.granted = Some(capabilities);
+  }
+
+  // The capability the native lambda needs that the VM wasn't granted, if
+  // any.
+  pub(crate) fn missing(&self, id: usize) -> Option<&str> {
+    let granted = self.granted.as_ref()?;
+    self
+      .capability(id)
+      .filter(|capability| !granted.allows(capability))
+  }
+
+  // Adds a method that values of the kind can call as `value.name(...)`. The
+  // lambda is passed an array of the value followed by the arguments.
+  pub fn add_method<F>(&mut self, kind: Kind, name: &str, lambda: F)
+  where
+    F: Fn(&mut Context, Value) -> Result<Value, Error> + 'static,
+  {
+    let lambda = NativeLambda::with_context(&format!("{}.{}", kind, name), lambda);
+    self
+      .methods
+      .insert((kind, name.to_string()), Gc::new(lambda));
+  }
+
+  pub fn method(&self, kind: Kind, name: &str) -> Option<&Gc<NativeLambda>> {
+    self.methods.get(&(kind, name.to_string()))
+  }
+
+  // Makes the method need the capability, so a sandboxed VM refuses to call
+  // it unless the capability is granted.
+  pub fn require_method(&mut self, kind: Kind, name: &str, capability: &str) {
+    self
+      .method_capabilities
+      .insert((kind, name.to_string()), capability.to_string());
+  }
+
+  pub fn method_capability(&self, kind: Kind, name: &str) -> Option<&str> {
+    self
+      .method_capabilities
+      .get(&(kind, name.to_string()))
+      .map(String::as_str)
+  }
+
+  // Like `missing`, for a method.
+  pub(crate) fn missing_method(&self, kind: Kind, name: &str) -> Option<&str> {
+    let granted = self.granted.as_ref()?;
+    self
+      .method_capability(kind, name)
+      .filter(|capability| !granted.allows(capability))
+  }
+
+  // Finds how the native lambda was registered, which is how snapshots refer
+  // to it.
+  pub(crate) fn find(&self, native: &Gc<NativeLambda>) -> Option<NativeId> {
+    if let Some(id) = self
+      .native_lambdas
+      .iter()
+      .position(|lambda| Gc::ptr_eq(lambda, native))
+    {
+      return Some(NativeId::Lambda(id));
+    }
+    self
+      .methods
+      .iter()
+      .find(|(_, method)| Gc::ptr_eq(method, native))
+      .map(|((kind, name), _)| NativeId::Method(*kind, name.clone()))
+  }
+}
+
+pub(crate) enum NativeId {
+  Lambda(usize),
+  Method(Kind, String),
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn denied_names_natives_needing_capabilities_not_granted() {
+    let mut registry = NativeLambdaRegistry::new();
+    registry.add("free", |value| value);
+    let print = registry.add("print", |value| value);
+    registry.require(print, "io.console");
+    let fetch = registry.add("fetch", |value| value);
+    registry.require(fetch, "net");
+
+    let mut capabilities = Capabilities::new();
+    assert_eq!(
+      registry.denied(&capabilities),
+      vec![
+        ("fetch".to_string(), "net".to_string()),
+        ("print".to_string(), "io.console".to_string()),
+      ]
+    );
+    capabilities.grant("io");
+    assert_eq!(
+      registry.denied(&capabilities),
+      vec![("fetch".to_string(), "net".to_string())]
+    );
+  }
 }
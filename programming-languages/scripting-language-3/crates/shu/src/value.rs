@@ -1,23 +1,65 @@
 use std::{
   cmp::PartialOrd,
   collections::HashMap,
+  convert::TryFrom,
   fmt,
-  ops::{Add, Div, Mul, Rem, Sub},
+  future::Future,
+  ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Rem, Sub},
+  pin::Pin,
   rc::Rc,
 };
 
 use gc::{unsafe_empty_trace, Finalize, Gc, GcCell, Trace};
+use num_traits::{ToPrimitive, Zero};
 
-use crate::chunk::Chunk;
+use crate::{
+  chunk::Chunk,
+  error::{Error, Reason},
+  fiber::Context,
+  intern::{Str, Symbol},
+};
 
 macro_rules! arithmetic {
+  ($left:expr, $right:expr, $overflow:expr, $op:ident, $checked_op:ident) => {
+    match ($left, $right) {
+      (Value::Int(left), Value::Int(right)) => match left.$checked_op(*right) {
+        Some(result) => Ok(Value::Int(result)),
+        None => match $overflow {
+          Overflow::Error => Err(Reason::Overflow),
+          Overflow::Promote => Ok(Value::big(
+            num_bigint::BigInt::from(*left).$op(num_bigint::BigInt::from(*right)),
+          )),
+        },
+      },
+      (Value::Int(_), Value::BigInt(_))
+      | (Value::BigInt(_), Value::Int(_))
+      | (Value::BigInt(_), Value::BigInt(_)) => {
+        let left = $left.to_big().expect("`left` is an integer");
+        let right = $right.to_big().expect("`right` is an integer");
+        Ok(Value::big(left.$op(right)))
+      }
+      (Value::Int(left), Value::Float(right)) => Ok(Value::Float((*left as f64).$op(right))),
+      (Value::Float(left), Value::Int(right)) => Ok(Value::Float(left.$op(*right as f64))),
+      (Value::BigInt(left), Value::Float(right)) => Ok(Value::Float(left.to_f64().$op(right))),
+      (Value::Float(left), Value::BigInt(right)) => Ok(Value::Float(left.$op(right.to_f64()))),
+      (Value::Float(left), Value::Float(right)) => Ok(Value::Float(left.$op(right))),
+      _ => Err(Reason::Type),
+    }
+  };
+}
+
+macro_rules! bitwise {
   ($left:expr, $right:expr, $op:ident) => {
     match ($left, $right) {
-      (Value::Int(left), Value::Int(right)) => Some(Value::Int(left.$op(right))),
-      (Value::Int(left), Value::Float(right)) => Some(Value::Float((*left as f64).$op(right))),
-      (Value::Float(left), Value::Int(right)) => Some(Value::Float(left.$op(*right as f64))),
-      (Value::Float(left), Value::Float(right)) => Some(Value::Float(left.$op(right))),
-      _ => None,
+      (Value::Int(left), Value::Int(right)) => Ok(Value::Int(left.$op(right))),
+      (Value::Int(_), Value::BigInt(_))
+      | (Value::BigInt(_), Value::Int(_))
+      | (Value::BigInt(_), Value::BigInt(_)) => {
+        let left = $left.to_big().expect("`left` is an integer");
+        let right = $right.to_big().expect("`right` is an integer");
+        Ok(Value::big(left.$op(right)))
+      }
+      _ => Err(Reason::Type),
     }
   };
 }
@@ -26,21 +68,80 @@ macro_rules! comparison {
   ($left:expr, $right:expr, $op:ident) => {
     match ($left, $right) {
       (Value::Int(left), Value::Int(right)) => Some(Value::Bool(left.$op(right))),
+      (Value::Int(_), Value::BigInt(_))
+      | (Value::BigInt(_), Value::Int(_))
+      | (Value::BigInt(_), Value::BigInt(_)) => {
+        let left = $left.to_big().expect("`left` is an integer");
+        let right = $right.to_big().expect("`right` is an integer");
+        Some(Value::Bool(left.$op(&right)))
+      }
       (Value::Int(left), Value::Float(right)) => Some(Value::Bool((*left as f64).$op(right))),
       (Value::Float(left), Value::Int(right)) => Some(Value::Bool(left.$op(&(*right as f64)))),
+      (Value::BigInt(left), Value::Float(right)) => Some(Value::Bool(left.to_f64().$op(right))),
+      (Value::Float(left), Value::BigInt(right)) => Some(Value::Bool(left.$op(&right.to_f64()))),
       (Value::Float(left), Value::Float(right)) => Some(Value::Bool(left.$op(right))),
       _ => None,
     }
   };
 }
 
+// Determines what integer arithmetic does when its result doesn't fit in an `i64`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overflow {
+  // Fail with `Reason::Overflow`.
+  Error,
+  // Promote the result to an arbitrary-precision integer.
+  Promote,
+}
+
+// The kinds of values that methods are registered for. Integers of any size
+// are one kind, as are Oma and native lambdas.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Kind {
+  Null,
+  Bool,
+  Int,
+  Float,
+  String,
+  Array,
+  Map,
+  Tagged,
+  Lambda,
+}
+
+impl Kind {
+  // Whether operators on values of this kind are built in, rather than
+  // looked up as methods.
+  pub fn is_primitive(self) -> bool {
+    !matches!(self, Kind::Array | Kind::Map | Kind::Tagged | Kind::Lambda)
+  }
+}
+
+impl fmt::Display for Kind {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let name = match self {
+      Kind::Null => "null",
+      Kind::Bool => "bool",
+      Kind::Int => "int",
+      Kind::Float => "float",
+      Kind::String => "string",
+      Kind::Array => "array",
+      Kind::Map => "map",
+      Kind::Tagged => "tagged",
+      Kind::Lambda => "lambda",
+    };
+    write!(f, "{}", name)
+  }
+}
+
 #[derive(Clone, Debug, Finalize, Trace)]
 pub enum Value {
   Null,
   Bool(bool),
   Int(i64),
+  BigInt(Gc<BigInt>),
   Float(f64),
-  String(Gc<String>),
+  String(Gc<Str>),
   Array(Gc<GcCell<Array>>),
   Map(Gc<GcCell<Map>>),
   Tagged(Gc<Tagged>),
@@ -57,36 +158,146 @@ impl Value {
     Value::Map(Gc::new(GcCell::new(Map::new())))
   }
 
-  pub fn add(&self, other: &Value) -> Option<Value> {
+  // Wraps an arbitrary-precision integer, demoting it to `Value::Int` if it fits.
+  pub fn big(int: num_bigint::BigInt) -> Value {
+    match int.to_i64() {
+      Some(int) => Value::Int(int),
+      None => Value::BigInt(Gc::new(BigInt(int))),
+    }
+  }
+
+  pub fn add(&self, other: &Value, overflow: Overflow) -> Result<Value, Reason> {
     if let (Value::String(left), Value::String(right)) = (self, other) {
       let result = [left.as_str(), right.as_str()].concat();
-      Some(Value::String(Gc::new(result)))
+      Ok(Value::String(Gc::new(Str::new(result))))
     } else {
-      arithmetic!(self, other, add)
+      arithmetic!(self, other, overflow, add, checked_add)
+    }
+  }
+
+  pub fn sub(&self, other: &Value, overflow: Overflow) -> Result<Value, Reason> {
+    arithmetic!(self, other, overflow, sub, checked_sub)
+  }
+
+  pub fn mul(&self, other: &Value, overflow: Overflow) -> Result<Value, Reason> {
+    arithmetic!(self, other, overflow, mul, checked_mul)
+  }
+
+  pub fn div(&self, other: &Value, overflow: Overflow) -> Result<Value, Reason> {
+    if self.is_integer() && other.is_zero() {
+      return Err(Reason::DivisionByZero);
     }
+    arithmetic!(self, other, overflow, div, checked_div)
   }
 
-  pub fn sub(&self, other: &Value) -> Option<Value> {
-    arithmetic!(self, other, sub)
+  pub fn rem(&self, other: &Value, overflow: Overflow) -> Result<Value, Reason> {
+    if self.is_integer() && other.is_zero() {
+      return Err(Reason::DivisionByZero);
+    }
+    arithmetic!(self, other, overflow, rem, checked_rem)
   }
 
-  pub fn mul(&self, other: &Value) -> Option<Value> {
-    arithmetic!(self, other, mul)
+  // Integer powers stay integers, so negative exponents truncate towards zero
+  // the same way integer division does.
+  pub fn pow(&self, other: &Value, overflow: Overflow) -> Result<Value, Reason> {
+    match (self, other) {
+      (Value::Int(_), Value::Int(exponent)) | (Value::BigInt(_), Value::Int(exponent))
+        if *exponent < 0 =>
+      {
+        // The result is a fraction, so it's a float as with `/` on floats.
+        match self {
+          Value::Int(0) => Err(Reason::DivisionByZero),
+          Value::Int(base) => Ok(Value::Float((*base as f64).powf(*exponent as f64))),
+          Value::BigInt(base) => Ok(Value::Float(base.to_f64().powf(*exponent as f64))),
+          _ => unreachable!(),
+        }
+      }
+      (Value::Int(base), Value::Int(exponent)) => {
+        let result = u32::try_from(*exponent)
+          .ok()
+          .and_then(|exponent| base.checked_pow(exponent));
+        match (result, overflow) {
+          (Some(result), _) => Ok(Value::Int(result)),
+          (None, _) if base.unsigned_abs() <= 1 => {
+            big_pow(num_bigint::BigInt::from(*base), *exponent)
+          }
+          (None, Overflow::Error) => Err(Reason::Overflow),
+          (None, Overflow::Promote) => big_pow(num_bigint::BigInt::from(*base), *exponent),
+        }
+      }
+      (Value::BigInt(base), Value::Int(exponent)) => big_pow(base.0.clone(), *exponent),
+      (_, Value::BigInt(_)) if self.is_integer() => Err(Reason::Overflow),
+      (Value::Float(base), Value::Float(exponent)) => Ok(Value::Float(base.powf(*exponent))),
+      (Value::Float(base), Value::Int(exponent)) => Ok(Value::Float(base.powf(*exponent as f64))),
+      (Value::Float(base), Value::BigInt(exponent)) => {
+        Ok(Value::Float(base.powf(exponent.to_f64())))
+      }
+      (Value::Int(base), Value::Float(exponent)) => {
+        Ok(Value::Float((*base as f64).powf(*exponent)))
+      }
+      (Value::BigInt(base), Value::Float(exponent)) => {
+        Ok(Value::Float(base.to_f64().powf(*exponent)))
+      }
+      _ => Err(Reason::Type),
+    }
   }
 
-  pub fn div(&self, other: &Value) -> Option<Value> {
-    arithmetic!(self, other, div)
+  pub fn bit_and(&self, other: &Value) -> Result<Value, Reason> {
+    bitwise!(self, other, bitand)
   }
 
-  pub fn rem(&self, other: &Value) -> Option<Value> {
-    arithmetic!(self, other, rem)
+  pub fn bit_or(&self, other: &Value) -> Result<Value, Reason> {
+    bitwise!(self, other, bitor)
   }
 
-  pub fn neg(&self) -> Option<Value> {
+  pub fn bit_xor(&self, other: &Value) -> Result<Value, Reason> {
+    bitwise!(self, other, bitxor)
+  }
+
+  // Shifts take the overflow mode as arithmetic does, since `<<` multiplies
+  // by a power of two and `>>` divides by one.
+  pub fn shl(&self, other: &Value, overflow: Overflow) -> Result<Value, Reason> {
+    let amount = shift_amount(self, other, overflow)?;
     match self {
-      Value::Int(int) => Some(Value::Int(-int)),
-      Value::Float(float) => Some(Value::Float(-float)),
-      _ => None,
+      Value::Int(0) => Ok(Value::Int(0)),
+      Value::Int(int) => {
+        let result = if amount < 64 {
+          Some(int << amount).filter(|result| result >> amount == *int)
+        } else {
+          None
+        };
+        match (result, overflow) {
+          (Some(result), _) => Ok(Value::Int(result)),
+          (None, Overflow::Error) => Err(Reason::Overflow),
+          (None, Overflow::Promote) => big_shl(num_bigint::BigInt::from(*int), amount),
+        }
+      }
+      Value::BigInt(int) => big_shl(int.0.clone(), amount),
+      _ => unreachable!("`shift_amount` checked that `self` is an integer"),
+    }
+  }
+
+  pub fn shr(&self, other: &Value, overflow: Overflow) -> Result<Value, Reason> {
+    let amount = shift_amount(self, other, overflow)?;
+    match self {
+      Value::Int(int) => Ok(Value::Int(int >> amount.min(63))),
+      Value::BigInt(int) => Ok(Value::big(&int.0 >> amount)),
+      _ => unreachable!("`shift_amount` checked that `self` is an integer"),
+    }
+  }
+
+  pub fn neg(&self, overflow: Overflow) -> Result<Value, Reason> {
+    match self {
+      Value::Int(int) => match int.checked_neg() {
+        Some(result) => Ok(Value::Int(result)),
+        None => match overflow {
+          Overflow::Error => Err(Reason::Overflow),
+          Overflow::Promote => Ok(Value::big(-num_bigint::BigInt::from(*int))),
+        },
+      },
+      Value::BigInt(int) => Ok(Value::big(-&int.0)),
+      Value::Float(float) => Ok(Value::Float(-float)),
+      _ => Err(Reason::Type),
     }
   }
 
@@ -95,14 +306,62 @@ impl Value {
       (Value::Null, Value::Null) => Value::Bool(true),
       (Value::Bool(left), Value::Bool(right)) => Value::Bool(left == right),
       (Value::Int(left), Value::Int(right)) => Value::Bool(left == right),
+      (Value::BigInt(left), Value::BigInt(right)) => Value::Bool(left.0 == right.0),
       (Value::Float(left), Value::Float(right)) => Value::Bool(left == right),
-      (Value::String(left), Value::String(right)) => Value::Bool(left == right),
+      (Value::String(left), Value::String(right)) => {
+        Value::Bool(Gc::ptr_eq(left, right) || left == right)
+      }
       (Value::Array(left), Value::Array(right)) => Value::Bool(Gc::ptr_eq(left, right)),
       (Value::Map(left), Value::Map(right)) => Value::Bool(Gc::ptr_eq(left, right)),
       _ => Value::Bool(false),
     }
   }
 
+  // Compares by structure rather than identity: arrays, maps and tagged
+  // values are equal if their items are, ignoring meta maps. Lambdas are
+  // only equal to themselves.
+  pub fn equals(&self, other: &Value) -> bool {
+    self.equals_within(other, &mut Vec::new())
+  }
+
+  // `visiting` holds the pairs of containers being compared further up, which
+  // are taken to be equal so that cycles end.
+  fn equals_within(&self, other: &Value, visiting: &mut Vec<(usize, usize)>) -> bool {
+    let pair = match (self, other) {
+      (Value::Array(left), Value::Array(right)) => (address(&**left), address(&**right)),
+      (Value::Map(left), Value::Map(right)) => (address(&**left), address(&**right)),
+      (Value::Tagged(left), Value::Tagged(right)) => {
+        return left.tag == right.tag
+          && left.names == right.names
+          && equal_items(&left.fields, &right.fields, visiting);
+      }
+      (Value::Lambda(left), Value::Lambda(right)) => return Gc::ptr_eq(left, right),
+      (Value::NativeLambda(left), Value::NativeLambda(right)) => return Gc::ptr_eq(left, right),
+      _ => return matches!(self.eql(other), Value::Bool(true)),
+    };
+    if pair.0 == pair.1 || visiting.contains(&pair) {
+      return true;
+    }
+
+    visiting.push(pair);
+    let equal = match (self, other) {
+      (Value::Array(left), Value::Array(right)) => {
+        equal_items(left.borrow().items(), right.borrow().items(), visiting)
+      }
+      (Value::Map(left), Value::Map(right)) => {
+        let (left, right) = (left.borrow(), right.borrow());
+        left.map.len() == right.map.len()
+          && left.entries().all(|(key, value)| match right.field(&key) {
+            Some(other) => value.equals_within(&other, visiting),
+            None => false,
+          })
+      }
+      _ => unreachable!(),
+    };
+    visiting.pop();
+    equal
+  }
+
   pub fn neq(&self, other: &Value) -> Value {
     if let Value::Bool(result) = self.eql(other) {
       Value::Bool(!result)
@@ -143,10 +402,13 @@ impl Value {
     }
   }
 
+  // Map keys are matched by address, so string keys must be interned, like
+  // the strings from an executable and from `Vm::intern`.
   pub fn get(&self, key: &Value) -> Option<Value> {
     match self {
       Value::Array(array) => array.borrow().get(key),
       Value::Map(map) => map.borrow().get(key),
+      Value::Tagged(tagged) => tagged.get(key),
       _ => None,
     }
   }
@@ -159,7 +421,29 @@ impl Value {
     }
   }
 
-  pub fn as_string(&self) -> Option<&Gc<String>> {
+  pub fn is_integer(&self) -> bool {
+    matches!(self, Value::Int(_) | Value::BigInt(_))
+  }
+
+  // Returns whether this is an integer equal to zero. Floats are excluded, as
+  // dividing by a zero float is well-defined.
+  pub fn is_zero(&self) -> bool {
+    match self {
+      Value::Int(int) => *int == 0,
+      Value::BigInt(int) => int.0.is_zero(),
+      _ => false,
+    }
+  }
+
+  pub fn to_big(&self) -> Option<num_bigint::BigInt> {
+    match self {
+      Value::Int(int) => Some(num_bigint::BigInt::from(*int)),
+      Value::BigInt(int) => Some(int.0.clone()),
+      _ => None,
+    }
+  }
+
+  pub fn as_string(&self) -> Option<&Gc<Str>> {
     if let Value::String(string) = self {
       Some(string)
     } else {
@@ -175,6 +459,50 @@ impl Value {
     }
   }
 
+  pub fn kind(&self) -> Kind {
+    match self {
+      Value::Null => Kind::Null,
+      Value::Bool(_) => Kind::Bool,
+      Value::Int(_) | Value::BigInt(_) => Kind::Int,
+      Value::Float(_) => Kind::Float,
+      Value::String(_) => Kind::String,
+      Value::Array(_) => Kind::Array,
+      Value::Map(_) => Kind::Map,
+      Value::Tagged(_) => Kind::Tagged,
+      Value::Lambda(_) | Value::NativeLambda(_) => Kind::Lambda,
+    }
+  }
+
+  pub fn as_map(&self) -> Option<&Gc<GcCell<Map>>> {
+    if let Value::Map(map) = self {
+      Some(map)
+    } else {
+      None
+    }
+  }
+
+  // Sets the meta map of a map, which methods are looked up in, or removes it
+  // if `meta` is null. Fails if either isn't a map, or if the map would end
+  // up in its own chain of meta maps.
+  pub fn set_meta(&self, meta: &Value) -> Result<(), Reason> {
+    let map = self.as_map().ok_or(Reason::Type)?;
+    let meta = match meta {
+      Value::Null => None,
+      meta => Some(Gc::clone(meta.as_map().ok_or(Reason::Type)?)),
+    };
+
+    let mut next = meta.clone();
+    while let Some(ancestor) = next {
+      if Gc::ptr_eq(&ancestor, map) {
+        return Err(Reason::MetaCycle);
+      }
+      next = ancestor.borrow().meta().cloned();
+    }
+
+    map.borrow_mut().meta = meta;
+    Ok(())
+  }
+
   pub fn as_array(&self) -> Option<&Gc<GcCell<Array>>> {
     if let Value::Array(array) = self {
       Some(array)
@@ -192,12 +520,69 @@ impl Value {
   }
 }
 
+fn address<T>(value: &T) -> usize {
+  value as *const T as usize
+}
+
+fn equal_items(left: &[Value], right: &[Value], visiting: &mut Vec<(usize, usize)>) -> bool {
+  left.len() == right.len()
+    && left
+      .iter()
+      .zip(right)
+      .all(|(left, right)| left.equals_within(right, visiting))
+}
+
+// Validates the operands of a shift, returning the amount to shift by.
+// The most bits `**` and `<<` will build an integer of, so that a program
+// can't exhaust memory with a single operator.
+const MAX_BITS: u64 = 1 << 24;
+
+fn big_pow(base: num_bigint::BigInt, exponent: i64) -> Result<Value, Reason> {
+  // Powers of -1, 0 and 1 never grow, however large the exponent.
+  if base.magnitude() <= &num_bigint::BigUint::from(1u8) {
+    let odd = exponent % 2 == 1;
+    return Ok(match base.sign() {
+      num_bigint::Sign::Minus if odd => Value::Int(-1),
+      num_bigint::Sign::NoSign if exponent > 0 => Value::Int(0),
+      _ => Value::Int(1),
+    });
+  }
+  let exponent = u32::try_from(exponent).map_err(|_| Reason::Overflow)?;
+  if base.bits().saturating_mul(exponent as u64) > MAX_BITS {
+    return Err(Reason::Overflow);
+  }
+  Ok(Value::big(base.pow(exponent)))
+}
+
+fn big_shl(int: num_bigint::BigInt, amount: usize) -> Result<Value, Reason> {
+  if int.bits().saturating_add(amount as u64) > MAX_BITS {
+    return Err(Reason::Overflow);
+  }
+  Ok(Value::big(int << amount))
+}
+
+// Amounts that don't fit in an `i64` only come up when promoting. They shift
+// everything out to the right, and are too large to shift left.
+fn shift_amount(value: &Value, amount: &Value, overflow: Overflow) -> Result<usize, Reason> {
+  match (value.is_integer(), amount) {
+    (true, Value::Int(amount)) if *amount < 0 => Err(Reason::NegativeShift),
+    (true, Value::Int(amount)) => usize::try_from(*amount).map_err(|_| Reason::Overflow),
+    (true, Value::BigInt(amount)) if amount.0.sign() == num_bigint::Sign::Minus => {
+      Err(Reason::NegativeShift)
+    }
+    (true, Value::BigInt(_)) if overflow == Overflow::Promote => Ok(usize::MAX),
+    (true, Value::BigInt(_)) => Err(Reason::Overflow),
+    _ => Err(Reason::Type),
+  }
+}
+
 impl fmt::Display for Value {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     match self {
       Value::Null => write!(f, "null"),
       Value::Bool(bool) => write!(f, "{}", bool),
       Value::Int(int) => write!(f, "{}", int),
+      Value::BigInt(int) => write!(f, "{}", int.0),
       Value::Float(float) => write!(f, "{}", float),
       Value::String(string) => write!(f, "{}", string),
       Value::Array(array) => write!(f, "{}", array.borrow()),
@@ -209,6 +594,23 @@ impl fmt::Display for Value {
   }
 }
 
+#[derive(Debug, Finalize)]
+pub struct BigInt(num_bigint::BigInt);
+
+impl BigInt {
+  pub fn to_f64(&self) -> f64 {
+    self.0.to_f64().unwrap_or(f64::NAN)
+  }
+
+  pub fn to_signed_bytes_le(&self) -> Vec<u8> {
+    self.0.to_signed_bytes_le()
+  }
+}
+
+unsafe impl Trace for BigInt {
+  unsafe_empty_trace!();
+}
+
 #[derive(Debug, Finalize, Trace)]
 pub struct Array {
   array: Vec<Value>,
@@ -219,39 +621,93 @@ impl Array {
     Array { array: Vec::new() }
   }
 
+  pub fn with_items(items: Vec<Value>) -> Array {
+    Array { array: items }
+  }
+
+  // Writing at `len` appends to the array. Writing further out is an error,
+  // rather than filling the gap with nulls.
   pub fn set(&mut self, key: Value, value: Value) -> Option<()> {
-    if let Value::Int(key) = key {
-      if key < 0 {
-        None
-      } else {
-        *self.array.get_mut(key as usize)? = value;
-        Some(())
-      }
+    let index = self.position(&key)?;
+    if index == self.array.len() {
+      self.array.push(value);
     } else {
-      None
+      self.array[index] = value;
     }
+    Some(())
   }
 
   pub fn get(&self, key: &Value) -> Option<Value> {
-    if let Value::Int(key) = key {
-      if *key < 0 {
-        None
-      } else {
-        Some(
-          self
-            .array
-            .get(*key as usize)
-            .cloned()
-            .unwrap_or(Value::Null),
-        )
-      }
+    self.array.get(self.position(key)?).cloned()
+  }
+
+  pub fn push(&mut self, value: &Value) {
+    self.array.push(value.clone());
+  }
+
+  pub fn len(&self) -> usize {
+    self.array.len()
+  }
+
+  // Copies the items in the range. Either bound can be null to leave that
+  // side of the range open.
+  pub fn slice(&self, start: &Value, end: &Value) -> Option<Array> {
+    let (start, end) = self.range(start, end)?;
+    Some(Array {
+      array: self.array[start..end].to_vec(),
+    })
+  }
+
+  // Removes the items in the range, returning the index the range started at.
+  pub fn remove(&mut self, start: &Value, end: &Value) -> Option<usize> {
+    let (start, end) = self.range(start, end)?;
+    self.array.drain(start..end);
+    Some(start)
+  }
+
+  // Inserts the items before the index, which can be `len` to append them.
+  pub fn insert(&mut self, key: &Value, items: Vec<Value>) -> Option<()> {
+    let index = self.position(key)?;
+    self.array.splice(index..index, items);
+    Some(())
+  }
+
+  pub fn items(&self) -> &[Value] {
+    &self.array
+  }
+
+  // Resolves an index, counting back from the end of the array if it is
+  // negative. The result can be `len`, one past the last item. Floats with no
+  // fractional part are indices too, as number literals are floats.
+  fn position(&self, key: &Value) -> Option<usize> {
+    let len = self.array.len() as i64;
+    let index = match key {
+      Value::Int(index) => *index,
+      Value::Float(float) if float.fract() == 0.0 => *float as i64,
+      _ => return None,
+    };
+    let index = if index < 0 { index + len } else { index };
+    if (0..=len).contains(&index) {
+      Some(index as usize)
     } else {
       None
     }
   }
 
-  pub fn push(&mut self, value: &Value) {
-    self.array.push(value.clone());
+  fn range(&self, start: &Value, end: &Value) -> Option<(usize, usize)> {
+    let start = match start {
+      Value::Null => 0,
+      start => self.position(start)?,
+    };
+    let end = match end {
+      Value::Null => self.array.len(),
+      end => self.position(end)?,
+    };
+    if start <= end {
+      Some((start, end))
+    } else {
+      None
+    }
   }
 }
 
@@ -271,19 +727,46 @@ impl fmt::Display for Array {
 
 #[derive(Debug, Finalize, Trace)]
 pub struct Map {
-  map: HashMap<Gc<String>, Value>,
+  map: HashMap<Symbol, Value>,
+  // Where methods that aren't fields of the map are looked up.
+  meta: Option<Gc<GcCell<Map>>>,
 }
 
 impl Map {
   pub fn new() -> Map {
     Map {
       map: HashMap::new(),
+      meta: None,
     }
   }
 
+  pub fn meta(&self) -> Option<&Gc<GcCell<Map>>> {
+    self.meta.as_ref()
+  }
+
+  // Like `get`, but gives `None` rather than null if the key isn't set.
+  pub fn field(&self, key: &Value) -> Option<Value> {
+    let key = Symbol::new(Gc::clone(key.as_string()?));
+    self.map.get(&key).cloned()
+  }
+
+  pub fn entries(&self) -> impl Iterator<Item = (Value, &Value)> + '_ {
+    self
+      .map
+      .iter()
+      .map(|(key, value)| (Value::String(Gc::clone(key.as_gc())), value))
+  }
+
+  pub fn keys(&self) -> impl Iterator<Item = Value> + '_ {
+    self
+      .map
+      .keys()
+      .map(|key| Value::String(Gc::clone(key.as_gc())))
+  }
+
   pub fn set(&mut self, key: Value, value: Value) -> Option<()> {
     if let Value::String(key) = &key {
-      self.map.insert(Gc::clone(key), value);
+      self.map.insert(Symbol::new(Gc::clone(key)), value);
       Some(())
     } else {
       None
@@ -291,8 +774,9 @@ impl Map {
   }
 
   pub fn get(&self, key: &Value) -> Option<Value> {
-    if let Value::String(key) = &key {
-      Some(self.map.get(key).cloned().unwrap_or(Value::Null))
+    if let Value::String(key) = key {
+      let key = Symbol::new(Gc::clone(key));
+      Some(self.map.get(&key).cloned().unwrap_or(Value::Null))
     } else {
       None
     }
@@ -315,27 +799,81 @@ impl fmt::Display for Map {
 
 #[derive(Debug, Finalize, Trace)]
 pub struct Tagged {
-  tag: Gc<String>,
-  value: Value,
+  tag: Gc<Str>,
+  // Empty unless the fields are named.
+  names: Vec<Gc<Str>>,
+  fields: Vec<Value>,
 }
 
 impl Tagged {
-  pub fn new(tag: Gc<String>, value: Value) -> Tagged {
-    Tagged { tag, value }
+  pub fn new(tag: Gc<Str>, fields: Vec<Value>) -> Tagged {
+    Tagged {
+      tag,
+      names: Vec::new(),
+      fields,
+    }
+  }
+
+  pub fn with_names(tag: Gc<Str>, names: Vec<Gc<Str>>, fields: Vec<Value>) -> Tagged {
+    Tagged { tag, names, fields }
   }
 
-  pub fn tag(&self) -> &Gc<String> {
+  pub fn tag(&self) -> &Gc<Str> {
     &self.tag
   }
 
-  pub fn value(&self) -> &Value {
-    &self.value
+  pub fn names(&self) -> &[Gc<Str>] {
+    &self.names
+  }
+
+  pub fn fields(&self) -> &[Value] {
+    &self.fields
+  }
+
+  pub fn field(&self, index: usize) -> Option<&Value> {
+    self.fields.get(index)
+  }
+
+  // Looks up a named field. Missing fields are null, like map keys.
+  pub fn get(&self, key: &Value) -> Option<Value> {
+    let key = key.as_string()?;
+    Some(
+      self
+        .position(key)
+        .map(|index| self.fields[index].clone())
+        .unwrap_or(Value::Null),
+    )
+  }
+
+  pub fn has(&self, key: &Value) -> bool {
+    key.as_string().and_then(|key| self.position(key)).is_some()
+  }
+
+  fn position(&self, key: &Gc<Str>) -> Option<usize> {
+    self
+      .names
+      .iter()
+      .position(|name| Gc::ptr_eq(name, key) || **name == **key)
   }
 }
 
 impl fmt::Display for Tagged {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    write!(f, "{}({})", self.tag, self.value)
+    write!(f, "{}", self.tag)?;
+    if self.fields.is_empty() {
+      return Ok(());
+    }
+    write!(f, "(")?;
+    for (index, field) in self.fields.iter().enumerate() {
+      if index > 0 {
+        write!(f, ", ")?;
+      }
+      if let Some(name) = self.names.get(index) {
+        write!(f, "{}: ", name)?;
+      }
+      write!(f, "{}", field)?;
+    }
+    write!(f, ")")
   }
 }
 
@@ -370,6 +908,11 @@ impl Lambda {
     &self.chunk
   }
 
+  // The name the compiler gave the lambda, if the executable has debug info.
+  pub fn name(&self) -> Option<&Rc<String>> {
+    self.chunk.info().map(|info| info.name())
+  }
+
   pub fn locals(&self) -> usize {
     self.locals
   }
@@ -377,27 +920,83 @@ impl Lambda {
   pub fn upvalue(&self, index: usize) -> Option<&Gc<GcCell<Upvalue>>> {
     self.upvalues.get(index)
   }
+
+  pub fn upvalues(&self) -> &[Gc<GcCell<Upvalue>>] {
+    &self.upvalues
+  }
 }
 
+type NativeFn = dyn Fn(&mut Context, Value) -> Result<Value, Error>;
+
+pub type NativeFuture = Pin<Box<dyn Future<Output = Result<Value, Error>>>>;
+
+type AsyncNativeFn = dyn Fn(Value) -> NativeFuture;
+
 #[derive(Finalize)]
-pub struct NativeLambda(Box<dyn Fn(Value) -> Value>);
+pub struct NativeLambda {
+  name: Rc<String>,
+  f: NativeBody,
+}
+
+enum NativeBody {
+  Sync(Box<NativeFn>),
+  Async(Box<AsyncNativeFn>),
+}
+
+// What calling a native lambda gives: its result, or for an async native the
+// future the fiber must wait on before it has one.
+pub enum NativeCall {
+  Done(Result<Value, Error>),
+  Pending(NativeFuture),
+}
 
 impl NativeLambda {
-  pub fn new<F>(f: F) -> NativeLambda
+  pub fn new<F>(name: &str, f: F) -> NativeLambda
   where
     F: Fn(Value) -> Value + 'static,
   {
-    NativeLambda(Box::new(f))
+    NativeLambda::with_context(name, move |_, argument| Ok(f(argument)))
   }
 
-  pub fn call(&self, argument: Value) -> Value {
-    (self.0)(argument)
+  // Creates a native lambda that can call back into the fiber running it.
+  pub fn with_context<F>(name: &str, f: F) -> NativeLambda
+  where
+    F: Fn(&mut Context, Value) -> Result<Value, Error> + 'static,
+  {
+    NativeLambda {
+      name: Rc::new(name.to_string()),
+      f: NativeBody::Sync(Box::new(f)),
+    }
+  }
+
+  // Creates a native lambda that returns a future, which the calling fiber is
+  // suspended on until it resolves.
+  pub fn with_future<F, R>(name: &str, f: F) -> NativeLambda
+  where
+    F: Fn(Value) -> R + 'static,
+    R: Future<Output = Result<Value, Error>> + 'static,
+  {
+    NativeLambda {
+      name: Rc::new(name.to_string()),
+      f: NativeBody::Async(Box::new(move |argument| Box::pin(f(argument)))),
+    }
+  }
+
+  pub fn name(&self) -> &Rc<String> {
+    &self.name
+  }
+
+  pub fn call(&self, context: &mut Context, argument: Value) -> NativeCall {
+    match &self.f {
+      NativeBody::Sync(f) => NativeCall::Done(f(context, argument)),
+      NativeBody::Async(f) => NativeCall::Pending(f(argument)),
+    }
   }
 }
 
 impl fmt::Debug for NativeLambda {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    write!(f, "<<native lambda>>")
+    write!(f, "<<native lambda {}>>", self.name)
   }
 }
 
@@ -420,3 +1019,92 @@ impl Upvalue {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::intern::Interner;
+
+  #[test]
+  fn pow_with_negative_exponent_is_float() {
+    for overflow in [Overflow::Error, Overflow::Promote] {
+      let result = Value::Int(2).pow(&Value::Int(-1), overflow);
+      assert!(matches!(result, Ok(Value::Float(half)) if half == 0.5));
+      let result = Value::Int(-2).pow(&Value::Int(-3), overflow);
+      assert!(matches!(result, Ok(Value::Float(eighth)) if eighth == -0.125));
+    }
+  }
+
+  #[test]
+  fn pow_of_zero_with_negative_exponent_is_error() {
+    let result = Value::Int(0).pow(&Value::Int(-1), Overflow::Error);
+    assert!(matches!(result, Err(Reason::DivisionByZero)));
+  }
+
+  #[test]
+  fn pow_overflow() {
+    let result = Value::Int(2).pow(&Value::Int(64), Overflow::Error);
+    assert!(matches!(result, Err(Reason::Overflow)));
+    let result = Value::Int(2).pow(&Value::Int(64), Overflow::Promote);
+    assert!(matches!(result, Ok(Value::BigInt(_))));
+  }
+
+  #[test]
+  fn promoted_results_are_capped() {
+    let result = Value::Int(1).shl(&Value::Int(1 << 40), Overflow::Promote);
+    assert!(matches!(result, Err(Reason::Overflow)));
+    let result = Value::Int(2).pow(&Value::Int(4_000_000_000), Overflow::Promote);
+    assert!(matches!(result, Err(Reason::Overflow)));
+    let result = Value::Int(1).shl(&Value::Int(100), Overflow::Promote);
+    assert!(matches!(result, Ok(Value::BigInt(_))));
+  }
+
+  #[test]
+  fn powers_of_small_bases_are_not_capped() {
+    for overflow in [Overflow::Error, Overflow::Promote] {
+      let exponent = Value::Int(1 << 40);
+      assert!(matches!(
+        Value::Int(1).pow(&exponent, overflow),
+        Ok(Value::Int(1))
+      ));
+      assert!(matches!(
+        Value::Int(-1).pow(&exponent, overflow),
+        Ok(Value::Int(1))
+      ));
+      assert!(matches!(
+        Value::Int(0).pow(&exponent, overflow),
+        Ok(Value::Int(0))
+      ));
+    }
+    let odd = Value::Int((1 << 40) + 1);
+    let result = Value::Int(-1).pow(&odd, Overflow::Promote);
+    assert!(matches!(result, Ok(Value::Int(-1))));
+  }
+
+  #[test]
+  fn shr_by_huge_amount() {
+    let amount = Value::big(num_bigint::BigInt::from(1) << 100);
+    let result = Value::Int(-5).shr(&amount, Overflow::Promote);
+    assert!(matches!(result, Ok(Value::Int(-1))));
+    let result = Value::Int(5).shr(&amount, Overflow::Promote);
+    assert!(matches!(result, Ok(Value::Int(0))));
+    let result = Value::Int(5).shr(&amount, Overflow::Error);
+    assert!(matches!(result, Err(Reason::Overflow)));
+  }
+
+  #[test]
+  fn map_matches_keys_by_content() {
+    let mut map = Map::new();
+    let key = || Value::String(Gc::new(Str::new("key".to_string())));
+    map.set(key(), Value::Int(1)).unwrap();
+    assert!(matches!(map.get(&key()), Some(Value::Int(1))));
+    assert!(matches!(map.field(&key()), Some(Value::Int(1))));
+
+    let interned = Interner::new().intern("key");
+    let interned = Value::String(Gc::clone(interned.as_gc()));
+    assert!(matches!(map.get(&interned), Some(Value::Int(1))));
+    map.set(interned, Value::Int(2)).unwrap();
+    assert!(matches!(map.get(&key()), Some(Value::Int(2))));
+    assert_eq!(map.keys().count(), 1);
+  }
+}
@@ -1,45 +1,255 @@
-use std::rc::Rc;
+use std::{cell::RefCell, future, rc::Rc, task::Poll};
+
+use gc::Gc;
 
 use crate::{
+  capability::Capabilities,
+  coverage::Coverage,
   error::{Error, Reason, Trace},
-  fiber::{Fiber, Status},
+  fiber::{Fiber, Module},
+  intern::Interner,
   parse::Parser,
   registry::NativeLambdaRegistry,
+  value::{Overflow, Value},
 };
 
 pub struct Vm {
   registry: Option<Rc<NativeLambdaRegistry>>,
+  overflow: Overflow,
+  interner: Rc<RefCell<Interner>>,
   fiber: Option<Fiber>,
+  // The executable the fiber runs, which snapshots refer to.
+  module: Option<Module>,
+  // Fibers waiting to be run by `join`.
+  spawned: Vec<Fiber>,
+  coverage: Option<Rc<RefCell<Coverage>>>,
+  // What natives executables may load, if the VM is sandboxed.
+  capabilities: Option<Capabilities>,
 }
 
 impl Vm {
   pub fn new() -> Vm {
+    Vm::with_overflow(Overflow::Error)
+  }
+
+  pub fn with_overflow(overflow: Overflow) -> Vm {
     Vm {
       registry: None,
+      overflow,
+      interner: Rc::new(RefCell::new(Interner::new())),
       fiber: None,
+      module: None,
+      spawned: Vec::new(),
+      coverage: None,
+      capabilities: None,
     }
   }
 
+  // Records the lines that run in the executables loaded after this, and in
+  // the lambdas they return, into `coverage`.
+  pub fn record_coverage(&mut self, coverage: Rc<RefCell<Coverage>>) {
+    self.coverage = Some(coverage);
+  }
+
+  // Sandboxes the executables loaded after this, so that they can only load
+  // the natives that need no capability or one of the given capabilities.
+  // Loading any other native fails with `Reason::NotGranted`, as does calling
+  // a method that needs a capability which isn't granted.
+  pub fn sandbox(&mut self, capabilities: Capabilities) {
+    self.capabilities = Some(capabilities);
+  }
+
   pub fn run(&mut self, registry: NativeLambdaRegistry, executable: &[u8]) -> Result<(), Error> {
-    let parser = Parser::new(executable);
-    let function = parser
+    self.eval(registry, executable)?;
+    Ok(())
+  }
+
+  // Runs the executable and returns the value of its module, which is how a
+  // module exports values to the host.
+  pub fn eval(
+    &mut self,
+    registry: NativeLambdaRegistry,
+    executable: &[u8],
+  ) -> Result<Value, Error> {
+    self.load(registry, executable)?;
+    self.resume()
+  }
+
+  // Like `eval`, but the module can call async natives, and waits on their
+  // futures instead of failing.
+  pub async fn eval_async(
+    &mut self,
+    registry: NativeLambdaRegistry,
+    executable: &[u8],
+  ) -> Result<Value, Error> {
+    self.load(registry, executable)?;
+    let fiber = self.fiber.as_mut().unwrap();
+    future::poll_fn(|cx| fiber.poll_run(cx)).await?;
+    self.finish()
+  }
+
+  // Loads the executable without running it, so it can be run a step at a
+  // time with `step`, and finished with `resume`.
+  pub fn load(
+    &mut self,
+    mut registry: NativeLambdaRegistry,
+    executable: &[u8],
+  ) -> Result<(), Error> {
+    if let Some(capabilities) = &self.capabilities {
+      registry.grant(capabilities.clone());
+    }
+
+    let function = Parser::new(executable, &mut self.interner.borrow_mut())
       .parse()
       .map_err(|error| Error::new(Reason::Parse(error), Trace::new()))?;
 
     self.registry = Some(Rc::new(registry));
+    self.module = Some(Module::new(executable, &function));
+
+    if let Some(coverage) = &self.coverage {
+      coverage
+        .borrow_mut()
+        .add(self.module.as_ref().unwrap().chunks());
+    }
 
-    self.fiber = Some(Fiber::new(
-      Rc::clone(&self.registry.as_ref().unwrap()),
+    let fiber = Fiber::new(
+      Rc::clone(self.registry.as_ref().unwrap()),
+      Rc::clone(&self.interner),
+      self.overflow,
       function,
-    ));
+    );
+    self.fiber = Some(self.attach(fiber));
+    Ok(())
+  }
 
-    while let Status::Running = self.fiber.as_mut().unwrap().step()? {}
+  // Runs the loaded module until it's done, and returns its value like `eval`.
+  pub fn resume(&mut self) -> Result<Value, Error> {
+    self
+      .fiber
+      .as_mut()
+      .expect("an executable must be loaded before resuming it")
+      .run()?;
+    self.finish()
+  }
 
-    Ok(())
+  // Writes the loaded module's fiber to bytes, to restore it later. It must
+  // be paused between steps, rather than in a call from the host.
+  //
+  // Panics if no executable has been loaded.
+  pub fn snapshot(&self) -> Result<Vec<u8>, Error> {
+    let fiber = self
+      .fiber
+      .as_ref()
+      .expect("an executable must be loaded before taking a snapshot");
+    fiber.snapshot(self.module.as_ref().unwrap())
   }
 
-  pub fn step(&mut self) -> Result<(), Error> {
-    self.fiber.as_mut().unwrap().step()?;
+  // Loads the executable, and restores a snapshot of its fiber in place of
+  // starting it. Natives must be registered the same way as when the
+  // snapshot was taken. Continue running it with `step` or `resume`.
+  pub fn restore(
+    &mut self,
+    registry: NativeLambdaRegistry,
+    executable: &[u8],
+    snapshot: &[u8],
+  ) -> Result<(), Error> {
+    self.load(registry, executable)?;
+    let fiber = Fiber::restore(
+      Rc::clone(self.registry.as_ref().unwrap()),
+      Rc::clone(&self.interner),
+      self.overflow,
+      self.module.as_ref().unwrap(),
+      snapshot,
+    )?;
+    self.fiber = Some(self.attach(fiber));
     Ok(())
   }
+
+  // Gives the fiber what the VM records into.
+  fn attach(&self, mut fiber: Fiber) -> Fiber {
+    if let Some(coverage) = &self.coverage {
+      fiber.record_coverage(Rc::clone(coverage));
+    }
+    fiber
+  }
+
+  fn finish(&mut self) -> Result<Value, Error> {
+    let fiber = self.fiber.as_mut().unwrap();
+    fiber.close_upvalues();
+    fiber.result()
+  }
+
+  // Returns the string interned, so that it shares its allocation, and the
+  // hash it keeps once it's used as a map key, with the equal strings in the
+  // executables the VM loads. Interned strings live as long as the VM.
+  pub fn intern(&mut self, string: &str) -> Value {
+    Value::String(Gc::clone(self.interner.borrow_mut().intern(string).as_gc()))
+  }
+
+  // Calls a lambda from the executable that was run last, on a new frame on
+  // top of the finished module, so it can still reach the module's bindings.
+  //
+  // Panics if no executable has been run.
+  pub fn call(&mut self, lambda: &Value, arguments: &[Value]) -> Result<Value, Error> {
+    self
+      .fiber
+      .as_mut()
+      .expect("an executable must be run before calling into it")
+      .call_from_host(lambda, arguments)
+  }
+
+  // Spawns a fiber calling a lambda from the executable that was run last,
+  // which `join` runs. Returns the index of its result in what `join` returns.
+  //
+  // Panics if no executable has been run.
+  pub fn spawn(&mut self, lambda: &Value, arguments: &[Value]) -> Result<usize, Error> {
+    let registry = self
+      .registry
+      .as_ref()
+      .expect("an executable must be run before spawning fibers");
+    let fiber = Fiber::with_lambda(
+      Rc::clone(registry),
+      Rc::clone(&self.interner),
+      self.overflow,
+      lambda,
+      arguments,
+    )?;
+    let fiber = self.attach(fiber);
+    self.spawned.push(fiber);
+    Ok(self.spawned.len() - 1)
+  }
+
+  // Runs the spawned fibers until they are all done, and returns their
+  // results in the order they were spawned. Each fiber runs until it waits on
+  // an async native, then the next one that can run does, in spawn order, so
+  // the interleaving only depends on when the futures resolve.
+  pub async fn join(&mut self) -> Vec<Result<Value, Error>> {
+    let mut fibers = self.spawned.drain(..).collect::<Vec<_>>();
+    let mut results = fibers.iter().map(|_| None).collect::<Vec<_>>();
+
+    future::poll_fn(|cx| {
+      for (fiber, result) in fibers.iter_mut().zip(&mut results) {
+        if result.is_some() {
+          continue;
+        }
+        if let Poll::Ready(done) = fiber.poll_run(cx) {
+          *result = Some(done.and_then(|_| fiber.result()));
+        }
+      }
+
+      if results.iter().all(Option::is_some) {
+        Poll::Ready(())
+      } else {
+        Poll::Pending
+      }
+    })
+    .await;
+
+    results.into_iter().map(Option::unwrap).collect()
+  }
+
+  // Runs one op of the loaded module, and returns whether it's done.
+  pub fn step(&mut self) -> Result<bool, Error> {
+    self.fiber.as_mut().unwrap().advance()
+  }
 }
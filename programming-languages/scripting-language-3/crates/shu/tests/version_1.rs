@@ -0,0 +1,14 @@
+use shu::{NativeLambdaRegistry, Vm};
+
+// Written by the compiler before tagged values could have several fields,
+// from `version_1.oma`. It has no natives, and its module is an array.
+const EXECUTABLE: &[u8] = include_bytes!("fixtures/version_1.bin");
+
+#[test]
+fn runs_version_1_executable() {
+  assert_eq!(EXECUTABLE[3], 1);
+  let value = Vm::new()
+    .eval(NativeLambdaRegistry::new(), EXECUTABLE)
+    .unwrap();
+  assert_eq!(value.to_string(), "[3, 10, ten, x, Some(10), 6]");
+}